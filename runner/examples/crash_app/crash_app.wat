@@ -0,0 +1,8 @@
+;; An app which traps every time it runs, for checking that the runner shows it crashed,
+;; restarts it, and eventually gives up on it. Text format modules are compiled as they're
+;; loaded, so there's nothing to build, just run `megabit-runner --app` on this directory.
+(module
+  (func (export "setup") (result i32)
+    i32.const 0)
+  (func (export "run") (result i32)
+    unreachable))
@@ -0,0 +1,93 @@
+//! Draws a framed greeting into a screen buffer with embedded-graphics and sends it to the device.
+//!
+//! cargo run --example embedded_graphics --features embedded-graphics -- --device /dev/ttyACM0
+
+use clap::Parser;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_5X8, MonoTextStyle},
+    pixelcolor::{BinaryColor, Rgb555},
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+use megabit_runner::{display::ScreenBuffer, serial};
+use megabit_serial_protocol::PixelRepresentation;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, Parser)]
+struct Args {
+    #[arg(short, long)]
+    device: PathBuf,
+}
+
+fn draw<D: DrawTarget>(target: &mut D, frame: D::Color, text: D::Color) -> Result<(), D::Error> {
+    let size = target.bounding_box().size;
+    Rectangle::new(Point::zero(), size)
+        .into_styled(PrimitiveStyle::with_stroke(frame, 1))
+        .draw(target)?;
+    Text::with_baseline(
+        "Hello",
+        Point::new(2, 2),
+        MonoTextStyle::new(&FONT_5X8, text),
+        Baseline::Top,
+    )
+    .draw(target)?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let (tx, rx) = async_channel::unbounded();
+    let (serial_conn, shutdown_handle, serial_task) =
+        serial::start_serial_task(args.device, serial::SerialConfig::default(), tx, rx);
+    let _serial_task_handle = tokio::spawn(Box::into_pin(serial_task));
+
+    let display_info = serial_conn.get_display_info().await?;
+    let is_rgb = match display_info.pixel_representation {
+        PixelRepresentation::Monocolor => false,
+        PixelRepresentation::RGB555 => true,
+        pixel_representation => {
+            anyhow::bail!("This example only draws on monocolor and RGB555 displays, not {pixel_representation:?}")
+        }
+    };
+    let (width, height) = (display_info.width as usize, display_info.height as usize);
+
+    if is_rgb {
+        let mut screen_buffer = ScreenBuffer::new(
+            width,
+            height,
+            Some(megabit_runner::display::DEFAULT_MONO_PALETTE),
+        );
+        let _ = draw(
+            &mut screen_buffer.rgb_target(),
+            Rgb555::BLUE,
+            Rgb555::YELLOW,
+        );
+        let rows = (0..height)
+            .map(|row| Ok((u8::try_from(row)?, screen_buffer.get_row_rgb(row)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        serial_conn.update_rows_rgb(&rows[..]).await?;
+    } else {
+        let mut screen_buffer = ScreenBuffer::new(width, height, None);
+        let _ = draw(
+            &mut screen_buffer.mono_target(),
+            BinaryColor::On,
+            BinaryColor::On,
+        );
+        let rows = (0..height)
+            .map(|row| Ok((u8::try_from(row)?, screen_buffer.get_row(row)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        serial_conn.update_rows(&rows[..]).await?;
+    }
+    if serial_conn
+        .capabilities()
+        .contains(serial::Capabilities::DOUBLE_BUFFERING)
+    {
+        serial_conn.commit_render().await?;
+    }
+
+    shutdown_handle.shutdown(vec![]).await;
+    Ok(())
+}
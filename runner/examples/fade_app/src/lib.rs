@@ -0,0 +1,80 @@
+//! An example app which reads its screen back, fades it out a little, and writes it again, with
+//! a new dot drawn every frame so it leaves a trail. Build it with
+//! `cargo build --release --target wasm32-unknown-unknown` in this directory and copy
+//! `fade_app.wasm` out of the target directory next to `manifest.json` to run it with
+//! `megabit-runner --app`.
+
+use extism_pdk::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const WHITE: u32 = 0x7fff;
+/// Format bytes at the start of what `read_region` returns
+const FORMAT_MONO: u8 = 0;
+const FORMAT_RGB: u8 = 1;
+
+#[host_fn]
+extern "ExtismHost" {
+    fn get_display_info() -> Vec<u8>;
+    fn clear_display(render: u32) -> ();
+    fn render_dirty() -> ();
+    fn set_pixel(x: u32, y: u32, color: u32) -> ();
+    fn read_region(x: u32, y: u32, width: u32, height: u32) -> Vec<u8>;
+    fn write_region(x: u32, y: u32, width: u32, height: u32, data: Vec<u8>) -> ();
+    fn write_region_rgb(x: u32, y: u32, width: u32, height: u32, data: Vec<u8>) -> ();
+}
+
+static FRAME: AtomicU32 = AtomicU32::new(0);
+
+/// Width and height of the app's display.
+fn display_size() -> FnResult<(u32, u32)> {
+    let info = unsafe { get_display_info()? };
+    let word = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Ok((word(&info[0..4]), word(&info[4..8])))
+}
+
+/// Halves each channel of an RGB555 color.
+fn dim(color: u16) -> u16 {
+    (color >> 1) & 0b0_01111_01111_01111
+}
+
+#[plugin_fn]
+pub fn setup() -> FnResult<()> {
+    unsafe { clear_display(1)? };
+    Ok(())
+}
+
+#[plugin_fn]
+pub fn run() -> FnResult<()> {
+    let frame = FRAME.fetch_add(1, Ordering::Relaxed);
+    let (width, height) = display_size()?;
+
+    let pixels = unsafe { read_region(0, 0, width, height)? };
+    match pixels.split_first() {
+        Some((&FORMAT_RGB, colors)) => {
+            let faded = colors
+                .chunks_exact(2)
+                .flat_map(|bytes| dim(u16::from_le_bytes([bytes[0], bytes[1]])).to_le_bytes())
+                .collect::<Vec<_>>();
+            unsafe { write_region_rgb(0, 0, width, height, faded)? };
+        }
+        Some((&FORMAT_MONO, bits)) => {
+            // Monocolor pixels can't dim, so a scattered few of them go out each frame instead
+            let mask = [0xee, 0xbb, 0x77, 0xdd][frame as usize % 4];
+            let faded = bits.iter().map(|byte| byte & mask).collect::<Vec<_>>();
+            unsafe { write_region(0, 0, width, height, faded)? };
+        }
+        _ => return Err(Error::msg("Unknown format from read_region").into()),
+    }
+
+    // A dot bouncing around the display
+    let bounce = |position: u32, length: u32| {
+        let period = 2 * (length - 1).max(1);
+        let step = position % period;
+        step.min(period - step)
+    };
+    unsafe {
+        set_pixel(bounce(frame, width), bounce(frame * 2 / 3, height), WHITE)?;
+        render_dirty()?;
+    }
+    Ok(())
+}
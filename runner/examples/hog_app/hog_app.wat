@@ -0,0 +1,11 @@
+;; An app which grabs another 1 MiB of memory every time it runs and never gives it back, for
+;; checking that the runner stops apps at their memory limit and restarts them. Text format
+;; modules are compiled as they're loaded, so there's nothing to build, just run
+;; `megabit-runner --app` on this directory.
+(module
+  (memory 1)
+  (func (export "setup") (result i32)
+    i32.const 0)
+  (func (export "run") (result i32)
+    (drop (memory.grow (i32.const 16)))
+    i32.const 0))
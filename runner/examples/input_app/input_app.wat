@@ -0,0 +1,37 @@
+;; An app which lights its top left pixel on runs where it got input and clears it otherwise,
+;; for checking that input from the device reaches apps. Text format modules are compiled as
+;; they're loaded, so there's nothing to build, just run `megabit-runner --app` on this directory.
+(module
+  (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+  (import "extism:host/env" "length" (func $length (param i64) (result i64)))
+  (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+  (import "extism:host/user" "poll_input_events" (func $poll_input_events (result i64)))
+  (import "extism:host/user" "set_pixel" (func $set_pixel (param i64 i64 i64) (result i64)))
+
+  ;; Puts a u32 in extism's memory, which is how host functions take their arguments
+  (func $u32 (param $value i32) (result i64)
+    (local $offset i64)
+    (local $byte i32)
+    (local.set $offset (call $alloc (i64.const 4)))
+    (loop $bytes
+      (call $store_u8
+        (i64.add (local.get $offset) (i64.extend_i32_u (local.get $byte)))
+        (i32.and
+          (i32.shr_u (local.get $value) (i32.mul (local.get $byte) (i32.const 8)))
+          (i32.const 0xff)))
+      (local.set $byte (i32.add (local.get $byte) (i32.const 1)))
+      (br_if $bytes (i32.lt_u (local.get $byte) (i32.const 4))))
+    (local.get $offset))
+
+  (func (export "run") (result i32)
+    ;; No input comes back as the empty array `[]`
+    (drop
+      (call $set_pixel
+        (call $u32 (i32.const 0))
+        (call $u32 (i32.const 0))
+        (call $u32
+          (select
+            (i32.const 0x7fff)
+            (i32.const 0)
+            (i64.gt_u (call $length (call $poll_input_events)) (i64.const 2))))))
+    i32.const 0))
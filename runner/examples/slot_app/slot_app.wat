@@ -0,0 +1,47 @@
+;; An app which asks for its turn to be extended by 500ms twice when it's set up, and lights its
+;; top left pixel if only the first was granted, for checking that apps can extend their turn in
+;; the rotation up to the most their manifest allows. Text format modules are compiled as
+;; they're loaded, so there's nothing to build, just run `megabit-runner --app` on this directory.
+(module
+  (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+  (import "extism:host/env" "load_u8" (func $load_u8 (param i64) (result i32)))
+  (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+  (import "extism:host/user" "request_slot_extension"
+    (func $request_slot_extension (param i64) (result i64)))
+  (import "extism:host/user" "set_pixel" (func $set_pixel (param i64 i64 i64) (result i64)))
+
+  ;; Whether the extensions went as expected
+  (global $as_expected (mut i32) (i32.const 0))
+
+  ;; Puts a u32 in extism's memory, which is how host functions take their arguments
+  (func $u32 (param $value i32) (result i64)
+    (local $offset i64)
+    (local $byte i32)
+    (local.set $offset (call $alloc (i64.const 4)))
+    (loop $bytes
+      (call $store_u8
+        (i64.add (local.get $offset) (i64.extend_i32_u (local.get $byte)))
+        (i32.and
+          (i32.shr_u (local.get $value) (i32.mul (local.get $byte) (i32.const 8)))
+          (i32.const 0xff)))
+      (local.set $byte (i32.add (local.get $byte) (i32.const 1)))
+      (br_if $bytes (i32.lt_u (local.get $byte) (i32.const 4))))
+    (local.get $offset))
+
+  ;; Asks for the turn to be extended by `ms`, giving back whether it was
+  (func $extend (param $ms i32) (result i32)
+    (call $load_u8 (call $request_slot_extension (call $u32 (local.get $ms)))))
+
+  (func (export "setup") (result i32)
+    (global.set $as_expected
+      (i32.and
+        (call $extend (i32.const 500))
+        (i32.eqz (call $extend (i32.const 500)))))
+    i32.const 0)
+  (func (export "update") (result i32)
+    (drop
+      (call $set_pixel
+        (call $u32 (i32.const 0))
+        (call $u32 (i32.const 0))
+        (call $u32 (select (i32.const 0x7fff) (i32.const 0) (global.get $as_expected)))))
+    i32.const 0))
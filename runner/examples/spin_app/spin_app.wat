@@ -0,0 +1,10 @@
+;; An app which never finishes running, for checking that the runner stops apps which run over
+;; their execution budget and moves on from them. Text format modules are compiled as they're
+;; loaded, so there's nothing to build, just run `megabit-runner --app` on this directory.
+(module
+  (func (export "setup") (result i32)
+    i32.const 0)
+  (func (export "run") (result i32)
+    (loop $spin
+      br $spin)
+    i32.const 0))
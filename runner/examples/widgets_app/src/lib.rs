@@ -0,0 +1,97 @@
+//! An example app drawing a progress bar and a sparkline of a wave. Build it with
+//! `cargo build --release --target wasm32-unknown-unknown` in this directory and copy
+//! `widgets_app.wasm` out of the target directory next to `manifest.json` to run it with
+//! `megabit-runner --app`.
+
+use extism_pdk::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const WHITE: u32 = 0x7fff;
+const BLACK: u32 = 0x0000;
+const GREEN: u32 = 0x03e0;
+/// Frames the progress bar takes to fill up
+const PROGRESS_PERIOD: u32 = 50;
+
+#[host_fn]
+extern "ExtismHost" {
+    fn get_display_info() -> Vec<u8>;
+    fn clear_display(render: u32) -> ();
+    fn render_dirty() -> ();
+    fn draw_progress_bar(
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        fraction: f32,
+        foreground: u32,
+        background: u32,
+        border: u32,
+    ) -> ();
+    fn draw_sparkline(
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        values: Vec<u8>,
+        range_min: f32,
+        range_max: f32,
+        color: u32,
+        fill: u32,
+    ) -> ();
+}
+
+static FRAME: AtomicU32 = AtomicU32::new(0);
+
+/// Width and height of the app's display.
+fn display_size() -> FnResult<(u32, u32)> {
+    let info = unsafe { get_display_info()? };
+    let word = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Ok((word(&info[0..4]), word(&info[4..8])))
+}
+
+#[plugin_fn]
+pub fn setup() -> FnResult<()> {
+    unsafe { clear_display(1)? };
+    Ok(())
+}
+
+#[plugin_fn]
+pub fn run() -> FnResult<()> {
+    let frame = FRAME.fetch_add(1, Ordering::Relaxed);
+    let (width, height) = display_size()?;
+    let bar_height = (height / 4).max(3);
+
+    let fraction = (frame % (PROGRESS_PERIOD + 1)) as f32 / PROGRESS_PERIOD as f32;
+
+    // A wave scrolling along, with a gap every so often to show how missing readings look
+    let values = (0..width)
+        .map(|col| {
+            let step = frame + col;
+            if step % 24 < 2 {
+                f32::NAN
+            } else {
+                (step as f32 / 4.0).sin()
+            }
+        })
+        .flat_map(f32::to_le_bytes)
+        .collect::<Vec<_>>();
+    let graph_top = bar_height + 1;
+    unsafe {
+        clear_display(0)?;
+        draw_progress_bar(0, 0, width, bar_height, fraction, WHITE, BLACK, 1)?;
+        draw_sparkline(
+            0,
+            graph_top,
+            width,
+            height - graph_top,
+            values,
+            -1.0,
+            1.0,
+            GREEN,
+            // Switches between a line and a filled graph every 100 frames
+            (frame / 100 % 2 == 1) as u32,
+        )?;
+        render_dirty()?;
+    }
+    Ok(())
+}
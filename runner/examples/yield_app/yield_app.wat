@@ -0,0 +1,31 @@
+;; An app which has nothing to show, so says it's idle when it's set up and gives up its turn
+;; every time it runs, for checking that the scheduler moves on from it without switching apps
+;; as fast as it can. Text format modules are compiled as they're loaded, so there's nothing to
+;; build, just run `megabit-runner --app` on this directory.
+(module
+  (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+  (import "extism:host/env" "store_u8" (func $store_u8 (param i64 i32)))
+  (import "extism:host/user" "set_idle" (func $set_idle (param i64) (result i64)))
+  (import "extism:host/user" "yield_slot" (func $yield_slot (result i64)))
+
+  ;; Puts a u32 in extism's memory, which is how host functions take their arguments
+  (func $u32 (param $value i32) (result i64)
+    (local $offset i64)
+    (local $byte i32)
+    (local.set $offset (call $alloc (i64.const 4)))
+    (loop $bytes
+      (call $store_u8
+        (i64.add (local.get $offset) (i64.extend_i32_u (local.get $byte)))
+        (i32.and
+          (i32.shr_u (local.get $value) (i32.mul (local.get $byte) (i32.const 8)))
+          (i32.const 0xff)))
+      (local.set $byte (i32.add (local.get $byte) (i32.const 1)))
+      (br_if $bytes (i32.lt_u (local.get $byte) (i32.const 4))))
+    (local.get $offset))
+
+  (func (export "setup") (result i32)
+    (drop (call $set_idle (call $u32 (i32.const 1))))
+    i32.const 0)
+  (func (export "update") (result i32)
+    (drop (call $yield_slot))
+    i32.const 0))
@@ -0,0 +1,65 @@
+use clap::Parser;
+use megabit_runner::serial::capture::{self, Direction};
+use std::path::PathBuf;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Prints the traffic recorded by megabit-runner --capture
+#[derive(Clone, Debug, Parser)]
+pub struct Args {
+    /// Capture file to read
+    capture: PathBuf,
+    /// The captured traffic was protected with a CRC16
+    #[arg(long)]
+    crc: bool,
+    /// Print the raw chunks of bytes instead of decoded messages
+    #[arg(long)]
+    raw: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "megabit_runner=info".into()),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let records = capture::read_capture(&args.capture)?;
+    if args.raw {
+        for record in records {
+            println!(
+                "{:>12.6} {} {:02x?}",
+                record.timestamp.as_secs_f64(),
+                direction_arrow(record.direction),
+                record.data
+            );
+        }
+    } else {
+        for (direction, timestamp, msg) in capture::decode_records(&records[..], args.crc) {
+            match msg {
+                Ok(msg) => println!(
+                    "{:>12.6} {} {msg:?}",
+                    timestamp.as_secs_f64(),
+                    direction_arrow(direction)
+                ),
+                Err(payload) => println!(
+                    "{:>12.6} {} undecodable payload {payload:02x?}",
+                    timestamp.as_secs_f64(),
+                    direction_arrow(direction)
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn direction_arrow(direction: Direction) -> &'static str {
+    match direction {
+        Direction::ToDevice => "->",
+        Direction::FromDevice => "<-",
+    }
+}
@@ -0,0 +1,238 @@
+use super::Args;
+use clap::Parser;
+use megabit_runner::serial::{self, Capabilities, KeepaliveConfig, LatencySummary, SerialConfig};
+use megabit_serial_protocol::{PixelRepresentation, SerialMessage};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the device to answer a single ping before counting it as lost
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Parser)]
+pub struct BenchArgs {
+    /// Number of pings to measure the round trip time with
+    #[arg(long, default_value_t = 100)]
+    pings: u32,
+    /// Seconds to stream full frames to the device for
+    #[arg(long, default_value_t = 5)]
+    stream_secs: u64,
+    /// Print the results as JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    pings_sent: u32,
+    pings_lost: u32,
+    ping_round_trip_ms: Option<LatencyReport>,
+    frames_sent: u64,
+    stream_secs: f64,
+    frames_per_sec: f64,
+    bytes_written: u64,
+    bytes_per_sec: f64,
+    /// Time from a frame being queued to being written, over the end of the stream
+    send_latency_ms: Option<LatencyReport>,
+    decode_errors: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct LatencyReport {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+impl From<LatencySummary> for LatencyReport {
+    fn from(summary: LatencySummary) -> Self {
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        LatencyReport {
+            p50: millis(summary.p50),
+            p90: millis(summary.p90),
+            p99: millis(summary.p99),
+            max: millis(summary.max),
+        }
+    }
+}
+
+/// Measures the round trip time of pings and then how fast full frames can be streamed to the
+/// device, and prints a summary.
+pub fn run(
+    rt: &tokio::runtime::Runtime,
+    args: &Args,
+    bench_args: &BenchArgs,
+) -> anyhow::Result<()> {
+    let mut transports = args.transports();
+    if transports.len() != 1 {
+        anyhow::bail!("Benchmarking needs exactly one device");
+    }
+    let transport = transports.remove(0);
+    // Keepalive pings would be answered alongside the measured ones and skew the results
+    let config = SerialConfig {
+        keepalive: KeepaliveConfig {
+            enabled: false,
+            ..Default::default()
+        },
+        ..args.serial_config()
+    };
+
+    let (tx, rx) = async_channel::unbounded();
+    let (serial_conn, shutdown_handle, serial_task) =
+        serial::start_transport_task(transport, config, tx, rx);
+    let _serial_task_handle = rt.spawn(Box::into_pin(serial_task));
+
+    let report = rt.block_on(async {
+        if args.wait_for_port {
+            serial_conn.wait_for_connection().await?;
+        }
+        let display_info = serial_conn.get_display_info().await?;
+        let report = bench(&serial_conn, &display_info, bench_args).await;
+        let goodbye = if args.no_blank_on_exit {
+            vec![]
+        } else {
+            serial::blank_display_sequence(&megabit_runner::display::DisplayConfiguration {
+                width: display_info.width as usize,
+                height: display_info.height as usize,
+                pixel_representation: display_info.pixel_representation,
+                orientation: Default::default(),
+                max_fps_hint: None,
+            })
+        };
+        if tokio::time::timeout(super::SHUTDOWN_DEADLINE, shutdown_handle.shutdown(goodbye))
+            .await
+            .is_err()
+        {
+            tracing::warn!("Timed out waiting for the device to finish shutting down");
+        }
+        report
+    })?;
+
+    if bench_args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_table(&report);
+    }
+    Ok(())
+}
+
+async fn bench(
+    serial_conn: &serial::SerialConnection,
+    display_info: &megabit_serial_protocol::GetDisplayInfoResponse,
+    bench_args: &BenchArgs,
+) -> anyhow::Result<BenchReport> {
+    tracing::info!("Measuring round trip time over {} pings", bench_args.pings);
+    let mut round_trips = Vec::with_capacity(bench_args.pings as usize);
+    for _ in 0..bench_args.pings {
+        let sent_at = Instant::now();
+        let res = serial_conn
+            .request(
+                SerialMessage::Ping,
+                |msg| matches!(msg, SerialMessage::PingResponse).then_some(()),
+                PING_TIMEOUT,
+            )
+            .await;
+        match res {
+            Ok(()) => round_trips.push(sent_at.elapsed()),
+            Err(err) if err.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let pings_lost = bench_args.pings - round_trips.len() as u32;
+
+    let stream_duration = Duration::from_secs(bench_args.stream_secs);
+    tracing::info!("Streaming full frames for {}s", stream_duration.as_secs());
+    let width = display_info.width as usize;
+    let height = u8::try_from(display_info.height)?;
+    let commit = serial_conn
+        .capabilities()
+        .contains(Capabilities::DOUBLE_BUFFERING);
+    let stats_before = serial_conn.stats();
+    let started_at = Instant::now();
+    let mut frames_sent = 0u64;
+    while started_at.elapsed() < stream_duration {
+        // Alternate between two checkerboards so every frame changes every pixel
+        let phase = frames_sent.is_multiple_of(2);
+        let is_lit = |row: u8, col: usize| (usize::from(row) + col).is_multiple_of(2) == phase;
+        if display_info.pixel_representation == PixelRepresentation::RGB888 {
+            let rows = (0..height)
+                .map(|row| {
+                    let pixels = (0..width)
+                        .map(|col| if is_lit(row, col) { 0xffffff } else { 0 })
+                        .collect::<Vec<u32>>();
+                    (row, pixels)
+                })
+                .collect::<Vec<_>>();
+            serial_conn.update_rows_rgb888(&rows[..]).await?;
+        } else if display_info.pixel_representation.is_rgb() {
+            let rows = (0..height)
+                .map(|row| {
+                    let pixels = (0..width)
+                        .map(|col| if is_lit(row, col) { 0xffff } else { 0 })
+                        .collect::<Vec<u16>>();
+                    (row, pixels)
+                })
+                .collect::<Vec<_>>();
+            serial_conn.update_rows_rgb(&rows[..]).await?;
+        } else {
+            let rows = (0..height)
+                .map(|row| {
+                    (
+                        row,
+                        (0..width).map(|col| is_lit(row, col)).collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            serial_conn.update_rows(&rows[..]).await?;
+        }
+        if commit {
+            serial_conn.commit_render().await?;
+        }
+        frames_sent += 1;
+    }
+    let elapsed = started_at.elapsed().as_secs_f64();
+    let stats = serial_conn.stats();
+    let bytes_written = stats.bytes_written - stats_before.bytes_written;
+
+    Ok(BenchReport {
+        pings_sent: bench_args.pings,
+        pings_lost,
+        ping_round_trip_ms: LatencySummary::from_samples(round_trips).map(Into::into),
+        frames_sent,
+        stream_secs: elapsed,
+        frames_per_sec: frames_sent as f64 / elapsed,
+        bytes_written,
+        bytes_per_sec: bytes_written as f64 / elapsed,
+        send_latency_ms: stats.send_latency.map(Into::into),
+        decode_errors: stats.decode_errors,
+    })
+}
+
+fn print_table(report: &BenchReport) {
+    let latency = |latency: &Option<LatencyReport>| match latency {
+        Some(latency) => format!(
+            "p50 {:.2}ms  p90 {:.2}ms  p99 {:.2}ms  max {:.2}ms",
+            latency.p50, latency.p90, latency.p99, latency.max
+        ),
+        None => "n/a".to_owned(),
+    };
+    println!("{:<20} {}", "pings sent", report.pings_sent);
+    println!("{:<20} {}", "pings lost", report.pings_lost);
+    println!(
+        "{:<20} {}",
+        "ping round trip",
+        latency(&report.ping_round_trip_ms)
+    );
+    println!(
+        "{:<20} {} in {:.1}s",
+        "frames sent", report.frames_sent, report.stream_secs
+    );
+    println!("{:<20} {:.1}", "frames per second", report.frames_per_sec);
+    println!("{:<20} {:.0}", "bytes per second", report.bytes_per_sec);
+    println!(
+        "{:<20} {}",
+        "send latency",
+        latency(&report.send_latency_ms)
+    );
+    println!("{:<20} {}", "decode errors", report.decode_errors);
+}
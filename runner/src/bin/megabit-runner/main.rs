@@ -0,0 +1,963 @@
+use clap::{ArgGroup, Parser, Subcommand};
+use jiff::tz::TimeZone;
+use megabit_runner::{
+    display::{
+        ColorCorrection, CompositeDisplay, DisplayConfiguration, DitherMode, MonocolorPalette,
+        Orientation, PaletteCycle, PanelLayout, RegionBounds, Rgb555, Rotation, Transition,
+        TransitionKind,
+    },
+    serial::{
+        self, DeviceSelector, DeviceWaitConfig, FlowControl, KeepaliveConfig, Parity,
+        ReplayTransport, RetransmitConfig, SerialConfig, SerialTransport, StopBits, TcpTransport,
+        Transport,
+    },
+    wasm_env,
+};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    path::PathBuf,
+    process::ExitCode,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+mod bench;
+mod reset;
+mod snapshot;
+mod test_app;
+mod test_pattern;
+
+/// How long to wait for queued frames and the goodbye sequence to be written when exiting
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, Parser)]
+#[command(group(ArgGroup::new("selector").required(true).args(["device", "usb_id", "manufacturer", "tcp", "replay"])))]
+#[command(group(ArgGroup::new("apps").required(true).args(["app", "app_rotation"])))]
+#[command(subcommand_negates_reqs = true)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Path to the tty serial device for the display coprocessor. Give this more than once to
+    /// combine several panels into one display
+    #[arg(short, long)]
+    device: Vec<PathBuf>,
+    /// How to arrange the panels when more than one device is given: horizontal or vertical
+    #[arg(long, default_value = "horizontal", value_parser = parse_layout)]
+    layout: PanelLayout,
+    /// Degrees the display is rotated clockwise from the way apps draw on it: 0, 90, 180, or 270
+    #[arg(long, default_value = "0", value_parser = parse_rotation)]
+    rotation: Rotation,
+    /// Confine the app to part of the display, given as X,Y,WIDTHxHEIGHT. The app sees the
+    /// region as its whole display
+    #[arg(long, value_parser = parse_region)]
+    region: Option<RegionBounds>,
+    /// Run the app at WIDTHxHEIGHT, scaled up by whole multiples and centered on its display.
+    /// Overrides the resolution in the app's manifest
+    #[arg(long, value_parser = parse_resolution)]
+    virtual_resolution: Option<(usize, usize)>,
+    /// Mirror the display left to right, after rotating it
+    #[arg(long)]
+    mirror_horizontal: bool,
+    /// Mirror the display top to bottom, after rotating it
+    #[arg(long)]
+    mirror_vertical: bool,
+    /// USB vendor and product ID of the display coprocessor in hex, e.g. 16c0:27dd
+    #[arg(long, value_parser = parse_usb_id)]
+    usb_id: Option<(u16, u16)>,
+    /// USB manufacturer string of the display coprocessor
+    #[arg(long)]
+    manufacturer: Option<String>,
+    /// Address of a network bridge to the display coprocessor, e.g. megabit.local:5000
+    #[arg(long, value_parser = parse_tcp_address)]
+    tcp: Option<(String, u16)>,
+    /// Play back the device's side of a session recorded with --capture instead of connecting to
+    /// a device
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// Speed multiplier for --replay
+    #[arg(long, default_value_t = 1.0)]
+    replay_speed: f64,
+    /// Directory containing an app manifest
+    #[arg(short, long)]
+    app: Option<PathBuf>,
+    /// TOML file listing apps to cycle through and how long to show each for, instead of
+    /// running a single app. Reloaded on SIGHUP
+    #[arg(long)]
+    app_rotation: Option<PathBuf>,
+    /// Reload apps when their manifest or wasm file changes, keeping the old version running if
+    /// the new one fails to load
+    #[arg(long)]
+    hot_reload: bool,
+    /// Switch to the next app whenever this button on the device is held down for a second,
+    /// whether or not the app takes input. Shorter presses are passed on to the app
+    #[arg(long)]
+    next_app_button: Option<u8>,
+    /// Keep this many apps which have been switched away from running in the background, so
+    /// switching back to them is instant rather than loading and setting them up again. Each
+    /// keeps its memory, which can be up to max_memory_pages
+    #[arg(long, default_value_t = 0)]
+    warm_instances: usize,
+    /// Baud rate of the serial device
+    #[arg(long, default_value_t = 230400)]
+    baud: u32,
+    /// Flow control for the serial device: none, software, or hardware
+    #[arg(long, default_value = "none", value_parser = parse_flow_control)]
+    flow_control: FlowControl,
+    /// Parity for the serial device: none, odd, or even
+    #[arg(long, default_value = "none", value_parser = parse_parity)]
+    parity: Parity,
+    /// Number of stop bits for the serial device: 1 or 2
+    #[arg(long, default_value = "1", value_parser = parse_stop_bits)]
+    stop_bits: StopBits,
+    /// Drive DTR high (true) or low (false) after opening the serial device
+    #[arg(long)]
+    dtr: Option<bool>,
+    /// Drive RTS high (true) or low (false) after opening the serial device
+    #[arg(long)]
+    rts: Option<bool>,
+    /// Milliseconds to wait after opening the serial device, for boards which reset on open
+    #[arg(long, default_value_t = 0)]
+    settle_ms: u64,
+    /// Wait up to this many milliseconds after opening the device for it to answer a ping
+    #[arg(long)]
+    wait_for_device_ms: Option<u64>,
+    /// Keep trying to open the device if it doesn't exist yet instead of exiting, e.g. when
+    /// started before the device has been plugged in
+    #[arg(long)]
+    wait_for_port: bool,
+    /// Give up on --wait-for-port after this many seconds, waits indefinitely if not given
+    #[arg(long, requires = "wait_for_port")]
+    port_wait_timeout_secs: Option<u64>,
+    /// Protect frames with a CRC16 if the firmware supports it
+    #[arg(long)]
+    crc: bool,
+    /// Resend row updates up to this many times when the device doesn't acknowledge them
+    #[arg(long)]
+    retransmit_attempts: Option<u32>,
+    /// Milliseconds a write to the device may take before the port is reopened
+    #[arg(long, default_value_t = 1000)]
+    write_timeout_ms: u64,
+    /// Milliseconds between keepalive pings to the device
+    #[arg(long, default_value_t = 333)]
+    ping_interval_ms: u64,
+    /// Drop queued row updates which are superseded before they're written to the device
+    #[arg(long)]
+    coalesce_rows: bool,
+    /// How apps' colors are drawn on a monocolor display: threshold, bayer, or floyd-steinberg
+    #[arg(long, default_value = "threshold", value_parser = parse_dither_mode)]
+    dither: DitherMode,
+    /// Gamma correct RGB colors sent to the display, 2.2 if no value is given
+    #[arg(long, num_args = 0..=1, default_missing_value = "2.2")]
+    gamma: Option<f32>,
+    /// Percentage to scale RGB colors sent to the display by, on top of the panel's brightness
+    #[arg(long, default_value_t = 100)]
+    rgb_brightness: u8,
+    /// Keep a copy of what's on the display and only send rows which have actually changed
+    #[arg(long)]
+    diff_render: bool,
+    /// Leave the display as it is on exit instead of blanking it
+    #[arg(long)]
+    no_blank_on_exit: bool,
+    /// Record all raw traffic with the device to this file
+    #[arg(long)]
+    capture: Option<PathBuf>,
+    /// Don't ping the device to check that it's responsive
+    #[arg(long)]
+    no_keepalive: bool,
+    /// Consecutive missed pings after which the connection is considered degraded
+    #[arg(long, default_value_t = 2)]
+    ping_miss_threshold: u32,
+    /// Panel brightness to set once connected, from 0 to 255. Left at the firmware's default if
+    /// not given
+    #[arg(long)]
+    brightness: Option<u8>,
+    /// Colors to show on and off pixels in on an RGB display, as ON:OFF with each color a name
+    /// like amber or navy or a hex color like #ffbf00. Give this more than once to cycle
+    /// through the palettes
+    #[arg(long, value_parser = parse_palette)]
+    palette: Vec<MonocolorPalette>,
+    /// Milliseconds to show each palette for when cycling through more than one
+    #[arg(long, default_value_t = 1000)]
+    palette_cycle_ms: u64,
+    /// How to switch to the app: cut, dissolve, or slide or wipe followed by -left, -right, -up,
+    /// or -down, e.g. slide-left. Apps can choose their own in their manifest
+    #[arg(long, default_value = "cut", value_parser = parse_transition)]
+    transition: TransitionKind,
+    /// Milliseconds transitions take, unless the app's manifest says otherwise
+    #[arg(long, default_value_t = 500)]
+    transition_ms: u64,
+    /// Frames per second transitions are drawn at. Transitions cut straight to the app if the
+    /// display can't keep up
+    #[arg(long, default_value_t = 30)]
+    transition_fps: u32,
+    /// Log a summary of the serial traffic and of how apps are faring every this many seconds
+    #[arg(long)]
+    stats_interval_secs: Option<u64>,
+    /// Save a PNG of the display into this directory whenever the runner receives SIGUSR2
+    #[arg(long)]
+    snapshot_dir: Option<PathBuf>,
+    /// Size in pixels of each display pixel in snapshots
+    #[arg(long, default_value_t = 8)]
+    snapshot_scale: u32,
+    /// Draw lines between the pixels in snapshots
+    #[arg(long)]
+    snapshot_grid: bool,
+    /// Time zone to give apps local times in, e.g. Europe/Berlin, instead of the system's
+    #[arg(long, value_parser = parse_time_zone)]
+    time_zone: Option<TimeZone>,
+    /// Seed the app's random numbers with this so they're the same on every run. Overrides the
+    /// seed in the app's manifest
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Directory to keep apps' stored keys and values in so they last across restarts. They're
+    /// only kept in memory if not given
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    /// Directory to keep apps in once they've been compiled, so they load faster each time
+    /// after the first. Apps are compiled again whenever they or the runner change
+    #[arg(long)]
+    module_cache_dir: Option<PathBuf>,
+    /// Most milliseconds each call into an app may take before it's stopped, unless the app's
+    /// manifest gives its own execution_budget_ms. Defaults to 500
+    #[arg(long)]
+    execution_budget_ms: Option<u64>,
+    /// Most 64 KiB pages of memory each app may use, unless the app's manifest gives its own
+    /// max_memory_pages. Defaults to 1024, which is 64 MiB
+    #[arg(long)]
+    max_memory_pages: Option<u32>,
+    /// Shortest refresh period in milliseconds apps may run at, whatever they ask for. Defaults
+    /// to 16
+    #[arg(long)]
+    min_refresh_period_ms: Option<u64>,
+    /// Longest refresh period in milliseconds apps may run at, whatever they ask for. Defaults to
+    /// 60000
+    #[arg(long)]
+    max_refresh_period_ms: Option<u64>,
+    /// Capability to deny every app whatever its manifest declares: network, storage, led, input
+    /// or time. May be given more than once or as a comma separated list
+    #[arg(long, value_delimiter = ',', value_parser = parse_capability)]
+    deny_capability: Vec<wasm_env::Capability>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum Command {
+    /// Measure the latency and throughput of the connection to the device instead of running an
+    /// app
+    Bench(bench::BenchArgs),
+    /// Restart the device's firmware. Exits with status 2 if the device doesn't drop off
+    Reset,
+    /// Restart the device into its bootloader to flash new firmware. Exits with status 2 if the
+    /// device doesn't drop off
+    Dfu,
+    /// Show test patterns instead of running an app, to check a panel on its own
+    TestPattern(test_pattern::TestPatternArgs),
+    /// Run an app against an in-memory display for a number of frames, recording every host
+    /// call it makes, and check it against a golden run. Needs the test-support feature
+    TestApp(test_app::TestAppArgs),
+}
+
+impl Args {
+    fn device_selectors(&self) -> Vec<DeviceSelector> {
+        if let Some((vid, pid)) = self.usb_id {
+            vec![DeviceSelector::UsbId { vid, pid }]
+        } else if let Some(manufacturer) = &self.manufacturer {
+            vec![DeviceSelector::Manufacturer(manufacturer.clone())]
+        } else {
+            self.device
+                .iter()
+                .cloned()
+                .map(DeviceSelector::Path)
+                .collect()
+        }
+    }
+
+    /// One transport for each panel of the display.
+    fn transports(&self) -> Vec<Box<dyn Transport>> {
+        if let Some((host, port)) = &self.tcp {
+            vec![Box::new(TcpTransport::new(host.clone(), *port))]
+        } else if let Some(path) = &self.replay {
+            vec![Box::new(ReplayTransport::new(
+                path.clone(),
+                self.replay_speed,
+            ))]
+        } else {
+            self.device_selectors()
+                .into_iter()
+                .map(|selector| {
+                    Box::new(SerialTransport::new(selector, self.serial_config()))
+                        as Box<dyn Transport>
+                })
+                .collect()
+        }
+    }
+
+    /// Settings of the serial ports the panels are on, or `None` when the display is reached
+    /// over TCP or replayed from a capture.
+    fn serial_link(&self) -> Option<SerialConfig> {
+        (self.tcp.is_none() && self.replay.is_none()).then(|| self.serial_config())
+    }
+
+    fn orientation(&self) -> Orientation {
+        Orientation {
+            rotation: self.rotation,
+            mirror_horizontal: self.mirror_horizontal,
+            mirror_vertical: self.mirror_vertical,
+        }
+    }
+
+    fn app_options(&self) -> anyhow::Result<wasm_env::AppOptions> {
+        let module_cache = self
+            .module_cache_dir
+            .as_ref()
+            .map(|dir| {
+                wasm_env::ModuleCache::open(dir).map_err(|err| {
+                    anyhow::anyhow!(
+                        "Failed to open the module cache at {}: {err}",
+                        dir.display()
+                    )
+                })
+            })
+            .transpose()?;
+        Ok(wasm_env::AppOptions {
+            region: self.region,
+            virtual_resolution: self.virtual_resolution,
+            time_zone: self.time_zone.clone(),
+            seed: self.seed,
+            state_dir: self.state_dir.clone(),
+            execution_budget: self.execution_budget_ms.map(Duration::from_millis),
+            max_memory_pages: self.max_memory_pages,
+            min_refresh_period: self.min_refresh_period_ms.map(Duration::from_millis),
+            max_refresh_period: self.max_refresh_period_ms.map(Duration::from_millis),
+            denied_capabilities: self.deny_capability.clone(),
+            module_cache,
+            trace_host_calls: None,
+        })
+    }
+
+    fn transition(&self) -> Transition {
+        Transition {
+            kind: self.transition,
+            duration: Duration::from_millis(self.transition_ms),
+            frame_rate: self.transition_fps,
+        }
+    }
+
+    fn serial_config(&self) -> SerialConfig {
+        SerialConfig {
+            baud_rate: self.baud,
+            flow_control: self.flow_control,
+            parity: self.parity,
+            stop_bits: self.stop_bits,
+            dtr: self.dtr,
+            rts: self.rts,
+            settle_delay: Duration::from_millis(self.settle_ms),
+            first_frame_timeout: self.wait_for_device_ms.map(Duration::from_millis),
+            wait_for_device: self.wait_for_port.then(|| DeviceWaitConfig {
+                timeout: self.port_wait_timeout_secs.map(Duration::from_secs),
+                ..Default::default()
+            }),
+            use_crc: self.crc,
+            write_timeout: Duration::from_millis(self.write_timeout_ms),
+            coalesce_rows: self.coalesce_rows,
+            capture_path: self.capture.clone(),
+            retransmit: self
+                .retransmit_attempts
+                .map(|max_attempts| RetransmitConfig {
+                    max_attempts,
+                    ..Default::default()
+                }),
+            keepalive: KeepaliveConfig {
+                interval: Duration::from_millis(self.ping_interval_ms),
+                enabled: !self.no_keepalive,
+                miss_threshold: self.ping_miss_threshold,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+fn parse_usb_id(arg: &str) -> Result<(u16, u16), String> {
+    let (vid, pid) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("Expected a USB ID of the form VID:PID, got {arg}"))?;
+    let parse_hex =
+        |id| u16::from_str_radix(id, 16).map_err(|err| format!("Invalid ID {id}: {err}"));
+    Ok((parse_hex(vid)?, parse_hex(pid)?))
+}
+
+fn parse_tcp_address(arg: &str) -> Result<(String, u16), String> {
+    let (host, port) = arg
+        .rsplit_once(':')
+        .ok_or_else(|| format!("Expected an address of the form HOST:PORT, got {arg}"))?;
+    let port = port
+        .parse()
+        .map_err(|err| format!("Invalid port {port}: {err}"))?;
+    Ok((host.to_owned(), port))
+}
+
+fn parse_flow_control(arg: &str) -> Result<FlowControl, String> {
+    match arg {
+        "none" => Ok(FlowControl::None),
+        "software" => Ok(FlowControl::Software),
+        "hardware" => Ok(FlowControl::Hardware),
+        _ => Err(format!("Unknown flow control: {arg}")),
+    }
+}
+
+fn parse_parity(arg: &str) -> Result<Parity, String> {
+    match arg {
+        "none" => Ok(Parity::None),
+        "odd" => Ok(Parity::Odd),
+        "even" => Ok(Parity::Even),
+        _ => Err(format!("Unknown parity: {arg}")),
+    }
+}
+
+fn parse_stop_bits(arg: &str) -> Result<StopBits, String> {
+    match arg {
+        "1" => Ok(StopBits::One),
+        "2" => Ok(StopBits::Two),
+        _ => Err(format!("Invalid number of stop bits: {arg}")),
+    }
+}
+
+fn parse_dither_mode(arg: &str) -> Result<DitherMode, String> {
+    match arg {
+        "threshold" => Ok(DitherMode::Threshold),
+        "bayer" => Ok(DitherMode::Bayer4x4),
+        "floyd-steinberg" => Ok(DitherMode::FloydSteinberg),
+        _ => Err(format!("Unknown dither mode: {arg}")),
+    }
+}
+
+fn parse_rotation(arg: &str) -> Result<Rotation, String> {
+    match arg {
+        "0" => Ok(Rotation::Rotate0),
+        "90" => Ok(Rotation::Rotate90),
+        "180" => Ok(Rotation::Rotate180),
+        "270" => Ok(Rotation::Rotate270),
+        _ => Err(format!("Rotation must be 0, 90, 180, or 270, got {arg}")),
+    }
+}
+
+fn parse_region(arg: &str) -> Result<RegionBounds, String> {
+    let invalid = || format!("Expected a region of the form X,Y,WIDTHxHEIGHT, got {arg}");
+    let (x, rest) = arg.split_once(',').ok_or_else(invalid)?;
+    let (y, size) = rest.split_once(',').ok_or_else(invalid)?;
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let parse = |value: &str| value.trim().parse::<usize>().map_err(|_| invalid());
+    Ok(RegionBounds {
+        x: parse(x)?,
+        y: parse(y)?,
+        width: parse(width)?,
+        height: parse(height)?,
+    })
+}
+
+fn parse_resolution(arg: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("Expected a resolution of the form WIDTHxHEIGHT, got {arg}");
+    let (width, height) = arg.split_once('x').ok_or_else(invalid)?;
+    let parse = |value: &str| value.trim().parse::<usize>().map_err(|_| invalid());
+    Ok((parse(width)?, parse(height)?))
+}
+
+fn parse_transition(arg: &str) -> Result<TransitionKind, String> {
+    arg.parse::<TransitionKind>().map_err(|err| err.to_string())
+}
+
+fn parse_palette(arg: &str) -> Result<MonocolorPalette, String> {
+    let (on, off) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("Expected a palette of the form ON:OFF, got {arg}"))?;
+    let parse_color = |color: &str| color.parse::<Rgb555>().map_err(|err| err.to_string());
+    Ok(MonocolorPalette::new(parse_color(on)?, parse_color(off)?))
+}
+
+fn parse_capability(arg: &str) -> Result<wasm_env::Capability, String> {
+    arg.parse::<wasm_env::Capability>()
+        .map_err(|err| err.to_string())
+}
+
+fn parse_time_zone(arg: &str) -> Result<TimeZone, String> {
+    TimeZone::get(arg).map_err(|err| err.to_string())
+}
+
+fn parse_layout(arg: &str) -> Result<PanelLayout, String> {
+    match arg {
+        "horizontal" => Ok(PanelLayout::Horizontal),
+        "vertical" => Ok(PanelLayout::Vertical),
+        _ => Err(format!("Unknown panel layout: {arg}")),
+    }
+}
+
+async fn log_stats(serial_conn: serial::SerialConnection, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    // The first tick completes immediately, before there's anything to report
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        tracing::info!("Serial stats: {}", serial_conn.stats());
+    }
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "megabit_runner=debug,device=info,wasm=debug".into()),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    match &args.command {
+        Some(Command::Bench(bench_args)) => {
+            bench::run(&rt, &args, bench_args)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Reset) => return reset::run(&rt, &args, reset::ResetKind::Firmware),
+        Some(Command::Dfu) => return reset::run(&rt, &args, reset::ResetKind::Bootloader),
+        Some(Command::TestApp(test_args)) => return test_app::run(&args, test_args),
+        Some(Command::TestPattern(_)) | None => {}
+    }
+
+    if args.transition_fps == 0 {
+        anyhow::bail!("--transition-fps must be at least 1");
+    }
+    let min_refresh_period = args
+        .min_refresh_period_ms
+        .map_or(wasm_env::DEFAULT_MIN_REFRESH_PERIOD, Duration::from_millis);
+    let max_refresh_period = args
+        .max_refresh_period_ms
+        .map_or(wasm_env::DEFAULT_MAX_REFRESH_PERIOD, Duration::from_millis);
+    if min_refresh_period > max_refresh_period {
+        anyhow::bail!(
+            "The shortest refresh period of {min_refresh_period:?} is longer than the longest of \
+             {max_refresh_period:?}"
+        );
+    }
+
+    let transports = args.transports();
+    if transports.len() > 1 && args.capture.is_some() {
+        anyhow::bail!("--capture can only record traffic with a single device");
+    }
+    let mut panels = transports
+        .into_iter()
+        .map(|transport| connect_panel(&rt, &args, transport))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let shutdown_requested = Arc::new(AtomicBool::new(false));
+    rt.spawn(wait_for_shutdown_signal(shutdown_requested.clone()));
+    let snapshot_requested = Arc::new(AtomicBool::new(false));
+    if args.snapshot_dir.is_some() {
+        if !cfg!(feature = "image") {
+            anyhow::bail!("--snapshot-dir needs the runner to be built with the image feature");
+        }
+        if args.snapshot_scale == 0 || (args.snapshot_grid && args.snapshot_scale < 2) {
+            anyhow::bail!(
+                "--snapshot-scale must be at least 1, or at least 2 with --snapshot-grid"
+            );
+        }
+        rt.spawn(snapshot::wait_for_snapshot_signal(
+            snapshot_requested.clone(),
+        ));
+    }
+
+    let mut display = CompositeDisplay::new(
+        panels
+            .iter()
+            .map(|panel| (panel.serial_conn.clone(), panel.display_info.clone()))
+            .collect(),
+        args.layout,
+        args.orientation(),
+    )?;
+    display
+        .screen_buffer_mut()
+        .set_shadow_enabled(args.diff_render);
+    display.screen_buffer_mut().set_dither_mode(args.dither);
+    if args.gamma.is_some() || args.rgb_brightness != 100 {
+        let correction = ColorCorrection::new(
+            args.gamma.unwrap_or(1.0),
+            f32::from(args.rgb_brightness) / 100.0,
+        )
+        .map_err(|_| {
+            anyhow::anyhow!("--gamma must be positive and --rgb-brightness at most 100")
+        })?;
+        display
+            .screen_buffer_mut()
+            .set_color_correction(Some(correction));
+    }
+    if panels.len() > 1 {
+        let display_config = display.display_config();
+        tracing::info!(
+            "Combined {} panels into one display: {display_config:?}",
+            panels.len()
+        );
+    }
+
+    if let Some(brightness) = args.brightness {
+        if let Err(err) = display.set_brightness(brightness) {
+            tracing::warn!("Failed to set the display brightness to {brightness}: {err}");
+        }
+    }
+    let mut palette_cycle = if args.palette.is_empty() {
+        None
+    } else {
+        let cycle = PaletteCycle::new(
+            args.palette.clone(),
+            Duration::from_millis(args.palette_cycle_ms),
+        )
+        .map_err(|_| anyhow::anyhow!("--palette-cycle-ms must be more than 0"))?;
+        display
+            .screen_buffer_mut()
+            .set_palette(cycle.current())
+            .map_err(|_| anyhow::anyhow!("--palette needs an RGB display"))?;
+        (args.palette.len() > 1).then_some(cycle)
+    };
+
+    if let Some(Command::TestPattern(pattern_args)) = &args.command {
+        let result = test_pattern::run(&mut display, pattern_args, &shutdown_requested);
+        shut_down(
+            &rt,
+            panels,
+            !args.no_blank_on_exit && !pattern_args.leaves_pattern(),
+        );
+        return result.map(|()| ExitCode::SUCCESS);
+    }
+
+    let rotation = match (&args.app, &args.app_rotation) {
+        (Some(app_path), _) => wasm_env::AppRotation::single(app_path),
+        (None, Some(rotation_path)) => wasm_env::AppRotation::open(rotation_path)?,
+        (None, None) => anyhow::bail!("An app is needed unless running a subcommand"),
+    };
+    let reload_requested = Arc::new(AtomicBool::new(false));
+    if args.app_rotation.is_some() {
+        rt.spawn(wait_for_reload_signal(reload_requested.clone()));
+    }
+    let mut scheduler = wasm_env::AppScheduler::new(
+        Rc::new(RefCell::new(display)),
+        rotation,
+        args.app_options()?,
+        args.transition(),
+    );
+    scheduler.set_hot_reload(args.hot_reload);
+    scheduler.set_warm_instances(args.warm_instances);
+    scheduler.set_next_app_button(args.next_app_button);
+    scheduler.poll(std::time::Instant::now())?;
+    let stats_interval = args
+        .stats_interval_secs
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    let mut next_app_stats = stats_interval.map(|interval| std::time::Instant::now() + interval);
+
+    loop {
+        if shutdown_requested.load(Ordering::Relaxed) {
+            break;
+        }
+        if let Some(rotation_path) = &args.app_rotation {
+            if reload_requested.swap(false, Ordering::Relaxed) {
+                tracing::info!("Reloading the rotation from {}", rotation_path.display());
+                let reloaded = wasm_env::AppRotation::open(rotation_path)
+                    .and_then(|rotation| scheduler.set_rotation(rotation));
+                if let Err(err) = reloaded {
+                    tracing::error!("Failed to reload the rotation: {err:#}");
+                }
+            }
+        }
+        let Some(wasm_app) = scheduler.app() else {
+            tracing::error!("No app is running, exiting");
+            break;
+        };
+        if let Some(dir) = &args.snapshot_dir {
+            if snapshot_requested.swap(false, Ordering::Relaxed) {
+                match wasm_app.screen_buffer().and_then(|screen_buffer| {
+                    snapshot::write(&screen_buffer, dir, args.snapshot_scale, args.snapshot_grid)
+                }) {
+                    Ok(path) => tracing::info!("Saved a snapshot to {}", path.display()),
+                    Err(err) => tracing::warn!("Failed to save a snapshot: {err}"),
+                }
+            }
+        }
+        let start_time = std::time::Instant::now();
+        if let Some(due) = next_app_stats.filter(|due| start_time >= *due) {
+            log_app_stats(scheduler.stats());
+            next_app_stats = stats_interval.map(|interval| due + interval);
+        }
+        let mut reconnected = false;
+        let mut reconfigured = false;
+        for (index, panel) in panels.iter_mut().enumerate() {
+            let mut panel_reconnected = false;
+            while let Ok(event) = panel.connection_events.try_recv() {
+                panel_reconnected |= event == serial::ConnectionEvent::Connected;
+            }
+            if panel_reconnected {
+                reconnected = true;
+                // The panel may have been swapped for one of another size while it was
+                // disconnected
+                match refresh_panel_config(panel, index, &scheduler.display()) {
+                    Ok(changed) => reconfigured |= changed,
+                    Err(err) => tracing::warn!(
+                        "Failed to check the display on panel {index} after reconnecting: {err}"
+                    ),
+                }
+            }
+        }
+        if reconfigured {
+            let display_config = scheduler.display().borrow().display_config();
+            tracing::info!(
+                "Display changed size to {}x{}, fitting the app to it",
+                display_config.width,
+                display_config.height
+            );
+            if let Err(err) = scheduler.display_reconfigured() {
+                tracing::error!("{err}, exiting");
+                break;
+            }
+        } else if reconnected {
+            tracing::info!("Device reconnected, redrawing the display");
+            if let Some(Err(err)) = scheduler.app().map(|wasm_app| wasm_app.redraw()) {
+                tracing::warn!("Failed to redraw after reconnecting: {err}");
+            }
+        }
+        if panels.iter().any(|panel| {
+            panel.serial_conn.connection_state() == serial::ConnectionState::Disconnected
+        }) {
+            tracing::trace!("Device is disconnected, pausing app");
+            std::thread::sleep(scheduler.refresh_period());
+            continue;
+        }
+        if let Some(palette) = palette_cycle
+            .as_mut()
+            .and_then(|cycle| cycle.poll(std::time::Instant::now()))
+        {
+            if let Some(Err(err)) = scheduler
+                .app()
+                .map(|wasm_app| wasm_app.set_palette(palette))
+            {
+                tracing::warn!("Failed to change the palette: {err}");
+            }
+        }
+        let now = std::time::Instant::now();
+        for panel in &panels {
+            for event in panel.input.drain() {
+                scheduler.handle_input(event, now);
+            }
+        }
+        if let Err(err) = scheduler.poll(now).and_then(|()| scheduler.run_once()) {
+            tracing::error!("{err}, exiting");
+            break;
+        }
+        std::thread::sleep(scheduler.time_until_next_frame(std::time::Instant::now()));
+    }
+
+    scheduler.stop();
+    shut_down(&rt, panels, !args.no_blank_on_exit);
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn log_app_stats(stats: &BTreeMap<String, wasm_env::AppStats>) {
+    let summary = stats
+        .iter()
+        .map(|(name, stats)| format!("{name}: {stats}"))
+        .collect::<Vec<_>>()
+        .join("; ");
+    tracing::info!("App stats: {summary}");
+}
+
+/// Lets queued frames finish sending to every panel, blanking them afterwards if `blank` is set.
+fn shut_down(rt: &tokio::runtime::Runtime, panels: Vec<Panel>, blank: bool) {
+    rt.block_on(async {
+        let mut shutdowns = tokio::task::JoinSet::new();
+        for panel in panels {
+            let goodbye = if blank {
+                serial::blank_display_sequence(&panel.display_info)
+            } else {
+                vec![]
+            };
+            shutdowns.spawn(panel.shutdown_handle.shutdown(goodbye));
+        }
+        let all_shut_down = async { while shutdowns.join_next().await.is_some() {} };
+        if tokio::time::timeout(SHUTDOWN_DEADLINE, all_shut_down)
+            .await
+            .is_err()
+        {
+            tracing::warn!("Timed out waiting for the device to finish shutting down");
+        }
+    });
+}
+
+/// A connection to one of the panels which make up the display.
+struct Panel {
+    serial_conn: serial::SyncSerialConnection,
+    shutdown_handle: serial::ShutdownHandle,
+    connection_events: async_channel::Receiver<serial::ConnectionEvent>,
+    /// Buttons pressed and encoders turned on the panel
+    input: serial::InputEvents,
+    display_info: DisplayConfiguration,
+    /// Settings of the serial port the panel is on, if it isn't reached some other way
+    link: Option<SerialConfig>,
+}
+
+/// Starts the serial task for a panel and asks the device about its firmware and display.
+fn connect_panel(
+    rt: &tokio::runtime::Runtime,
+    args: &Args,
+    transport: Box<dyn Transport>,
+) -> anyhow::Result<Panel> {
+    let (tx, rx) = async_channel::unbounded();
+    let transport_name = transport.to_string();
+    let (serial_conn, shutdown_handle, serial_task) =
+        serial::start_transport_task(transport, args.serial_config(), tx, rx);
+    if let Some(interval) = args.stats_interval_secs.filter(|secs| *secs > 0) {
+        rt.spawn(log_stats(
+            serial_conn.clone(),
+            Duration::from_secs(interval),
+        ));
+    }
+    let serial_conn = serial::SyncSerialConnection::new(serial_conn, rt.handle().clone());
+
+    let connection_events = serial_conn.subscribe_events();
+    let input = serial_conn.subscribe_input();
+    let _serial_task_handle = rt.spawn(Box::into_pin(serial_task));
+
+    if args.wait_for_port {
+        // The app can't start until the size of the display is known, so it stays paused until
+        // the device shows up
+        serial_conn.wait_for_connection().inspect_err(|_| {
+            tracing::error!("Gave up waiting for {transport_name} to appear");
+        })?;
+    }
+
+    let firmware_info = serial_conn.get_firmware_info()?;
+    match firmware_info.version {
+        Some(version) => tracing::info!(
+            "Connected to firmware {version} on {transport_name} with capabilities {:?}",
+            firmware_info.capabilities
+        ),
+        None => tracing::info!("Connected to legacy firmware on {transport_name}"),
+    }
+    if args.crc
+        && !firmware_info
+            .capabilities
+            .contains(serial::Capabilities::CRC)
+    {
+        tracing::info!(
+            "CRC framing is enabled, but the firmware does not report supporting it, so frames \
+             are sent without a CRC"
+        );
+    }
+
+    let link = args.serial_link();
+    let display_info = query_display_config(&serial_conn, link.as_ref()).map_err(|err| {
+        tracing::error!(
+            "Failed to get display info from {transport_name}: {err}. Check that the device is \
+             running megabit firmware and speaks the serial protocol at {} baud",
+            args.baud
+        );
+        err
+    })?;
+    tracing::info!("Retrieved info about the display on {transport_name}: {display_info:?}");
+
+    Ok(Panel {
+        serial_conn,
+        shutdown_handle,
+        connection_events,
+        input,
+        display_info,
+        link,
+    })
+}
+
+/// Asks a panel for the size and pixel format of its display, estimating how fast it can be
+/// updated from the settings of the serial port it's on.
+fn query_display_config(
+    serial_conn: &serial::SyncSerialConnection,
+    link: Option<&SerialConfig>,
+) -> std::io::Result<DisplayConfiguration> {
+    let display_info = serial_conn.get_display_info()?;
+    let mut config = DisplayConfiguration {
+        width: display_info.width as usize,
+        height: display_info.height as usize,
+        pixel_representation: display_info.pixel_representation,
+        orientation: Default::default(),
+        max_fps_hint: None,
+    };
+    config.max_fps_hint = link.and_then(|link| serial::max_fps_hint(&config, link));
+    Ok(config)
+}
+
+/// Asks a panel which has reconnected about its display again, and updates the combined display
+/// if it has changed. Returns whether it had.
+fn refresh_panel_config(
+    panel: &mut Panel,
+    index: usize,
+    display: &RefCell<CompositeDisplay>,
+) -> anyhow::Result<bool> {
+    let display_info = query_display_config(&panel.serial_conn, panel.link.as_ref())?;
+    let changed = display
+        .borrow_mut()
+        .reconfigure_panel(index, display_info.clone())?;
+    if changed {
+        tracing::info!("Panel {index} is now {display_info:?}");
+    }
+    panel.display_info = display_info;
+    Ok(changed)
+}
+
+/// Asks for the rotation to be reloaded whenever the runner receives SIGHUP.
+async fn wait_for_reload_signal(reload_requested: Arc<AtomicBool>) {
+    #[cfg(unix)]
+    {
+        let mut signal = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        {
+            Ok(signal) => signal,
+            Err(err) => {
+                tracing::warn!("Failed to listen for SIGHUP, reloading is disabled: {err}");
+                return;
+            }
+        };
+        while signal.recv().await.is_some() {
+            reload_requested.store(true, Ordering::Relaxed);
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = reload_requested;
+        tracing::warn!("The rotation can only be reloaded with SIGHUP on unix");
+    }
+}
+
+async fn wait_for_shutdown_signal(shutdown_requested: Arc<AtomicBool>) {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                tracing::warn!("Failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutting down");
+    shutdown_requested.store(true, Ordering::Relaxed);
+}
@@ -0,0 +1,90 @@
+use super::{Args, SHUTDOWN_DEADLINE};
+use megabit_runner::serial::{self, Capabilities, ConnectionEvent, ConnectionState};
+use std::{process::ExitCode, time::Duration};
+
+/// How long to wait for the device to drop off after telling it to restart
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Exit status when the device accepted the command but never dropped off
+const NO_DISCONNECT_EXIT_CODE: u8 = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ResetKind {
+    Firmware,
+    Bootloader,
+}
+
+/// Tells the device to restart and waits for it to drop off. Exits with status 2 if the device
+/// is still there afterwards.
+pub fn run(rt: &tokio::runtime::Runtime, args: &Args, kind: ResetKind) -> anyhow::Result<ExitCode> {
+    let mut transports = args.transports();
+    if transports.len() != 1 {
+        anyhow::bail!("Resetting needs exactly one device");
+    }
+    let transport = transports.remove(0);
+    let transport_name = transport.to_string();
+
+    let (tx, rx) = async_channel::unbounded();
+    let (serial_conn, shutdown_handle, serial_task) =
+        serial::start_transport_task(transport, args.serial_config(), tx, rx);
+    let _serial_task_handle = rt.spawn(Box::into_pin(serial_task));
+
+    rt.block_on(async {
+        if args.wait_for_port {
+            serial_conn.wait_for_connection().await?;
+        }
+        let firmware_info = serial_conn.get_firmware_info().await?;
+        if !firmware_info
+            .capabilities
+            .contains(Capabilities::REMOTE_RESET)
+        {
+            anyhow::bail!("The firmware on {transport_name} can't be reset over the serial port");
+        }
+
+        let events = serial_conn.subscribe_events();
+        let res = match kind {
+            ResetKind::Firmware => serial_conn.reset_device().await,
+            ResetKind::Bootloader => serial_conn.enter_bootloader().await,
+        };
+        // The device can restart quickly enough to cut off the write itself
+        if let Err(err) = res {
+            if serial_conn.connection_state() != ConnectionState::Disconnected {
+                return Err(err.into());
+            }
+        }
+
+        let disconnected = async {
+            while serial_conn.connection_state() != ConnectionState::Disconnected {
+                match events.recv().await {
+                    Ok(ConnectionEvent::Disconnected) | Err(_) => break,
+                    Ok(ConnectionEvent::Connected | ConnectionEvent::Degraded) => {}
+                }
+            }
+        };
+        let exit_code = if tokio::time::timeout(DISCONNECT_TIMEOUT, disconnected)
+            .await
+            .is_ok()
+        {
+            match kind {
+                ResetKind::Firmware => tracing::info!("{transport_name} is restarting"),
+                ResetKind::Bootloader => {
+                    tracing::info!("{transport_name} has restarted into its bootloader")
+                }
+            }
+            ExitCode::SUCCESS
+        } else {
+            tracing::error!(
+                "{transport_name} was still connected {}s after being told to restart",
+                DISCONNECT_TIMEOUT.as_secs()
+            );
+            ExitCode::from(NO_DISCONNECT_EXIT_CODE)
+        };
+
+        if tokio::time::timeout(SHUTDOWN_DEADLINE, shutdown_handle.shutdown(vec![]))
+            .await
+            .is_err()
+        {
+            tracing::warn!("Timed out waiting for the serial task to shut down");
+        }
+        Ok(exit_code)
+    })
+}
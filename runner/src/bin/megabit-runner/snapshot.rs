@@ -0,0 +1,58 @@
+use megabit_runner::display::ScreenBuffer;
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Flags that a snapshot should be taken every time the runner receives SIGUSR2.
+pub async fn wait_for_snapshot_signal(snapshot_requested: Arc<AtomicBool>) {
+    #[cfg(unix)]
+    {
+        let mut signal =
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined2()) {
+                Ok(signal) => signal,
+                Err(err) => {
+                    tracing::warn!("Failed to listen for SIGUSR2, snapshots are disabled: {err}");
+                    return;
+                }
+            };
+        while signal.recv().await.is_some() {
+            snapshot_requested.store(true, Ordering::Relaxed);
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = snapshot_requested;
+        tracing::warn!("Snapshots can only be requested with SIGUSR2 on unix");
+    }
+}
+
+/// Writes the screen buffer to `snapshot-<milliseconds since the epoch>.png` in `dir`.
+#[cfg(feature = "image")]
+pub fn write(
+    screen_buffer: &ScreenBuffer,
+    dir: &Path,
+    scale: u32,
+    grid: bool,
+) -> anyhow::Result<std::path::PathBuf> {
+    let png = screen_buffer.to_png(scale, grid)?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_millis();
+    let path = dir.join(format!("snapshot-{timestamp}.png"));
+    std::fs::write(&path, png)?;
+    Ok(path)
+}
+
+#[cfg(not(feature = "image"))]
+pub fn write(
+    _screen_buffer: &ScreenBuffer,
+    _dir: &Path,
+    _scale: u32,
+    _grid: bool,
+) -> anyhow::Result<std::path::PathBuf> {
+    anyhow::bail!("Snapshots need the runner to be built with the image feature")
+}
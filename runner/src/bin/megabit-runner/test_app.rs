@@ -0,0 +1,111 @@
+use super::Args;
+use clap::Parser;
+use std::{path::PathBuf, process::ExitCode};
+
+#[derive(Clone, Debug, Parser)]
+pub struct TestAppArgs {
+    /// Directory of the app to test, containing its manifest
+    app: PathBuf,
+    /// Number of frames to run the app for after setting it up
+    #[arg(long, default_value_t = 10)]
+    frames: u32,
+    /// Size of the display to run the app on, as WIDTHxHEIGHT
+    #[arg(long, default_value = "32x16", value_parser = super::parse_resolution)]
+    display_size: (usize, usize),
+    /// Run the app on an RGB display rather than a monocolor one
+    #[arg(long)]
+    rgb: bool,
+    /// Directory of the golden trace, and screen with the image feature, to check the app
+    /// against. Exits with status 1 if the app doesn't match them
+    #[arg(long)]
+    golden: Option<PathBuf>,
+    /// Replace the golden run with this one instead of checking against it
+    #[arg(long, requires = "golden")]
+    update_golden: bool,
+    /// Write every host call the app made to this file as JSON lines
+    #[arg(long)]
+    trace: Option<PathBuf>,
+    /// Write what the app had drawn after the last frame to this file as a PNG
+    #[arg(long)]
+    png: Option<PathBuf>,
+}
+
+/// Runs the app against an in-memory display for the given number of frames, writing out what
+/// it did and checking it against the golden run if asked to.
+#[cfg(feature = "test-support")]
+pub fn run(args: &Args, test_args: &TestAppArgs) -> anyhow::Result<ExitCode> {
+    use megabit_runner::{
+        display::{DisplayConfiguration, PixelRepresentation},
+        wasm_env::{self, AppHarness},
+    };
+
+    let (width, height) = test_args.display_size;
+    let display_config = DisplayConfiguration {
+        width,
+        height,
+        pixel_representation: if test_args.rgb {
+            PixelRepresentation::RGB555
+        } else {
+            PixelRepresentation::Monocolor
+        },
+        orientation: Default::default(),
+        max_fps_hint: None,
+    };
+    let mut harness = AppHarness::load(&test_args.app, display_config, args.app_options()?)?;
+    // What the app did up until it failed is still worth having
+    let frames = harness.run_frames(test_args.frames);
+    if let Some(path) = &test_args.trace {
+        let file = std::fs::File::create(path)?;
+        wasm_env::write_trace(&harness.trace(), std::io::BufWriter::new(file))?;
+    }
+    if let Some(path) = &test_args.png {
+        write_png(&harness, path)?;
+    }
+    frames?;
+    tracing::info!(
+        "Ran {} for {} frames, making {} host calls",
+        harness.runner().name(),
+        test_args.frames,
+        harness.trace().len()
+    );
+
+    let Some(golden_dir) = &test_args.golden else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    if test_args.update_golden {
+        harness.write_golden(golden_dir)?;
+        tracing::info!("Updated the golden run in {}", golden_dir.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let differences = harness.check_golden(golden_dir)?;
+    if differences.is_empty() {
+        tracing::info!("The app matches the golden run in {}", golden_dir.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+    for difference in differences {
+        tracing::error!("{difference}");
+    }
+    Ok(ExitCode::FAILURE)
+}
+
+#[cfg(not(feature = "test-support"))]
+pub fn run(_args: &Args, _test_args: &TestAppArgs) -> anyhow::Result<ExitCode> {
+    anyhow::bail!("Testing apps needs the runner to be built with the test-support feature")
+}
+
+#[cfg(all(feature = "test-support", feature = "image"))]
+fn write_png(
+    harness: &megabit_runner::wasm_env::AppHarness,
+    path: &std::path::Path,
+) -> anyhow::Result<()> {
+    std::fs::write(path, harness.screen_png(1)?)?;
+    Ok(())
+}
+
+#[cfg(all(feature = "test-support", not(feature = "image")))]
+fn write_png(
+    _harness: &megabit_runner::wasm_env::AppHarness,
+    _path: &std::path::Path,
+) -> anyhow::Result<()> {
+    anyhow::bail!("Writing PNGs needs the runner to be built with the image feature")
+}
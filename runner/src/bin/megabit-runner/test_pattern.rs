@@ -0,0 +1,76 @@
+use clap::Parser;
+use megabit_runner::display::{CompositeDisplay, TestPattern};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+/// How often to check for Ctrl-C while waiting to show the next pattern
+const SHUTDOWN_POLL_PERIOD: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Parser)]
+pub struct TestPatternArgs {
+    /// Pattern to show and leave on the display: checkerboard, gradient, colorbars, border, or
+    /// pixel-index. Every pattern is shown in turn until interrupted if not given
+    #[arg(long, value_parser = parse_test_pattern)]
+    pattern: Option<TestPattern>,
+    /// Seconds to show each pattern for when cycling through them
+    #[arg(long, default_value_t = 3)]
+    cycle_secs: u64,
+}
+
+impl TestPatternArgs {
+    /// Whether the pattern should still be showing after the runner exits.
+    pub fn leaves_pattern(&self) -> bool {
+        self.pattern.is_some()
+    }
+}
+
+/// Shows the chosen pattern, or cycles through all of them until a shutdown is requested.
+pub fn run(
+    display: &mut CompositeDisplay,
+    pattern_args: &TestPatternArgs,
+    shutdown_requested: &AtomicBool,
+) -> anyhow::Result<()> {
+    let config = display.display_config();
+    tracing::info!(
+        "Showing test patterns on a {}x{} {:?} display",
+        config.width,
+        config.height,
+        config.pixel_representation
+    );
+    if let Some(pattern) = pattern_args.pattern {
+        return show(display, pattern);
+    }
+
+    let cycle_period = Duration::from_secs(pattern_args.cycle_secs);
+    for pattern in TestPattern::ALL.into_iter().cycle() {
+        show(display, pattern)?;
+        let shown_at = Instant::now();
+        while shown_at.elapsed() < cycle_period {
+            if shutdown_requested.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            std::thread::sleep(SHUTDOWN_POLL_PERIOD.min(cycle_period));
+        }
+    }
+    Ok(())
+}
+
+fn show(display: &mut CompositeDisplay, pattern: TestPattern) -> anyhow::Result<()> {
+    tracing::info!("Showing {pattern:?}");
+    display.screen_buffer_mut().draw_test_pattern(pattern);
+    display.redraw()?;
+    Ok(())
+}
+
+fn parse_test_pattern(arg: &str) -> Result<TestPattern, String> {
+    match arg {
+        "checkerboard" => Ok(TestPattern::Checkerboard),
+        "gradient" => Ok(TestPattern::VerticalGradient),
+        "colorbars" => Ok(TestPattern::ColorBars),
+        "border" => Ok(TestPattern::BorderWithCross),
+        "pixel-index" => Ok(TestPattern::PixelIndexBits),
+        _ => Err(format!("Unknown test pattern: {arg}")),
+    }
+}
@@ -25,7 +25,8 @@ async fn main() -> anyhow::Result<()> {
 
     let (tx, rx) = async_channel::unbounded();
 
-    let (serial_conn, serial_task) = serial::start_serial_task(args.device, tx, rx.clone());
+    let (serial_conn, _shutdown_handle, serial_task) =
+        serial::start_serial_task(args.device, serial::SerialConfig::default(), tx, rx.clone());
     let _serial_task_handle = tokio::spawn(Box::into_pin(serial_task));
 
     let colors = [(0xff, 0x00, 0x00), (0x00, 0xff, 0x00), (0x00, 0x00, 0xff)];
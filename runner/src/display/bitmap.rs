@@ -0,0 +1,109 @@
+use super::{Rgb555, ScreenBuffer};
+use std::io;
+
+/// An image converted to the display's RGB555 format, ready to be drawn
+#[derive(Debug, Clone)]
+pub struct DecodedImage {
+    width: usize,
+    height: usize,
+    /// Colors row by row
+    pixels: Vec<Rgb555>,
+}
+
+impl DecodedImage {
+    /// Wraps colors given row by row. Fails if there aren't exactly `width` by `height` of them.
+    pub fn from_rgb555(width: usize, height: usize, pixels: Vec<Rgb555>) -> io::Result<Self> {
+        if pixels.len() != width * height {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        Ok(DecodedImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Decodes a PNG, BMP, or GIF image, taking the first frame of animated GIFs, and scales it
+    /// to `size` if given.
+    #[cfg(feature = "image")]
+    pub fn decode(bytes: &[u8], size: Option<(usize, usize)>) -> io::Result<Self> {
+        let image = ::image::load_from_memory(bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+            .to_rgb8();
+        let pixels = image
+            .pixels()
+            .map(|pixel| Rgb555::from_rgb888(pixel.0[0], pixel.0[1], pixel.0[2]))
+            .collect();
+        let decoded =
+            DecodedImage::from_rgb555(image.width() as usize, image.height() as usize, pixels)?;
+        Ok(match size {
+            Some((width, height)) => decoded.scaled(width, height),
+            None => decoded,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Resizes the image with nearest neighbour sampling.
+    pub fn scaled(&self, width: usize, height: usize) -> Self {
+        if self.width == 0 || self.height == 0 {
+            return DecodedImage {
+                width,
+                height,
+                pixels: vec![Rgb555::BLACK; width * height],
+            };
+        }
+        let pixels = (0..height)
+            .flat_map(|row| {
+                let src_row = row * self.height / height;
+                (0..width).map(move |col| {
+                    let src_col = col * self.width / width;
+                    self.pixels[src_row * self.width + src_col]
+                })
+            })
+            .collect();
+        DecodedImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+impl ScreenBuffer {
+    /// Draws an image with its top left corner at (`x`, `y`), clipping whatever falls outside
+    /// of the buffer. Monocolor buffers dither the image as a whole according to the dither
+    /// mode.
+    pub fn draw_image(&mut self, x: i32, y: i32, image: &DecodedImage) {
+        let is_lit = (!self.is_rgb()).then(|| {
+            super::dither::region_is_lit(
+                self.dither,
+                &image.pixels[..],
+                image.width,
+                (i64::from(x), i64::from(y)),
+            )
+        });
+        for (image_row, colors) in image.pixels.chunks(image.width.max(1)).enumerate() {
+            for (image_col, &color) in colors.iter().enumerate() {
+                let Some((row, col)) = self.clipped_cell(
+                    i64::from(y) + image_row as i64,
+                    i64::from(x) + image_col as i64,
+                ) else {
+                    continue;
+                };
+                let _ = match &is_lit {
+                    Some(is_lit) => {
+                        self.set_cell(row, col, is_lit[image_row * image.width + image_col])
+                    }
+                    None => self.set_pixel_rgb(row, col, color),
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use super::{PixelValue, Rgb555, ScreenBuffer, MONO_ALPHA_THRESHOLD};
+use std::io;
+
+/// How opaque each pixel of a blended write is, from 0 for invisible to 255 for opaque
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMask<'a> {
+    /// Every pixel is equally opaque
+    Uniform(u8),
+    /// An alpha for each pixel, given row by row like the colors
+    PerPixel(&'a [u8]),
+}
+
+impl ScreenBuffer {
+    /// Blends `color` over a pixel, `alpha` being its weight out of 255. Monocolor buffers have
+    /// nothing to blend with, so the color is drawn as usual when `alpha` is at least 128 and
+    /// the pixel is left alone otherwise.
+    pub fn blend_pixel(
+        &mut self,
+        row: usize,
+        col: usize,
+        color: Rgb555,
+        alpha: u8,
+    ) -> io::Result<()> {
+        let under = self.get_pixel(row, col)?;
+        match under {
+            PixelValue::Rgb(_) if alpha == 0 => Ok(()),
+            PixelValue::Rgb(under) => self.set_pixel_rgb(row, col, color.blend_over(under, alpha)),
+            PixelValue::Mono(_) if alpha >= MONO_ALPHA_THRESHOLD => {
+                self.set_pixel_rgb(row, col, color)
+            }
+            PixelValue::Mono(_) => Ok(()),
+        }
+    }
+
+    /// Blends a `width` by `height` rectangle of RGB555 colors, given row by row, over the
+    /// buffer with its top left corner at (`x`, `y`), like [`ScreenBuffer::blend_pixel`] for
+    /// each of them. Parts of the rectangle which fall outside the buffer, or the region being
+    /// drawn through, are dropped. Fails without drawing anything if there aren't exactly
+    /// enough colors, or alphas when given per pixel, for the rectangle.
+    pub fn write_region_rgb_blended(
+        &mut self,
+        (x, y): (usize, usize),
+        (width, height): (usize, usize),
+        colors: &[Rgb555],
+        alpha: AlphaMask,
+    ) -> io::Result<()> {
+        let alpha_len = match alpha {
+            AlphaMask::Uniform(_) => colors.len(),
+            AlphaMask::PerPixel(alphas) => alphas.len(),
+        };
+        if colors.len() != width * height || alpha_len != colors.len() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+
+        let bounds = self.clip_bounds();
+        let visible_width = width.min(bounds.right().saturating_sub(x));
+        let visible_height = height.min(bounds.bottom().saturating_sub(y));
+        for region_row in 0..visible_height {
+            for region_col in 0..visible_width {
+                let index = region_row * width + region_col;
+                let pixel_alpha = match alpha {
+                    AlphaMask::Uniform(alpha) => alpha,
+                    AlphaMask::PerPixel(alphas) => alphas[index],
+                };
+                self.blend_pixel(y + region_row, x + region_col, colors[index], pixel_alpha)?;
+            }
+        }
+        Ok(())
+    }
+}
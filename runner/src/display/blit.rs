@@ -0,0 +1,49 @@
+use super::{rgb555_is_lit, PixelValue, RegionBounds, Rgb555, ScreenBuffer, ScreenBufferKind};
+
+impl ScreenBuffer {
+    /// Copies the `src_rect` rectangle of `src` onto the buffer with its top left corner at
+    /// (`dest_x`, `dest_y`), e.g. to show part of something drawn ahead of time on an offscreen
+    /// buffer. Parts of the rectangle which fall outside of `src`, or outside of the buffer or
+    /// the region being drawn through, are skipped.
+    ///
+    /// Pixels which are on or off, including those drawn in palette colors on an RGB buffer,
+    /// are drawn in this buffer's palette. Other RGB colors copied onto a monocolor buffer are
+    /// lit where any channel is at least half intensity.
+    pub fn blit_from(
+        &mut self,
+        src: &ScreenBuffer,
+        src_rect: RegionBounds,
+        dest_x: i32,
+        dest_y: i32,
+    ) {
+        let width = src_rect.width.min(src.width.saturating_sub(src_rect.x));
+        let height = src_rect.height.min(src.height.saturating_sub(src_rect.y));
+        for row in 0..height {
+            for col in 0..width {
+                let Some((dest_row, dest_col)) = self.clipped_cell(
+                    i64::from(dest_y) + row as i64,
+                    i64::from(dest_x) + col as i64,
+                ) else {
+                    continue;
+                };
+                let index = (src_rect.y + row) * src.width + src_rect.x + col;
+                let value = match &src.buffer {
+                    ScreenBufferKind::Monocolor(buffer) => PixelValue::Mono(buffer[index]),
+                    ScreenBufferKind::Rgb555(buffer, _) => match src.palette_cells[index] {
+                        Some(is_lit) => PixelValue::Mono(is_lit),
+                        None => PixelValue::Rgb(Rgb555::from_raw(buffer[index])),
+                    },
+                };
+                let _ = match value {
+                    PixelValue::Rgb(color) if self.is_rgb() => {
+                        self.set_pixel_rgb(dest_row, dest_col, color)
+                    }
+                    PixelValue::Rgb(color) => {
+                        self.set_cell(dest_row, dest_col, rgb555_is_lit(color))
+                    }
+                    PixelValue::Mono(is_lit) => self.set_cell(dest_row, dest_col, is_lit),
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::{io, str::FromStr};
+
+/// A color as sent to the display, with five bits per channel packed as `0RRRRRGGGGGBBBBB`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rgb555(u16);
+
+/// Largest value of a single channel
+const CHANNEL_MAX: u8 = 0x1f;
+const RED_SHIFT: u16 = 10;
+const GREEN_SHIFT: u16 = 5;
+const BLUE_SHIFT: u16 = 0;
+
+impl Rgb555 {
+    pub const BLACK: Rgb555 = Rgb555::new(0, 0, 0);
+    pub const WHITE: Rgb555 = Rgb555::new(CHANNEL_MAX, CHANNEL_MAX, CHANNEL_MAX);
+    pub const RED: Rgb555 = Rgb555::new(CHANNEL_MAX, 0, 0);
+    pub const GREEN: Rgb555 = Rgb555::new(0, CHANNEL_MAX, 0);
+    pub const BLUE: Rgb555 = Rgb555::new(0, 0, CHANNEL_MAX);
+    pub const YELLOW: Rgb555 = Rgb555::new(CHANNEL_MAX, CHANNEL_MAX, 0);
+    pub const CYAN: Rgb555 = Rgb555::new(0, CHANNEL_MAX, CHANNEL_MAX);
+    pub const MAGENTA: Rgb555 = Rgb555::new(CHANNEL_MAX, 0, CHANNEL_MAX);
+    pub const AMBER: Rgb555 = Rgb555::from_rgb888(0xff, 0xbf, 0x00);
+    pub const ORANGE: Rgb555 = Rgb555::from_rgb888(0xff, 0x80, 0x00);
+    pub const PURPLE: Rgb555 = Rgb555::from_rgb888(0x80, 0x00, 0xff);
+    pub const PINK: Rgb555 = Rgb555::from_rgb888(0xff, 0x69, 0xb4);
+    pub const NAVY: Rgb555 = Rgb555::from_rgb888(0x00, 0x00, 0x80);
+    pub const GRAY: Rgb555 = Rgb555::from_rgb888(0x80, 0x80, 0x80);
+
+    /// Every named color with the name [`Rgb555::from_str`] takes for it
+    pub const NAMED: [(&'static str, Rgb555); 14] = [
+        ("black", Rgb555::BLACK),
+        ("white", Rgb555::WHITE),
+        ("red", Rgb555::RED),
+        ("green", Rgb555::GREEN),
+        ("blue", Rgb555::BLUE),
+        ("yellow", Rgb555::YELLOW),
+        ("cyan", Rgb555::CYAN),
+        ("magenta", Rgb555::MAGENTA),
+        ("amber", Rgb555::AMBER),
+        ("orange", Rgb555::ORANGE),
+        ("purple", Rgb555::PURPLE),
+        ("pink", Rgb555::PINK),
+        ("navy", Rgb555::NAVY),
+        ("gray", Rgb555::GRAY),
+    ];
+
+    /// A color from five bit channels. Anything above the lowest five bits of each is dropped.
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Rgb555(
+            ((red & CHANNEL_MAX) as u16) << RED_SHIFT
+                | ((green & CHANNEL_MAX) as u16) << GREEN_SHIFT
+                | ((blue & CHANNEL_MAX) as u16) << BLUE_SHIFT,
+        )
+    }
+
+    /// A color as it's packed for the display. The unused top bit is cleared.
+    pub const fn from_raw(raw: u16) -> Self {
+        Rgb555(raw & 0x7fff)
+    }
+
+    pub const fn raw(self) -> u16 {
+        self.0
+    }
+
+    /// Drops the lowest three bits of each eight bit channel.
+    pub const fn from_rgb888(red: u8, green: u8, blue: u8) -> Self {
+        Rgb555::new(red >> 3, green >> 3, blue >> 3)
+    }
+
+    /// Widens each channel to eight bits, so full intensity stays full intensity.
+    pub const fn to_rgb888(self) -> (u8, u8, u8) {
+        const fn widen(channel: u8) -> u8 {
+            (channel << 3) | (channel >> 2)
+        }
+        (widen(self.red()), widen(self.green()), widen(self.blue()))
+    }
+
+    /// Blends the color over `under`, `alpha` being its weight out of 255. The channels are
+    /// mixed at eight bits and only cut back down to five at the end, so blends of nearby
+    /// colors don't all round to the same one.
+    pub fn blend_over(self, under: Rgb555, alpha: u8) -> Self {
+        let alpha = u16::from(alpha);
+        let channel = |over: u8, under: u8| {
+            ((u16::from(over) * alpha + u16::from(under) * (255 - alpha) + 127) / 255) as u8
+        };
+        let (over, under) = (self.to_rgb888(), under.to_rgb888());
+        Rgb555::from_rgb888(
+            channel(over.0, under.0),
+            channel(over.1, under.1),
+            channel(over.2, under.2),
+        )
+    }
+
+    /// Packs the color as `0x00RRGGBB` for displays with eight bits per channel.
+    pub const fn to_packed_rgb888(self) -> u32 {
+        let (red, green, blue) = self.to_rgb888();
+        (red as u32) << 16 | (green as u32) << 8 | blue as u32
+    }
+
+    /// Packs the color as `RRRRRGGGGGGBBBBB`, widening green to six bits.
+    pub const fn to_rgb565(self) -> u16 {
+        let green = (self.green() << 1) | (self.green() >> 4);
+        (self.red() as u16) << 11 | (green as u16) << 5 | self.blue() as u16
+    }
+
+    /// Drops the lowest bit of green from a color packed as `RRRRRGGGGGGBBBBB`.
+    pub const fn from_rgb565(raw: u16) -> Self {
+        Rgb555::new((raw >> 11) as u8, (raw >> 6) as u8, raw as u8)
+    }
+
+    /// A color from a hue in degrees, which wraps around at 360, and a saturation and value
+    /// from 0 to 255.
+    pub fn from_hsv(hue: u16, saturation: u8, value: u8) -> Self {
+        let hue = u32::from(hue % 360);
+        let (saturation, value) = (u32::from(saturation), u32::from(value));
+        let sector = hue / 60;
+        // How far through the sector the hue is, from 0 to 255
+        let fraction = (hue % 60) * 255 / 60;
+        let min = value * (255 - saturation) / 255;
+        let falling = value * (255 - saturation * fraction / 255) / 255;
+        let rising = value * (255 - saturation * (255 - fraction) / 255) / 255;
+        let (red, green, blue) = match sector {
+            0 => (value, rising, min),
+            1 => (falling, value, min),
+            2 => (min, value, rising),
+            3 => (min, falling, value),
+            4 => (rising, min, value),
+            _ => (value, min, falling),
+        };
+        Rgb555::from_rgb888(red as u8, green as u8, blue as u8)
+    }
+
+    pub const fn red(self) -> u8 {
+        ((self.0 >> RED_SHIFT) as u8) & CHANNEL_MAX
+    }
+
+    pub const fn green(self) -> u8 {
+        ((self.0 >> GREEN_SHIFT) as u8) & CHANNEL_MAX
+    }
+
+    pub const fn blue(self) -> u8 {
+        ((self.0 >> BLUE_SHIFT) as u8) & CHANNEL_MAX
+    }
+}
+
+/// Parses one of the names in [`Rgb555::NAMED`], ignoring case, or an eight bit per channel hex
+/// color like `#ffbf00`.
+impl FromStr for Rgb555 {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Expected a color name or #RRGGBB, got {s}"),
+            )
+        };
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(invalid());
+            }
+            let channel = |range| u8::from_str_radix(&hex[range], 16).map_err(|_| invalid());
+            return Ok(Rgb555::from_rgb888(
+                channel(0..2)?,
+                channel(2..4)?,
+                channel(4..6)?,
+            ));
+        }
+        Rgb555::NAMED
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(_, color)| color)
+            .ok_or_else(invalid)
+    }
+}
+
+impl From<u16> for Rgb555 {
+    fn from(raw: u16) -> Self {
+        Rgb555::from_raw(raw)
+    }
+}
+
+impl From<Rgb555> for u16 {
+    fn from(color: Rgb555) -> Self {
+        color.raw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_are_packed_red_first() {
+        assert_eq!(Rgb555::RED.raw(), 0x7c00);
+        assert_eq!(Rgb555::GREEN.raw(), 0x03e0);
+        assert_eq!(Rgb555::BLUE.raw(), 0x001f);
+        let color = Rgb555::new(1, 2, 3);
+        assert_eq!(color.raw(), 0b0_00001_00010_00011);
+        assert_eq!((color.red(), color.green(), color.blue()), (1, 2, 3));
+        // Bits which don't fit are dropped rather than spilling into the next channel
+        assert_eq!(Rgb555::new(0xff, 0, 0), Rgb555::RED);
+        assert_eq!(Rgb555::from_raw(0xffff), Rgb555::WHITE);
+    }
+
+    #[test]
+    fn every_color_round_trips_through_eight_bit_channels() {
+        for raw in 0..=0x7fff {
+            let color = Rgb555::from_raw(raw);
+            let (red, green, blue) = color.to_rgb888();
+            assert_eq!(Rgb555::from_rgb888(red, green, blue), color);
+            assert_eq!(Rgb555::from_rgb565(color.to_rgb565()), color);
+            let packed = color.to_packed_rgb888();
+            assert_eq!(
+                Rgb555::from_rgb888((packed >> 16) as u8, (packed >> 8) as u8, packed as u8),
+                color
+            );
+        }
+        assert_eq!(Rgb555::WHITE.to_rgb888(), (0xff, 0xff, 0xff));
+        assert_eq!(Rgb555::RED.to_packed_rgb888(), 0x00ff0000);
+        assert_eq!(Rgb555::BLUE.to_rgb565(), 0x001f);
+        assert_eq!(Rgb555::GREEN.to_rgb565(), 0x07e0);
+    }
+
+    #[test]
+    fn hues_go_from_red_through_green_and_blue() {
+        assert_eq!(Rgb555::from_hsv(0, 255, 255), Rgb555::RED);
+        assert_eq!(Rgb555::from_hsv(60, 255, 255), Rgb555::YELLOW);
+        assert_eq!(Rgb555::from_hsv(120, 255, 255), Rgb555::GREEN);
+        assert_eq!(Rgb555::from_hsv(240, 255, 255), Rgb555::BLUE);
+        assert_eq!(Rgb555::from_hsv(360 + 120, 255, 255), Rgb555::GREEN);
+        assert_eq!(Rgb555::from_hsv(200, 0, 255), Rgb555::WHITE);
+        assert_eq!(Rgb555::from_hsv(200, 255, 0), Rgb555::BLACK);
+    }
+
+    #[test]
+    fn colors_are_parsed_by_name_or_hex() {
+        assert_eq!("#ff0000".parse::<Rgb555>().unwrap(), Rgb555::RED);
+        assert_eq!("#FFBF00".parse::<Rgb555>().unwrap(), Rgb555::AMBER);
+        assert_eq!("Navy".parse::<Rgb555>().unwrap(), Rgb555::NAVY);
+        for invalid in ["#fff", "#gg0000", "#ff00é", "chartreuse", ""] {
+            assert_eq!(
+                invalid.parse::<Rgb555>().unwrap_err().kind(),
+                io::ErrorKind::InvalidInput,
+                "{invalid}"
+            );
+        }
+    }
+}
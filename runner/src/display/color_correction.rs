@@ -0,0 +1,59 @@
+use super::Rgb555;
+use std::io;
+
+pub const DEFAULT_GAMMA: f32 = 2.2;
+/// Largest value of a single RGB555 channel
+const CHANNEL_MAX: u8 = 0x1f;
+
+/// Gamma correction and brightness scaling applied to RGB rows on their way to the device, so
+/// apps can keep working with the colors they drew
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorCorrection {
+    gamma: f32,
+    brightness: f32,
+    /// Corrected value of each channel value, shared by red, green, and blue
+    lut: [u8; CHANNEL_MAX as usize + 1],
+}
+
+impl ColorCorrection {
+    /// Fails if `gamma` isn't a positive number or `brightness` isn't between 0 and 1.
+    pub fn new(gamma: f32, brightness: f32) -> io::Result<Self> {
+        if !gamma.is_finite() || gamma <= 0.0 || !(0.0..=1.0).contains(&brightness) {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        let mut lut = [0; CHANNEL_MAX as usize + 1];
+        for (value, corrected) in lut.iter_mut().enumerate() {
+            let level = (value as f32 / f32::from(CHANNEL_MAX)).powf(gamma) * brightness;
+            *corrected = (level * f32::from(CHANNEL_MAX)).round() as u8;
+        }
+        Ok(ColorCorrection {
+            gamma,
+            brightness,
+            lut,
+        })
+    }
+
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    /// Corrects each channel of a color.
+    pub fn apply(&self, color: Rgb555) -> Rgb555 {
+        let correct = |channel: u8| self.lut[usize::from(channel)];
+        Rgb555::new(
+            correct(color.red()),
+            correct(color.green()),
+            correct(color.blue()),
+        )
+    }
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        ColorCorrection::new(DEFAULT_GAMMA, 1.0).unwrap()
+    }
+}
@@ -0,0 +1,537 @@
+use super::{
+    Compositor, DisplayConfiguration, Orientation, PixelRepresentation, Rgb555, ScreenBuffer,
+    SharedScreenBuffer, DEFAULT_MONO_PALETTE,
+};
+use crate::serial::{Capabilities, SyncSerialConnection};
+use std::{
+    borrow::Cow,
+    io,
+    sync::{RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// Renders with more rows than this are sent to a panel in a single batch
+const BATCH_ROW_THRESHOLD: usize = 4;
+
+/// How panels are placed next to each other to make up the logical display. Panels are placed
+/// in the order they're given, starting from the top left.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PanelLayout {
+    /// Panels are placed left to right and must all be the same height
+    #[default]
+    Horizontal,
+    /// Panels are placed top to bottom and must all be the same width
+    Vertical,
+}
+
+/// Where the panels go on the combined display
+struct Placement {
+    /// Row and column of each panel's top left pixel
+    offsets: Vec<(usize, usize)>,
+    width: usize,
+    height: usize,
+}
+
+#[derive(Debug)]
+struct Panel {
+    serial_conn: SyncSerialConnection,
+    config: DisplayConfiguration,
+    /// Position of the panel's top left pixel on the logical display
+    row_offset: usize,
+    col_offset: usize,
+}
+
+/// A single logical display made up of one or more panels, each with its own connection. Apps
+/// draw into one screen buffer at the combined resolution and renders are split up between the
+/// panels.
+#[derive(Debug)]
+pub struct CompositeDisplay {
+    screen_buffer: SharedScreenBuffer,
+    /// Layers drawn over the screen buffer before each render
+    compositor: Compositor,
+    panels: Vec<Panel>,
+    layout: PanelLayout,
+}
+
+impl CompositeDisplay {
+    /// Combines the panels into one display. Fails if the panels can't be lined up in the given
+    /// layout or don't all use the same pixel format. The orientation applies to the combined
+    /// display as a whole, after the panels have been lined up.
+    pub fn new(
+        panels: Vec<(SyncSerialConnection, DisplayConfiguration)>,
+        layout: PanelLayout,
+        orientation: Orientation,
+    ) -> anyhow::Result<Self> {
+        let configs = panels.iter().map(|(_, config)| config).collect::<Vec<_>>();
+        let Placement {
+            offsets,
+            width,
+            height,
+        } = place_panels(&configs[..], layout)?;
+        let pixel_representation = configs[0].pixel_representation;
+        let placed_panels = panels
+            .into_iter()
+            .zip(offsets)
+            .map(|((serial_conn, config), (row_offset, col_offset))| Panel {
+                serial_conn,
+                config,
+                row_offset,
+                col_offset,
+            })
+            .collect();
+
+        let mut screen_buffer = ScreenBuffer::with_orientation(
+            width,
+            height,
+            pixel_representation
+                .is_rgb()
+                .then_some(DEFAULT_MONO_PALETTE),
+            orientation,
+        );
+        screen_buffer.set_pixel_representation(pixel_representation)?;
+        Ok(CompositeDisplay {
+            compositor: Compositor::new(screen_buffer.display_config()),
+            screen_buffer: SharedScreenBuffer::new(screen_buffer),
+            panels: placed_panels,
+            layout,
+        })
+    }
+
+    /// Changes the geometry of one of the panels after it has reported a different one, e.g.
+    /// when it was swapped for a panel of another size while disconnected. The screen buffer is
+    /// resized to the new combined size, keeping what had been drawn in its top left, and every
+    /// row is sent at the next render. Returns whether the geometry changed, though a new frame
+    /// rate hint is taken either way. Fails without changing anything if the panels no longer
+    /// line up, or the panel switched between monocolor and RGB, which needs the display to be
+    /// set up again from scratch.
+    pub fn reconfigure_panel(
+        &mut self,
+        index: usize,
+        config: DisplayConfiguration,
+    ) -> anyhow::Result<bool> {
+        let Some(panel) = self.panels.get(index) else {
+            anyhow::bail!("There's no panel {index} to reconfigure");
+        };
+        let current = &panel.config;
+        if current.width == config.width
+            && current.height == config.height
+            && current.pixel_representation == config.pixel_representation
+        {
+            self.panels[index].config.max_fps_hint = config.max_fps_hint;
+            return Ok(false);
+        }
+        let mut screen_buffer = self.screen_buffer.write();
+        if config.is_rgb() != screen_buffer.is_rgb() {
+            anyhow::bail!(
+                "Panel {index} switched from {} to {} pixels, restart the runner to use it",
+                pixel_format_name(current.pixel_representation),
+                pixel_format_name(config.pixel_representation)
+            );
+        }
+        let mut configs = self
+            .panels
+            .iter()
+            .map(|panel| &panel.config)
+            .collect::<Vec<_>>();
+        configs[index] = &config;
+        let Placement {
+            offsets,
+            width,
+            height,
+        } = place_panels(&configs[..], self.layout)?;
+
+        let orientation = screen_buffer.display_config().orientation;
+        let (width, height) = orientation.logical_size(width, height);
+        screen_buffer.resize(width, height, true);
+        screen_buffer.set_pixel_representation(config.pixel_representation)?;
+        drop(screen_buffer);
+        self.panels[index].config = config;
+        for (panel, (row_offset, col_offset)) in self.panels.iter_mut().zip(offsets) {
+            panel.row_offset = row_offset;
+            panel.col_offset = col_offset;
+        }
+        Ok(true)
+    }
+
+    /// The geometry of the combined display. Its frame rate hint is that of the slowest panel
+    /// with one, since every panel is sent its part of each frame.
+    pub fn display_config(&self) -> DisplayConfiguration {
+        let max_fps_hint = self
+            .panels
+            .iter()
+            .filter_map(|panel| panel.config.max_fps_hint)
+            .reduce(f32::min);
+        DisplayConfiguration {
+            max_fps_hint,
+            ..self.screen_buffer.read().display_config()
+        }
+    }
+
+    /// Locks the screen buffer for reading, see [`SharedScreenBuffer`] for what else can be
+    /// locked while holding it.
+    pub fn screen_buffer(&self) -> RwLockReadGuard<'_, ScreenBuffer> {
+        self.screen_buffer.read()
+    }
+
+    /// Locks the screen buffer for drawing, see [`SharedScreenBuffer`] for what else can be
+    /// locked while holding it.
+    pub fn screen_buffer_mut(&mut self) -> RwLockWriteGuard<'_, ScreenBuffer> {
+        self.screen_buffer.write()
+    }
+
+    /// Another handle to the screen buffer, for looking at what's on the display from elsewhere
+    /// without borrowing the display itself.
+    pub fn shared_screen_buffer(&self) -> SharedScreenBuffer {
+        self.screen_buffer.clone()
+    }
+
+    pub fn compositor(&self) -> &Compositor {
+        &self.compositor
+    }
+
+    /// Layers drawn on top of whatever the app has drawn, e.g. for the runner's own status
+    /// icons.
+    pub fn compositor_mut(&mut self) -> &mut Compositor {
+        &mut self.compositor
+    }
+
+    /// Sends the given rows of the screen buffer to whichever panels they fall on, after drawing
+    /// the compositor's layers over it. With a shadow buffer, rows which already match what's on
+    /// the display are skipped. Fails with a [`DeviceError`](crate::serial::DeviceError) if a
+    /// panel has rejected a row or commit, which can be one sent by an earlier render as the
+    /// panels answer after the rows have been written.
+    pub fn render(&mut self, rows: &[u8]) -> io::Result<()> {
+        self.send_rows(rows, true)
+    }
+
+    /// Like [`CompositeDisplay::render_dirty`], but doesn't wait for the rows to be written and
+    /// fails with `WouldBlock` rather than waiting if a panel's send queue is full. For
+    /// animations which would rather give up than fall behind the device.
+    pub fn try_render_dirty(&mut self) -> io::Result<()> {
+        let rows = self.composite_dirty_rows();
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.send_rows(&rows[..], false)
+    }
+
+    /// Draws the compositor's layers and returns the rows which have changed since they were
+    /// last sent.
+    fn composite_dirty_rows(&mut self) -> Vec<u8> {
+        let mut screen_buffer = self.screen_buffer.write();
+        self.compositor.composite_into(&mut screen_buffer);
+        screen_buffer.dirty_rows()
+    }
+
+    fn send_rows(&mut self, rows: &[u8], wait_for_queue: bool) -> io::Result<()> {
+        let mut screen_buffer = self.screen_buffer.write();
+        self.compositor.composite_into(&mut screen_buffer);
+        drop(screen_buffer);
+        // Only a read lock is held while the rows are queued, so observers can still take
+        // snapshots if a queue is full and has to be waited on
+        let screen_buffer = self.screen_buffer.read();
+        let changed_rows = screen_buffer.changed_rows(rows);
+        let physical_rows = screen_buffer.physical_rows(&changed_rows[..]);
+        let panel_rows = self
+            .panels
+            .iter()
+            .map(|panel| {
+                physical_rows
+                    .iter()
+                    .filter_map(|&row| panel.local_row(row))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Rows are queued on every panel without waiting on each write, and then waited on all
+        // at once so the panels change as close together as possible
+        for (panel, rows) in self.panels.iter().zip(&panel_rows) {
+            if !rows.is_empty() {
+                panel.write_rows(&screen_buffer, rows, wait_for_queue)?;
+            }
+        }
+        drop(screen_buffer);
+        for (panel, rows) in self.panels.iter().zip(&panel_rows) {
+            if wait_for_queue && !rows.is_empty() {
+                panel.serial_conn.flush()?;
+            }
+        }
+        // Firmware which double buffers holds the rows back until the commit so the whole frame
+        // changes at once, without it each row shows as soon as it arrives
+        for (panel, rows) in self.panels.iter().zip(&panel_rows) {
+            if !rows.is_empty()
+                && panel
+                    .serial_conn
+                    .capabilities()
+                    .contains(Capabilities::DOUBLE_BUFFERING)
+            {
+                panel.serial_conn.commit_render()?;
+            }
+        }
+        self.screen_buffer.write().mark_sent(rows);
+        for panel in &self.panels {
+            if let Some(err) = panel.serial_conn.take_rejection() {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends only the rows which have changed since the last time they were sent, writing
+    /// nothing at all if the screen buffer hasn't changed.
+    pub fn render_dirty(&mut self) -> io::Result<()> {
+        let rows = self.composite_dirty_rows();
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.render(&rows[..])
+    }
+
+    /// Sends the full contents of the screen buffer to every panel.
+    pub fn redraw(&mut self) -> io::Result<()> {
+        let rows = (0..self.screen_buffer.read().display_config().height)
+            .filter_map(|row| u8::try_from(row).ok())
+            .collect::<Vec<_>>();
+        self.render(&rows[..])
+    }
+
+    /// Sends every row regardless of what the shadow buffer says is on the display, for when the
+    /// device's state is unknown, e.g. after it has reconnected.
+    pub fn force_full_render(&mut self) -> io::Result<()> {
+        self.screen_buffer.write().force_full_render();
+        self.redraw()
+    }
+
+    pub fn set_brightness(&self, level: u8) -> io::Result<()> {
+        for panel in &self.panels {
+            panel.serial_conn.set_brightness(level)?;
+        }
+        Ok(())
+    }
+
+    /// Turns the status LED of every panel's board on or off.
+    pub fn set_led_state(&self, new_state: bool) -> io::Result<()> {
+        for panel in &self.panels {
+            panel.serial_conn.set_led_state(new_state)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the RGB LED of every panel's board.
+    pub fn set_rgb_state(&self, (r, g, b): (u8, u8, u8)) -> io::Result<()> {
+        for panel in &self.panels {
+            panel.serial_conn.set_rgb_state((r, g, b))?;
+        }
+        Ok(())
+    }
+}
+
+impl Panel {
+    /// Translates a row of the combined physical display into the panel's own row number, if the
+    /// row falls on this panel.
+    fn local_row(&self, row: usize) -> Option<(u8, usize)> {
+        let local_row = row
+            .checked_sub(self.row_offset)
+            .filter(|local_row| *local_row < self.config.height)?;
+        Some((u8::try_from(local_row).ok()?, row))
+    }
+
+    fn columns(&self) -> std::ops::Range<usize> {
+        self.col_offset..self.col_offset + self.config.width
+    }
+
+    fn row_bits<'a>(
+        &self,
+        screen_buffer: &'a ScreenBuffer,
+        row: usize,
+    ) -> io::Result<Cow<'a, [bool]>> {
+        Ok(self.panel_columns(screen_buffer.physical_row_bits(row)?))
+    }
+
+    /// The panel's part of an RGB row as the 16 bit words it takes, converted from RGB555 if the
+    /// panel uses RGB565.
+    fn row_words<'a>(
+        &self,
+        screen_buffer: &'a ScreenBuffer,
+        row: usize,
+    ) -> io::Result<Cow<'a, [u16]>> {
+        let row = self.panel_columns(screen_buffer.physical_row_slice(row)?);
+        Ok(match self.config.pixel_representation {
+            PixelRepresentation::RGB565 => Cow::Owned(
+                row.iter()
+                    .map(|&color| Rgb555::from_raw(color).to_rgb565())
+                    .collect(),
+            ),
+            _ => row,
+        })
+    }
+
+    fn row_rgb888(&self, screen_buffer: &ScreenBuffer, row: usize) -> io::Result<Vec<u32>> {
+        Ok(self
+            .panel_columns(screen_buffer.physical_row_slice(row)?)
+            .iter()
+            .map(|&color| Rgb555::from_raw(color).to_packed_rgb888())
+            .collect())
+    }
+
+    /// Cuts the part of a physical row which falls on this panel out of it.
+    fn panel_columns<'a, T: Clone>(&self, row: Cow<'a, [T]>) -> Cow<'a, [T]> {
+        match row {
+            Cow::Borrowed(row) => Cow::Borrowed(&row[self.columns()]),
+            Cow::Owned(row) => Cow::Owned(row[self.columns()].to_vec()),
+        }
+    }
+
+    /// Queues the rows on the panel, given as pairs of the panel's row number and the row of the
+    /// combined physical display to take the pixels from. RGB rows are converted to the panel's
+    /// pixel format on the way out. Unless `wait_for_queue` is set, a full send queue fails with
+    /// `WouldBlock` instead of being waited on.
+    fn write_rows(
+        &self,
+        screen_buffer: &ScreenBuffer,
+        rows: &[(u8, usize)],
+        wait_for_queue: bool,
+    ) -> io::Result<()> {
+        let serial_conn = &self.serial_conn;
+        let is_batch = rows.len() > BATCH_ROW_THRESHOLD;
+        match self.config.pixel_representation {
+            PixelRepresentation::Monocolor => {
+                let rows = panel_rows(rows, |row| self.row_bits(screen_buffer, row))?;
+                if is_batch {
+                    return enqueue_or_wait(serial_conn, wait_for_queue, || {
+                        serial_conn.try_update_rows(&rows[..])
+                    });
+                }
+                for (row_number, row_data) in &rows {
+                    enqueue_or_wait(serial_conn, wait_for_queue, || {
+                        serial_conn.try_update_row(*row_number, row_data)
+                    })?;
+                }
+            }
+            PixelRepresentation::RGB555 | PixelRepresentation::RGB565 => {
+                let rows = panel_rows(rows, |row| self.row_words(screen_buffer, row))?;
+                if is_batch {
+                    return enqueue_or_wait(serial_conn, wait_for_queue, || {
+                        serial_conn.try_update_rows_rgb(&rows[..])
+                    });
+                }
+                for (row_number, row_data) in &rows {
+                    enqueue_or_wait(serial_conn, wait_for_queue, || {
+                        serial_conn.try_update_row_rgb(*row_number, row_data)
+                    })?;
+                }
+            }
+            PixelRepresentation::RGB888 => {
+                let rows = panel_rows(rows, |row| self.row_rgb888(screen_buffer, row))?;
+                if is_batch {
+                    return enqueue_or_wait(serial_conn, wait_for_queue, || {
+                        serial_conn.try_update_rows_rgb888(&rows[..])
+                    });
+                }
+                for (row_number, row_data) in &rows {
+                    enqueue_or_wait(serial_conn, wait_for_queue, || {
+                        serial_conn.try_update_row_rgb888(*row_number, row_data)
+                    })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Lines the panels up in the given layout. Fails if there are no panels, they can't be lined up,
+/// or they don't all use the same pixel format.
+fn place_panels(
+    configs: &[&DisplayConfiguration],
+    layout: PanelLayout,
+) -> anyhow::Result<Placement> {
+    let Some(first_config) = configs.first() else {
+        anyhow::bail!("A display needs at least one panel");
+    };
+    let pixel_representation = first_config.pixel_representation;
+    let (first_width, first_height) = (first_config.width, first_config.height);
+
+    let mut width = 0;
+    let mut height = 0;
+    let mut offsets = Vec::with_capacity(configs.len());
+    for (index, config) in configs.iter().enumerate() {
+        if config.pixel_representation != pixel_representation {
+            anyhow::bail!(
+                "Panel {index} uses {} pixels but panel 0 uses {} pixels, panels with different \
+                 pixel formats can't be combined",
+                pixel_format_name(config.pixel_representation),
+                pixel_format_name(pixel_representation)
+            );
+        }
+        offsets.push(match layout {
+            PanelLayout::Horizontal => {
+                if config.height != first_height {
+                    anyhow::bail!(
+                        "Panel {index} is {} pixels tall but panel 0 is {first_height}, panels \
+                         placed side by side must be the same height",
+                        config.height
+                    );
+                }
+                let col_offset = width;
+                width += config.width;
+                height = first_height;
+                (0, col_offset)
+            }
+            PanelLayout::Vertical => {
+                if config.width != first_width {
+                    anyhow::bail!(
+                        "Panel {index} is {} pixels wide but panel 0 is {first_width}, stacked \
+                         panels must be the same width",
+                        config.width
+                    );
+                }
+                let row_offset = height;
+                height += config.height;
+                width = first_width;
+                (row_offset, 0)
+            }
+        });
+    }
+    Ok(Placement {
+        offsets,
+        width,
+        height,
+    })
+}
+
+/// Pairs each of the panel's row numbers with its pixel data.
+fn panel_rows<T>(
+    rows: &[(u8, usize)],
+    row_data: impl Fn(usize) -> io::Result<T>,
+) -> io::Result<Vec<(u8, T)>> {
+    rows.iter()
+        .map(|&(row_number, row)| Ok((row_number, row_data(row)?)))
+        .collect()
+}
+
+/// Queues a write, waiting for the send queue to drain first if it's full and `wait_for_queue`
+/// is set.
+fn enqueue_or_wait(
+    serial_conn: &SyncSerialConnection,
+    wait_for_queue: bool,
+    enqueue: impl Fn() -> io::Result<()>,
+) -> io::Result<()> {
+    if !wait_for_queue {
+        return enqueue();
+    }
+    loop {
+        match enqueue() {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => serial_conn.flush()?,
+            res => return res,
+        }
+    }
+}
+
+fn pixel_format_name(pixel_representation: PixelRepresentation) -> &'static str {
+    match pixel_representation {
+        PixelRepresentation::Monocolor => "monocolor",
+        PixelRepresentation::RGB555 => "RGB555",
+        PixelRepresentation::RGB565 => "RGB565",
+        PixelRepresentation::RGB888 => "RGB888",
+    }
+}
@@ -0,0 +1,250 @@
+use super::{
+    DisplayConfiguration, PixelValue, Rgb555, ScreenBuffer, DEFAULT_MONO_PALETTE,
+    MONO_ALPHA_THRESHOLD,
+};
+use std::io;
+
+/// Identifies a layer of a [`Compositor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerId(u32);
+
+/// Which pixels of an RGB layer show through to what's below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transparency {
+    /// Every pixel covers what's below it
+    Opaque,
+    /// Pixels of this color are left out and everything else covers what's below
+    ColorKey(Rgb555),
+    /// Every pixel is blended with what's below it, from 0 for invisible to 255 for opaque
+    Alpha(u8),
+}
+
+/// How a monocolor layer is combined with what's below it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MonoBlend {
+    /// Lit pixels are drawn and unlit pixels show what's below
+    #[default]
+    Or,
+    /// Every pixel covers what's below it
+    Overwrite,
+}
+
+/// Pixel data drawn on top of the screen buffer by a [`Compositor`]. The layer's buffer can be
+/// drawn on with everything a screen buffer supports.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    buffer: ScreenBuffer,
+    /// Position of the layer's top left pixel on the screen buffer, which may be off of it
+    offset: (i32, i32),
+    z_order: i32,
+    is_visible: bool,
+    transparency: Transparency,
+    mono_blend: MonoBlend,
+    /// For each pixel of the layer, what was below it and what it was turned into the last time
+    /// it was composited, so compositing again doesn't blend the layer over itself
+    last_blend: Vec<Option<(PixelValue, PixelValue)>>,
+}
+
+/// Stacks layers on top of the screen buffer, e.g. for status icons the runner shows over
+/// whatever the app has drawn without the app having to know about them. Layers are drawn from
+/// the lowest z-order to the highest, and in the order they were added where the z-order is the
+/// same.
+///
+/// Layers are drawn into the screen buffer itself, so moving, hiding, or removing a layer leaves
+/// what it covered behind until the app draws over it again.
+#[derive(Debug, Clone)]
+pub struct Compositor {
+    is_rgb: bool,
+    layers: Vec<(LayerId, Layer)>,
+    next_id: u32,
+}
+
+impl Layer {
+    pub fn buffer(&self) -> &ScreenBuffer {
+        &self.buffer
+    }
+
+    pub fn buffer_mut(&mut self) -> &mut ScreenBuffer {
+        &mut self.buffer
+    }
+
+    pub fn offset(&self) -> (i32, i32) {
+        self.offset
+    }
+
+    pub fn z_order(&self) -> i32 {
+        self.z_order
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.is_visible
+    }
+
+    pub fn set_visible(&mut self, is_visible: bool) {
+        self.is_visible = is_visible;
+    }
+
+    pub fn transparency(&self) -> Transparency {
+        self.transparency
+    }
+
+    /// Changes which pixels of an RGB layer are drawn. Has no effect on monocolor layers.
+    pub fn set_transparency(&mut self, transparency: Transparency) {
+        self.transparency = transparency;
+    }
+
+    pub fn mono_blend(&self) -> MonoBlend {
+        self.mono_blend
+    }
+
+    /// Changes how a monocolor layer is drawn. Has no effect on RGB layers.
+    pub fn set_mono_blend(&mut self, mono_blend: MonoBlend) {
+        self.mono_blend = mono_blend;
+    }
+
+    /// What the layer turns a pixel below it into, if anything.
+    fn blend(&self, value: PixelValue, below: PixelValue) -> Option<PixelValue> {
+        match (value, self.mono_blend, self.transparency) {
+            (PixelValue::Mono(false), MonoBlend::Or, _) => None,
+            (PixelValue::Mono(_), _, _) => Some(value),
+            (PixelValue::Rgb(_), _, Transparency::Opaque) => Some(value),
+            (PixelValue::Rgb(color), _, Transparency::ColorKey(key)) => {
+                (color != key).then_some(value)
+            }
+            (PixelValue::Rgb(_), _, Transparency::Alpha(0)) => None,
+            (PixelValue::Rgb(color), _, Transparency::Alpha(alpha)) => match below {
+                PixelValue::Rgb(below) => Some(PixelValue::Rgb(color.blend_over(below, alpha))),
+                PixelValue::Mono(_) => (alpha >= MONO_ALPHA_THRESHOLD).then_some(value),
+            },
+        }
+    }
+}
+
+impl Compositor {
+    /// A compositor with no layers for a screen buffer of the given kind.
+    pub fn new(display_config: DisplayConfiguration) -> Self {
+        Compositor {
+            is_rgb: display_config.is_rgb(),
+            layers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Adds a blank `width` by `height` layer at the top left of the screen buffer. RGB layers
+    /// start out treating black as transparent and monocolor layers only draw lit pixels, so a
+    /// new layer doesn't cover anything until it's drawn on.
+    pub fn add_layer(&mut self, width: usize, height: usize, z_order: i32) -> LayerId {
+        let id = LayerId(self.next_id);
+        self.next_id += 1;
+        let layer = Layer {
+            buffer: ScreenBuffer::new(width, height, self.is_rgb.then_some(DEFAULT_MONO_PALETTE)),
+            offset: (0, 0),
+            z_order,
+            is_visible: true,
+            transparency: Transparency::ColorKey(Rgb555::BLACK),
+            mono_blend: MonoBlend::default(),
+            last_blend: vec![None; width * height],
+        };
+        self.layers.push((id, layer));
+        id
+    }
+
+    pub fn remove_layer(&mut self, id: LayerId) -> Option<Layer> {
+        let index = self
+            .layers
+            .iter()
+            .position(|(layer_id, _)| *layer_id == id)?;
+        Some(self.layers.remove(index).1)
+    }
+
+    pub fn layer(&self, id: LayerId) -> Option<&Layer> {
+        self.layers
+            .iter()
+            .find(|(layer_id, _)| *layer_id == id)
+            .map(|(_, layer)| layer)
+    }
+
+    pub fn layer_mut(&mut self, id: LayerId) -> Option<&mut Layer> {
+        self.layers
+            .iter_mut()
+            .find(|(layer_id, _)| *layer_id == id)
+            .map(|(_, layer)| layer)
+    }
+
+    /// Moves a layer's top left pixel to (`x`, `y`) on the screen buffer.
+    pub fn set_layer_offset(&mut self, id: LayerId, x: i32, y: i32) -> io::Result<()> {
+        let layer = self.layer_mut(id).ok_or(io::ErrorKind::NotFound)?;
+        if layer.offset != (x, y) {
+            layer.offset = (x, y);
+            layer.last_blend.fill(None);
+        }
+        Ok(())
+    }
+
+    pub fn set_layer_z_order(&mut self, id: LayerId, z_order: i32) -> io::Result<()> {
+        self.layer_mut(id).ok_or(io::ErrorKind::NotFound)?.z_order = z_order;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Draws every visible layer on top of `target`. Only pixels which actually change are
+    /// written, so compositing again before anything has been drawn leaves no rows dirty.
+    pub fn composite_into(&mut self, target: &mut ScreenBuffer) {
+        let mut layers = self
+            .layers
+            .iter_mut()
+            .map(|(_, layer)| layer)
+            .filter(|layer| layer.is_visible)
+            .collect::<Vec<_>>();
+        layers.sort_by_key(|layer| layer.z_order);
+
+        let config = target.display_config();
+        for layer in layers {
+            let layer_config = layer.buffer.display_config();
+            for layer_row in 0..layer_config.height {
+                let Some(row) = position_on(layer.offset.1, layer_row, config.height) else {
+                    continue;
+                };
+                for layer_col in 0..layer_config.width {
+                    let Some(col) = position_on(layer.offset.0, layer_col, config.width) else {
+                        continue;
+                    };
+                    let Ok(value) = layer.buffer.get_pixel(layer_row, layer_col) else {
+                        continue;
+                    };
+                    let Ok(current) = target.get_pixel(row, col) else {
+                        continue;
+                    };
+                    // If the pixel hasn't been drawn over since the last composite, blend over
+                    // what was there before the layer rather than the layer's own output
+                    let index = layer_row * layer_config.width + layer_col;
+                    let below = match layer.last_blend[index] {
+                        Some((below, written)) if written == current => below,
+                        _ => current,
+                    };
+                    let Some(value) = layer.blend(value, below) else {
+                        continue;
+                    };
+                    layer.last_blend[index] = Some((below, value));
+                    if current == value {
+                        continue;
+                    }
+                    let _ = match value {
+                        PixelValue::Mono(is_lit) => target.set_cell(row, col, is_lit),
+                        PixelValue::Rgb(color) => target.set_pixel_rgb(row, col, color),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Where a pixel of a layer lands along one axis of a buffer `size` pixels long, if on it.
+fn position_on(offset: i32, index: usize, size: usize) -> Option<usize> {
+    usize::try_from(i64::from(offset) + index as i64)
+        .ok()
+        .filter(|&position| position < size)
+}
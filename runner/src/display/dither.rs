@@ -0,0 +1,160 @@
+use super::Rgb555;
+
+/// 4x4 Bayer matrix, each entry being the order in which that position lights up as the
+/// intensity rises
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+/// Intensity at and above which Floyd-Steinberg dithering turns a pixel on
+const DIFFUSION_THRESHOLD: i16 = 128;
+
+/// How colors are turned into on and off when they're drawn on a monocolor buffer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DitherMode {
+    /// On if any of the red, green, or blue channels is at least half intensity
+    #[default]
+    Threshold,
+    /// Ordered dithering with a 4x4 Bayer matrix anchored to the top left of the buffer
+    Bayer4x4,
+    /// Error diffusion across a whole region or image. Single pixels have no neighbours to
+    /// spread their error to, so they're dithered like [`DitherMode::Bayer4x4`].
+    FloydSteinberg,
+}
+
+/// Whether a single pixel at the given position is on.
+pub(super) fn pixel_is_lit(mode: DitherMode, color: Rgb555, row: i64, col: i64) -> bool {
+    match mode {
+        DitherMode::Threshold => super::rgb555_is_lit(color),
+        DitherMode::Bayer4x4 | DitherMode::FloydSteinberg => {
+            let threshold =
+                BAYER_4X4[row.rem_euclid(4) as usize][col.rem_euclid(4) as usize] * 16 + 8;
+            intensity(color) > threshold
+        }
+    }
+}
+
+/// Whether each pixel of a `width` by `height` block of colors, given row by row, is on. The
+/// block's top left corner is at (`x`, `y`) on the buffer so ordered dithering lines up with
+/// single pixels drawn around it.
+pub(super) fn region_is_lit(
+    mode: DitherMode,
+    colors: &[Rgb555],
+    width: usize,
+    (x, y): (i64, i64),
+) -> Vec<bool> {
+    let width = width.max(1);
+    match mode {
+        DitherMode::Threshold | DitherMode::Bayer4x4 => colors
+            .iter()
+            .enumerate()
+            .map(|(index, &color)| {
+                let (row, col) = ((index / width) as i64, (index % width) as i64);
+                pixel_is_lit(mode, color, y + row, x + col)
+            })
+            .collect(),
+        DitherMode::FloydSteinberg => floyd_steinberg(colors, width),
+    }
+}
+
+fn floyd_steinberg(colors: &[Rgb555], width: usize) -> Vec<bool> {
+    let mut levels = colors
+        .iter()
+        .map(|&color| i16::from(intensity(color)))
+        .collect::<Vec<_>>();
+    let mut is_lit = vec![false; levels.len()];
+    for index in 0..levels.len() {
+        let level = levels[index];
+        is_lit[index] = level >= DIFFUSION_THRESHOLD;
+        let error = level - if is_lit[index] { 255 } else { 0 };
+
+        let col = index % width;
+        let mut spread = |offset: usize, weight: i16| {
+            if let Some(level) = levels.get_mut(offset) {
+                *level += error * weight / 16;
+            }
+        };
+        if col + 1 < width {
+            spread(index + 1, 7);
+            spread(index + width + 1, 1);
+        }
+        if col > 0 {
+            spread(index + width - 1, 3);
+        }
+        spread(index + width, 5);
+    }
+    is_lit
+}
+
+/// The brightest of a color's channels, scaled to 0 to 255. Taking the brightest channel rather
+/// than luminance keeps pure colors like the default red palette fully on.
+fn intensity(color: Rgb555) -> u8 {
+    let (red, green, blue) = color.to_rgb888();
+    red.max(green).max(blue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tests::picture, ScreenBuffer};
+    use super::*;
+
+    /// A gray gradient 16 pixels wide and 4 tall, dark on the left and bright on the right,
+    /// drawn on a monocolor buffer with `mode`.
+    fn dithered_gradient(mode: DitherMode) -> Vec<String> {
+        let gradient = (0..4)
+            .flat_map(|_| (0..16).map(|col| Rgb555::new(col * 2, col * 2, col * 2)))
+            .collect::<Vec<_>>();
+        let mut buffer = ScreenBuffer::new(16, 4, None);
+        buffer.set_dither_mode(mode);
+        buffer.write_region_rgb(0, 0, 16, 4, &gradient).unwrap();
+        picture(&buffer)
+    }
+
+    #[test]
+    fn thresholding_splits_a_gradient_in_half() {
+        assert_eq!(
+            dithered_gradient(DitherMode::Threshold),
+            ["........########"; 4]
+        );
+    }
+
+    #[test]
+    fn bayer_dithering_matches_the_golden_gradient() {
+        assert_eq!(
+            dithered_gradient(DitherMode::Bayer4x4),
+            [
+                "....#.#.########",
+                ".....#.#.#.#.#.#",
+                "..#.#.#.#.######",
+                ".......#.#.#.###",
+            ]
+        );
+    }
+
+    #[test]
+    fn floyd_steinberg_dithering_matches_the_golden_gradient() {
+        assert_eq!(
+            dithered_gradient(DitherMode::FloydSteinberg),
+            [
+                "......#.#.######",
+                "....#..#.#.#.###",
+                ".....#.#.#######",
+                "...#..#.#.#.####",
+            ]
+        );
+    }
+
+    #[test]
+    fn ordered_dithering_is_anchored_to_the_buffer() {
+        // A region drawn away from the corner lines up with the same colors drawn pixel by pixel
+        let gray = Rgb555::new(15, 15, 15);
+        let mut region = ScreenBuffer::new(8, 8, None);
+        region.set_dither_mode(DitherMode::Bayer4x4);
+        region.write_region_rgb(1, 2, 6, 5, &[gray; 30]).unwrap();
+        let mut pixels = ScreenBuffer::new(8, 8, None);
+        pixels.set_dither_mode(DitherMode::FloydSteinberg);
+        for row in 2..7 {
+            for col in 1..7 {
+                pixels.set_pixel_rgb(row, col, gray).unwrap();
+            }
+        }
+        assert_eq!(picture(&region), picture(&pixels));
+    }
+}
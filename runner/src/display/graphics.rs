@@ -0,0 +1,103 @@
+use super::ScreenBuffer;
+use embedded_graphics_core::{
+    pixelcolor::{BinaryColor, Rgb555, RgbColor},
+    prelude::{DrawTarget, OriginDimensions, Pixel, Size},
+};
+use std::convert::Infallible;
+
+/// Draws into a screen buffer in [`BinaryColor`], going through the palette on RGB buffers like
+/// [`ScreenBuffer::set_cell`].
+#[derive(Debug)]
+pub struct MonoTarget<'a>(&'a mut ScreenBuffer);
+
+/// Draws into a screen buffer in [`Rgb555`], converting to on and off on monocolor buffers like
+/// [`ScreenBuffer::set_pixel_rgb`].
+#[derive(Debug)]
+pub struct RgbTarget<'a>(&'a mut ScreenBuffer);
+
+impl ScreenBuffer {
+    /// An embedded-graphics draw target for drawing in on and off.
+    pub fn mono_target(&mut self) -> MonoTarget<'_> {
+        MonoTarget(self)
+    }
+
+    /// An embedded-graphics draw target for drawing in RGB555.
+    pub fn rgb_target(&mut self) -> RgbTarget<'_> {
+        RgbTarget(self)
+    }
+
+    /// The row and column of a point, if it's on the buffer.
+    fn cell_at(&self, point: embedded_graphics_core::geometry::Point) -> Option<(usize, usize)> {
+        let row = usize::try_from(point.y).ok()?;
+        let col = usize::try_from(point.x).ok()?;
+        (row < self.height && col < self.width).then_some((row, col))
+    }
+
+    fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
+    }
+}
+
+impl OriginDimensions for MonoTarget<'_> {
+    fn size(&self) -> Size {
+        self.0.size()
+    }
+}
+
+impl DrawTarget for MonoTarget<'_> {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        // Pixels off the buffer are dropped rather than failing the whole drawing
+        for Pixel(point, color) in pixels {
+            if let Some((row, col)) = self.0.cell_at(point) {
+                let _ = self.0.set_cell(row, col, color.is_on());
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.0.fill(color.is_on());
+        Ok(())
+    }
+}
+
+impl OriginDimensions for RgbTarget<'_> {
+    fn size(&self) -> Size {
+        self.0.size()
+    }
+}
+
+impl DrawTarget for RgbTarget<'_> {
+    type Color = Rgb555;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some((row, col)) = self.0.cell_at(point) {
+                let _ = self.0.set_pixel_rgb(row, col, to_display_color(color));
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        let color = to_display_color(color);
+        if self.0.fill_rgb(color).is_err() {
+            self.0.fill(super::rgb555_is_lit(color));
+        }
+        Ok(())
+    }
+}
+
+fn to_display_color(color: Rgb555) -> super::Rgb555 {
+    super::Rgb555::new(color.r(), color.g(), color.b())
+}
@@ -0,0 +1,79 @@
+use super::{CompositeDisplay, DisplayConfiguration, PanelLayout};
+use crate::serial::{
+    self, Capabilities, MockDevice, SerialConfig, ShutdownHandle, SyncSerialConnection,
+};
+
+/// A panel on a [`MockDevice`], with a runtime of its own running the device and the connection
+/// to it, for checking anything which draws to a display without the hardware.
+pub struct MockPanel {
+    device: MockDevice,
+    serial_conn: SyncSerialConnection,
+    config: DisplayConfiguration,
+    _shutdown_handle: ShutdownHandle,
+    /// Runs the device and the connection to it, so it's dropped last
+    rt: tokio::runtime::Runtime,
+}
+
+impl MockPanel {
+    /// A panel on a device which reports the legacy capabilities.
+    pub fn new(config: DisplayConfiguration) -> anyhow::Result<Self> {
+        Self::with_capabilities(config, Capabilities::LEGACY, SerialConfig::default())
+    }
+
+    /// A panel on a device which reports `capabilities`, connected to with `serial_config`. The
+    /// capabilities are looked up before this returns, so renders take them into account.
+    pub fn with_capabilities(
+        config: DisplayConfiguration,
+        capabilities: Capabilities,
+        serial_config: SerialConfig,
+    ) -> anyhow::Result<Self> {
+        let rt = tokio::runtime::Runtime::new()?;
+        let (device, transport) = MockDevice::with_capabilities(config.clone(), capabilities);
+        rt.spawn({
+            let device = device.clone();
+            async move { device.run().await }
+        });
+        let (tx, rx) = async_channel::unbounded();
+        let (serial_conn, shutdown_handle, serial_task) =
+            serial::start_transport_task(Box::new(transport), serial_config, tx, rx);
+        rt.spawn(Box::into_pin(serial_task));
+        let serial_conn = SyncSerialConnection::new(serial_conn, rt.handle().clone());
+        serial_conn.get_firmware_info()?;
+        Ok(MockPanel {
+            device,
+            serial_conn,
+            config,
+            _shutdown_handle: shutdown_handle,
+            rt,
+        })
+    }
+
+    /// A display made up of just this panel.
+    pub fn display(&self) -> anyhow::Result<CompositeDisplay> {
+        CompositeDisplay::new(
+            vec![(self.serial_conn.clone(), self.config.clone())],
+            PanelLayout::Horizontal,
+            Default::default(),
+        )
+    }
+
+    /// Waits for the device to have handled everything sent to it so far. Renders only wait for
+    /// rows to be written, so check the device after this rather than straight after rendering.
+    pub fn sync(&self) -> std::io::Result<()> {
+        // The device answers in order, so once it has answered it has handled what came before
+        self.serial_conn.get_display_info().map(|_| ())
+    }
+
+    pub fn device(&self) -> &MockDevice {
+        &self.device
+    }
+
+    pub fn serial_conn(&self) -> &SyncSerialConnection {
+        &self.serial_conn
+    }
+
+    /// The runtime the device and the connection to it run on.
+    pub fn runtime(&self) -> &tokio::runtime::Runtime {
+        &self.rt
+    }
+}
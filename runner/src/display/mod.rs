@@ -0,0 +1,819 @@
+pub use bitmap::DecodedImage;
+pub use blend::AlphaMask;
+pub use color::Rgb555;
+pub use color_correction::{ColorCorrection, DEFAULT_GAMMA};
+pub use composite::{CompositeDisplay, PanelLayout};
+pub use compositor::{Compositor, Layer, LayerId, MonoBlend, Transparency};
+pub use dither::DitherMode;
+#[cfg(feature = "embedded-graphics")]
+pub use graphics::{MonoTarget, RgbTarget};
+pub use megabit_serial_protocol::PixelRepresentation;
+#[cfg(feature = "test-support")]
+pub use mock::MockPanel;
+pub use orientation::{Orientation, Rotation};
+pub use palette_cycle::PaletteCycle;
+pub use region::{BufferRegion, RegionBounds};
+pub use scale::ScaleMapping;
+pub use scroll::ScrollMode;
+pub use shared::SharedScreenBuffer;
+use std::{borrow::Cow, io, ops::Range};
+pub use test_pattern::TestPattern;
+pub use text::{Font, RenderedExtent};
+pub use transition::{Direction, Transition, TransitionKind};
+
+mod bitmap;
+mod blend;
+mod blit;
+mod color;
+mod color_correction;
+mod composite;
+mod compositor;
+mod dither;
+#[cfg(feature = "embedded-graphics")]
+mod graphics;
+#[cfg(feature = "test-support")]
+mod mock;
+mod orientation;
+mod palette_cycle;
+#[cfg(feature = "qr")]
+mod qr;
+mod region;
+mod scale;
+mod scroll;
+mod serialize;
+mod seven_segment;
+mod shapes;
+mod shared;
+#[cfg(feature = "image")]
+mod snapshot;
+mod test_pattern;
+mod text;
+mod transition;
+mod widgets;
+
+#[derive(Debug, Clone)]
+pub struct DisplayConfiguration {
+    pub width: usize,
+    pub height: usize,
+    /// The pixel format the display takes rows in
+    pub pixel_representation: PixelRepresentation,
+    pub orientation: Orientation,
+    /// Roughly the most frames a second the link to the display can carry, if it's known.
+    /// Rendering faster than this backs up the send queues.
+    pub max_fps_hint: Option<f32>,
+}
+
+impl DisplayConfiguration {
+    pub fn is_rgb(&self) -> bool {
+        self.pixel_representation.is_rgb()
+    }
+}
+
+pub const DEFAULT_MONO_PALETTE: MonocolorPalette =
+    MonocolorPalette::new(Rgb555::RED, Rgb555::BLACK);
+
+/// Channel value from which an RGB555 color counts as lit on a monocolor display
+const RGB555_HALF_INTENSITY: u8 = 0x10;
+
+/// Alpha from which a blended color is drawn on a monocolor buffer, where it can't be mixed
+const MONO_ALPHA_THRESHOLD: u8 = 0x80;
+
+#[derive(Debug, Clone)]
+pub struct ScreenBuffer {
+    buffer: ScreenBufferKind,
+    width: usize,
+    height: usize,
+    /// Rows which have changed since they were last sent to the display
+    dirty_rows: Vec<bool>,
+    /// What was last sent to the display, if renders should skip rows which haven't changed
+    shadow: Option<Shadow>,
+    /// How the logical buffer maps onto the physical display
+    orientation: Orientation,
+    /// How colors are converted when drawn on a monocolor buffer
+    dither: DitherMode,
+    /// Applied to RGB rows as they're sent to the display
+    color_correction: Option<ColorCorrection>,
+    /// Rectangle drawing is confined to while drawing through a region
+    clip: Option<RegionBounds>,
+    /// For RGB buffers, whether each pixel was last set on or off through the palette, so
+    /// changing the palette can recolor it. `None` for pixels last set to a color directly, and
+    /// empty for monocolor buffers.
+    palette_cells: Vec<Option<bool>>,
+    /// The pixel format of the display the buffer is shown on. RGB buffers are stored and drawn
+    /// on in RGB555 whatever the display's format, and converted as rows are sent.
+    pixel_representation: PixelRepresentation,
+}
+
+#[derive(Debug, Clone)]
+struct Shadow {
+    contents: ScreenBufferKind,
+    /// Rows whose contents on the display aren't known, e.g. because they haven't been sent yet
+    /// or the device has restarted since
+    is_stale: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonocolorPalette {
+    on: Rgb555,
+    off: Rgb555,
+}
+
+impl MonocolorPalette {
+    pub const fn new(on: Rgb555, off: Rgb555) -> Self {
+        Self { on, off }
+    }
+
+    pub fn from_on_color(color: Rgb555) -> Self {
+        Self {
+            on: color,
+            off: Rgb555::BLACK,
+        }
+    }
+
+    /// A palette from two colors with eight bits per channel.
+    pub const fn from_rgb888(on: (u8, u8, u8), off: (u8, u8, u8)) -> Self {
+        Self {
+            on: Rgb555::from_rgb888(on.0, on.1, on.2),
+            off: Rgb555::from_rgb888(off.0, off.1, off.2),
+        }
+    }
+
+    pub fn on(&self) -> Rgb555 {
+        self.on
+    }
+
+    pub fn off(&self) -> Rgb555 {
+        self.off
+    }
+
+    /// The same palette with the on and off colors swapped.
+    pub fn inverted(&self) -> Self {
+        Self {
+            on: self.off,
+            off: self.on,
+        }
+    }
+
+    /// The raw color a pixel in the given state is stored as.
+    fn color(&self, is_lit: bool) -> u16 {
+        if is_lit {
+            self.on.raw()
+        } else {
+            self.off.raw()
+        }
+    }
+}
+
+/// The value of a single pixel, in the format of the buffer it came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelValue {
+    Mono(bool),
+    Rgb(Rgb555),
+}
+
+#[derive(Debug, Clone)]
+pub enum ScreenBufferKind {
+    Monocolor(Vec<bool>),
+    Rgb555(Vec<u16>, MonocolorPalette),
+}
+
+impl ScreenBuffer {
+    pub fn new(width: usize, height: usize, rgb_monocolor: Option<MonocolorPalette>) -> Self {
+        ScreenBuffer {
+            buffer: if let Some(palette) = rgb_monocolor {
+                ScreenBufferKind::Rgb555(vec![0u16; width * height], palette)
+            } else {
+                ScreenBufferKind::Monocolor(vec![false; width * height])
+            },
+            width,
+            height,
+            dirty_rows: vec![false; height],
+            shadow: None,
+            orientation: Orientation::default(),
+            dither: DitherMode::default(),
+            color_correction: None,
+            clip: None,
+            palette_cells: if rgb_monocolor.is_some() {
+                vec![None; width * height]
+            } else {
+                vec![]
+            },
+            pixel_representation: if rgb_monocolor.is_some() {
+                PixelRepresentation::RGB555
+            } else {
+                PixelRepresentation::Monocolor
+            },
+        }
+    }
+
+    /// A buffer for a physical display of the given size mounted in the given orientation. Its
+    /// width and height are swapped from the physical display's when rotated by 90 or 270
+    /// degrees.
+    pub fn with_orientation(
+        physical_width: usize,
+        physical_height: usize,
+        rgb_monocolor: Option<MonocolorPalette>,
+        orientation: Orientation,
+    ) -> Self {
+        let (width, height) = orientation.logical_size(physical_width, physical_height);
+        ScreenBuffer {
+            orientation,
+            ..ScreenBuffer::new(width, height, rgb_monocolor)
+        }
+    }
+
+    pub fn is_rgb(&self) -> bool {
+        matches!(self.buffer, ScreenBufferKind::Rgb555(_, _))
+    }
+
+    pub fn display_config(&self) -> DisplayConfiguration {
+        DisplayConfiguration {
+            width: self.width,
+            height: self.height,
+            pixel_representation: self.pixel_representation,
+            orientation: self.orientation,
+            max_fps_hint: None,
+        }
+    }
+
+    pub fn pixel_representation(&self) -> PixelRepresentation {
+        self.pixel_representation
+    }
+
+    /// Changes the pixel format the buffer's display takes rows in, e.g. to RGB565 for a display
+    /// which reported it. Fails if the format is RGB and the buffer isn't, or the other way
+    /// around. Every row is sent again at the next render since the display may have been
+    /// showing them in the old format.
+    pub fn set_pixel_representation(
+        &mut self,
+        pixel_representation: PixelRepresentation,
+    ) -> io::Result<()> {
+        if pixel_representation.is_rgb() != self.is_rgb() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        if pixel_representation != self.pixel_representation {
+            self.pixel_representation = pixel_representation;
+            self.force_full_render();
+        }
+        Ok(())
+    }
+
+    /// Changes the buffer's logical size. With `preserve` set, whatever had been drawn where the
+    /// old and new sizes overlap is kept in the top left and the rest of the buffer is blank as if
+    /// cleared, otherwise the whole buffer is blank. Every row is sent at the next render, and
+    /// drawing through a region ends since it may no longer fit.
+    pub fn resize(&mut self, width: usize, height: usize, preserve: bool) {
+        let mut buffer = match &self.buffer {
+            ScreenBufferKind::Monocolor(_) => {
+                ScreenBufferKind::Monocolor(vec![false; width * height])
+            }
+            ScreenBufferKind::Rgb555(_, palette) => {
+                ScreenBufferKind::Rgb555(vec![palette.color(false); width * height], *palette)
+            }
+        };
+        let mut palette_cells = if self.is_rgb() {
+            vec![Some(false); width * height]
+        } else {
+            vec![]
+        };
+        if preserve {
+            let kept_width = width.min(self.width);
+            for row in 0..height.min(self.height) {
+                let (from, to) = (row * self.width, row * width);
+                let (from, to) = (from..from + kept_width, to..to + kept_width);
+                buffer.copy_range_from(&self.buffer, from.clone(), to.clone());
+                if !palette_cells.is_empty() {
+                    palette_cells[to].copy_from_slice(&self.palette_cells[from]);
+                }
+            }
+        }
+
+        self.buffer = buffer;
+        self.palette_cells = palette_cells;
+        self.width = width;
+        self.height = height;
+        self.dirty_rows = vec![true; height];
+        self.clip = None;
+        if let Some(shadow) = &mut self.shadow {
+            *shadow = Shadow {
+                contents: self.buffer.clone(),
+                is_stale: vec![true; height],
+            };
+        }
+    }
+
+    /// The width and height of the physical display the buffer is shown on.
+    pub fn physical_size(&self) -> (usize, usize) {
+        if self.orientation.swaps_dimensions() {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+
+    /// The physical rows which need to be sent to show the given logical rows, in order.
+    pub fn physical_rows(&self, rows: &[u8]) -> Vec<usize> {
+        let (_, physical_height) = self.physical_size();
+        let mut physical_rows = rows
+            .iter()
+            .filter(|&&row| usize::from(row) < self.height)
+            .flat_map(|&row| {
+                self.orientation
+                    .physical_rows(usize::from(row), physical_height)
+            })
+            .collect::<Vec<_>>();
+        physical_rows.sort_unstable();
+        physical_rows.dedup();
+        physical_rows
+    }
+
+    /// Changes the colors on and off pixels are drawn in on an RGB buffer. Pixels which were
+    /// set on or off are recolored and their rows marked dirty, while pixels set to a color
+    /// directly are left alone. Fails on a monocolor buffer.
+    pub fn set_palette(&mut self, palette: MonocolorPalette) -> io::Result<()> {
+        let ScreenBufferKind::Rgb555(buffer, current_palette) = &mut self.buffer else {
+            return Err(io::ErrorKind::InvalidData.into());
+        };
+        *current_palette = palette;
+        for (index, is_lit) in self.palette_cells.iter().enumerate() {
+            let Some(is_lit) = is_lit else {
+                continue;
+            };
+            let color = palette.color(*is_lit);
+            if buffer[index] != color {
+                buffer[index] = color;
+                self.dirty_rows[index / self.width] = true;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn palette(&self) -> Option<MonocolorPalette> {
+        match &self.buffer {
+            ScreenBufferKind::Rgb555(_, palette) => Some(*palette),
+            ScreenBufferKind::Monocolor(_) => None,
+        }
+    }
+
+    pub fn dither_mode(&self) -> DitherMode {
+        self.dither
+    }
+
+    /// Changes how colors drawn from now on are converted on a monocolor buffer. Has no effect
+    /// on RGB buffers.
+    pub fn set_dither_mode(&mut self, mode: DitherMode) {
+        self.dither = mode;
+    }
+
+    pub fn color_correction(&self) -> Option<ColorCorrection> {
+        self.color_correction
+    }
+
+    /// Changes the correction applied to RGB rows sent to the display, without touching the
+    /// colors apps read back. Every row is marked for sending again since what the display
+    /// should show has changed.
+    pub fn set_color_correction(&mut self, color_correction: Option<ColorCorrection>) {
+        if self.color_correction != color_correction {
+            self.color_correction = color_correction;
+            self.force_full_render();
+        }
+    }
+
+    pub fn set_cell(&mut self, row: usize, col: usize, value: bool) -> io::Result<()> {
+        if row >= self.height || col >= self.width {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+
+        let index = row * self.width + col;
+        match &mut self.buffer {
+            ScreenBufferKind::Monocolor(ref mut buffer) => {
+                buffer[index] = value;
+            }
+            ScreenBufferKind::Rgb555(ref mut buffer, palette) => {
+                buffer[index] = palette.color(value);
+                self.palette_cells[index] = Some(value);
+            }
+        }
+        self.dirty_rows[row] = true;
+
+        Ok(())
+    }
+
+    /// Sets a pixel to an RGB555 color. On a monocolor buffer the color is converted to on or
+    /// off according to the dither mode, so apps can draw in color without checking what kind of
+    /// display they're on.
+    pub fn set_pixel_rgb(&mut self, row: usize, col: usize, color: Rgb555) -> io::Result<()> {
+        if row >= self.height || col >= self.width {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+
+        let index = row * self.width + col;
+        match &mut self.buffer {
+            ScreenBufferKind::Monocolor(ref mut buffer) => {
+                buffer[index] = dither::pixel_is_lit(self.dither, color, row as i64, col as i64);
+            }
+            ScreenBufferKind::Rgb555(ref mut buffer, _) => {
+                buffer[index] = color.raw();
+                self.palette_cells[index] = None;
+            }
+        }
+        self.dirty_rows[row] = true;
+
+        Ok(())
+    }
+
+    /// Copies a `width` by `height` rectangle of RGB555 colors, given row by row, to the buffer
+    /// with its top left corner at (`x`, `y`). Parts of the rectangle which fall outside the
+    /// buffer, or the region being drawn through, are dropped. Monocolor buffers dither the
+    /// region as a whole according to the dither mode.
+    pub fn write_region_rgb(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        colors: &[Rgb555],
+    ) -> io::Result<()> {
+        if colors.len() != width * height {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+
+        let bounds = self.clip_bounds();
+        let visible_width = width.min(bounds.right().saturating_sub(x));
+        let visible_height = height.min(bounds.bottom().saturating_sub(y));
+        if visible_width == 0 {
+            return Ok(());
+        }
+        // The whole region is dithered at once, including what's clipped, so error diffusion
+        // sees every neighbour
+        let is_lit = match &self.buffer {
+            ScreenBufferKind::Monocolor(_) => {
+                dither::region_is_lit(self.dither, colors, width, (x as i64, y as i64))
+            }
+            ScreenBufferKind::Rgb555(_, _) => vec![],
+        };
+        for region_row in 0..visible_height {
+            let start = (y + region_row) * self.width + x;
+            let src_start = region_row * width;
+            match &mut self.buffer {
+                ScreenBufferKind::Monocolor(buffer) => buffer[start..start + visible_width]
+                    .copy_from_slice(&is_lit[src_start..src_start + visible_width]),
+                ScreenBufferKind::Rgb555(buffer, _) => {
+                    buffer[start..start + visible_width]
+                        .iter_mut()
+                        .zip(&colors[src_start..src_start + visible_width])
+                        .for_each(|(pixel, color)| *pixel = color.raw());
+                    self.palette_cells[start..start + visible_width].fill(None);
+                }
+            }
+            self.dirty_rows[y + region_row] = true;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_pixel(&self, row: usize, col: usize) -> io::Result<PixelValue> {
+        if row >= self.height || col >= self.width {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+
+        let index = row * self.width + col;
+        Ok(match &self.buffer {
+            ScreenBufferKind::Monocolor(buffer) => PixelValue::Mono(buffer[index]),
+            ScreenBufferKind::Rgb555(buffer, _) => PixelValue::Rgb(Rgb555::from_raw(buffer[index])),
+        })
+    }
+
+    /// Turns every pixel off, or sets it to the off color of the palette for RGB buffers.
+    pub fn clear(&mut self) {
+        self.fill(false);
+    }
+
+    /// Sets every pixel to the same state, going through the palette for RGB buffers like
+    /// [`ScreenBuffer::set_cell`].
+    pub fn fill(&mut self, value: bool) {
+        match &mut self.buffer {
+            ScreenBufferKind::Monocolor(buffer) => buffer.fill(value),
+            ScreenBufferKind::Rgb555(buffer, palette) => {
+                buffer.fill(palette.color(value));
+                self.palette_cells.fill(Some(value));
+            }
+        }
+        self.dirty_rows.fill(true);
+    }
+
+    /// Sets every pixel of an RGB buffer to the same color.
+    pub fn fill_rgb(&mut self, color: Rgb555) -> io::Result<()> {
+        match &mut self.buffer {
+            ScreenBufferKind::Rgb555(buffer, _) => {
+                buffer.fill(color.raw());
+                self.palette_cells.fill(None);
+                self.dirty_rows.fill(true);
+                Ok(())
+            }
+            ScreenBufferKind::Monocolor(_) => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+
+    /// The rows which have changed since the last call to [`ScreenBuffer::clear_dirty`].
+    pub fn dirty_rows(&self) -> Vec<u8> {
+        self.dirty_rows
+            .iter()
+            .enumerate()
+            .filter(|(_, is_dirty)| **is_dirty)
+            .filter_map(|(row, _)| u8::try_from(row).ok())
+            .collect()
+    }
+
+    /// Marks every row as matching what's on the display.
+    pub fn clear_dirty(&mut self) {
+        self.dirty_rows.fill(false);
+    }
+
+    /// Keeps a copy of what was last sent to the display so renders can skip rows which haven't
+    /// actually changed, at the cost of a second buffer.
+    pub fn set_shadow_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.shadow = None;
+        } else if self.shadow.is_none() {
+            self.shadow = Some(Shadow {
+                contents: self.buffer.clone(),
+                is_stale: vec![true; self.height],
+            });
+        }
+    }
+
+    /// The subset of `rows` which differ from what was last sent to the display. Without a
+    /// shadow buffer every row counts as changed.
+    pub fn changed_rows(&self, rows: &[u8]) -> Vec<u8> {
+        let Some(shadow) = &self.shadow else {
+            return rows.to_vec();
+        };
+        rows.iter()
+            .copied()
+            .filter(|&row| {
+                let row = row as usize;
+                row < self.height
+                    && (shadow.is_stale[row]
+                        || !self.buffer.row_eq(&shadow.contents, self.row_range(row)))
+            })
+            .collect()
+    }
+
+    /// Records that `rows` now match what's on the display.
+    pub fn mark_sent(&mut self, rows: &[u8]) {
+        for row in rows.iter().map(|&row| row as usize) {
+            if row >= self.height {
+                continue;
+            }
+            self.dirty_rows[row] = false;
+            let range = self.row_range(row);
+            if let Some(shadow) = &mut self.shadow {
+                shadow.contents.copy_row_from(&self.buffer, range);
+                shadow.is_stale[row] = false;
+            }
+        }
+    }
+
+    /// Forgets what's on the display and marks every row as dirty so the next render sends
+    /// everything, e.g. after the device has reconnected and lost its state.
+    pub fn force_full_render(&mut self) {
+        if let Some(shadow) = &mut self.shadow {
+            shadow.is_stale.fill(true);
+        }
+        self.dirty_rows.fill(true);
+    }
+
+    fn row_range(&self, row: usize) -> Range<usize> {
+        row * self.width..(row + 1) * self.width
+    }
+
+    /// Borrows a row of a monocolor buffer.
+    pub fn row_bits(&self, row_number: usize) -> io::Result<&[bool]> {
+        if row_number >= self.height {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+
+        match &self.buffer {
+            ScreenBufferKind::Monocolor(buffer) => {
+                let start_idx = row_number * self.width;
+                let end_idx = (row_number + 1) * self.width;
+                Ok(&buffer[start_idx..end_idx])
+            }
+            ScreenBufferKind::Rgb555(_, _) => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+
+    /// Borrows a row of an RGB buffer.
+    pub fn row_slice(&self, row_number: usize) -> io::Result<&[u16]> {
+        if row_number >= self.height {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+
+        match &self.buffer {
+            ScreenBufferKind::Rgb555(buffer, _) => {
+                let start_idx = row_number * self.width;
+                let end_idx = (row_number + 1) * self.width;
+                Ok(&buffer[start_idx..end_idx])
+            }
+            ScreenBufferKind::Monocolor(_) => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+
+    /// Borrows every row of a monocolor buffer in order, alongside its row number. Fails on an
+    /// RGB buffer like [`ScreenBuffer::row_bits`].
+    pub fn rows(&self) -> io::Result<impl Iterator<Item = (usize, &[bool])> + '_> {
+        match &self.buffer {
+            ScreenBufferKind::Monocolor(buffer) => {
+                Ok((0..self.height).map(move |row| (row, &buffer[self.row_range(row)])))
+            }
+            ScreenBufferKind::Rgb555(_, _) => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+
+    /// Borrows every row of an RGB buffer in order, alongside its row number. Fails on a
+    /// monocolor buffer like [`ScreenBuffer::row_slice`].
+    pub fn rows_rgb(&self) -> io::Result<impl Iterator<Item = (usize, &[u16])> + '_> {
+        match &self.buffer {
+            ScreenBufferKind::Rgb555(buffer, _) => {
+                Ok((0..self.height).map(move |row| (row, &buffer[self.row_range(row)])))
+            }
+            ScreenBufferKind::Monocolor(_) => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+
+    /// A row of a monocolor buffer as it appears on the physical display, which is only copied
+    /// if the display isn't mounted the way apps draw on it.
+    pub fn physical_row_bits(&self, row_number: usize) -> io::Result<Cow<'_, [bool]>> {
+        if self.orientation.is_identity() {
+            return self.row_bits(row_number).map(Cow::Borrowed);
+        }
+        match &self.buffer {
+            ScreenBufferKind::Monocolor(buffer) => {
+                Ok(Cow::Owned(self.remap_physical_row(buffer, row_number)?))
+            }
+            ScreenBufferKind::Rgb555(_, _) => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+
+    /// A row of an RGB buffer as it should be sent to the physical display, which is only copied
+    /// if the display isn't mounted the way apps draw on it or the colors need correcting.
+    pub fn physical_row_slice(&self, row_number: usize) -> io::Result<Cow<'_, [u16]>> {
+        let row = if self.orientation.is_identity() {
+            Cow::Borrowed(self.row_slice(row_number)?)
+        } else {
+            match &self.buffer {
+                ScreenBufferKind::Rgb555(buffer, _) => {
+                    Cow::Owned(self.remap_physical_row(buffer, row_number)?)
+                }
+                ScreenBufferKind::Monocolor(_) => return Err(io::ErrorKind::InvalidData.into()),
+            }
+        };
+        Ok(match &self.color_correction {
+            Some(color_correction) => Cow::Owned(
+                row.iter()
+                    .map(|&color| color_correction.apply(Rgb555::from_raw(color)).raw())
+                    .collect(),
+            ),
+            None => row,
+        })
+    }
+
+    pub fn get_physical_row(&self, row_number: usize) -> io::Result<Vec<bool>> {
+        self.physical_row_bits(row_number).map(Cow::into_owned)
+    }
+
+    pub fn get_physical_row_rgb(&self, row_number: usize) -> io::Result<Vec<u16>> {
+        self.physical_row_slice(row_number).map(Cow::into_owned)
+    }
+
+    fn remap_physical_row<T: Copy>(&self, buffer: &[T], row_number: usize) -> io::Result<Vec<T>> {
+        let physical_size = self.physical_size();
+        if row_number >= physical_size.1 {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        Ok((0..physical_size.0)
+            .map(|col| {
+                let (row, col) = self
+                    .orientation
+                    .logical_position((row_number, col), physical_size);
+                buffer[row * self.width + col]
+            })
+            .collect())
+    }
+
+    pub fn get_row(&self, row_number: usize) -> io::Result<Vec<bool>> {
+        self.row_bits(row_number).map(<[bool]>::to_vec)
+    }
+
+    pub fn get_row_rgb(&self, row_number: usize) -> io::Result<Vec<u16>> {
+        self.row_slice(row_number).map(<[u16]>::to_vec)
+    }
+}
+
+impl ScreenBufferKind {
+    fn row_eq(&self, other: &ScreenBufferKind, range: Range<usize>) -> bool {
+        match (self, other) {
+            (ScreenBufferKind::Monocolor(a), ScreenBufferKind::Monocolor(b)) => {
+                a[range.clone()] == b[range]
+            }
+            (ScreenBufferKind::Rgb555(a, _), ScreenBufferKind::Rgb555(b, _)) => {
+                a[range.clone()] == b[range]
+            }
+            _ => false,
+        }
+    }
+
+    fn copy_row_from(&mut self, other: &ScreenBufferKind, range: Range<usize>) {
+        self.copy_range_from(other, range.clone(), range);
+    }
+
+    /// Copies the pixels in `from` of another buffer of the same kind into `to` of this one.
+    fn copy_range_from(&mut self, other: &ScreenBufferKind, from: Range<usize>, to: Range<usize>) {
+        match (self, other) {
+            (ScreenBufferKind::Monocolor(a), ScreenBufferKind::Monocolor(b)) => {
+                a[to].copy_from_slice(&b[from])
+            }
+            (ScreenBufferKind::Rgb555(a, _), ScreenBufferKind::Rgb555(b, _)) => {
+                a[to].copy_from_slice(&b[from])
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether an RGB555 color counts as lit on a monocolor display, which is when any of its red,
+/// green, or blue channels is at least half intensity.
+fn rgb555_is_lit(color: Rgb555) -> bool {
+    [color.red(), color.green(), color.blue()]
+        .into_iter()
+        .any(|channel| channel >= RGB555_HALF_INTENSITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draws the buffer a row to a line, with `#` for lit pixels and `.` for unlit ones, to
+    /// compare against a picture of what it should look like. Pixels of RGB buffers are lit
+    /// unless they're black.
+    pub(super) fn picture(buffer: &ScreenBuffer) -> Vec<String> {
+        (0..buffer.height)
+            .map(|row| {
+                (0..buffer.width)
+                    .map(|col| match buffer.get_pixel(row, col).unwrap() {
+                        PixelValue::Mono(true) => '#',
+                        PixelValue::Rgb(color) if color != Rgb555::BLACK => '#',
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rows_are_borrowed_from_buffers_of_their_kind() {
+        let mut mono = ScreenBuffer::new(4, 2, None);
+        mono.set_cell(1, 2, true).unwrap();
+        assert_eq!(mono.row_bits(1).unwrap(), [false, false, true, false]);
+        assert_eq!(mono.rows().unwrap().count(), 2);
+
+        let mut rgb = ScreenBuffer::new(4, 2, Some(DEFAULT_MONO_PALETTE));
+        rgb.set_pixel_rgb(0, 3, Rgb555::WHITE).unwrap();
+        assert_eq!(rgb.row_slice(0).unwrap()[3], Rgb555::WHITE.raw());
+        assert_eq!(rgb.rows_rgb().unwrap().count(), 2);
+    }
+
+    #[test]
+    fn rows_of_the_wrong_kind_are_refused() {
+        let mono = ScreenBuffer::new(4, 2, None);
+        let rgb = ScreenBuffer::new(4, 2, Some(DEFAULT_MONO_PALETTE));
+        for err in [
+            mono.row_slice(0).unwrap_err(),
+            mono.rows_rgb().err().unwrap(),
+            rgb.row_bits(0).unwrap_err(),
+            rgb.rows().err().unwrap(),
+        ] {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rows_past_the_bottom_are_refused() {
+        let mono = ScreenBuffer::new(4, 2, None);
+        let rgb = ScreenBuffer::new(4, 2, Some(DEFAULT_MONO_PALETTE));
+        assert_eq!(
+            mono.row_bits(2).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            rgb.row_slice(2).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        // Out of range takes priority over the wrong kind
+        assert_eq!(
+            rgb.row_bits(2).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
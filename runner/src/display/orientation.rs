@@ -0,0 +1,175 @@
+/// How far the image the app draws is turned clockwise to get it onto the physical display
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+/// How the physical display is mounted relative to the way apps draw on it. The rotation is
+/// applied first and the mirroring after, both in the physical display's coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    /// Swap the left and right of the physical display
+    pub mirror_horizontal: bool,
+    /// Swap the top and bottom of the physical display
+    pub mirror_vertical: bool,
+}
+
+impl Orientation {
+    pub fn is_identity(&self) -> bool {
+        *self == Orientation::default()
+    }
+
+    /// Whether the logical display is as wide as the physical display is tall.
+    pub fn swaps_dimensions(&self) -> bool {
+        matches!(self.rotation, Rotation::Rotate90 | Rotation::Rotate270)
+    }
+
+    /// The size apps draw at on a physical display of the given size.
+    pub fn logical_size(&self, physical_width: usize, physical_height: usize) -> (usize, usize) {
+        if self.swaps_dimensions() {
+            (physical_height, physical_width)
+        } else {
+            (physical_width, physical_height)
+        }
+    }
+
+    /// The row and column of the logical pixel shown at a physical row and column, given the
+    /// physical display's width and height.
+    pub fn logical_position(
+        &self,
+        (row, col): (usize, usize),
+        (width, height): (usize, usize),
+    ) -> (usize, usize) {
+        let row = if self.mirror_vertical {
+            height - 1 - row
+        } else {
+            row
+        };
+        let col = if self.mirror_horizontal {
+            width - 1 - col
+        } else {
+            col
+        };
+        match self.rotation {
+            Rotation::Rotate0 => (row, col),
+            Rotation::Rotate90 => (width - 1 - col, row),
+            Rotation::Rotate180 => (height - 1 - row, width - 1 - col),
+            Rotation::Rotate270 => (col, height - 1 - row),
+        }
+    }
+
+    /// The physical rows which show part of a logical row, given the physical display's height.
+    /// Rotating by 90 or 270 degrees turns each logical row into a physical column, which
+    /// touches every physical row.
+    pub fn physical_rows(&self, row: usize, height: usize) -> std::ops::Range<usize> {
+        let row = match self.rotation {
+            Rotation::Rotate0 => row,
+            Rotation::Rotate180 => height - 1 - row,
+            Rotation::Rotate90 | Rotation::Rotate270 => return 0..height,
+        };
+        let row = if self.mirror_vertical {
+            height - 1 - row
+        } else {
+            row
+        };
+        row..row + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ScreenBuffer;
+    use super::*;
+
+    /// Where a pixel lit at row 0, column 1 of what the app draws ends up on a physical display
+    /// 4 pixels wide and 3 tall.
+    fn physical_position(orientation: Orientation) -> Vec<(usize, usize)> {
+        let mut buffer = ScreenBuffer::with_orientation(4, 3, None, orientation);
+        buffer.set_cell(0, 1, true).unwrap();
+        (0..3)
+            .flat_map(|row| {
+                let lit = buffer.get_physical_row(row).unwrap();
+                (0..4)
+                    .filter(move |&col| lit[col])
+                    .map(move |col| (row, col))
+            })
+            .collect()
+    }
+
+    fn rotated(rotation: Rotation) -> Orientation {
+        Orientation {
+            rotation,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pixels_are_rotated_onto_the_physical_display() {
+        assert_eq!(physical_position(rotated(Rotation::Rotate0)), [(0, 1)]);
+        assert_eq!(physical_position(rotated(Rotation::Rotate90)), [(1, 3)]);
+        assert_eq!(physical_position(rotated(Rotation::Rotate180)), [(2, 2)]);
+        assert_eq!(physical_position(rotated(Rotation::Rotate270)), [(1, 0)]);
+    }
+
+    #[test]
+    fn pixels_are_mirrored_after_being_rotated() {
+        let mirrored = |rotation, mirror_horizontal, mirror_vertical| Orientation {
+            rotation,
+            mirror_horizontal,
+            mirror_vertical,
+        };
+        assert_eq!(
+            physical_position(mirrored(Rotation::Rotate0, true, false)),
+            [(0, 2)]
+        );
+        assert_eq!(
+            physical_position(mirrored(Rotation::Rotate0, false, true)),
+            [(2, 1)]
+        );
+        assert_eq!(
+            physical_position(mirrored(Rotation::Rotate0, true, true)),
+            [(2, 2)]
+        );
+        assert_eq!(
+            physical_position(mirrored(Rotation::Rotate90, true, false)),
+            [(1, 0)]
+        );
+        assert_eq!(
+            physical_position(mirrored(Rotation::Rotate270, false, true)),
+            [(1, 0)]
+        );
+    }
+
+    #[test]
+    fn quarter_turns_swap_the_size_apps_see() {
+        for (rotation, size) in [
+            (Rotation::Rotate0, (4, 3)),
+            (Rotation::Rotate90, (3, 4)),
+            (Rotation::Rotate180, (4, 3)),
+            (Rotation::Rotate270, (3, 4)),
+        ] {
+            let buffer = ScreenBuffer::with_orientation(4, 3, None, rotated(rotation));
+            let config = buffer.display_config();
+            assert_eq!((config.width, config.height), size, "{rotation:?}");
+            assert_eq!(buffer.physical_size(), (4, 3), "{rotation:?}");
+        }
+    }
+
+    #[test]
+    fn logical_rows_are_sent_as_the_physical_rows_showing_them() {
+        let buffer = ScreenBuffer::with_orientation(4, 3, None, rotated(Rotation::Rotate180));
+        assert_eq!(buffer.physical_rows(&[0, 2]), [0, 2]);
+        assert_eq!(buffer.physical_rows(&[0]), [2]);
+        // Rows past the bottom are left out
+        assert_eq!(buffer.physical_rows(&[1, 3]), [1]);
+
+        // A logical row is a physical column once turned a quarter
+        let buffer = ScreenBuffer::with_orientation(4, 3, None, rotated(Rotation::Rotate90));
+        assert_eq!(buffer.physical_rows(&[0]), [0, 1, 2]);
+    }
+}
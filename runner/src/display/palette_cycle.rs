@@ -0,0 +1,46 @@
+use super::MonocolorPalette;
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+/// Steps through a list of palettes on a timer, for simple color effects on apps which only
+/// draw pixels on and off
+#[derive(Debug, Clone)]
+pub struct PaletteCycle {
+    palettes: Vec<MonocolorPalette>,
+    period: Duration,
+    /// Index of the palette which is currently showing
+    current: usize,
+    last_change: Instant,
+}
+
+impl PaletteCycle {
+    /// Starts on the first palette. Fails if there are no palettes or the period is zero.
+    pub fn new(palettes: Vec<MonocolorPalette>, period: Duration) -> io::Result<Self> {
+        if palettes.is_empty() || period.is_zero() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        Ok(PaletteCycle {
+            palettes,
+            period,
+            current: 0,
+            last_change: Instant::now(),
+        })
+    }
+
+    pub fn current(&self) -> MonocolorPalette {
+        self.palettes[self.current]
+    }
+
+    /// The next palette, if a whole period has passed since the last change. Periods which were
+    /// missed entirely are skipped rather than caught up on.
+    pub fn poll(&mut self, now: Instant) -> Option<MonocolorPalette> {
+        if now.saturating_duration_since(self.last_change) < self.period {
+            return None;
+        }
+        self.last_change = now;
+        self.current = (self.current + 1) % self.palettes.len();
+        Some(self.current())
+    }
+}
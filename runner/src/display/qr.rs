@@ -0,0 +1,472 @@
+use super::ScreenBuffer;
+use std::io;
+
+/// Light modules drawn around the symbol, which scanners need to find its edges
+const QUIET_ZONE: usize = 4;
+
+/// Largest version of symbol drawn, which is 57 modules across
+const MAX_VERSION: usize = 10;
+
+/// For each version, the number of codewords in the symbol and how many blocks they're split
+/// into, at error correction level M. Each block has the same number of error correction
+/// codewords, given last.
+const CODEWORDS_M: [(usize, usize, usize); MAX_VERSION] = [
+    (26, 1, 10),
+    (44, 1, 16),
+    (70, 1, 26),
+    (100, 2, 18),
+    (134, 2, 24),
+    (172, 4, 16),
+    (196, 4, 18),
+    (242, 4, 22),
+    (292, 5, 22),
+    (346, 5, 26),
+];
+
+/// Format bits for error correction level M
+const LEVEL_M: u32 = 0b00;
+
+/// The modules of a QR code, `true` for dark
+struct QrSymbol {
+    size: usize,
+    modules: Vec<bool>,
+    /// Modules which are part of the fixed patterns rather than the data
+    is_function: Vec<bool>,
+}
+
+impl ScreenBuffer {
+    /// Draws `data` as a QR code at error correction level M with its top left corner, including
+    /// the four module quiet zone around it, at (`x`, `y`), each module drawn `module_size`
+    /// pixels square. The smallest version of symbol which holds the data is used, and nothing
+    /// is drawn if it doesn't fit on the buffer.
+    ///
+    /// Dark modules are drawn unlit and light modules and the quiet zone lit, as a camera sees
+    /// a lit LED as light. RGB buffers draw them in the palette's off and on colors.
+    pub fn draw_qr(&mut self, x: usize, y: usize, data: &str, module_size: u32) -> io::Result<()> {
+        if module_size == 0 {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        let symbol = QrSymbol::encode(data.as_bytes())?;
+        let module_size = module_size as usize;
+        let extent = (symbol.size + 2 * QUIET_ZONE) * module_size;
+        let bounds = self.clip_bounds();
+        if x < bounds.x
+            || y < bounds.y
+            || x + extent > bounds.right()
+            || y + extent > bounds.bottom()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "A {extent}x{extent} QR code with its quiet zone doesn't fit there on the \
+                     {}x{} display",
+                    bounds.width, bounds.height
+                ),
+            ));
+        }
+
+        for module_row in 0..symbol.size + 2 * QUIET_ZONE {
+            for module_col in 0..symbol.size + 2 * QUIET_ZONE {
+                let is_dark = symbol.is_dark(
+                    module_row as isize - QUIET_ZONE as isize,
+                    module_col as isize - QUIET_ZONE as isize,
+                );
+                for row in y + module_row * module_size..y + (module_row + 1) * module_size {
+                    for col in x + module_col * module_size..x + (module_col + 1) * module_size {
+                        self.set_cell(row, col, !is_dark)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl QrSymbol {
+    /// Encodes the data in byte mode in the smallest version which holds it.
+    fn encode(data: &[u8]) -> io::Result<Self> {
+        let (version, codewords) = (1..=MAX_VERSION)
+            .find_map(|version| Some((version, data_codewords(data, version)?)))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} bytes is too much data for a version {MAX_VERSION} QR code",
+                        data.len()
+                    ),
+                )
+            })?;
+        let codewords = add_error_correction(&codewords[..], version);
+
+        let size = 17 + 4 * version;
+        let mut symbol = QrSymbol {
+            size,
+            modules: vec![false; size * size],
+            is_function: vec![false; size * size],
+        };
+        symbol.draw_function_patterns(version);
+        symbol.draw_codewords(&codewords[..]);
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                symbol.apply_mask(mask);
+                symbol.draw_format_bits(mask);
+                let penalty = symbol.penalty();
+                symbol.apply_mask(mask);
+                penalty
+            })
+            .unwrap_or(0);
+        symbol.apply_mask(mask);
+        symbol.draw_format_bits(mask);
+        Ok(symbol)
+    }
+
+    /// Whether the module is dark, with everything off the symbol being light.
+    fn is_dark(&self, row: isize, col: isize) -> bool {
+        let (Ok(row), Ok(col)) = (usize::try_from(row), usize::try_from(col)) else {
+            return false;
+        };
+        row < self.size && col < self.size && self.modules[row * self.size + col]
+    }
+
+    fn set_function(&mut self, row: usize, col: usize, is_dark: bool) {
+        let index = row * self.size + col;
+        self.modules[index] = is_dark;
+        self.is_function[index] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        for index in 0..size {
+            self.set_function(6, index, index.is_multiple_of(2));
+            self.set_function(index, 6, index.is_multiple_of(2));
+        }
+
+        // Finder patterns along with the light separators around them
+        for (center_row, center_col) in [(3, 3), (3, size - 4), (size - 4, 3)] {
+            for dy in -4isize..=4 {
+                for dx in -4isize..=4 {
+                    let (Some(row), Some(col)) = (
+                        center_row.checked_add_signed(dy).filter(|&row| row < size),
+                        center_col.checked_add_signed(dx).filter(|&col| col < size),
+                    ) else {
+                        continue;
+                    };
+                    let distance = dy.abs().max(dx.abs());
+                    self.set_function(row, col, distance != 2 && distance != 4);
+                }
+            }
+        }
+
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &center_row) in positions.iter().enumerate() {
+            for (j, &center_col) in positions.iter().enumerate() {
+                // Those which would overlap the finder patterns are left out
+                if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                    continue;
+                }
+                for dy in -2isize..=2 {
+                    for dx in -2isize..=2 {
+                        self.set_function(
+                            center_row.saturating_add_signed(dy),
+                            center_col.saturating_add_signed(dx),
+                            dy.abs().max(dx.abs()) != 1,
+                        );
+                    }
+                }
+            }
+        }
+
+        // Reserves the format areas, which are filled in once the mask has been picked
+        self.draw_format_bits(0);
+
+        if version >= 7 {
+            let mut remainder = version as u32;
+            for _ in 0..12 {
+                remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1f25);
+            }
+            let bits = (version as u32) << 12 | remainder;
+            for i in 0..18 {
+                let is_dark = (bits >> i) & 1 != 0;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set_function(b, a, is_dark);
+                self.set_function(a, b, is_dark);
+            }
+        }
+    }
+
+    /// Draws both copies of the format bits for the mask, along with the dark module which
+    /// always sits next to the second copy.
+    fn draw_format_bits(&mut self, mask: u32) {
+        let size = self.size;
+        let data = LEVEL_M << 3 | mask;
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (data << 10 | remainder) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+
+        for i in 0..6 {
+            self.set_function(i, 8, bit(i));
+        }
+        self.set_function(7, 8, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(8, 7, bit(8));
+        for i in 9..15 {
+            self.set_function(8, 14 - i, bit(i));
+        }
+
+        for i in 0..8 {
+            self.set_function(8, size - 1 - i, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(size - 15 + i, 8, bit(i));
+        }
+        self.set_function(size - 8, 8, true);
+    }
+
+    /// Places the codewords in the zigzag order, two columns at a time from the bottom right.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let bit_count = codewords.len() * 8;
+        let mut bit_index = 0;
+        let mut right = size - 1;
+        loop {
+            // The vertical timing pattern is skipped over entirely
+            if right == 6 {
+                right = 5;
+            }
+            let is_upward = (right + 1) & 2 == 0;
+            for vertical in 0..size {
+                let row = if is_upward {
+                    size - 1 - vertical
+                } else {
+                    vertical
+                };
+                for col in [right, right - 1] {
+                    let index = row * size + col;
+                    if !self.is_function[index] && bit_index < bit_count {
+                        self.modules[index] =
+                            (codewords[bit_index / 8] >> (7 - bit_index % 8)) & 1 != 0;
+                        bit_index += 1;
+                    }
+                }
+            }
+            if right < 3 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    /// Flips the data modules picked out by the mask. Applying the same mask twice undoes it.
+    fn apply_mask(&mut self, mask: u32) {
+        for row in 0..self.size {
+            for col in 0..self.size {
+                let index = row * self.size + col;
+                let should_flip = match mask {
+                    0 => (row + col) % 2 == 0,
+                    1 => row % 2 == 0,
+                    2 => col % 3 == 0,
+                    3 => (row + col) % 3 == 0,
+                    4 => (row / 2 + col / 3) % 2 == 0,
+                    5 => (row * col) % 2 + (row * col) % 3 == 0,
+                    6 => ((row * col) % 2 + (row * col) % 3) % 2 == 0,
+                    _ => ((row + col) % 2 + (row * col) % 3) % 2 == 0,
+                };
+                if should_flip && !self.is_function[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    /// Scores how hard the symbol is for a scanner to read, the mask with the lowest score
+    /// being the one to use.
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let module = |row: usize, col: usize| self.modules[row * size + col];
+        let mut penalty = 0;
+
+        // Runs of five or more modules of the same color, and patterns which look like finders
+        const FINDER_LIKE: [[bool; 11]; 2] = [
+            [
+                true, false, true, true, true, false, true, false, false, false, false,
+            ],
+            [
+                false, false, false, false, true, false, true, true, true, false, true,
+            ],
+        ];
+        for is_row in [true, false] {
+            for line in 0..size {
+                let at = |index: usize| {
+                    if is_row {
+                        module(line, index)
+                    } else {
+                        module(index, line)
+                    }
+                };
+                let mut run = 1;
+                for index in 1..size {
+                    if at(index) == at(index - 1) {
+                        run += 1;
+                        if run == 5 {
+                            penalty += 3;
+                        } else if run > 5 {
+                            penalty += 1;
+                        }
+                    } else {
+                        run = 1;
+                    }
+                }
+                for start in 0..size.saturating_sub(10) {
+                    if FINDER_LIKE
+                        .iter()
+                        .any(|pattern| (0..11).all(|offset| at(start + offset) == pattern[offset]))
+                    {
+                        penalty += 40;
+                    }
+                }
+            }
+        }
+
+        // Two by two blocks of the same color
+        for row in 0..size - 1 {
+            for col in 0..size - 1 {
+                let color = module(row, col);
+                if module(row, col + 1) == color
+                    && module(row + 1, col) == color
+                    && module(row + 1, col + 1) == color
+                {
+                    penalty += 3;
+                }
+            }
+        }
+
+        // How far the balance of dark and light modules is from even, in steps of 5%
+        let total = size * size;
+        let dark = self.modules.iter().filter(|&&is_dark| is_dark).count();
+        let steps = (dark * 20)
+            .abs_diff(total * 10)
+            .div_ceil(total)
+            .saturating_sub(1);
+        penalty + steps * 10
+    }
+}
+
+/// The data codewords for the data in byte mode, if it fits in a symbol of the given version.
+fn data_codewords(data: &[u8], version: usize) -> Option<Vec<u8>> {
+    let (total, blocks, ec_per_block) = CODEWORDS_M[version - 1];
+    let capacity_bits = (total - blocks * ec_per_block) * 8;
+    let length_bits = if version < 10 { 8 } else { 16 };
+    if data.len() >= 1 << length_bits || 4 + length_bits + data.len() * 8 > capacity_bits {
+        return None;
+    }
+
+    let mut bits = Vec::with_capacity(capacity_bits);
+    let mut push = |value: u32, count: usize| {
+        bits.extend((0..count).rev().map(|i| (value >> i) & 1 != 0));
+    };
+    push(0b0100, 4);
+    push(data.len() as u32, length_bits);
+    for &byte in data {
+        push(u32::from(byte), 8);
+    }
+    // Terminator, then padding out to a whole number of bytes
+    let terminator = (capacity_bits - bits.len()).min(4);
+    bits.extend(std::iter::repeat_n(false, terminator));
+    bits.resize(bits.len().next_multiple_of(8), false);
+
+    let mut codewords = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0u8, |acc, &bit| acc << 1 | u8::from(bit)))
+        .collect::<Vec<_>>();
+    for pad in [0xec, 0x11].into_iter().cycle() {
+        if codewords.len() * 8 >= capacity_bits {
+            break;
+        }
+        codewords.push(pad);
+    }
+    Some(codewords)
+}
+
+/// Splits the data into blocks, adds Reed-Solomon error correction to each, and interleaves
+/// the blocks into the order they're placed in.
+fn add_error_correction(data: &[u8], version: usize) -> Vec<u8> {
+    let (total, block_count, ec_per_block) = CODEWORDS_M[version - 1];
+    let short_blocks = block_count - total % block_count;
+    let short_data_len = total / block_count - ec_per_block;
+    let generator = rs_generator(ec_per_block);
+
+    let mut blocks = Vec::with_capacity(block_count);
+    let mut start = 0;
+    for index in 0..block_count {
+        let len = short_data_len + usize::from(index >= short_blocks);
+        let block = &data[start..start + len];
+        blocks.push((block, rs_remainder(block, &generator[..])));
+        start += len;
+    }
+
+    let mut out = Vec::with_capacity(total);
+    for index in 0..=short_data_len {
+        out.extend(blocks.iter().filter_map(|(block, _)| block.get(index)));
+    }
+    for index in 0..ec_per_block {
+        out.extend(blocks.iter().map(|(_, ec)| ec[index]));
+    }
+    out
+}
+
+/// Multiplies two elements of GF(256) with the QR code polynomial.
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut product = 0u16;
+    for i in (0..8).rev() {
+        product = (product << 1) ^ ((product >> 7) * 0x11d);
+        product ^= u16::from((y >> i) & 1) * u16::from(x);
+    }
+    product as u8
+}
+
+/// Coefficients of the generator polynomial for `degree` error correction codewords, from the
+/// highest power down and leaving off the leading 1.
+fn rs_generator(degree: usize) -> Vec<u8> {
+    let mut generator = vec![0u8; degree];
+    generator[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for i in 0..degree {
+            generator[i] = gf_multiply(generator[i], root);
+            if i + 1 < degree {
+                generator[i] ^= generator[i + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    generator
+}
+
+fn rs_remainder(data: &[u8], generator: &[u8]) -> Vec<u8> {
+    let mut remainder = vec![0u8; generator.len()];
+    for &byte in data {
+        let factor = byte ^ remainder.remove(0);
+        remainder.push(0);
+        for (value, &coefficient) in remainder.iter_mut().zip(generator) {
+            *value ^= gf_multiply(coefficient, factor);
+        }
+    }
+    remainder
+}
+
+/// Row and column positions of the alignment pattern centers.
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return vec![];
+    }
+    let count = version / 7 + 2;
+    let step = (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2;
+    let last = 17 + 4 * version - 7;
+    let mut positions = vec![6];
+    positions.extend((0..count - 1).rev().map(|index| last - index * step));
+    positions
+}
@@ -0,0 +1,360 @@
+use super::{
+    AlphaMask, DecodedImage, Font, PixelValue, RenderedExtent, Rgb555, ScreenBuffer, ScrollMode,
+};
+use std::io;
+
+/// A rectangle of a screen buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionBounds {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl RegionBounds {
+    pub fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    /// The buffer position of a point given relative to the top left corner of the rectangle,
+    /// if it falls inside of it.
+    fn to_buffer(self, row: i64, col: i64) -> Option<(usize, usize)> {
+        let row = usize::try_from(row).ok().filter(|&row| row < self.height)?;
+        let col = usize::try_from(col).ok().filter(|&col| col < self.width)?;
+        Some((self.y + row, self.x + col))
+    }
+}
+
+/// A view of part of a screen buffer which can be drawn on as if it were a whole buffer, so
+/// several apps can share one display. Positions are relative to the top left corner of the
+/// region. Shapes, text, and images are clipped to it and writes to single pixels or whole
+/// rectangles which fall outside of it are refused.
+///
+/// Nothing stops two regions of the same buffer from overlapping. Where they do, whichever
+/// drew last is what ends up on the display.
+#[derive(Debug)]
+pub struct BufferRegion<'a> {
+    buffer: &'a mut ScreenBuffer,
+    bounds: RegionBounds,
+}
+
+impl ScreenBuffer {
+    /// A view of the `width` by `height` rectangle with its top left corner at (`x`, `y`).
+    /// Fails if the rectangle doesn't fit on the buffer.
+    pub fn region(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> io::Result<BufferRegion<'_>> {
+        let bounds = RegionBounds {
+            x,
+            y,
+            width,
+            height,
+        };
+        if bounds.right() > self.width || bounds.bottom() > self.height {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        Ok(BufferRegion {
+            buffer: self,
+            bounds,
+        })
+    }
+
+    /// A view of the whole buffer, for code which draws on regions to draw on everything.
+    pub fn full_region(&mut self) -> BufferRegion<'_> {
+        let bounds = RegionBounds {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        };
+        BufferRegion {
+            buffer: self,
+            bounds,
+        }
+    }
+
+    /// The rectangle drawing is currently clipped to, which is the whole buffer unless drawing
+    /// through a region.
+    pub(super) fn clip_bounds(&self) -> RegionBounds {
+        self.clip.unwrap_or(RegionBounds {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    /// The row and column of a point, if it falls inside the clip rectangle.
+    pub(super) fn clipped_cell(&self, row: i64, col: i64) -> Option<(usize, usize)> {
+        let bounds = self.clip_bounds();
+        bounds.to_buffer(row - bounds.y as i64, col - bounds.x as i64)
+    }
+}
+
+impl BufferRegion<'_> {
+    pub fn bounds(&self) -> RegionBounds {
+        self.bounds
+    }
+
+    pub fn width(&self) -> usize {
+        self.bounds.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.bounds.height
+    }
+
+    pub fn is_rgb(&self) -> bool {
+        self.buffer.is_rgb()
+    }
+
+    /// The buffer the region is a part of, for settings which apply to the whole display.
+    pub fn buffer(&mut self) -> &mut ScreenBuffer {
+        self.buffer
+    }
+
+    pub fn set_cell(&mut self, row: usize, col: usize, value: bool) -> io::Result<()> {
+        let (row, col) = self.to_buffer(row, col)?;
+        self.buffer.set_cell(row, col, value)
+    }
+
+    pub fn set_pixel_rgb(&mut self, row: usize, col: usize, color: Rgb555) -> io::Result<()> {
+        let (row, col) = self.to_buffer(row, col)?;
+        self.buffer.set_pixel_rgb(row, col, color)
+    }
+
+    pub fn get_pixel(&self, row: usize, col: usize) -> io::Result<PixelValue> {
+        let (row, col) = self.to_buffer(row, col)?;
+        self.buffer.get_pixel(row, col)
+    }
+
+    /// Sets a `width` by `height` rectangle of pixels, given row by row, with its top left
+    /// corner at (`x`, `y`). Fails without writing anything if the rectangle doesn't fit in the
+    /// region or there aren't exactly enough values for it.
+    pub fn write_region(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        values: &[bool],
+    ) -> io::Result<()> {
+        if x + width > self.bounds.width
+            || y + height > self.bounds.height
+            || values.len() != width * height
+        {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        for (index, &value) in values.iter().enumerate() {
+            self.set_cell(y + index / width, x + index % width, value)?;
+        }
+        Ok(())
+    }
+
+    /// Copies a rectangle of colors like [`ScreenBuffer::write_region_rgb`], dropping the parts
+    /// of it which fall outside the region. Fails if the rectangle starts outside the region.
+    pub fn write_region_rgb(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        colors: &[Rgb555],
+    ) -> io::Result<()> {
+        let (y, x) = self.to_buffer(y, x)?;
+        self.clipped(|buffer| buffer.write_region_rgb(x, y, width, height, colors))
+    }
+
+    pub fn blend_pixel(
+        &mut self,
+        row: usize,
+        col: usize,
+        color: Rgb555,
+        alpha: u8,
+    ) -> io::Result<()> {
+        let (row, col) = self.to_buffer(row, col)?;
+        self.buffer.blend_pixel(row, col, color, alpha)
+    }
+
+    /// Blends a rectangle of colors like [`ScreenBuffer::write_region_rgb_blended`], dropping
+    /// the parts of it which fall outside the region. Fails if the rectangle starts outside the
+    /// region.
+    pub fn write_region_rgb_blended(
+        &mut self,
+        (x, y): (usize, usize),
+        size: (usize, usize),
+        colors: &[Rgb555],
+        alpha: AlphaMask,
+    ) -> io::Result<()> {
+        let (y, x) = self.to_buffer(y, x)?;
+        self.clipped(|buffer| buffer.write_region_rgb_blended((x, y), size, colors, alpha))
+    }
+
+    /// Sets every pixel of the region to the same state like [`ScreenBuffer::fill`].
+    pub fn fill(&mut self, value: bool) {
+        self.fill_rect(
+            0,
+            0,
+            self.bounds.width as u32,
+            self.bounds.height as u32,
+            PixelValue::Mono(value),
+        );
+    }
+
+    /// Sets every pixel of the region to the same color. Fails on a monocolor buffer.
+    pub fn fill_rgb(&mut self, color: Rgb555) -> io::Result<()> {
+        if !self.is_rgb() {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        self.fill_rect(
+            0,
+            0,
+            self.bounds.width as u32,
+            self.bounds.height as u32,
+            PixelValue::Rgb(color),
+        );
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.fill(false);
+    }
+
+    pub fn draw_text(
+        &mut self,
+        x: i32,
+        y: i32,
+        text: &str,
+        color: Rgb555,
+        font: Font,
+    ) -> io::Result<RenderedExtent> {
+        let (x, y) = self.offset(x, y);
+        self.clipped(|buffer| buffer.draw_text(x, y, text, color, font))
+    }
+
+    pub fn draw_seven_segment(
+        &mut self,
+        x: i32,
+        y: i32,
+        digit_height: u32,
+        value: &str,
+        color: Rgb555,
+    ) -> io::Result<usize> {
+        let (x, y) = self.offset(x, y);
+        self.clipped(|buffer| buffer.draw_seven_segment(x, y, digit_height, value, color))
+    }
+
+    pub fn draw_progress_bar(
+        &mut self,
+        (x, y): (i32, i32),
+        size: (u32, u32),
+        fraction: f32,
+        colors: (Rgb555, Rgb555),
+        border: bool,
+    ) {
+        let position = self.offset(x, y);
+        self.clipped(|buffer| buffer.draw_progress_bar(position, size, fraction, colors, border));
+    }
+
+    pub fn draw_sparkline(
+        &mut self,
+        (x, y): (i32, i32),
+        size: (u32, u32),
+        values: &[f32],
+        range: (Option<f32>, Option<f32>),
+        color: Rgb555,
+        fill: bool,
+    ) -> io::Result<()> {
+        let position = self.offset(x, y);
+        self.clipped(|buffer| buffer.draw_sparkline(position, size, values, range, color, fill))
+    }
+
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, value: PixelValue) {
+        let ((x0, y0), (x1, y1)) = (self.offset(x0, y0), self.offset(x1, y1));
+        self.clipped(|buffer| buffer.draw_line(x0, y0, x1, y1, value));
+    }
+
+    pub fn draw_rect(&mut self, x: i32, y: i32, width: u32, height: u32, value: PixelValue) {
+        let (x, y) = self.offset(x, y);
+        self.clipped(|buffer| buffer.draw_rect(x, y, width, height, value));
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, value: PixelValue) {
+        let (x, y) = self.offset(x, y);
+        self.clipped(|buffer| buffer.fill_rect(x, y, width, height, value));
+    }
+
+    pub fn draw_circle(&mut self, center_x: i32, center_y: i32, radius: u32, value: PixelValue) {
+        let (center_x, center_y) = self.offset(center_x, center_y);
+        self.clipped(|buffer| buffer.draw_circle(center_x, center_y, radius, value));
+    }
+
+    pub fn blit_from(&mut self, src: &ScreenBuffer, src_rect: RegionBounds, x: i32, y: i32) {
+        let (x, y) = self.offset(x, y);
+        self.clipped(|buffer| buffer.blit_from(src, src_rect, x, y));
+    }
+
+    pub fn draw_image(&mut self, x: i32, y: i32, image: &DecodedImage) {
+        let (x, y) = self.offset(x, y);
+        self.clipped(|buffer| buffer.draw_image(x, y, image));
+    }
+
+    /// Draws a QR code like [`ScreenBuffer::draw_qr`], failing if it doesn't fit in the region.
+    #[cfg(feature = "qr")]
+    pub fn draw_qr(&mut self, x: usize, y: usize, data: &str, module_size: u32) -> io::Result<()> {
+        let (x, y) = (self.bounds.x + x, self.bounds.y + y);
+        self.clipped(|buffer| buffer.draw_qr(x, y, data, module_size))
+    }
+
+    /// Shifts the contents of the region like [`ScreenBuffer::scroll`], leaving the rest of the
+    /// buffer alone.
+    pub fn scroll(&mut self, dx: i32, dy: i32, mode: ScrollMode) {
+        let bounds = self.bounds;
+        self.buffer.scroll_region(
+            (bounds.x, bounds.y),
+            (bounds.width, bounds.height),
+            dx,
+            dy,
+            mode,
+        );
+    }
+
+    /// The buffer rows which make up the given rows of the region, skipping any past its
+    /// bottom.
+    pub fn buffer_rows(&self, rows: &[u8]) -> Vec<u8> {
+        rows.iter()
+            .filter(|&&row| usize::from(row) < self.bounds.height)
+            .filter_map(|&row| u8::try_from(self.bounds.y + usize::from(row)).ok())
+            .collect()
+    }
+
+    fn to_buffer(&self, row: usize, col: usize) -> io::Result<(usize, usize)> {
+        self.bounds
+            .to_buffer(row as i64, col as i64)
+            .ok_or_else(|| io::ErrorKind::InvalidInput.into())
+    }
+
+    fn offset(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            x.saturating_add(self.bounds.x as i32),
+            y.saturating_add(self.bounds.y as i32),
+        )
+    }
+
+    /// Runs a drawing operation on the buffer with everything outside the region masked off.
+    fn clipped<T>(&mut self, draw: impl FnOnce(&mut ScreenBuffer) -> T) -> T {
+        let previous_clip = self.buffer.clip.replace(self.bounds);
+        let result = draw(self.buffer);
+        self.buffer.clip = previous_clip;
+        result
+    }
+}
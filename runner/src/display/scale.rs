@@ -0,0 +1,198 @@
+use super::{BufferRegion, PixelValue, ScreenBuffer};
+use std::{io, ops::Range};
+
+/// Where the pixels of a virtual screen land when it's shown on a bigger one. Each virtual pixel
+/// is drawn as a `factor` by `factor` square, using the largest whole factor which fits, and the
+/// result is centered with the space around it left blank. Where the sizes don't divide evenly
+/// this can mean a factor of 1, centering the virtual screen without scaling it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaleMapping {
+    factor: usize,
+    /// Position of the virtual screen's top left pixel, as (x, y)
+    offset: (usize, usize),
+    virtual_size: (usize, usize),
+}
+
+impl ScaleMapping {
+    /// Maps a `virtual_size` screen onto a `physical_size` one, both given as (width, height).
+    /// Fails if the virtual screen is empty or doesn't fit.
+    pub fn new(virtual_size: (usize, usize), physical_size: (usize, usize)) -> io::Result<Self> {
+        let (virtual_width, virtual_height) = virtual_size;
+        let (physical_width, physical_height) = physical_size;
+        if virtual_width == 0 || virtual_height == 0 {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        let factor = (physical_width / virtual_width).min(physical_height / virtual_height);
+        if factor == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "A {virtual_width}x{virtual_height} virtual screen doesn't fit on a \
+                     {physical_width}x{physical_height} display"
+                ),
+            ));
+        }
+        Ok(ScaleMapping {
+            factor,
+            offset: (
+                (physical_width - virtual_width * factor) / 2,
+                (physical_height - virtual_height * factor) / 2,
+            ),
+            virtual_size,
+        })
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    pub fn offset(&self) -> (usize, usize) {
+        self.offset
+    }
+
+    /// The physical rows a row of the virtual screen is drawn on.
+    pub fn physical_rows(&self, row: usize) -> Range<usize> {
+        let top = self.offset.1 + row * self.factor;
+        top..top + self.factor
+    }
+
+    /// The virtual pixel a physical pixel shows, if it isn't in the blank space around the
+    /// virtual screen.
+    pub fn virtual_pixel(&self, row: usize, col: usize) -> Option<(usize, usize)> {
+        let row = row.checked_sub(self.offset.1)? / self.factor;
+        let col = col.checked_sub(self.offset.0)? / self.factor;
+        (row < self.virtual_size.1 && col < self.virtual_size.0).then_some((row, col))
+    }
+}
+
+impl ScreenBuffer {
+    /// Draws the buffer onto `target` as laid out by `mapping`, blanking the space around it.
+    /// Only pixels which actually change are written, so scaling again before the buffer has
+    /// been drawn on leaves no rows dirty.
+    pub fn scale_into(&self, target: &mut BufferRegion, mapping: &ScaleMapping) {
+        let blank = match self.palette() {
+            Some(palette) => PixelValue::Rgb(palette.off()),
+            None => PixelValue::Mono(false),
+        };
+        for row in 0..target.height() {
+            for col in 0..target.width() {
+                let value = match mapping.virtual_pixel(row, col) {
+                    Some((virtual_row, virtual_col)) => {
+                        self.get_pixel(virtual_row, virtual_col).unwrap_or(blank)
+                    }
+                    None => blank,
+                };
+                if target
+                    .get_pixel(row, col)
+                    .is_ok_and(|current| current == value)
+                {
+                    continue;
+                }
+                let _ = match value {
+                    PixelValue::Mono(is_lit) => target.set_cell(row, col, is_lit),
+                    PixelValue::Rgb(color) => target.set_pixel_rgb(row, col, color),
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tests::picture, MonocolorPalette, Rgb555};
+    use super::*;
+
+    /// A 3 by 2 virtual screen with its top left and its right of the second row lit
+    fn virtual_screen() -> ScreenBuffer {
+        let mut buffer = ScreenBuffer::new(3, 2, None);
+        buffer.set_cell(0, 0, true).unwrap();
+        buffer.set_cell(1, 2, true).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn mappings_use_the_largest_whole_factor_which_fits() {
+        let mapping = ScaleMapping::new((32, 16), (64, 32)).unwrap();
+        assert_eq!((mapping.factor(), mapping.offset()), (2, (0, 0)));
+        let mapping = ScaleMapping::new((32, 16), (70, 40)).unwrap();
+        assert_eq!((mapping.factor(), mapping.offset()), (2, (3, 4)));
+        let mapping = ScaleMapping::new((32, 16), (48, 20)).unwrap();
+        assert_eq!((mapping.factor(), mapping.offset()), (1, (8, 2)));
+
+        for (virtual_size, physical_size) in [((32, 16), (16, 16)), ((0, 4), (8, 8))] {
+            let err = ScaleMapping::new(virtual_size, physical_size).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn physical_pixels_map_back_to_the_virtual_pixel_they_show() {
+        let mapping = ScaleMapping::new((32, 16), (70, 40)).unwrap();
+        assert_eq!(mapping.physical_rows(0), 4..6);
+        assert_eq!(mapping.physical_rows(15), 34..36);
+        assert_eq!(mapping.virtual_pixel(4, 3), Some((0, 0)));
+        assert_eq!(mapping.virtual_pixel(7, 66), Some((1, 31)));
+        // The blank space around the virtual screen
+        assert_eq!(mapping.virtual_pixel(3, 3), None);
+        assert_eq!(mapping.virtual_pixel(4, 2), None);
+        assert_eq!(mapping.virtual_pixel(36, 3), None);
+        assert_eq!(mapping.virtual_pixel(4, 67), None);
+    }
+
+    #[test]
+    fn doubled_pixels_are_drawn_as_squares() {
+        let mapping = ScaleMapping::new((3, 2), (6, 4)).unwrap();
+        let mut physical = ScreenBuffer::new(6, 4, None);
+        virtual_screen().scale_into(&mut physical.full_region(), &mapping);
+        assert_eq!(picture(&physical), ["##....", "##....", "....##", "....##"]);
+    }
+
+    #[test]
+    fn uneven_sizes_are_centered_and_letterboxed() {
+        // Three times as wide would fit but not three times as tall, so it's only doubled
+        let mapping = ScaleMapping::new((3, 2), (10, 5)).unwrap();
+        let mut physical = ScreenBuffer::new(10, 5, None);
+        physical.fill(true);
+        virtual_screen().scale_into(&mut physical.full_region(), &mapping);
+        assert_eq!(
+            picture(&physical),
+            [
+                "..##......",
+                "..##......",
+                "......##..",
+                "......##..",
+                "..........",
+            ]
+        );
+
+        // Without room to double it, it's centered as it is
+        let mapping = ScaleMapping::new((3, 2), (5, 3)).unwrap();
+        let mut physical = ScreenBuffer::new(5, 3, None);
+        virtual_screen().scale_into(&mut physical.full_region(), &mapping);
+        assert_eq!(picture(&physical), [".#...", "...#.", "....."]);
+    }
+
+    #[test]
+    fn scaling_an_unchanged_screen_again_leaves_nothing_dirty() {
+        let mapping = ScaleMapping::new((3, 2), (6, 4)).unwrap();
+        let mut physical = ScreenBuffer::new(6, 4, None);
+        virtual_screen().scale_into(&mut physical.full_region(), &mapping);
+        physical.clear_dirty();
+        virtual_screen().scale_into(&mut physical.full_region(), &mapping);
+        assert!(physical.dirty_rows().is_empty());
+    }
+
+    #[test]
+    fn rgb_screens_are_letterboxed_in_their_off_color() {
+        let palette = MonocolorPalette::new(Rgb555::AMBER, Rgb555::NAVY);
+        let mut virtual_buffer = ScreenBuffer::new(1, 1, Some(palette));
+        virtual_buffer.set_pixel_rgb(0, 0, Rgb555::GREEN).unwrap();
+        let mapping = ScaleMapping::new((1, 1), (3, 1)).unwrap();
+        let mut physical = ScreenBuffer::new(3, 1, Some(palette));
+        virtual_buffer.scale_into(&mut physical.full_region(), &mapping);
+        assert_eq!(
+            physical.get_row_rgb(0).unwrap(),
+            [Rgb555::NAVY.raw(), Rgb555::GREEN.raw(), Rgb555::NAVY.raw()]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use super::{rgb555_is_lit, PixelValue, ScreenBuffer, ScreenBufferKind};
+
+/// What to do with the pixels which are scrolled off one edge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollMode {
+    /// Bring them back in at the opposite edge
+    Wrap,
+    /// Drop them and fill the space left behind with a single value
+    Fill(PixelValue),
+}
+
+impl ScreenBuffer {
+    /// Shifts the whole buffer `dx` pixels to the right and `dy` pixels down, negative values
+    /// going left and up.
+    pub fn scroll(&mut self, dx: i32, dy: i32, mode: ScrollMode) {
+        self.scroll_region((0, 0), (self.width, self.height), dx, dy, mode);
+    }
+
+    /// Shifts a `width` by `height` rectangle with its top left corner at (`x`, `y`) like
+    /// [`ScreenBuffer::scroll`], leaving everything outside of it alone. The rectangle is
+    /// clipped to the buffer.
+    pub fn scroll_region(
+        &mut self,
+        (x, y): (usize, usize),
+        (width, height): (usize, usize),
+        dx: i32,
+        dy: i32,
+        mode: ScrollMode,
+    ) {
+        let width = width.min(self.width.saturating_sub(x));
+        let height = height.min(self.height.saturating_sub(y));
+        if width == 0 || height == 0 || (dx == 0 && dy == 0) {
+            return;
+        }
+
+        let region = Region {
+            stride: self.width,
+            x,
+            y,
+            width,
+            height,
+        };
+        match &mut self.buffer {
+            ScreenBufferKind::Monocolor(buffer) => {
+                let fill = match mode {
+                    ScrollMode::Wrap => None,
+                    ScrollMode::Fill(PixelValue::Mono(is_lit)) => Some(is_lit),
+                    ScrollMode::Fill(PixelValue::Rgb(color)) => Some(rgb555_is_lit(color)),
+                };
+                region.shift(buffer, dx.into(), dy.into(), fill);
+            }
+            ScreenBufferKind::Rgb555(buffer, palette) => {
+                let (fill, palette_fill) = match mode {
+                    ScrollMode::Wrap => (None, None),
+                    ScrollMode::Fill(PixelValue::Mono(is_lit)) => {
+                        (Some(palette.color(is_lit)), Some(Some(is_lit)))
+                    }
+                    ScrollMode::Fill(PixelValue::Rgb(color)) => (Some(color.raw()), Some(None)),
+                };
+                region.shift(buffer, dx.into(), dy.into(), fill);
+                // Which pixels came from the palette moves along with them
+                region.shift(&mut self.palette_cells, dx.into(), dy.into(), palette_fill);
+            }
+        }
+        self.dirty_rows[y..y + height].fill(true);
+    }
+}
+
+/// A rectangle of a buffer laid out row by row, `stride` pixels per row
+struct Region {
+    stride: usize,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Region {
+    /// Moves the contents of the region a row at a time, wrapping around if there's no fill
+    /// value.
+    fn shift<T: Copy>(&self, buffer: &mut [T], dx: i64, dy: i64, fill: Option<T>) {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let original = (0..self.height)
+            .flat_map(|row| self.row(buffer, row).to_vec())
+            .collect::<Vec<_>>();
+
+        for row in 0..self.height {
+            let src_row = row as i64 - dy;
+            let dest = &mut buffer[(self.y + row) * self.stride + self.x..][..self.width];
+            if let Some(fill) = fill {
+                if !(0..height).contains(&src_row) {
+                    dest.fill(fill);
+                    continue;
+                }
+            }
+            let src_row = src_row.rem_euclid(height) as usize;
+            let src = &original[src_row * self.width..][..self.width];
+            match fill {
+                None => {
+                    dest.copy_from_slice(src);
+                    dest.rotate_right(dx.rem_euclid(width) as usize);
+                }
+                Some(fill) if dx.abs() >= width => dest.fill(fill),
+                Some(fill) if dx >= 0 => {
+                    let shift = dx as usize;
+                    dest[shift..].copy_from_slice(&src[..self.width - shift]);
+                    dest[..shift].fill(fill);
+                }
+                Some(fill) => {
+                    let shift = dx.unsigned_abs() as usize;
+                    dest[..self.width - shift].copy_from_slice(&src[shift..]);
+                    dest[self.width - shift..].fill(fill);
+                }
+            }
+        }
+    }
+
+    fn row<'a, T>(&self, buffer: &'a [T], row: usize) -> &'a [T] {
+        &buffer[(self.y + row) * self.stride + self.x..][..self.width]
+    }
+}
@@ -0,0 +1,321 @@
+use super::{MonocolorPalette, Rgb555, ScreenBuffer, ScreenBufferKind};
+use std::io;
+
+/// Marks the start of a saved screen buffer
+const MAGIC: &[u8; 4] = b"MBSB";
+const FORMAT_VERSION: u8 = 1;
+const KIND_MONOCOLOR: u8 = 0;
+const KIND_RGB555: u8 = 1;
+/// How each pixel of a saved RGB buffer was last set, so palette changes still recolor it
+const CELL_DIRECT: u8 = 0;
+const CELL_OFF: u8 = 1;
+const CELL_ON: u8 = 2;
+
+/// What's at the start of a saved buffer, ahead of the pixel data
+struct Header {
+    is_rgb: bool,
+    width: usize,
+    height: usize,
+    palette: Option<MonocolorPalette>,
+}
+
+impl ScreenBuffer {
+    /// Saves the buffer's dimensions, kind, palette, and pixels so it can be put back later with
+    /// [`ScreenBuffer::from_bytes`] or [`ScreenBuffer::restore_from_bytes`], e.g. when switching
+    /// between apps. Runner settings like the orientation and dither mode aren't saved.
+    ///
+    /// The format is the magic bytes `MBSB`, a version byte, a kind byte (0 for monocolor, 1 for
+    /// RGB555), and the width and height as big endian `u16`s. RGB buffers follow that with the
+    /// palette's on and off colors, each pixel as a big endian `u16`, and then a byte per pixel
+    /// for whether it was last set to a color (0) or through the palette off (1) or on (2).
+    /// Monocolor buffers follow it with the pixels packed eight to a byte, least significant bit
+    /// first.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(&MAGIC[..]);
+        bytes.push(FORMAT_VERSION);
+        bytes.push(if self.is_rgb() {
+            KIND_RGB555
+        } else {
+            KIND_MONOCOLOR
+        });
+        bytes.extend((self.width as u16).to_be_bytes());
+        bytes.extend((self.height as u16).to_be_bytes());
+        match &self.buffer {
+            ScreenBufferKind::Monocolor(buffer) => {
+                bytes.extend(buffer.chunks(8).map(|pixels| {
+                    pixels
+                        .iter()
+                        .enumerate()
+                        .fold(0u8, |byte, (bit, &is_lit)| byte | (u8::from(is_lit) << bit))
+                }));
+            }
+            ScreenBufferKind::Rgb555(buffer, palette) => {
+                bytes.extend(palette.on.raw().to_be_bytes());
+                bytes.extend(palette.off.raw().to_be_bytes());
+                bytes.extend(buffer.iter().flat_map(|color| color.to_be_bytes()));
+                bytes.extend(self.palette_cells.iter().map(|cell| match cell {
+                    None => CELL_DIRECT,
+                    Some(false) => CELL_OFF,
+                    Some(true) => CELL_ON,
+                }));
+            }
+        }
+        bytes
+    }
+
+    /// Loads a buffer saved with [`ScreenBuffer::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let (header, pixels) = Header::parse(bytes)?;
+        let mut screen_buffer = ScreenBuffer::new(header.width, header.height, header.palette);
+        screen_buffer.load_pixels(pixels)?;
+        Ok(screen_buffer)
+    }
+
+    /// Replaces the buffer's palette and pixels with ones saved with [`ScreenBuffer::to_bytes`]
+    /// and marks every row dirty. If the saved buffer is a different size or kind, or can't be
+    /// read, the buffer is cleared instead and an error returned.
+    pub fn restore_from_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let result = Header::parse(bytes).and_then(|(header, pixels)| {
+            if header.width != self.width
+                || header.height != self.height
+                || header.is_rgb != self.is_rgb()
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Saved buffer is a {}x{} {} buffer but this is a {}x{} {} buffer",
+                        header.width,
+                        header.height,
+                        kind_name(header.is_rgb),
+                        self.width,
+                        self.height,
+                        kind_name(self.is_rgb())
+                    ),
+                ));
+            }
+            if let Some(palette) = header.palette {
+                self.set_palette(palette)?;
+            }
+            self.load_pixels(pixels)
+        });
+        if result.is_err() {
+            self.clear();
+        }
+        result
+    }
+
+    /// Copies saved pixel data into a buffer of the same size and kind, checking it's all there
+    /// first so nothing is left half written.
+    fn load_pixels(&mut self, pixels: &[u8]) -> io::Result<()> {
+        let pixel_count = self.width * self.height;
+        match &mut self.buffer {
+            ScreenBufferKind::Monocolor(buffer) => {
+                if pixels.len() != pixel_count.div_ceil(8) {
+                    return Err(truncated());
+                }
+                for (index, is_lit) in buffer.iter_mut().enumerate() {
+                    *is_lit = pixels[index / 8] & (1 << (index % 8)) != 0;
+                }
+            }
+            ScreenBufferKind::Rgb555(buffer, _) => {
+                if pixels.len() != pixel_count * 3 {
+                    return Err(truncated());
+                }
+                let (colors, cells) = pixels.split_at(pixel_count * 2);
+                if cells.iter().any(|&cell| cell > CELL_ON) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Saved buffer has an unknown palette cell value",
+                    ));
+                }
+                for (color, bytes) in buffer.iter_mut().zip(colors.chunks_exact(2)) {
+                    *color = Rgb555::from_raw(u16::from_be_bytes([bytes[0], bytes[1]])).raw();
+                }
+                for (cell, &saved) in self.palette_cells.iter_mut().zip(cells) {
+                    *cell = match saved {
+                        CELL_OFF => Some(false),
+                        CELL_ON => Some(true),
+                        _ => None,
+                    };
+                }
+            }
+        }
+        self.dirty_rows.fill(true);
+        Ok(())
+    }
+}
+
+impl Header {
+    /// Reads the header, returning it along with the pixel data which follows.
+    fn parse(bytes: &[u8]) -> io::Result<(Header, &[u8])> {
+        let (magic, rest) = split(bytes, MAGIC.len())?;
+        if magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a saved screen buffer",
+            ));
+        }
+        let (fixed, rest) = split(rest, 6)?;
+        if fixed[0] != FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported saved screen buffer version {}", fixed[0]),
+            ));
+        }
+        let width = usize::from(u16::from_be_bytes([fixed[2], fixed[3]]));
+        let height = usize::from(u16::from_be_bytes([fixed[4], fixed[5]]));
+        match fixed[1] {
+            KIND_MONOCOLOR => Ok((
+                Header {
+                    is_rgb: false,
+                    width,
+                    height,
+                    palette: None,
+                },
+                rest,
+            )),
+            KIND_RGB555 => {
+                let (palette, rest) = split(rest, 4)?;
+                let on = Rgb555::from_raw(u16::from_be_bytes([palette[0], palette[1]]));
+                let off = Rgb555::from_raw(u16::from_be_bytes([palette[2], palette[3]]));
+                Ok((
+                    Header {
+                        is_rgb: true,
+                        width,
+                        height,
+                        palette: Some(MonocolorPalette::new(on, off)),
+                    },
+                    rest,
+                ))
+            }
+            kind => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown saved screen buffer kind {kind}"),
+            )),
+        }
+    }
+}
+
+fn split(bytes: &[u8], len: usize) -> io::Result<(&[u8], &[u8])> {
+    if bytes.len() < len {
+        return Err(truncated());
+    }
+    Ok(bytes.split_at(len))
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "Saved screen buffer is the wrong length",
+    )
+}
+
+fn kind_name(is_rgb: bool) -> &'static str {
+    if is_rgb {
+        "RGB555"
+    } else {
+        "monocolor"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tests::picture, DEFAULT_MONO_PALETTE};
+    use super::*;
+
+    #[test]
+    fn monocolor_buffers_round_trip() {
+        // Fifteen pixels, so the last byte is only partly used
+        let mut buffer = ScreenBuffer::new(5, 3, None);
+        for (row, col) in [(0, 0), (0, 4), (1, 2), (2, 1), (2, 4)] {
+            buffer.set_cell(row, col, true).unwrap();
+        }
+        let bytes = buffer.to_bytes();
+        let (header, pixels) = bytes.split_at(10);
+        assert_eq!(header, b"MBSB\x01\x00\x00\x05\x00\x03");
+        assert_eq!(pixels, [0b1001_0001, 0b0100_1000]);
+
+        let restored = ScreenBuffer::from_bytes(&bytes).unwrap();
+        assert!(!restored.is_rgb());
+        assert_eq!(picture(&restored), ["#...#", "..#..", ".#..#"]);
+    }
+
+    #[test]
+    fn rgb_buffers_round_trip_with_their_palette() {
+        let palette = MonocolorPalette::new(Rgb555::AMBER, Rgb555::NAVY);
+        let mut buffer = ScreenBuffer::new(3, 2, Some(palette));
+        buffer.set_pixel_rgb(0, 0, Rgb555::PINK).unwrap();
+        buffer.set_cell(0, 1, true).unwrap();
+        buffer.set_cell(1, 2, false).unwrap();
+
+        let mut restored = ScreenBuffer::from_bytes(&buffer.to_bytes()).unwrap();
+        assert_eq!(restored.palette(), Some(palette));
+        for row in 0..2 {
+            assert_eq!(
+                restored.get_row_rgb(row).unwrap(),
+                buffer.get_row_rgb(row).unwrap()
+            );
+        }
+        // Pixels drawn through the palette still follow it, and ones drawn in color don't
+        restored
+            .set_palette(MonocolorPalette::new(Rgb555::GREEN, Rgb555::BLUE))
+            .unwrap();
+        assert_eq!(
+            restored.get_row_rgb(0).unwrap(),
+            [Rgb555::PINK.raw(), Rgb555::GREEN.raw(), Rgb555::BLACK.raw()]
+        );
+        assert_eq!(restored.get_row_rgb(1).unwrap()[2], Rgb555::BLUE.raw());
+    }
+
+    #[test]
+    fn restoring_marks_every_row_dirty() {
+        let mut buffer = ScreenBuffer::new(4, 3, None);
+        buffer.set_cell(1, 1, true).unwrap();
+        let bytes = buffer.to_bytes();
+        buffer.clear_dirty();
+        buffer.restore_from_bytes(&bytes).unwrap();
+        assert_eq!(buffer.dirty_rows(), [0, 1, 2]);
+        assert_eq!(picture(&buffer), ["....", ".#..", "...."]);
+    }
+
+    #[test]
+    fn restoring_a_different_buffer_clears_instead() {
+        let saved = ScreenBuffer::new(5, 2, None).to_bytes();
+        let saved_rgb = ScreenBuffer::new(4, 2, Some(DEFAULT_MONO_PALETTE)).to_bytes();
+        for bytes in [saved, saved_rgb] {
+            let mut buffer = ScreenBuffer::new(4, 2, None);
+            buffer.set_cell(0, 0, true).unwrap();
+            let err = buffer.restore_from_bytes(&bytes).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(picture(&buffer), ["....", "...."]);
+        }
+    }
+
+    #[test]
+    fn damaged_buffers_are_refused() {
+        let bytes = ScreenBuffer::new(4, 2, Some(DEFAULT_MONO_PALETTE)).to_bytes();
+        let with = |index: usize, value: u8| {
+            let mut bytes = bytes.clone();
+            bytes[index] = value;
+            bytes
+        };
+        for (damaged, kind) in [
+            (
+                bytes[..bytes.len() - 1].to_vec(),
+                io::ErrorKind::UnexpectedEof,
+            ),
+            ([&bytes[..], &[0]].concat(), io::ErrorKind::UnexpectedEof),
+            (bytes[..3].to_vec(), io::ErrorKind::UnexpectedEof),
+            (with(0, b'X'), io::ErrorKind::InvalidData),
+            (with(4, 2), io::ErrorKind::InvalidData),
+            (with(5, 7), io::ErrorKind::InvalidData),
+            (
+                with(bytes.len() - 1, CELL_ON + 1),
+                io::ErrorKind::InvalidData,
+            ),
+        ] {
+            let err = ScreenBuffer::from_bytes(&damaged).err().unwrap();
+            assert_eq!(err.kind(), kind, "{err}");
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use super::{PixelValue, Rgb555, ScreenBuffer};
+use std::io;
+
+/// Shortest digit which still has room between its segments
+const MIN_DIGIT_HEIGHT: u32 = 5;
+
+/// Segments lit for each digit, as bits from `a` (the top) round to `f` (top left), with `g` in
+/// the middle
+const DIGIT_SEGMENTS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+    0b1111111, 0b1101111,
+];
+const MINUS_SEGMENTS: u8 = 0b1000000;
+
+/// Sizes of the parts of a seven segment character, all derived from its height
+#[derive(Debug, Clone, Copy)]
+struct Geometry {
+    height: u32,
+    stroke: u32,
+    digit_width: u32,
+    /// Row the middle segment starts on
+    middle: u32,
+}
+
+impl Geometry {
+    fn new(height: u32) -> Self {
+        let stroke = (height / 7).max(1);
+        Geometry {
+            height,
+            stroke,
+            digit_width: height / 2 + stroke,
+            middle: (height - stroke) / 2,
+        }
+    }
+
+    fn width(&self, character: char) -> u32 {
+        match character {
+            ':' => self.stroke,
+            _ => self.digit_width,
+        }
+    }
+
+    /// Rectangles making up each segment, as x, y, width, and height from the character's top
+    /// left corner.
+    fn segments(&self) -> [(u32, u32, u32, u32); 7] {
+        let Geometry {
+            height,
+            stroke,
+            digit_width: width,
+            middle,
+        } = *self;
+        [
+            (0, 0, width, stroke),
+            (width - stroke, 0, stroke, middle + stroke),
+            (width - stroke, middle, stroke, height - middle),
+            (0, height - stroke, width, stroke),
+            (0, middle, stroke, height - middle),
+            (0, 0, stroke, middle + stroke),
+            (0, middle, width, stroke),
+        ]
+    }
+
+    /// The two dots of a colon, centered in the top and bottom halves.
+    fn colon_dots(&self) -> [(u32, u32, u32, u32); 2] {
+        let Geometry {
+            height,
+            stroke,
+            middle,
+            ..
+        } = *self;
+        let upper = (stroke + middle) / 2 - stroke / 2;
+        let lower = (middle + height) / 2 - stroke / 2;
+        [(0, upper, stroke, stroke), (0, lower, stroke, stroke)]
+    }
+}
+
+impl ScreenBuffer {
+    /// Draws `value` as seven segment characters `digit_height` pixels tall with their top left
+    /// corner at (`x`, `y`). Digits, colons, minus signs, and spaces are supported, with the
+    /// stroke being a seventh of the height and one stroke left between characters. Only lit
+    /// segments are drawn, so the area should be cleared first when redrawing.
+    ///
+    /// Characters are drawn until the next one would run past the right edge, and the width
+    /// taken up by those drawn is returned so callers can right-align the value. Fails without
+    /// drawing anything if the value has other characters in it or the height is less than 5.
+    pub fn draw_seven_segment(
+        &mut self,
+        x: i32,
+        y: i32,
+        digit_height: u32,
+        value: &str,
+        color: Rgb555,
+    ) -> io::Result<usize> {
+        if digit_height < MIN_DIGIT_HEIGHT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Seven segment digits must be at least {MIN_DIGIT_HEIGHT} pixels tall"),
+            ));
+        }
+        if let Some(character) = value
+            .chars()
+            .find(|character| !matches!(character, '0'..='9' | ':' | '-' | ' '))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Can't draw {character:?} as a seven segment character"),
+            ));
+        }
+
+        let geometry = Geometry::new(digit_height);
+        let right_edge = self.clip_bounds().right() as i64;
+        let mut width = 0u32;
+        for character in value.chars() {
+            let gap = if width == 0 { 0 } else { geometry.stroke };
+            let left = i64::from(x) + i64::from(width + gap);
+            if left + i64::from(geometry.width(character)) > right_edge {
+                break;
+            }
+            let rects = match character {
+                ':' => geometry.colon_dots().to_vec(),
+                ' ' => vec![],
+                _ => {
+                    let segments = match character.to_digit(10) {
+                        Some(digit) => DIGIT_SEGMENTS[digit as usize],
+                        None => MINUS_SEGMENTS,
+                    };
+                    geometry
+                        .segments()
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(index, _)| segments & (1 << index) != 0)
+                        .map(|(_, rect)| rect)
+                        .collect()
+                }
+            };
+            for (rect_x, rect_y, rect_width, rect_height) in rects {
+                self.fill_rect(
+                    (left + i64::from(rect_x)) as i32,
+                    y.saturating_add(rect_y as i32),
+                    rect_width,
+                    rect_height,
+                    PixelValue::Rgb(color),
+                );
+            }
+            width += gap + geometry.width(character);
+        }
+        Ok(width as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::picture;
+    use super::*;
+
+    /// Draws `value` `digit_height` pixels tall at the top left of a monocolor buffer, returning
+    /// the width taken up along with what it looks like.
+    fn draw(width: usize, digit_height: u32, value: &str) -> (usize, Vec<String>) {
+        let mut buffer = ScreenBuffer::new(width, digit_height as usize, None);
+        let drawn = buffer
+            .draw_seven_segment(0, 0, digit_height, value, Rgb555::WHITE)
+            .unwrap();
+        (drawn, picture(&buffer))
+    }
+
+    #[test]
+    fn small_digits_have_single_pixel_strokes() {
+        let (drawn, lines) = draw(7, 5, "18");
+        assert_eq!(drawn, 7);
+        assert_eq!(
+            lines,
+            ["..#.###", "..#.#.#", "..#.###", "..#.#.#", "..#.###"]
+        );
+    }
+
+    #[test]
+    fn taller_digits_have_thicker_strokes() {
+        let (drawn, seven) = draw(9, 14, "7");
+        assert_eq!(drawn, 9);
+        assert_eq!(seven[..2], ["#########"; 2]);
+        assert_eq!(seven[2..], [".......##"; 12]);
+
+        let (drawn, minus) = draw(9, 14, "-");
+        assert_eq!(drawn, 9);
+        for (row, line) in minus.iter().enumerate() {
+            let expected = if (6..8).contains(&row) {
+                "#########"
+            } else {
+                "........."
+            };
+            assert_eq!(line, expected, "row {row}");
+        }
+
+        let (drawn, colon) = draw(2, 14, ":");
+        assert_eq!(drawn, 2);
+        let lit_rows = (0..14)
+            .filter(|&row| colon[row] == "##")
+            .collect::<Vec<_>>();
+        assert_eq!(lit_rows, [3, 4, 9, 10]);
+    }
+
+    #[test]
+    fn characters_past_the_right_edge_are_left_off() {
+        let (drawn, lines) = draw(10, 5, "123");
+        assert_eq!(drawn, 7);
+        assert_eq!(
+            lines,
+            [
+                "..#.###...",
+                "..#...#...",
+                "..#.###...",
+                "..#.#.....",
+                "..#.###...",
+            ]
+        );
+    }
+
+    #[test]
+    fn spaces_take_up_room_without_being_drawn() {
+        let (drawn, lines) = draw(7, 5, " 1");
+        assert_eq!(drawn, 7);
+        assert_eq!(lines, ["......#"; 5]);
+    }
+
+    #[test]
+    fn unsupported_values_draw_nothing() {
+        let mut buffer = ScreenBuffer::new(16, 8, None);
+        for (digit_height, value) in [(4, "1"), (5, "1a"), (5, "1.5")] {
+            let err = buffer
+                .draw_seven_segment(0, 0, digit_height, value, Rgb555::WHITE)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert_eq!(picture(&buffer), ["................"; 8]);
+    }
+}
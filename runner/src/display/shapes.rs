@@ -0,0 +1,220 @@
+use super::{PixelValue, ScreenBuffer};
+
+impl ScreenBuffer {
+    /// Draws a one pixel wide line between two points, inclusive of both ends.
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, value: PixelValue) {
+        let (x0, y0, x1, y1) = (i64::from(x0), i64::from(y0), i64::from(x1), i64::from(y1));
+        // Lines which never cross the buffer aren't walked at all
+        let bounds = self.clip_bounds();
+        let (left, top) = (bounds.x as i64, bounds.y as i64);
+        let (right, bottom) = (bounds.right() as i64, bounds.bottom() as i64);
+        if (x0 < left && x1 < left)
+            || (y0 < top && y1 < top)
+            || (x0 >= right && x1 >= right)
+            || (y0 >= bottom && y1 >= bottom)
+        {
+            return;
+        }
+
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (step_x, step_y) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+        let (mut x, mut y) = (x0, y0);
+        let mut error = dx + dy;
+        loop {
+            self.set_pixel_clipped(x, y, value);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled_error = 2 * error;
+            if doubled_error >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled_error <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draws the one pixel wide outline of a `width` by `height` rectangle with its top left
+    /// corner at (`x`, `y`).
+    pub fn draw_rect(&mut self, x: i32, y: i32, width: u32, height: u32, value: PixelValue) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (x, y) = (i64::from(x), i64::from(y));
+        let (right, bottom) = (x + i64::from(width) - 1, y + i64::from(height) - 1);
+        self.fill_clipped(x, y, right, y, value);
+        self.fill_clipped(x, bottom, right, bottom, value);
+        self.fill_clipped(x, y, x, bottom, value);
+        self.fill_clipped(right, y, right, bottom, value);
+    }
+
+    /// Sets every pixel of a `width` by `height` rectangle with its top left corner at
+    /// (`x`, `y`).
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, value: PixelValue) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (x, y) = (i64::from(x), i64::from(y));
+        self.fill_clipped(
+            x,
+            y,
+            x + i64::from(width) - 1,
+            y + i64::from(height) - 1,
+            value,
+        );
+    }
+
+    /// Draws the one pixel wide outline of a circle around (`center_x`, `center_y`).
+    pub fn draw_circle(&mut self, center_x: i32, center_y: i32, radius: u32, value: PixelValue) {
+        if radius == 0 {
+            return;
+        }
+        let (center_x, center_y, radius) =
+            (i64::from(center_x), i64::from(center_y), i64::from(radius));
+        let bounds = self.clip_bounds();
+        if center_x + radius < bounds.x as i64
+            || center_y + radius < bounds.y as i64
+            || center_x - radius >= bounds.right() as i64
+            || center_y - radius >= bounds.bottom() as i64
+        {
+            return;
+        }
+
+        // Midpoint circle, walking one octant and mirroring it into the other seven
+        let (mut x, mut y) = (radius, 0);
+        let mut error = 1 - radius;
+        while x >= y {
+            for (dx, dy) in [
+                (x, y),
+                (y, x),
+                (-y, x),
+                (-x, y),
+                (-x, -y),
+                (-y, -x),
+                (y, -x),
+                (x, -y),
+            ] {
+                self.set_pixel_clipped(center_x + dx, center_y + dy, value);
+            }
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Sets every pixel between two corners, inclusive, which falls on the buffer.
+    fn fill_clipped(&mut self, left: i64, top: i64, right: i64, bottom: i64, value: PixelValue) {
+        let bounds = self.clip_bounds();
+        let (left, top) = (left.max(bounds.x as i64), top.max(bounds.y as i64));
+        let right = right.min(bounds.right() as i64 - 1);
+        let bottom = bottom.min(bounds.bottom() as i64 - 1);
+        for y in top..=bottom {
+            for x in left..=right {
+                self.set_pixel_clipped(x, y, value);
+            }
+        }
+    }
+
+    fn set_pixel_clipped(&mut self, x: i64, y: i64, value: PixelValue) {
+        let Some((row, col)) = self.clipped_cell(y, x) else {
+            return;
+        };
+        let _ = match value {
+            PixelValue::Mono(is_lit) => self.set_cell(row, col, is_lit),
+            PixelValue::Rgb(color) => self.set_pixel_rgb(row, col, color),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tests::picture, Rgb555, DEFAULT_MONO_PALETTE};
+    use super::*;
+
+    const LIT: PixelValue = PixelValue::Mono(true);
+
+    #[test]
+    fn lines_are_walked_from_end_to_end() {
+        let mut buffer = ScreenBuffer::new(5, 4, None);
+        buffer.draw_line(0, 0, 4, 3, LIT);
+        buffer.draw_line(4, 0, 4, 0, LIT);
+        assert_eq!(picture(&buffer), ["#...#", ".#...", "..##.", "....#"]);
+    }
+
+    #[test]
+    fn lines_are_clipped_to_the_buffer() {
+        let mut buffer = ScreenBuffer::new(5, 3, None);
+        buffer.draw_line(-3, 1, 7, 1, LIT);
+        buffer.draw_line(2, -10, 2, 10, LIT);
+        assert_eq!(picture(&buffer), ["..#..", "#####", "..#.."]);
+
+        let mut buffer = ScreenBuffer::new(5, 3, None);
+        buffer.draw_line(-5, -1, 10, -1, LIT);
+        buffer.draw_line(5, 0, 9, 2, LIT);
+        buffer.draw_line(i32::MIN, i32::MIN, i32::MIN, i32::MAX, LIT);
+        assert_eq!(picture(&buffer), [".....", ".....", "....."]);
+    }
+
+    #[test]
+    fn rectangles_are_outlined_or_filled() {
+        let mut buffer = ScreenBuffer::new(6, 5, None);
+        buffer.draw_rect(0, 0, 4, 4, LIT);
+        buffer.fill_rect(4, 3, 5, 5, LIT);
+        assert_eq!(
+            picture(&buffer),
+            ["####..", "#..#..", "#..#..", "######", "....##"]
+        );
+    }
+
+    #[test]
+    fn rectangles_partly_off_the_buffer_are_clipped() {
+        let mut buffer = ScreenBuffer::new(4, 3, None);
+        buffer.draw_rect(-1, -1, 3, 3, LIT);
+        buffer.fill_rect(3, -5, u32::MAX, u32::MAX, LIT);
+        assert_eq!(picture(&buffer), [".#.#", "##.#", "...#"]);
+    }
+
+    #[test]
+    fn empty_shapes_draw_nothing() {
+        let mut buffer = ScreenBuffer::new(4, 3, None);
+        buffer.draw_rect(1, 1, 0, 2, LIT);
+        buffer.fill_rect(1, 1, 2, 0, LIT);
+        buffer.draw_circle(1, 1, 0, LIT);
+        buffer.draw_circle(-10, -10, 3, LIT);
+        assert_eq!(picture(&buffer), ["....", "....", "...."]);
+    }
+
+    #[test]
+    fn circles_are_outlined_around_their_center() {
+        let mut buffer = ScreenBuffer::new(7, 7, None);
+        buffer.draw_circle(3, 3, 2, LIT);
+        assert_eq!(
+            picture(&buffer),
+            [".......", "..###..", ".#...#.", ".#...#.", ".#...#.", "..###..", ".......",]
+        );
+
+        // Only the part of the circle on the buffer is drawn
+        let mut buffer = ScreenBuffer::new(3, 3, None);
+        buffer.draw_circle(0, 0, 2, LIT);
+        assert_eq!(picture(&buffer), ["..#", "..#", "##."]);
+    }
+
+    #[test]
+    fn shapes_are_drawn_in_color_on_rgb_buffers() {
+        let mut buffer = ScreenBuffer::new(3, 2, Some(DEFAULT_MONO_PALETTE));
+        buffer.draw_line(0, 0, 2, 0, PixelValue::Rgb(Rgb555::GREEN));
+        buffer.fill_rect(1, 1, 1, 1, PixelValue::Rgb(Rgb555::BLUE));
+        assert_eq!(buffer.get_row_rgb(0).unwrap(), [Rgb555::GREEN.raw(); 3]);
+        assert_eq!(
+            buffer.get_row_rgb(1).unwrap(),
+            [Rgb555::BLACK.raw(), Rgb555::BLUE.raw(), Rgb555::BLACK.raw()]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use super::ScreenBuffer;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A handle to a screen buffer which can be drawn on and looked at from several places at once,
+/// including other threads, e.g. to take snapshots of the display while an app is running.
+/// Cloning the handle gives another handle to the same buffer.
+///
+/// To keep from deadlocking, locks are only ever taken in this order, and nothing waits on a
+/// lock earlier in the order while holding one later in it:
+///
+/// 1. An app's host state, which host functions lock for the whole call
+/// 2. The app's offscreen buffers
+/// 3. The app's virtual screen
+/// 4. The screen buffer of the [`CompositeDisplay`](super::CompositeDisplay)
+///
+/// Observers should only ever hold one of these at a time, and not for long, which is what
+/// [`SharedScreenBuffer::read_snapshot`] is for. The locks aren't reentrant, so a buffer also
+/// mustn't be locked again by the thread already holding it.
+#[derive(Debug, Clone)]
+pub struct SharedScreenBuffer(Arc<RwLock<ScreenBuffer>>);
+
+impl SharedScreenBuffer {
+    pub fn new(screen_buffer: ScreenBuffer) -> Self {
+        SharedScreenBuffer(Arc::new(RwLock::new(screen_buffer)))
+    }
+
+    /// Locks the buffer for reading, waiting for anything drawing on it to finish.
+    pub fn read(&self) -> RwLockReadGuard<'_, ScreenBuffer> {
+        self.0.read().unwrap()
+    }
+
+    /// Locks the buffer for drawing, waiting for everything else using it to finish.
+    pub fn write(&self) -> RwLockWriteGuard<'_, ScreenBuffer> {
+        self.0.write().unwrap()
+    }
+
+    /// A copy of the buffer as it is now. The buffer is only locked for as long as it takes to
+    /// copy it, so observers can take snapshots without holding up rendering.
+    pub fn read_snapshot(&self) -> ScreenBuffer {
+        self.read().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    const FRAMES: usize = 1000;
+
+    /// The value every pixel of the buffer has, or `None` if they differ.
+    fn uniform_pixel(buffer: &ScreenBuffer) -> Option<bool> {
+        let mut pixels = buffer
+            .rows()
+            .unwrap()
+            .flat_map(|(_, row)| row.iter().copied());
+        let first = pixels.next()?;
+        pixels.all(|pixel| pixel == first).then_some(first)
+    }
+
+    #[test]
+    fn observers_never_see_a_frame_half_drawn() {
+        let shared = SharedScreenBuffer::new(ScreenBuffer::new(32, 16, None));
+        let done = Arc::new(AtomicBool::new(false));
+        let looks = Arc::new(AtomicUsize::new(0));
+        let observers = (0..4)
+            .map(|observer| {
+                let (shared, done, looks) = (shared.clone(), done.clone(), looks.clone());
+                std::thread::spawn(move || {
+                    // At least one look is taken, however quickly the frames are drawn
+                    loop {
+                        // Every frame is drawn a pixel at a time, so one which isn't all the same
+                        // was looked at partway through drawing
+                        let pixel = if observer % 2 == 0 {
+                            uniform_pixel(&shared.read_snapshot())
+                        } else {
+                            uniform_pixel(&shared.read())
+                        };
+                        assert!(pixel.is_some(), "Saw a half drawn frame");
+                        looks.fetch_add(1, Ordering::Relaxed);
+                        if done.load(Ordering::Relaxed) {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let renderers = (0..2)
+            .map(|renderer| {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    for frame in 0..FRAMES {
+                        let mut screen_buffer = shared.write();
+                        let lit = (frame + renderer) % 2 == 0;
+                        for row in 0..screen_buffer.height {
+                            for col in 0..screen_buffer.width {
+                                screen_buffer.set_cell(row, col, lit).unwrap();
+                            }
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for renderer in renderers {
+            renderer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        for observer in observers {
+            observer.join().unwrap();
+        }
+        assert!(looks.load(Ordering::Relaxed) >= 4);
+        assert!(uniform_pixel(&shared.read()).is_some());
+    }
+}
@@ -0,0 +1,50 @@
+use super::{Rgb555, ScreenBuffer, ScreenBufferKind};
+use std::io;
+
+/// Color of the lines drawn between pixels when a snapshot has a grid
+const GRID_COLOR: [u8; 3] = [0x40, 0x40, 0x40];
+
+impl ScreenBuffer {
+    /// Encodes the buffer as a PNG with every pixel drawn as a `scale` by `scale` square.
+    /// Monocolor buffers are drawn in white and black. With `grid`, the last row and column of
+    /// each square are drawn as a grid line instead, which needs a scale of at least 2 to leave
+    /// anything of the pixels.
+    pub fn to_png(&self, scale: u32, grid: bool) -> io::Result<Vec<u8>> {
+        let image = self.to_image(scale, grid)?;
+        let mut png = io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut png, ::image::ImageFormat::Png)
+            .map_err(io::Error::other)?;
+        Ok(png.into_inner())
+    }
+
+    /// The buffer drawn as it would be in a PNG from [`ScreenBuffer::to_png`].
+    pub(crate) fn to_image(&self, scale: u32, grid: bool) -> io::Result<::image::RgbImage> {
+        if scale == 0 || (grid && scale < 2) {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        Ok(::image::RgbImage::from_fn(
+            self.width as u32 * scale,
+            self.height as u32 * scale,
+            |x, y| {
+                if grid && (x % scale == scale - 1 || y % scale == scale - 1) {
+                    return ::image::Rgb(GRID_COLOR);
+                }
+                let index = (y / scale) as usize * self.width + (x / scale) as usize;
+                ::image::Rgb(match &self.buffer {
+                    ScreenBufferKind::Monocolor(buffer) => {
+                        if buffer[index] {
+                            [0xff; 3]
+                        } else {
+                            [0x00; 3]
+                        }
+                    }
+                    ScreenBufferKind::Rgb555(buffer, _) => {
+                        let (red, green, blue) = Rgb555::from_raw(buffer[index]).to_rgb888();
+                        [red, green, blue]
+                    }
+                })
+            },
+        ))
+    }
+}
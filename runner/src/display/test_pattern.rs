@@ -0,0 +1,88 @@
+use super::{PixelValue, Rgb555, ScreenBuffer};
+
+/// Colors of the bars in [`TestPattern::ColorBars`], from left to right
+const COLOR_BARS: [Rgb555; 8] = [
+    Rgb555::WHITE,
+    Rgb555::YELLOW,
+    Rgb555::CYAN,
+    Rgb555::GREEN,
+    Rgb555::MAGENTA,
+    Rgb555::RED,
+    Rgb555::BLUE,
+    Rgb555::BLACK,
+];
+
+/// Known images for checking that a panel shows what it's sent, without needing an app
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPattern {
+    /// Neighbouring pixels alternate between on and off, starting with the top left on
+    Checkerboard,
+    /// Gray ramping from black at the top to white at the bottom, dithered on monocolor buffers
+    VerticalGradient,
+    /// Vertical bars of white, yellow, cyan, green, magenta, red, blue, and black
+    ColorBars,
+    /// A one pixel border around the edge and lines between opposite corners
+    BorderWithCross,
+    /// Each column shows its index in binary with the least significant bit at the top,
+    /// repeated down the display, so columns which end up in the wrong place stand out
+    PixelIndexBits,
+}
+
+impl TestPattern {
+    pub const ALL: [TestPattern; 5] = [
+        TestPattern::Checkerboard,
+        TestPattern::VerticalGradient,
+        TestPattern::ColorBars,
+        TestPattern::BorderWithCross,
+        TestPattern::PixelIndexBits,
+    ];
+}
+
+impl ScreenBuffer {
+    /// Replaces everything on the buffer with a test pattern.
+    pub fn draw_test_pattern(&mut self, pattern: TestPattern) {
+        self.clear();
+        let (width, height) = (self.width, self.height);
+        match pattern {
+            TestPattern::Checkerboard => {
+                for row in 0..height {
+                    for col in 0..width {
+                        let _ = self.set_cell(row, col, (row + col).is_multiple_of(2));
+                    }
+                }
+            }
+            TestPattern::VerticalGradient => {
+                for row in 0..height {
+                    let level = (row * 255 / height.saturating_sub(1).max(1)) as u8;
+                    let color = Rgb555::from_rgb888(level, level, level);
+                    for col in 0..width {
+                        let _ = self.set_pixel_rgb(row, col, color);
+                    }
+                }
+            }
+            TestPattern::ColorBars => {
+                for col in 0..width {
+                    let color = COLOR_BARS[col * COLOR_BARS.len() / width];
+                    for row in 0..height {
+                        let _ = self.set_pixel_rgb(row, col, color);
+                    }
+                }
+            }
+            TestPattern::BorderWithCross => {
+                let (right, bottom) = (width as i32 - 1, height as i32 - 1);
+                let lit = PixelValue::Mono(true);
+                self.draw_rect(0, 0, width as u32, height as u32, lit);
+                self.draw_line(0, 0, right, bottom, lit);
+                self.draw_line(right, 0, 0, bottom, lit);
+            }
+            TestPattern::PixelIndexBits => {
+                let bits = (usize::BITS - width.saturating_sub(1).leading_zeros()).max(1) as usize;
+                for row in 0..height {
+                    for col in 0..width {
+                        let _ = self.set_cell(row, col, col & (1 << (row % bits)) != 0);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use super::{CompositeDisplay, PixelValue, ScreenBuffer};
+use std::{
+    io,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// The way a transition moves across the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// How the display changes from one screen to another
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransitionKind {
+    /// Switches straight to the new screen
+    #[default]
+    Cut,
+    /// Pushes the old screen off the display with the new one following it in
+    Slide(Direction),
+    /// Draws the new screen over the old one behind an edge sweeping across the display
+    Wipe(Direction),
+    /// Swaps pixels over to the new screen a few at a time in a scattered order
+    Dissolve,
+}
+
+/// An animation from one screen to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration: Duration,
+    /// Frames per second the transition is drawn at
+    pub frame_rate: u32,
+}
+
+/// Which of the two screens a pixel of a transition frame is taken from
+enum Source {
+    Outgoing,
+    Incoming,
+}
+
+impl FromStr for TransitionKind {
+    type Err = io::Error;
+
+    /// Parses names like `cut`, `slide-left`, `wipe-down`, or `dissolve`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let direction = |direction| match direction {
+            "left" => Some(Direction::Left),
+            "right" => Some(Direction::Right),
+            "up" => Some(Direction::Up),
+            "down" => Some(Direction::Down),
+            _ => None,
+        };
+        let kind = match s.split_once('-') {
+            None if s == "cut" => Some(TransitionKind::Cut),
+            None if s == "dissolve" => Some(TransitionKind::Dissolve),
+            Some(("slide", to)) => direction(to).map(TransitionKind::Slide),
+            Some(("wipe", to)) => direction(to).map(TransitionKind::Wipe),
+            _ => None,
+        };
+        kind.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Expected cut, dissolve, or slide or wipe followed by -left, -right, -up, or \
+                     -down, got {s}"
+                ),
+            )
+        })
+    }
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Transition {
+            kind: TransitionKind::Cut,
+            duration: Duration::from_millis(500),
+            frame_rate: 30,
+        }
+    }
+}
+
+impl Transition {
+    /// Number of frames drawn between the two screens, the last of which is the incoming screen.
+    /// Cuts have none.
+    pub fn frame_count(&self) -> u32 {
+        if self.kind == TransitionKind::Cut {
+            return 0;
+        }
+        (self.duration.as_secs_f64() * f64::from(self.frame_rate)).round() as u32
+    }
+
+    /// Draws `frame` out of [`Transition::frame_count`] into `target`, which must be the same size
+    /// as both screens. Only pixels which actually change are written, so parts of the display
+    /// which look the same on both screens are never dirtied.
+    pub fn draw_frame(
+        &self,
+        (outgoing, incoming): (&ScreenBuffer, &ScreenBuffer),
+        frame: u32,
+        target: &mut ScreenBuffer,
+    ) -> io::Result<()> {
+        let config = target.display_config();
+        let size = (config.width, config.height);
+        for buffer in [outgoing, incoming] {
+            let buffer_config = buffer.display_config();
+            if (buffer_config.width, buffer_config.height) != size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Can't transition to a {}x{} display from a {}x{} screen",
+                        config.width, config.height, buffer_config.width, buffer_config.height
+                    ),
+                ));
+            }
+        }
+        let progress = match self.frame_count() {
+            0 => 1.0,
+            count => f64::from(frame.min(count)) / f64::from(count),
+        };
+        for row in 0..config.height {
+            for col in 0..config.width {
+                let (source, (source_row, source_col)) = self.source(progress, (row, col), size);
+                let value = match source {
+                    Source::Outgoing => outgoing.get_pixel(source_row, source_col)?,
+                    Source::Incoming => incoming.get_pixel(source_row, source_col)?,
+                };
+                if target.get_pixel(row, col)? == value {
+                    continue;
+                }
+                match value {
+                    PixelValue::Mono(is_lit) => target.set_cell(row, col, is_lit)?,
+                    PixelValue::Rgb(color) => target.set_pixel_rgb(row, col, color)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the pixel at `position`, given as (row, column), comes from partway through the
+    /// transition on a display of the given width and height.
+    fn source(
+        &self,
+        progress: f64,
+        (row, col): (usize, usize),
+        (width, height): (usize, usize),
+    ) -> (Source, (usize, usize)) {
+        let moved = |length: usize| (progress * length as f64).round() as usize;
+        match self.kind {
+            TransitionKind::Cut => (Source::Incoming, (row, col)),
+            TransitionKind::Slide(direction) => {
+                // Both screens are laid out end to end and shifted along by how far the
+                // transition has got, so positions past the end of one land on the other
+                let (length, position) = match direction {
+                    Direction::Left | Direction::Right => (width, col),
+                    Direction::Up | Direction::Down => (height, row),
+                };
+                let shift = moved(length);
+                let (source, position) = match direction {
+                    Direction::Left | Direction::Up if position + shift >= length => {
+                        (Source::Incoming, position + shift - length)
+                    }
+                    Direction::Left | Direction::Up => (Source::Outgoing, position + shift),
+                    Direction::Right | Direction::Down if position < shift => {
+                        (Source::Incoming, position + length - shift)
+                    }
+                    Direction::Right | Direction::Down => (Source::Outgoing, position - shift),
+                };
+                match direction {
+                    Direction::Left | Direction::Right => (source, (row, position)),
+                    Direction::Up | Direction::Down => (source, (position, col)),
+                }
+            }
+            TransitionKind::Wipe(direction) => {
+                let is_revealed = match direction {
+                    Direction::Left => col >= width - moved(width),
+                    Direction::Right => col < moved(width),
+                    Direction::Up => row >= height - moved(height),
+                    Direction::Down => row < moved(height),
+                };
+                let source = if is_revealed {
+                    Source::Incoming
+                } else {
+                    Source::Outgoing
+                };
+                (source, (row, col))
+            }
+            TransitionKind::Dissolve => {
+                let source = if scatter(row * width + col) < progress {
+                    Source::Incoming
+                } else {
+                    Source::Outgoing
+                };
+                (source, (row, col))
+            }
+        }
+    }
+}
+
+/// A fraction from 0 up to 1 which looks random but is always the same for the same pixel, so
+/// pixels which have dissolved stay that way as the transition goes on.
+fn scatter(index: usize) -> f64 {
+    // The finalizer of splitmix64, which spreads neighbouring inputs all over the output range
+    let mut hash = index as u64;
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^= hash >> 31;
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl CompositeDisplay {
+    /// Animates the display from `outgoing` to `incoming`, leaving the screen buffer showing
+    /// `incoming`. Frames are sent without waiting for the device, and if it falls far enough
+    /// behind that a send queue fills up the rest of the transition is skipped and the display
+    /// cut straight to `incoming`. Returns whether the whole transition was shown.
+    pub fn play_transition(
+        &mut self,
+        transition: &Transition,
+        outgoing: &ScreenBuffer,
+        incoming: &ScreenBuffer,
+    ) -> io::Result<bool> {
+        let frame_count = transition.frame_count();
+        let frame_period = Duration::from_secs(1) / transition.frame_rate.max(1);
+        let mut is_complete = true;
+        for frame in 1..frame_count {
+            let start_time = Instant::now();
+            transition.draw_frame((outgoing, incoming), frame, &mut self.screen_buffer_mut())?;
+            match self.try_render_dirty() {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    tracing::debug!(
+                        "The display couldn't keep up with the transition at frame {frame} of \
+                         {frame_count}, cutting to the end"
+                    );
+                    is_complete = false;
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+            std::thread::sleep(frame_period.saturating_sub(start_time.elapsed()));
+        }
+        transition.draw_frame(
+            (outgoing, incoming),
+            frame_count,
+            &mut self.screen_buffer_mut(),
+        )?;
+        self.render_dirty()?;
+        Ok(is_complete)
+    }
+}
@@ -0,0 +1,140 @@
+use super::{PixelValue, Rgb555, ScreenBuffer};
+use std::io;
+
+impl ScreenBuffer {
+    /// Draws a `width` by `height` bar with its top left corner at (`x`, `y`), filled from the
+    /// left in `foreground` by `fraction` of the way across and `background` for the rest.
+    /// Fractions outside of 0 to 1 are clamped, and NaN is drawn as empty. With a border the bar
+    /// is outlined in `foreground` and filled inside of the outline.
+    pub fn draw_progress_bar(
+        &mut self,
+        (x, y): (i32, i32),
+        (width, height): (u32, u32),
+        fraction: f32,
+        (foreground, background): (Rgb555, Rgb555),
+        border: bool,
+    ) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (x, y, width, height) = if border {
+            self.draw_rect(x, y, width, height, PixelValue::Rgb(foreground));
+            (
+                x.saturating_add(1),
+                y.saturating_add(1),
+                width.saturating_sub(2),
+                height.saturating_sub(2),
+            )
+        } else {
+            (x, y, width, height)
+        };
+        let fraction = if fraction.is_nan() {
+            0.0
+        } else {
+            fraction.clamp(0.0, 1.0)
+        };
+        let filled = (fraction * width as f32).round() as u32;
+        self.fill_rect(x, y, filled, height, PixelValue::Rgb(foreground));
+        self.fill_rect(
+            x.saturating_add(filled as i32),
+            y,
+            width - filled,
+            height,
+            PixelValue::Rgb(background),
+        );
+    }
+
+    /// Draws `values` as a line graph spread evenly across a `width` by `height` box with its
+    /// top left corner at (`x`, `y`), with the area under the line filled in if `fill` is set.
+    ///
+    /// The graph is scaled so the lowest value is on the bottom row and the highest on the top
+    /// row, unless either end of `range` is fixed, in which case values beyond it are drawn at
+    /// the edge. NaN and infinite values are gaps in the line. Fails if the fixed ends of the
+    /// range aren't finite or are the wrong way round.
+    pub fn draw_sparkline(
+        &mut self,
+        (x, y): (i32, i32),
+        (width, height): (u32, u32),
+        values: &[f32],
+        range: (Option<f32>, Option<f32>),
+        color: Rgb555,
+        fill: bool,
+    ) -> io::Result<()> {
+        let finite = || values.iter().copied().filter(|value| value.is_finite());
+        let low = range
+            .0
+            .unwrap_or_else(|| finite().fold(f32::INFINITY, f32::min));
+        let high = range
+            .1
+            .unwrap_or_else(|| finite().fold(f32::NEG_INFINITY, f32::max));
+        if width == 0 || height == 0 || finite().next().is_none() {
+            return Ok(());
+        }
+        if !low.is_finite() || !high.is_finite() || low > high {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Can't draw a sparkline from {low} to {high}"),
+            ));
+        }
+
+        let bottom = height as f32 - 1.0;
+        // Rows and columns are kept fractional until they're drawn, so filled columns between
+        // two points follow the line
+        let row_of = |value: f32| {
+            if low == high {
+                (bottom / 2.0).floor()
+            } else {
+                bottom - (value.clamp(low, high) - low) / (high - low) * bottom
+            }
+        };
+        let col_of = |index: usize| match values.len() {
+            1 => ((width - 1) / 2) as f32,
+            count => index as f32 * (width - 1) as f32 / (count - 1) as f32,
+        };
+        let point = |index: usize| {
+            let value = *values.get(index)?;
+            value
+                .is_finite()
+                .then(|| (col_of(index).round(), row_of(value)))
+        };
+        let to_pixel = |(col, row): (f32, f32)| {
+            (
+                x.saturating_add(col as i32),
+                y.saturating_add(row.round() as i32),
+            )
+        };
+
+        for index in 0..values.len() {
+            let Some(start) = point(index) else {
+                continue;
+            };
+            let end = point(index + 1);
+            let isolated = end.is_none() && index.checked_sub(1).and_then(point).is_none();
+            if fill {
+                let (start_col, end_col) = match end {
+                    Some(end) => (start.0 as u32, end.0 as u32),
+                    None if isolated => (start.0 as u32, start.0 as u32),
+                    None => continue,
+                };
+                for col in start_col..=end_col {
+                    let row = match end {
+                        Some(end) if end.0 > start.0 => {
+                            start.1 + (end.1 - start.1) * (col as f32 - start.0) / (end.0 - start.0)
+                        }
+                        _ => start.1,
+                    };
+                    let (pixel_x, pixel_y) = to_pixel((col as f32, row));
+                    let fill_height = height - row.round() as u32;
+                    self.fill_rect(pixel_x, pixel_y, 1, fill_height, PixelValue::Rgb(color));
+                }
+            } else if let Some(end) = end {
+                let ((x0, y0), (x1, y1)) = (to_pixel(start), to_pixel(end));
+                self.draw_line(x0, y0, x1, y1, PixelValue::Rgb(color));
+            } else if isolated {
+                let (pixel_x, pixel_y) = to_pixel(start);
+                self.fill_rect(pixel_x, pixel_y, 1, 1, PixelValue::Rgb(color));
+            }
+        }
+        Ok(())
+    }
+}
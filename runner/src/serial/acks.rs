@@ -0,0 +1,265 @@
+use super::DeviceError;
+use megabit_serial_protocol::{
+    CommitRenderResponse, SerialMessage, SetBrightnessResponse, SetLedStateResponse,
+    SetRgbStateResponse, Status, UpdateRowResponse, UpdateRowRgbResponse,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+use tokio::sync::oneshot;
+
+/// Acknowledgements of a single kind which are kept track of at once. Firmware which never
+/// acknowledges a kind of message would otherwise have them pile up for as long as it's
+/// connected.
+const MAX_PENDING_ACKS: usize = 1024;
+
+/// The messages the device acknowledges, grouped by the response it acknowledges them with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum AckKind {
+    UpdateRow,
+    /// Both RGB row formats are acknowledged with the same response
+    UpdateRowRgb,
+    CommitRender,
+    SetLedState,
+    SetRgbState,
+    SetBrightness,
+}
+
+impl AckKind {
+    /// The kind of acknowledgement the device answers `msg` with, if it acknowledges it at all.
+    fn of_request(msg: &SerialMessage) -> Option<Self> {
+        Some(match msg {
+            SerialMessage::UpdateRow(_) => AckKind::UpdateRow,
+            SerialMessage::UpdateRowRgb(_) | SerialMessage::UpdateRowRgb888(_) => {
+                AckKind::UpdateRowRgb
+            }
+            SerialMessage::CommitRender(_) => AckKind::CommitRender,
+            SerialMessage::SetLedState(_) => AckKind::SetLedState,
+            SerialMessage::SetRgbState(_) => AckKind::SetRgbState,
+            SerialMessage::SetBrightness(_) => AckKind::SetBrightness,
+            _ => return None,
+        })
+    }
+
+    /// The kind of acknowledgement `msg` is along with the status it carries, if it's one.
+    fn of_response(msg: &SerialMessage) -> Option<(Self, Status)> {
+        let (kind, status) = match msg {
+            SerialMessage::UpdateRowResponse(UpdateRowResponse { status }) => {
+                (AckKind::UpdateRow, status)
+            }
+            SerialMessage::UpdateRowRgbResponse(UpdateRowRgbResponse { status }) => {
+                (AckKind::UpdateRowRgb, status)
+            }
+            SerialMessage::CommitRenderResponse(CommitRenderResponse { status }) => {
+                (AckKind::CommitRender, status)
+            }
+            SerialMessage::SetLedStateResponse(SetLedStateResponse { status }) => {
+                (AckKind::SetLedState, status)
+            }
+            SerialMessage::SetRgbStateResponse(SetRgbStateResponse { status }) => {
+                (AckKind::SetRgbState, status)
+            }
+            SerialMessage::SetBrightnessResponse(SetBrightnessResponse { status }) => {
+                (AckKind::SetBrightness, status)
+            }
+            _ => return None,
+        };
+        Some((kind, status.clone()))
+    }
+}
+
+#[derive(Debug)]
+struct PendingAck {
+    request: &'static str,
+    /// Where the status goes if somebody is waiting for it
+    waiter: Option<oneshot::Sender<Status>>,
+}
+
+/// Matches the device's acknowledgements up with the messages they answer. The device carries
+/// out messages in the order they're written, so an acknowledgement belongs to the oldest
+/// message of its kind which is still waiting for one. Acknowledgements which nobody is waiting
+/// for, like those of queued and batched rows, are dealt with here instead of being left in the
+/// inbox, and the first rejection among them is kept until it's taken by a flush.
+#[derive(Debug, Default)]
+pub struct AckTracker {
+    pending: Mutex<HashMap<AckKind, VecDeque<PendingAck>>>,
+    rejection: Mutex<Option<DeviceError>>,
+}
+
+impl AckTracker {
+    /// Records that `msgs` are about to be written, so their acknowledgements can be told
+    /// apart. `waiter` is sent the status of the first of them which the device acknowledges.
+    pub fn expect(&self, msgs: &[SerialMessage], mut waiter: Option<oneshot::Sender<Status>>) {
+        let mut pending = self.pending.lock().unwrap();
+        for msg in msgs {
+            let Some(kind) = AckKind::of_request(msg) else {
+                continue;
+            };
+            let queue = pending.entry(kind).or_default();
+            if queue.len() == MAX_PENDING_ACKS {
+                queue.pop_front();
+            }
+            queue.push_back(PendingAck {
+                request: msg.into(),
+                waiter: waiter.take(),
+            });
+        }
+    }
+
+    /// Hands an acknowledgement to the message it answers. Returns whether `msg` was an
+    /// acknowledgement which was expected, and so shouldn't go on to the inbox.
+    pub fn resolve(&self, msg: &SerialMessage) -> bool {
+        let Some((kind, status)) = AckKind::of_response(msg) else {
+            return false;
+        };
+        let Some(pending_ack) = self
+            .pending
+            .lock()
+            .unwrap()
+            .get_mut(&kind)
+            .and_then(VecDeque::pop_front)
+        else {
+            return false;
+        };
+        match pending_ack.waiter {
+            // Whoever was waiting may have given up already, which is fine
+            Some(waiter) => {
+                let _ = waiter.send(status);
+            }
+            None => {
+                if let Some(err) = DeviceError::from_status(&status, pending_ack.request) {
+                    tracing::debug!("{err}");
+                    self.rejection.lock().unwrap().get_or_insert(err);
+                }
+            }
+        }
+        true
+    }
+
+    /// Forgets the acknowledgements owed for messages like `msg` up to the last one whose
+    /// waiter has given up on it. Anything written before a message which went unacknowledged
+    /// for that long isn't going to be acknowledged either, e.g. because it was lost on the way
+    /// to the device, and would otherwise be matched up with the acknowledgements of messages
+    /// written after it.
+    pub fn forget_abandoned(&self, msg: &SerialMessage) {
+        let Some(kind) = AckKind::of_request(msg) else {
+            return;
+        };
+        let mut pending = self.pending.lock().unwrap();
+        let Some(queue) = pending.get_mut(&kind) else {
+            return;
+        };
+        let abandoned = queue.iter().rposition(|pending_ack| {
+            pending_ack
+                .waiter
+                .as_ref()
+                .is_some_and(oneshot::Sender::is_closed)
+        });
+        if let Some(abandoned) = abandoned {
+            queue.drain(..=abandoned);
+        }
+    }
+
+    /// The first rejection of a message nobody was waiting on since the last time this was
+    /// called.
+    pub fn take_rejection(&self) -> Option<DeviceError> {
+        self.rejection.lock().unwrap().take()
+    }
+
+    /// Forgets every acknowledgement still owed, e.g. once the device has gone away. Anybody
+    /// waiting on one is told it isn't coming.
+    pub fn clear(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use megabit_serial_protocol::{SetLedState, UpdateRow};
+
+    fn row(row_number: u8) -> SerialMessage {
+        SerialMessage::UpdateRow(UpdateRow {
+            row_number,
+            column_offset: 0,
+            row_data_len: 1,
+            row_data: vec![0],
+        })
+    }
+
+    fn row_ack(status: Status) -> SerialMessage {
+        SerialMessage::UpdateRowResponse(UpdateRowResponse { status })
+    }
+
+    #[test]
+    fn acks_go_to_the_oldest_message_of_their_kind() {
+        let acks = AckTracker::default();
+        let (led_tx, mut led_rx) = oneshot::channel();
+        let (row_tx, mut row_rx) = oneshot::channel();
+        // A batch of rows nobody waits on, then a row and an LED change which are waited on
+        acks.expect(&[row(0), row(1)], None);
+        acks.expect(&[row(2)], Some(row_tx));
+        acks.expect(
+            &[SerialMessage::SetLedState(SetLedState { new_state: true })],
+            Some(led_tx),
+        );
+
+        assert!(
+            acks.resolve(&SerialMessage::SetLedStateResponse(SetLedStateResponse {
+                status: Status::Success
+            }))
+        );
+        assert!(matches!(led_rx.try_recv(), Ok(Status::Success)));
+        assert!(acks.resolve(&row_ack(Status::Success)));
+        assert!(acks.resolve(&row_ack(Status::Success)));
+        assert!(row_rx.try_recv().is_err());
+        assert!(acks.resolve(&row_ack(Status::Failure)));
+        assert!(matches!(row_rx.try_recv(), Ok(Status::Failure)));
+        // Nothing is owed any more, so another ack is left for the inbox
+        assert!(!acks.resolve(&row_ack(Status::Success)));
+        assert!(!acks.resolve(&SerialMessage::PingResponse));
+    }
+
+    #[test]
+    fn rejections_nobody_waited_for_are_kept_until_taken() {
+        let acks = AckTracker::default();
+        acks.expect(&[row(0), row(1), row(2)], None);
+        acks.resolve(&row_ack(Status::Success));
+        acks.resolve(&row_ack(Status::Failure));
+        acks.resolve(&row_ack(Status::InProgress));
+
+        assert_eq!(
+            acks.take_rejection(),
+            Some(DeviceError::Rejected {
+                request: "UpdateRow".to_owned()
+            })
+        );
+        assert_eq!(acks.take_rejection(), None);
+    }
+
+    #[test]
+    fn abandoned_acks_are_forgotten_along_with_everything_before_them() {
+        let acks = AckTracker::default();
+        let (lost_tx, lost_rx) = oneshot::channel();
+        let (retry_tx, mut retry_rx) = oneshot::channel();
+        acks.expect(&[row(0)], None);
+        acks.expect(&[row(1)], Some(lost_tx));
+        drop(lost_rx);
+        acks.forget_abandoned(&row(1));
+        acks.expect(&[row(1)], Some(retry_tx));
+
+        assert!(acks.resolve(&row_ack(Status::Success)));
+        assert!(matches!(retry_rx.try_recv(), Ok(Status::Success)));
+    }
+
+    #[test]
+    fn acks_for_firmware_which_never_sends_them_stay_bounded() {
+        let acks = AckTracker::default();
+        for _ in 0..MAX_PENDING_ACKS * 2 {
+            acks.expect(&[row(0)], None);
+        }
+        let pending = acks.pending.lock().unwrap();
+        assert_eq!(pending[&AckKind::UpdateRow].len(), MAX_PENDING_ACKS);
+    }
+}
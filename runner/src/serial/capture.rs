@@ -0,0 +1,165 @@
+//! Records the raw bytes exchanged with the device so framing problems can be debugged offline.
+//!
+//! A capture file starts with [`CAPTURE_MAGIC`] followed by records made up of a direction byte
+//! (`0` for host to device, `1` for device to host), the microseconds since the capture started
+//! as a big-endian `u64`, the length of the data as a big-endian `u32`, and then the data exactly
+//! as it was written to or read from the stream.
+
+use super::framing::FrameDecoder;
+use async_channel::{Receiver, Sender, TrySendError};
+use megabit_serial_protocol::SerialMessage;
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+pub const CAPTURE_MAGIC: &[u8; 8] = b"MBCAP\x00\x00\x01";
+const CAPTURE_QUEUE_DEPTH: usize = 1024;
+const RECORD_HEADER_LEN: usize = 13;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    ToDevice,
+    FromDevice,
+}
+
+#[derive(Clone, Debug)]
+pub struct CaptureRecord {
+    pub direction: Direction,
+    /// Time since the capture started
+    pub timestamp: Duration,
+    pub data: Vec<u8>,
+}
+
+/// Handle for recording traffic. Records are handed to a separate task which writes them to the
+/// file, so recording never waits on the filesystem. If the writer falls behind, records are
+/// dropped rather than stalling the serial task.
+#[derive(Clone, Debug)]
+pub struct Capture {
+    record_tx: Sender<CaptureRecord>,
+    start_time: Instant,
+}
+
+impl Capture {
+    /// Creates the capture handle along with the task which writes records to `path`.
+    pub fn start(path: PathBuf) -> (Self, impl Future<Output = ()>) {
+        let (record_tx, record_rx) = async_channel::bounded(CAPTURE_QUEUE_DEPTH);
+        let capture = Self {
+            record_tx,
+            start_time: Instant::now(),
+        };
+        (capture, capture_task(path, record_rx))
+    }
+
+    pub fn record(&self, direction: Direction, data: &[u8]) {
+        let record = CaptureRecord {
+            direction,
+            timestamp: self.start_time.elapsed(),
+            data: data.to_vec(),
+        };
+        if let Err(TrySendError::Full(_)) = self.record_tx.try_send(record) {
+            tracing::warn!(
+                "Capture writer is falling behind, dropped {} bytes",
+                data.len()
+            );
+        }
+    }
+}
+
+async fn capture_task(path: PathBuf, record_rx: Receiver<CaptureRecord>) {
+    if let Err(err) = write_records(&path, &record_rx).await {
+        tracing::error!("Stopped capturing to {}: {err}", path.display());
+    }
+}
+
+async fn write_records(path: &Path, record_rx: &Receiver<CaptureRecord>) -> io::Result<()> {
+    let mut file = BufWriter::new(tokio::fs::File::create(path).await?);
+    tracing::info!("Capturing serial traffic to {}", path.display());
+    file.write_all(&CAPTURE_MAGIC[..]).await?;
+
+    while let Ok(record) = record_rx.recv().await {
+        file.write_u8(match record.direction {
+            Direction::ToDevice => 0,
+            Direction::FromDevice => 1,
+        })
+        .await?;
+        file.write_u64(record.timestamp.as_micros() as u64).await?;
+        file.write_u32(record.data.len() as u32).await?;
+        file.write_all(&record.data[..]).await?;
+        // Only flush once caught up so bursts of traffic share a write
+        if record_rx.is_empty() {
+            file.flush().await?;
+        }
+    }
+
+    file.flush().await
+}
+
+/// Reads every record from a capture file.
+pub fn read_capture(path: &Path) -> io::Result<Vec<CaptureRecord>> {
+    let contents = std::fs::read(path)?;
+    let Some(mut remaining) = contents.strip_prefix(&CAPTURE_MAGIC[..]) else {
+        tracing::error!("{} is not a capture file", path.display());
+        return Err(io::ErrorKind::InvalidData.into());
+    };
+
+    let mut records = Vec::new();
+    while !remaining.is_empty() {
+        if remaining.len() < RECORD_HEADER_LEN {
+            tracing::warn!("Capture ends with a truncated record");
+            break;
+        }
+        let direction = match remaining[0] {
+            0 => Direction::ToDevice,
+            1 => Direction::FromDevice,
+            other => {
+                tracing::error!("Invalid direction {other} in capture record");
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+        };
+        let timestamp =
+            Duration::from_micros(u64::from_be_bytes(remaining[1..9].try_into().unwrap()));
+        let len = u32::from_be_bytes(remaining[9..13].try_into().unwrap()) as usize;
+        let Some(data) = remaining.get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + len) else {
+            tracing::warn!("Capture ends with a truncated record");
+            break;
+        };
+        records.push(CaptureRecord {
+            direction,
+            timestamp,
+            data: data.to_vec(),
+        });
+        remaining = &remaining[RECORD_HEADER_LEN + len..];
+    }
+
+    Ok(records)
+}
+
+/// Reassembles frames from captured records and decodes them, keeping the direction and the
+/// timestamp of the record which completed each frame. Payloads which aren't valid messages are
+/// returned as errors along with their bytes.
+pub fn decode_records(
+    records: &[CaptureRecord],
+    use_crc: bool,
+) -> Vec<(Direction, Duration, Result<SerialMessage, Vec<u8>>)> {
+    let mut to_device = FrameDecoder::new(use_crc);
+    let mut from_device = FrameDecoder::new(use_crc);
+    let mut messages = Vec::new();
+
+    for record in records {
+        let decoder = match record.direction {
+            Direction::ToDevice => &mut to_device,
+            Direction::FromDevice => &mut from_device,
+        };
+        decoder.buffer_mut().extend_from_slice(&record.data[..]);
+        while let Some(payload) = decoder.next_frame() {
+            let msg = SerialMessage::try_from_bytes(&payload[..]).map_err(|_| payload);
+            messages.push((record.direction, record.timestamp, msg));
+        }
+    }
+
+    messages
+}
@@ -0,0 +1,162 @@
+use std::{path::PathBuf, time::Duration};
+pub use tokio_serial::{FlowControl, Parity, StopBits};
+
+#[derive(Clone, Debug)]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    pub flow_control: FlowControl,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    /// How long to wait for the operating system to open the device before giving up
+    pub open_timeout: Duration,
+    /// Level to drive DTR to after opening the port, or left as the driver set it if `None`
+    pub dtr: Option<bool>,
+    /// Level to drive RTS to after opening the port, or left as the driver set it if `None`
+    pub rts: Option<bool>,
+    /// Time to wait after opening the port before talking to the device, for boards which reset
+    /// when the port is opened
+    pub settle_delay: Duration,
+    /// Don't consider the device connected until it has sent a valid frame, pinging it until it
+    /// does. Opening the device fails if nothing arrives within this time.
+    pub first_frame_timeout: Option<Duration>,
+    /// Keep trying to open the device if it isn't there when the serial task starts, instead of
+    /// giving up straight away
+    pub wait_for_device: Option<DeviceWaitConfig>,
+    /// Append a CRC16 to every outgoing frame and verify it on incoming frames, if the firmware
+    /// reports supporting CRCs when connecting. Firmware which doesn't is spoken to in plain
+    /// COBS.
+    pub use_crc: bool,
+    /// How long a single write to the device may take before the port is considered wedged and
+    /// reopened
+    pub write_timeout: Duration,
+    /// How long messages the device acknowledges, like row updates and LED changes, wait for it
+    /// to answer when they aren't retransmitted. Messages which aren't answered in time are
+    /// assumed to have been carried out.
+    pub ack_timeout: Duration,
+    /// Retransmit row updates which the device rejects or fails to acknowledge
+    pub retransmit: Option<RetransmitConfig>,
+    pub keepalive: KeepaliveConfig,
+    /// Number of outgoing frames which can be queued for the device before senders have to wait
+    pub send_queue_depth: usize,
+    /// Drop queued row updates which are superseded by a newer update to the same row before
+    /// they're written to the device
+    pub coalesce_rows: bool,
+    /// Record all raw traffic with the device to this file
+    pub capture_path: Option<PathBuf>,
+    pub inbox: InboxConfig,
+}
+
+/// Limits on the messages received from the device which are kept around for lookups
+#[derive(Clone, Debug)]
+pub struct InboxConfig {
+    /// How long to keep a message before it expires
+    pub retention: Option<Duration>,
+    /// Maximum number of messages to keep, the oldest are evicted first
+    pub max_messages: Option<usize>,
+}
+
+impl Default for InboxConfig {
+    fn default() -> Self {
+        Self {
+            retention: Some(Duration::from_secs(30)),
+            max_messages: Some(1024),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeepaliveConfig {
+    /// Time between pings sent to the device
+    pub interval: Duration,
+    /// Whether to ping the device at all. Without pings the connection is considered healthy as
+    /// long as the port is open.
+    pub enabled: bool,
+    /// Consecutive unacknowledged pings after which the connection is considered degraded
+    pub miss_threshold: u32,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(333),
+            enabled: true,
+            miss_threshold: 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceWaitConfig {
+    /// Time between attempts to open the device while it's missing
+    pub poll_interval: Duration,
+    /// Give up if the device hasn't appeared after this long, or wait indefinitely if `None`
+    pub timeout: Option<Duration>,
+}
+
+impl Default for DeviceWaitConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(250),
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RetransmitConfig {
+    /// Total number of times to send a message before giving up
+    pub max_attempts: u32,
+    /// How long to wait for the device to acknowledge each attempt
+    pub ack_timeout: Duration,
+}
+
+impl Default for RetransmitConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            ack_timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate: 230400,
+            flow_control: FlowControl::None,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            open_timeout: Duration::from_secs(2),
+            dtr: None,
+            rts: None,
+            settle_delay: Duration::ZERO,
+            first_frame_timeout: None,
+            wait_for_device: None,
+            use_crc: false,
+            write_timeout: Duration::from_secs(1),
+            ack_timeout: Duration::from_millis(100),
+            retransmit: None,
+            keepalive: KeepaliveConfig::default(),
+            send_queue_depth: 64,
+            coalesce_rows: false,
+            capture_path: None,
+            inbox: InboxConfig::default(),
+        }
+    }
+}
+
+impl SerialConfig {
+    /// Bytes a second the port can carry, counting the start, parity, and stop bits sent around
+    /// each byte.
+    pub fn byte_rate(&self) -> f32 {
+        let parity_bits = match self.parity {
+            Parity::None => 0,
+            Parity::Odd | Parity::Even => 1,
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        self.baud_rate as f32 / (1 + 8 + parity_bits + stop_bits) as f32
+    }
+}
@@ -0,0 +1,42 @@
+use megabit_serial_protocol::Status;
+use std::{fmt, io};
+
+/// A message which reached the device, but which the device refused to carry out. Returned
+/// wrapped in an `io::Error`, from which it can be recovered with `io::Error::get_ref`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceError {
+    /// The device failed the request, e.g. because a row was outside the display or the
+    /// firmware doesn't understand the message
+    Rejected { request: String },
+    /// The device was still busy carrying out the request when it answered
+    Busy { request: String },
+}
+
+impl DeviceError {
+    /// The error matching a status the device answered `request` with, if it's a failure.
+    pub fn from_status(status: &Status, request: &str) -> Option<Self> {
+        let request = request.to_owned();
+        match status {
+            Status::Success => None,
+            Status::Failure => Some(DeviceError::Rejected { request }),
+            Status::InProgress => Some(DeviceError::Busy { request }),
+        }
+    }
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::Rejected { request } => write!(f, "Device rejected {request}"),
+            DeviceError::Busy { request } => write!(f, "Device was still busy with {request}"),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+impl From<DeviceError> for io::Error {
+    fn from(err: DeviceError) -> Self {
+        io::Error::other(err)
+    }
+}
@@ -0,0 +1,43 @@
+use async_channel::Receiver;
+use megabit_serial_protocol::{DebugLog, LogLevel, SerialMessage};
+
+/// Re-emits log lines from the firmware through `tracing` under the `device` target, so the host
+/// and firmware logs end up in one stream. Runs of identical lines are collapsed into a count.
+pub async fn forward_device_logs(log_rx: Receiver<SerialMessage>) {
+    let mut last_line: Option<(LogLevel, String)> = None;
+    let mut repeats = 0usize;
+
+    while let Ok(msg) = log_rx.recv().await {
+        let Ok(DebugLog { level, message }) = DebugLog::try_from(msg) else {
+            continue;
+        };
+        let line = match String::from_utf8(message) {
+            Ok(line) => line.trim_end().to_owned(),
+            Err(err) => format!("<invalid UTF-8> {:02x?}", err.as_bytes()),
+        };
+
+        if last_line.as_ref() == Some(&(level, line.clone())) {
+            repeats += 1;
+            continue;
+        }
+        if let (Some((last_level, _)), 1..) = (&last_line, repeats) {
+            emit(
+                *last_level,
+                &format!("(previous line repeated {repeats} times)"),
+            );
+        }
+        emit(level, &line);
+        last_line = Some((level, line));
+        repeats = 0;
+    }
+}
+
+fn emit(level: LogLevel, line: &str) {
+    match level {
+        LogLevel::Error => tracing::error!(target: "device", "{line}"),
+        LogLevel::Warn => tracing::warn!(target: "device", "{line}"),
+        LogLevel::Info => tracing::info!(target: "device", "{line}"),
+        LogLevel::Debug => tracing::debug!(target: "device", "{line}"),
+        LogLevel::Trace => tracing::trace!(target: "device", "{line}"),
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio_serial::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+/// Describes how to find the serial device for the display coprocessor
+#[derive(Clone, Debug)]
+pub enum DeviceSelector {
+    /// A fixed path to a tty device
+    Path(PathBuf),
+    /// The first USB serial port with a matching vendor and product ID
+    UsbId { vid: u16, pid: u16 },
+    /// The first USB serial port whose manufacturer string matches
+    Manufacturer(String),
+}
+
+impl From<PathBuf> for DeviceSelector {
+    fn from(value: PathBuf) -> Self {
+        DeviceSelector::Path(value)
+    }
+}
+
+impl From<&Path> for DeviceSelector {
+    fn from(value: &Path) -> Self {
+        DeviceSelector::Path(value.to_path_buf())
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceSelector::Path(path) => write!(f, "{}", path.display()),
+            DeviceSelector::UsbId { vid, pid } => write!(f, "USB device {vid:04x}:{pid:04x}"),
+            DeviceSelector::Manufacturer(manufacturer) => {
+                write!(f, "USB device from manufacturer \"{manufacturer}\"")
+            }
+        }
+    }
+}
+
+impl DeviceSelector {
+    /// Resolves the selector to the path of a tty device which currently exists.
+    pub fn resolve(&self) -> io::Result<PathBuf> {
+        let matcher = match self {
+            DeviceSelector::Path(path) => return Ok(path.clone()),
+            DeviceSelector::UsbId { vid, pid } => {
+                Box::new(move |info: &UsbPortInfo| info.vid == *vid && info.pid == *pid)
+                    as Box<dyn Fn(&UsbPortInfo) -> bool>
+            }
+            DeviceSelector::Manufacturer(manufacturer) => Box::new(move |info: &UsbPortInfo| {
+                info.manufacturer.as_deref() == Some(manufacturer.as_str())
+            }),
+        };
+
+        let ports = tokio_serial::available_ports().map_err(io::Error::from)?;
+        let mut candidates = ports
+            .into_iter()
+            .filter(|SerialPortInfo { port_type, .. }| {
+                matches!(port_type, SerialPortType::UsbPort(info) if matcher(info))
+            })
+            .map(|port| PathBuf::from(port.port_name))
+            .collect::<Vec<_>>();
+
+        if candidates.len() > 1 {
+            // The most recently created device node is most likely to be the one which was
+            // plugged in last
+            candidates.sort_by_key(|path| enumeration_time(path));
+            tracing::warn!(
+                "Found {} serial ports matching {self}, using the most recently enumerated: {:?}",
+                candidates.len(),
+                candidates
+            );
+        }
+
+        candidates.pop().ok_or_else(|| {
+            tracing::debug!("No serial ports found matching {self}");
+            io::ErrorKind::NotFound.into()
+        })
+    }
+}
+
+fn enumeration_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,35 @@
+use async_channel::{Receiver, Sender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+const EVENT_QUEUE_DEPTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    Connected,
+    /// The device stopped answering pings, or a write to it stalled and the port is about to be
+    /// reopened
+    Degraded,
+    Disconnected,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EventSubscribers {
+    subscribers: Arc<Mutex<Vec<Sender<ConnectionEvent>>>>,
+}
+
+impl EventSubscribers {
+    pub fn subscribe(&self) -> Receiver<ConnectionEvent> {
+        let (tx, rx) = async_channel::bounded(EVENT_QUEUE_DEPTH);
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, event: ConnectionEvent) {
+        // Subscribers which fall behind miss events rather than growing the queue, but are only
+        // removed once they've dropped their receiver
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| !matches!(tx.try_send(event), Err(TrySendError::Closed(_))));
+    }
+}
@@ -0,0 +1,43 @@
+use megabit_serial_protocol::{Capabilities, GetFirmwareInfoResponse};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FirmwareVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// What was learned about the firmware when connecting to it
+#[derive(Clone, Copy, Debug)]
+pub struct FirmwareInfo {
+    /// Unknown for legacy firmware which doesn't answer the firmware info request
+    pub version: Option<FirmwareVersion>,
+    pub capabilities: Capabilities,
+}
+
+impl FirmwareInfo {
+    pub const LEGACY: FirmwareInfo = FirmwareInfo {
+        version: None,
+        capabilities: Capabilities::LEGACY,
+    };
+}
+
+impl From<GetFirmwareInfoResponse> for FirmwareInfo {
+    fn from(value: GetFirmwareInfoResponse) -> Self {
+        Self {
+            version: Some(FirmwareVersion {
+                major: value.version_major,
+                minor: value.version_minor,
+                patch: value.version_patch,
+            }),
+            capabilities: value.capabilities,
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use bytes::BytesMut;
+use megabit_serial_protocol::{append_crc, strip_crc};
+
+const FRAME_DELIMITER: u8 = 0x00;
+/// Largest amount of data buffered while waiting for a delimiter. Well above the size of any
+/// frame the protocol produces, so reaching it means the stream is out of sync.
+const MAX_BUFFER_LEN: usize = 64 * 1024;
+
+/// Encodes a payload into a COBS frame ready to be written to the device.
+pub fn encode_frame(mut payload: Vec<u8>, use_crc: bool) -> Vec<u8> {
+    if use_crc {
+        append_crc(&mut payload);
+    }
+    let mut frame = cobs::encode_vec(&payload[..]);
+    frame.push(FRAME_DELIMITER);
+    frame
+}
+
+/// Accumulates bytes read from the device and splits them into COBS-encoded frames terminated by
+/// a `0x00` delimiter.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    buffer: BytesMut,
+    /// How much of the buffer is already known not to contain a delimiter
+    scanned_len: usize,
+    /// Set after the buffer overflowed, until the next delimiter is found
+    resyncing: bool,
+    use_crc: bool,
+    invalid_frames: u64,
+}
+
+impl FrameDecoder {
+    pub fn new(use_crc: bool) -> Self {
+        Self {
+            buffer: BytesMut::with_capacity(1024),
+            scanned_len: 0,
+            resyncing: false,
+            use_crc,
+            invalid_frames: 0,
+        }
+    }
+
+    /// The buffer incoming bytes should be appended to.
+    pub fn buffer_mut(&mut self) -> &mut BytesMut {
+        &mut self.buffer
+    }
+
+    /// Whether frames are expected to carry a CRC.
+    pub fn use_crc(&self) -> bool {
+        self.use_crc
+    }
+
+    /// Expects frames decoded from now on to carry a CRC or not, for when the framing is
+    /// switched partway through a connection.
+    pub fn set_use_crc(&mut self, use_crc: bool) {
+        self.use_crc = use_crc;
+    }
+
+    /// Number of frames dropped since the last call because they couldn't be decoded.
+    pub fn take_invalid_frames(&mut self) -> u64 {
+        std::mem::take(&mut self.invalid_frames)
+    }
+
+    /// Decodes the next complete frame in the buffer. Frames which fail to decode, such as
+    /// garbage left over from before the port was opened, are dropped. Returns `None` once only
+    /// a partial frame (or nothing) remains.
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let Some(delimiter_idx) = self.find_delimiter() else {
+                if self.buffer.len() > MAX_BUFFER_LEN {
+                    tracing::warn!(
+                        "Dropping {} bytes received without a frame delimiter",
+                        self.buffer.len()
+                    );
+                    self.buffer.clear();
+                    self.scanned_len = 0;
+                    self.resyncing = true;
+                    self.invalid_frames += 1;
+                }
+                return None;
+            };
+            // Splitting off the front of the buffer doesn't move the rest of it
+            let encoded_frame = self.buffer.split_to(delimiter_idx + 1);
+            let encoded_frame = &encoded_frame[..delimiter_idx];
+            if std::mem::take(&mut self.resyncing) {
+                tracing::debug!("Resynchronized on a frame delimiter");
+                continue;
+            }
+            if encoded_frame.is_empty() {
+                continue;
+            }
+
+            match cobs::decode_vec(encoded_frame) {
+                Ok(decoded_data) if self.use_crc => match strip_crc(&decoded_data[..]) {
+                    Ok(payload) => break Some(payload.to_vec()),
+                    Err(_) => {
+                        tracing::warn!("Dropping frame with invalid CRC: {decoded_data:02x?}");
+                        self.invalid_frames += 1;
+                    }
+                },
+                Ok(decoded_data) => {
+                    tracing::trace!(
+                        "Decoded a payload of {} bytes from a frame of {} bytes",
+                        decoded_data.len(),
+                        encoded_frame.len()
+                    );
+                    break Some(decoded_data);
+                }
+                Err(()) => {
+                    tracing::warn!(
+                        "Dropping {} bytes which could not be decoded: {encoded_frame:02x?}",
+                        encoded_frame.len()
+                    );
+                    self.invalid_frames += 1;
+                }
+            }
+        }
+    }
+
+    /// Finds the next delimiter, skipping over the part of the buffer searched by earlier calls.
+    fn find_delimiter(&mut self) -> Option<usize> {
+        match self.buffer[self.scanned_len..]
+            .iter()
+            .position(|byte| *byte == FRAME_DELIMITER)
+        {
+            Some(offset) => {
+                let delimiter_idx = self.scanned_len + offset;
+                self.scanned_len = 0;
+                Some(delimiter_idx)
+            }
+            None => {
+                self.scanned_len = self.buffer.len();
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAYLOADS: [&[u8]; 3] = [&[0x01, 0x02, 0x03], &[0x00, 0x00, 0x7f], &[0xff; 300]];
+
+    fn encoded_payloads(use_crc: bool) -> Vec<u8> {
+        PAYLOADS
+            .iter()
+            .flat_map(|payload| encode_frame(payload.to_vec(), use_crc))
+            .collect()
+    }
+
+    /// Feeds `bytes` to the decoder `chunk_len` bytes at a time, as separate reads would.
+    fn decode_in_chunks(
+        decoder: &mut FrameDecoder,
+        bytes: &[u8],
+        chunk_len: usize,
+    ) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        for chunk in bytes.chunks(chunk_len) {
+            decoder.buffer_mut().extend_from_slice(chunk);
+            while let Some(frame) = decoder.next_frame() {
+                frames.push(frame);
+            }
+        }
+        frames
+    }
+
+    #[test]
+    fn decodes_frames_split_across_reads() {
+        for use_crc in [false, true] {
+            let bytes = encoded_payloads(use_crc);
+            for chunk_len in 1..=bytes.len() {
+                let mut decoder = FrameDecoder::new(use_crc);
+                let frames = decode_in_chunks(&mut decoder, &bytes, chunk_len);
+                assert_eq!(
+                    frames, PAYLOADS,
+                    "Reads of {chunk_len} bytes, CRC {use_crc}"
+                );
+                assert_eq!(decoder.take_invalid_frames(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_partial_frame_until_delimiter_arrives() {
+        let frame = encode_frame(PAYLOADS[0].to_vec(), false);
+        let mut decoder = FrameDecoder::new(false);
+        decoder
+            .buffer_mut()
+            .extend_from_slice(&frame[..frame.len() - 1]);
+        assert_eq!(decoder.next_frame(), None);
+        decoder
+            .buffer_mut()
+            .extend_from_slice(&frame[frame.len() - 1..]);
+        assert_eq!(decoder.next_frame().as_deref(), Some(PAYLOADS[0]));
+    }
+
+    #[test]
+    fn drops_garbage_between_delimiters() {
+        // A code byte promising more data than follows it before the delimiter
+        let garbage = [0x05, 0x01, FRAME_DELIMITER];
+        let mut bytes = garbage.to_vec();
+        bytes.extend(encode_frame(PAYLOADS[0].to_vec(), false));
+        bytes.extend(garbage);
+        bytes.extend([FRAME_DELIMITER, FRAME_DELIMITER]);
+        bytes.extend(encode_frame(PAYLOADS[1].to_vec(), false));
+        let mut decoder = FrameDecoder::new(false);
+        let frames = decode_in_chunks(&mut decoder, &bytes, bytes.len());
+        assert_eq!(frames, [PAYLOADS[0], PAYLOADS[1]]);
+        assert_eq!(decoder.take_invalid_frames(), 2);
+    }
+
+    #[test]
+    fn drops_frames_with_crc_mismatch() {
+        let mut corrupted = encode_frame(PAYLOADS[0].to_vec(), true);
+        // Changes a data byte without making the frame invalid COBS
+        corrupted[1] ^= 0x40;
+        let mut bytes = corrupted;
+        bytes.extend(encode_frame(PAYLOADS[1].to_vec(), true));
+        let mut decoder = FrameDecoder::new(true);
+        let frames = decode_in_chunks(&mut decoder, &bytes, bytes.len());
+        assert_eq!(frames, [PAYLOADS[1]]);
+        assert_eq!(decoder.take_invalid_frames(), 1);
+    }
+
+    /// Compares decoding a stream of small frames against draining a `Vec` up to each delimiter,
+    /// which is how frames used to be split off. Run with
+    /// `cargo test --release decoding_small_frames -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark, only meaningful in release builds"]
+    fn decoding_small_frames_benchmark() {
+        const FRAMES: usize = 10_000;
+        // A read returns everything which has arrived, which is many frames when the device is
+        // streaming
+        const READ_LEN: usize = 4096;
+        let bytes = (0..FRAMES)
+            .flat_map(|idx| encode_frame((idx as u32).to_le_bytes().to_vec(), false))
+            .collect::<Vec<_>>();
+
+        let started = std::time::Instant::now();
+        let mut decoder = FrameDecoder::new(false);
+        let frames = decode_in_chunks(&mut decoder, &bytes, READ_LEN);
+        let decoder_time = started.elapsed();
+        assert_eq!(frames.len(), FRAMES);
+
+        let started = std::time::Instant::now();
+        let mut buffer = Vec::new();
+        let mut drained = 0;
+        for chunk in bytes.chunks(READ_LEN) {
+            buffer.extend_from_slice(chunk);
+            while let Some(delimiter_idx) = buffer.iter().position(|byte| *byte == FRAME_DELIMITER)
+            {
+                let encoded_frame = buffer.drain(..=delimiter_idx).collect::<Vec<_>>();
+                cobs::decode_vec(&encoded_frame[..delimiter_idx]).unwrap();
+                drained += 1;
+            }
+        }
+        let draining_time = started.elapsed();
+        assert_eq!(drained, FRAMES);
+
+        println!(
+            "Decoded {FRAMES} frames in {decoder_time:?}, draining a Vec took {draining_time:?}"
+        );
+    }
+
+    #[test]
+    fn resyncs_after_buffer_overflow() {
+        let mut decoder = FrameDecoder::new(false);
+        decoder
+            .buffer_mut()
+            .extend_from_slice(&vec![0x01; MAX_BUFFER_LEN + 1]);
+        assert_eq!(decoder.next_frame(), None);
+        assert_eq!(decoder.take_invalid_frames(), 1);
+        assert!(decoder.buffer_mut().is_empty());
+
+        // The rest of the overlong frame is skipped up to its delimiter, and what follows it is
+        // decoded as usual
+        let mut bytes = vec![0x01; 16];
+        bytes.push(FRAME_DELIMITER);
+        bytes.extend(encode_frame(PAYLOADS[0].to_vec(), false));
+        let frames = decode_in_chunks(&mut decoder, &bytes, 7);
+        assert_eq!(frames, [PAYLOADS[0]]);
+        assert_eq!(decoder.take_invalid_frames(), 0);
+    }
+}
@@ -0,0 +1,186 @@
+use super::{
+    config::KeepaliveConfig,
+    events::{ConnectionEvent, EventSubscribers},
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Multiple of the keepalive miss threshold after which the device is considered gone
+const DISCONNECTED_MISS_MULTIPLIER: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The device is acknowledging pings
+    Connected,
+    /// The port is open, but the device has recently missed pings, or a write to the device
+    /// stalled and the port is being reopened
+    Degraded,
+    /// The port is closed or the device has stopped responding
+    Disconnected,
+}
+
+#[derive(Debug)]
+struct HealthState {
+    is_port_open: bool,
+    missed_pings: u32,
+    /// A write to the device has stopped making progress, and the port hasn't been reopened
+    /// since
+    is_write_stalled: bool,
+    /// The device was asked to restart, so losing it until then isn't a fault
+    reset_expected_until: Option<Instant>,
+    last_state: ConnectionState,
+}
+
+/// Tracks whether the device is responsive and publishes changes to event subscribers.
+#[derive(Clone, Debug)]
+pub struct ConnectionHealth {
+    state: Arc<Mutex<HealthState>>,
+    keepalive: KeepaliveConfig,
+    event_subscribers: EventSubscribers,
+}
+
+impl ConnectionHealth {
+    pub fn new(keepalive: KeepaliveConfig, event_subscribers: EventSubscribers) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(HealthState {
+                is_port_open: false,
+                missed_pings: 0,
+                is_write_stalled: false,
+                reset_expected_until: None,
+                last_state: ConnectionState::Disconnected,
+            })),
+            keepalive,
+            event_subscribers,
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state.lock().unwrap().last_state
+    }
+
+    pub fn set_port_open(&self, is_port_open: bool) {
+        let reopened = {
+            let mut state = self.state.lock().unwrap();
+            let reopened = is_port_open && !state.is_port_open;
+            state.is_port_open = is_port_open;
+            state.missed_pings = 0;
+            if is_port_open {
+                state.is_write_stalled = false;
+                state.reset_expected_until = None;
+            }
+            reopened
+        };
+        self.update(reopened);
+    }
+
+    pub fn record_ping_ack(&self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.missed_pings >= self.keepalive.miss_threshold {
+                tracing::info!(
+                    "Device acknowledged a ping after missing {}",
+                    state.missed_pings
+                );
+            }
+            state.missed_pings = 0;
+        }
+        self.update(false);
+    }
+
+    pub fn record_missed_ping(&self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.missed_pings += 1;
+            // Only warn on the transition so a dead device doesn't flood the log
+            let is_restarting = state
+                .reset_expected_until
+                .is_some_and(|until| Instant::now() < until);
+            if state.missed_pings == self.keepalive.miss_threshold && !is_restarting {
+                tracing::warn!(
+                    "Device has not acknowledged the last {} pings",
+                    state.missed_pings
+                );
+            }
+        }
+        self.update(false);
+    }
+
+    /// Notes that the device has been asked to restart and should drop off for up to `window`.
+    pub fn expect_reset(&self, window: Duration) {
+        self.state.lock().unwrap().reset_expected_until = Some(Instant::now() + window);
+    }
+
+    /// Whether the device is expected to be unavailable because it was asked to restart.
+    pub fn is_reset_expected(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .reset_expected_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Marks the connection as degraded after a write to the device timed out. It stays degraded
+    /// while the port is closed and reopened, rather than showing as disconnected, until the
+    /// reopened port is back in use.
+    pub fn record_write_stall(&self) {
+        self.state.lock().unwrap().is_write_stalled = true;
+        self.update(false);
+    }
+
+    /// Stops treating a stalled connection as degraded once the port couldn't be reopened, as the
+    /// device is gone rather than recovering.
+    pub fn record_reconnect_failed(&self) {
+        self.state.lock().unwrap().is_write_stalled = false;
+        self.update(false);
+    }
+
+    /// Re-evaluates the connection state, notifying subscribers if the device has connected,
+    /// degraded, or disconnected since the last evaluation. Reopening the port counts as connecting even if
+    /// the device wasn't considered disconnected in the meantime, as it may have lost what it
+    /// was showing.
+    fn update(&self, reopened: bool) {
+        let (old_state, new_state) = {
+            let mut state = self.state.lock().unwrap();
+            let new_state = if state.is_write_stalled {
+                ConnectionState::Degraded
+            } else if !state.is_port_open {
+                ConnectionState::Disconnected
+            } else if !self.keepalive.enabled {
+                ConnectionState::Connected
+            } else if state.missed_pings
+                >= self.keepalive.miss_threshold * DISCONNECTED_MISS_MULTIPLIER
+            {
+                ConnectionState::Disconnected
+            } else if state.missed_pings >= self.keepalive.miss_threshold {
+                ConnectionState::Degraded
+            } else {
+                ConnectionState::Connected
+            };
+            (
+                std::mem::replace(&mut state.last_state, new_state),
+                new_state,
+            )
+        };
+
+        if old_state != new_state {
+            tracing::debug!("Connection state changed from {old_state:?} to {new_state:?}");
+        }
+        let connected = new_state != ConnectionState::Disconnected
+            && (old_state == ConnectionState::Disconnected || reopened);
+        if connected {
+            self.event_subscribers.publish(ConnectionEvent::Connected);
+        } else if old_state != new_state {
+            match new_state {
+                ConnectionState::Degraded => {
+                    self.event_subscribers.publish(ConnectionEvent::Degraded)
+                }
+                ConnectionState::Disconnected => self
+                    .event_subscribers
+                    .publish(ConnectionEvent::Disconnected),
+                ConnectionState::Connected => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use async_channel::Receiver;
+use megabit_serial_protocol::{InputKind, ReportInput, SerialMessage};
+use serde::Serialize;
+
+/// Input from one of the device's buttons or encoders, which are numbered from 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputEvent {
+    ButtonPressed {
+        button: u8,
+    },
+    ButtonReleased {
+        button: u8,
+    },
+    /// Turned by `steps` detents, positive for clockwise
+    EncoderTurned {
+        encoder: u8,
+        steps: i8,
+    },
+}
+
+impl InputEvent {
+    /// The input `msg` reports, if any. Firmware from before `ReportInput` only says that its one
+    /// button was pressed, which is taken as button 0 being pressed and released.
+    pub fn from_message(msg: &SerialMessage) -> Vec<InputEvent> {
+        match msg {
+            SerialMessage::ReportButtonPress => vec![
+                InputEvent::ButtonPressed { button: 0 },
+                InputEvent::ButtonReleased { button: 0 },
+            ],
+            SerialMessage::ReportInput(ReportInput { kind, id, value }) => vec![match kind {
+                InputKind::ButtonPressed => InputEvent::ButtonPressed { button: *id },
+                InputKind::ButtonReleased => InputEvent::ButtonReleased { button: *id },
+                InputKind::EncoderTurned => InputEvent::EncoderTurned {
+                    encoder: *id,
+                    steps: *value,
+                },
+            }],
+            _ => vec![],
+        }
+    }
+
+    pub fn is_input_message(msg: &SerialMessage) -> bool {
+        matches!(
+            msg,
+            SerialMessage::ReportButtonPress | SerialMessage::ReportInput(_)
+        )
+    }
+}
+
+/// Input reported by the device since it was subscribed to, see
+/// [`SerialConnection::subscribe_input`](super::SerialConnection::subscribe_input).
+#[derive(Debug)]
+pub struct InputEvents {
+    rx: Receiver<SerialMessage>,
+}
+
+impl InputEvents {
+    pub(super) fn new(rx: Receiver<SerialMessage>) -> Self {
+        InputEvents { rx }
+    }
+
+    /// Takes every event which has arrived since the last call, without waiting for more.
+    pub fn drain(&self) -> Vec<InputEvent> {
+        std::iter::from_fn(|| self.rx.try_recv().ok())
+            .flat_map(|msg| InputEvent::from_message(&msg))
+            .collect()
+    }
+}
@@ -0,0 +1,129 @@
+use super::SerialTaskRequest;
+use async_channel::{Receiver, RecvError, SendError, Sender, TryRecvError, TrySendError};
+use megabit_serial_protocol::SerialMessage;
+use std::io;
+
+/// Requests are split by how urgent they are so that a burst of row updates doesn't hold up a
+/// ping, and so that control messages don't sit in front of frame data for long.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lane {
+    /// Small messages such as pings, LED and brightness changes, and info requests
+    Control,
+    /// Frame data, which has to reach the device in the order it was sent
+    Bulk,
+}
+
+impl Lane {
+    pub fn of(request: &SerialTaskRequest) -> Self {
+        if let SerialTaskRequest::Flush { lane, .. } = request {
+            return *lane;
+        }
+        let is_bulk = request.messages().iter().any(|msg| {
+            matches!(
+                msg,
+                SerialMessage::UpdateRow(_)
+                    | SerialMessage::UpdateRowRgb(_)
+                    | SerialMessage::UpdateRowRgb888(_)
+                    | SerialMessage::CommitRender(_)
+            )
+        });
+        if is_bulk {
+            Lane::Bulk
+        } else {
+            Lane::Control
+        }
+    }
+}
+
+/// Creates the channels to the serial task, each holding up to `depth` requests.
+pub fn request_channel(depth: usize) -> (RequestSender, RequestReceiver) {
+    let (control_tx, control_rx) = async_channel::bounded(depth);
+    let (bulk_tx, bulk_rx) = async_channel::bounded(depth);
+    (
+        RequestSender {
+            control: control_tx,
+            bulk: bulk_tx,
+        },
+        RequestReceiver {
+            control: control_rx,
+            bulk: bulk_rx,
+        },
+    )
+}
+
+/// Sends requests to the serial task on the lane which matches their messages
+#[derive(Clone, Debug)]
+pub struct RequestSender {
+    control: Sender<SerialTaskRequest>,
+    bulk: Sender<SerialTaskRequest>,
+}
+
+impl RequestSender {
+    fn lane(&self, request: &SerialTaskRequest) -> &Sender<SerialTaskRequest> {
+        match Lane::of(request) {
+            Lane::Control => &self.control,
+            Lane::Bulk => &self.bulk,
+        }
+    }
+
+    pub async fn send(
+        &self,
+        request: SerialTaskRequest,
+    ) -> Result<(), SendError<SerialTaskRequest>> {
+        self.lane(&request).send(request).await
+    }
+
+    pub fn try_send(
+        &self,
+        request: SerialTaskRequest,
+    ) -> Result<(), TrySendError<SerialTaskRequest>> {
+        self.lane(&request).try_send(request)
+    }
+
+    pub fn close(&self) {
+        self.control.close();
+        self.bulk.close();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.control.is_closed() && self.bulk.is_closed()
+    }
+}
+
+#[derive(Debug)]
+pub struct RequestReceiver {
+    control: Receiver<SerialTaskRequest>,
+    bulk: Receiver<SerialTaskRequest>,
+}
+
+impl RequestReceiver {
+    /// Waits for the next request from either lane, preferring control requests. Fails once both
+    /// lanes are closed and empty.
+    pub async fn recv(&self) -> Result<SerialTaskRequest, RecvError> {
+        if let Ok(request) = self.try_recv() {
+            return Ok(request);
+        }
+        tokio::select! {
+            biased;
+            Ok(request) = self.control.recv() => Ok(request),
+            Ok(request) = self.bulk.recv() => Ok(request),
+            else => Err(RecvError),
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<SerialTaskRequest, TryRecvError> {
+        self.control.try_recv().or_else(|_| self.bulk.try_recv())
+    }
+}
+
+impl Drop for RequestReceiver {
+    fn drop(&mut self) {
+        // Requests left in the channels would otherwise never be answered, since the channels
+        // live on for as long as there are senders
+        self.control.close();
+        self.bulk.close();
+        while let Ok(request) = self.try_recv() {
+            request.respond(Err(io::ErrorKind::NotConnected.into()));
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use super::{
+    framing::{encode_frame, FrameDecoder},
+    transport::{LoopbackPeer, LoopbackTransport},
+};
+use crate::display::DisplayConfiguration;
+use async_channel::{Receiver, Sender};
+use megabit_serial_protocol::*;
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+/// An in-memory stand-in for the display coprocessor. It speaks the device side of the protocol
+/// over a [`LoopbackTransport`], applying row updates to a framebuffer which tests can inspect.
+#[derive(Clone, Debug)]
+pub struct MockDevice {
+    display_config: DisplayConfiguration,
+    /// What the device reports supporting. With CRCs it switches to them when the host asks it
+    /// to, and with double buffering rows only show once they're committed.
+    capabilities: Capabilities,
+    peer: LoopbackPeer,
+    state: Arc<Mutex<MockDeviceState>>,
+    /// Messages to send to the host unprompted, like a real device reporting input
+    reports: (Sender<SerialMessage>, Receiver<SerialMessage>),
+}
+
+#[derive(Debug)]
+struct MockDeviceState {
+    framebuffer: Vec<Vec<u32>>,
+    /// Rows written since the last commit, when double buffering
+    back_buffer: Vec<Vec<u32>>,
+    received_messages: Vec<SerialMessage>,
+}
+
+impl MockDevice {
+    /// Creates the device along with the transport to hand to
+    /// [`start_transport_task`](super::start_transport_task). Each connection starts out in
+    /// plain COBS, and if `supports_crc` is set the device reports supporting CRCs and switches
+    /// to them when sent [`SerialMessage::EnableCrc`].
+    pub fn new(
+        display_config: DisplayConfiguration,
+        supports_crc: bool,
+    ) -> (Self, LoopbackTransport) {
+        let capabilities = if supports_crc {
+            Capabilities::LEGACY | Capabilities::CRC
+        } else {
+            Capabilities::LEGACY
+        };
+        Self::with_capabilities(display_config, capabilities)
+    }
+
+    /// Like [`MockDevice::new`], but reporting `capabilities` instead of the legacy ones.
+    pub fn with_capabilities(
+        display_config: DisplayConfiguration,
+        capabilities: Capabilities,
+    ) -> (Self, LoopbackTransport) {
+        let (transport, peer) = LoopbackTransport::new();
+        let framebuffer = vec![vec![0; display_config.width]; display_config.height];
+        let device = Self {
+            display_config,
+            capabilities,
+            peer,
+            state: Arc::new(Mutex::new(MockDeviceState {
+                back_buffer: framebuffer.clone(),
+                framebuffer,
+                received_messages: Vec::new(),
+            })),
+            reports: async_channel::unbounded(),
+        };
+        (device, transport)
+    }
+
+    /// Serves connections from the host until the transport is dropped.
+    pub async fn run(&self) {
+        while let Some(stream) = self.peer.accept().await {
+            if let Err(err) = self.serve(stream).await {
+                tracing::debug!("Mock device connection closed: {err}");
+            }
+        }
+    }
+
+    /// What the display is showing. Monocolor pixels are `1` when on and `0` when off,
+    /// and RGB pixels are in the display's own pixel format.
+    pub fn framebuffer(&self) -> Vec<Vec<u32>> {
+        self.state.lock().unwrap().framebuffer.clone()
+    }
+
+    /// Every message received from the host, in order.
+    pub fn received_messages(&self) -> Vec<SerialMessage> {
+        self.state.lock().unwrap().received_messages.clone()
+    }
+
+    /// Sends `msg` to the host without being asked, e.g. to report a button press.
+    pub fn report(&self, msg: SerialMessage) {
+        let _ = self.reports.0.try_send(msg);
+    }
+
+    async fn serve(&self, mut stream: DuplexStream) -> io::Result<()> {
+        let mut use_crc = false;
+        let mut frame_decoder = FrameDecoder::new(use_crc);
+        loop {
+            tokio::select! {
+                read = stream.read_buf(frame_decoder.buffer_mut()) => {
+                    if read? == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                }
+                Ok(report) = self.reports.1.recv() => {
+                    stream
+                        .write_all(&encode_frame(report.to_bytes(), use_crc)[..])
+                        .await?;
+                    continue;
+                }
+            }
+            while let Some(decoded_data) = frame_decoder.next_frame() {
+                let Ok(msg) = SerialMessage::try_from_bytes(&decoded_data[..]) else {
+                    continue;
+                };
+                if matches!(msg, SerialMessage::EnableCrc)
+                    && self.capabilities.contains(Capabilities::CRC)
+                {
+                    use_crc = true;
+                    frame_decoder.set_use_crc(true);
+                }
+                let Some(response) = self.handle_message(msg) else {
+                    continue;
+                };
+                // The host may have already hung up, but anything it wrote before that should
+                // still be applied
+                if let Err(err) = stream
+                    .write_all(&encode_frame(response.to_bytes(), use_crc)[..])
+                    .await
+                {
+                    tracing::trace!("Mock device failed to respond: {err}");
+                }
+            }
+        }
+    }
+
+    fn handle_message(&self, msg: SerialMessage) -> Option<SerialMessage> {
+        let mut state = self.state.lock().unwrap();
+        state.received_messages.push(msg.clone());
+        match msg {
+            SerialMessage::Ping => Some(SerialMessage::PingResponse),
+            SerialMessage::GetFirmwareInfo(GetFirmwareInfo) => Some(
+                SerialMessage::GetFirmwareInfoResponse(GetFirmwareInfoResponse {
+                    version_major: 0,
+                    version_minor: 1,
+                    version_patch: 0,
+                    capabilities: self.capabilities,
+                }),
+            ),
+            SerialMessage::GetDisplayInfo(GetDisplayInfo) => Some(
+                SerialMessage::GetDisplayInfoResponse(GetDisplayInfoResponse {
+                    width: self.display_config.width as u32,
+                    height: self.display_config.height as u32,
+                    pixel_representation: self.display_config.pixel_representation,
+                }),
+            ),
+            SerialMessage::UpdateRow(UpdateRow {
+                row_number,
+                column_offset,
+                row_data_len,
+                row_data,
+            }) => {
+                let pixels = (0..usize::from(row_data_len)).map(|idx| {
+                    row_data
+                        .get(idx / 8)
+                        .map_or(0, |byte| u32::from((byte & (1 << (idx % 8))) != 0))
+                });
+                let status = Self::apply_row(
+                    state.rows_to_write(self.capabilities),
+                    !self.display_config.is_rgb(),
+                    row_number,
+                    column_offset,
+                    pixels,
+                );
+                Some(SerialMessage::UpdateRowResponse(UpdateRowResponse {
+                    status,
+                }))
+            }
+            SerialMessage::UpdateRowRgb(update) => {
+                let status = Self::apply_row(
+                    state.rows_to_write(self.capabilities),
+                    matches!(
+                        self.display_config.pixel_representation,
+                        PixelRepresentation::RGB555 | PixelRepresentation::RGB565
+                    ),
+                    update.row_number,
+                    update.column_offset,
+                    update.pixels().map(u32::from),
+                );
+                Some(SerialMessage::UpdateRowRgbResponse(UpdateRowRgbResponse {
+                    status,
+                }))
+            }
+            SerialMessage::UpdateRowRgb888(update) => {
+                let status = Self::apply_row(
+                    state.rows_to_write(self.capabilities),
+                    self.display_config.pixel_representation == PixelRepresentation::RGB888,
+                    update.row_number,
+                    update.column_offset,
+                    update.pixels(),
+                );
+                Some(SerialMessage::UpdateRowRgbResponse(UpdateRowRgbResponse {
+                    status,
+                }))
+            }
+            SerialMessage::CommitRender(CommitRender)
+                if self.capabilities.contains(Capabilities::DOUBLE_BUFFERING) =>
+            {
+                state.framebuffer = state.back_buffer.clone();
+                Some(SerialMessage::CommitRenderResponse(CommitRenderResponse {
+                    status: Status::Success,
+                }))
+            }
+            SerialMessage::SetLedState(_) => {
+                Some(SerialMessage::SetLedStateResponse(SetLedStateResponse {
+                    status: Status::Success,
+                }))
+            }
+            SerialMessage::SetRgbState(_) => {
+                Some(SerialMessage::SetRgbStateResponse(SetRgbStateResponse {
+                    status: Status::Success,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    fn apply_row(
+        framebuffer: &mut [Vec<u32>],
+        is_supported: bool,
+        row_number: u8,
+        column_offset: u16,
+        pixels: impl Iterator<Item = u32>,
+    ) -> Status {
+        match framebuffer.get_mut(usize::from(row_number)) {
+            Some(row) if is_supported && usize::from(column_offset) < row.len() => {
+                row[usize::from(column_offset)..]
+                    .iter_mut()
+                    .zip(pixels)
+                    .for_each(|(pixel, value)| *pixel = value);
+                Status::Success
+            }
+            _ => Status::Failure,
+        }
+    }
+}
+
+impl MockDeviceState {
+    /// Where row updates go, which is held back until the next commit when double buffering.
+    fn rows_to_write(&mut self, capabilities: Capabilities) -> &mut [Vec<u32>] {
+        if capabilities.contains(Capabilities::DOUBLE_BUFFERING) {
+            &mut self.back_buffer
+        } else {
+            &mut self.framebuffer
+        }
+    }
+}
@@ -3,97 +3,683 @@ use megabit_serial_protocol::*;
 use std::{
     future::Future,
     io,
-    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
-    sync::oneshot,
+    runtime::RuntimeFlavor,
+    sync::{oneshot, watch},
 };
-use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tracing::Instrument;
+
+use crate::display::DisplayConfiguration;
 
-use self::msg_inbox::{InboxHandle, MessageInbox};
+use self::{
+    acks::AckTracker,
+    capture::{Capture, Direction},
+    events::EventSubscribers,
+    framing::{encode_frame, FrameDecoder},
+    health::ConnectionHealth,
+    msg_inbox::{InboxHandle, MessageInbox},
+    send_queue::SendQueue,
+    sequence::SequenceTracker,
+};
+pub use config::{
+    DeviceWaitConfig, FlowControl, InboxConfig, KeepaliveConfig, Parity, RetransmitConfig,
+    SerialConfig, StopBits,
+};
+pub use device_error::DeviceError;
+pub use discovery::DeviceSelector;
+pub use events::ConnectionEvent;
+pub use firmware::{FirmwareInfo, FirmwareVersion};
+pub use health::ConnectionState;
+pub use input::{InputEvent, InputEvents};
+use lanes::{Lane, RequestReceiver, RequestSender};
+pub use megabit_serial_protocol::Capabilities;
+#[cfg(feature = "test-support")]
+pub use mock_device::MockDevice;
+pub use msg_inbox::InboxStats;
+pub use shutdown::{blank_display_sequence, ShutdownHandle};
+use stats::StatsRecorder;
+pub use stats::{LatencySummary, SerialStats};
+pub use transport::{
+    LoopbackPeer, LoopbackTransport, ReplayTransport, SerialTransport, TcpTransport, Transport,
+    TransportStream,
+};
 
+mod acks;
+pub mod capture;
+mod config;
+mod device_error;
+mod device_log;
+mod discovery;
+mod events;
+mod firmware;
+mod framing;
+mod health;
+mod input;
+mod lanes;
+#[cfg(feature = "test-support")]
+mod mock_device;
 mod msg_inbox;
+mod send_queue;
+mod sequence;
+mod shutdown;
+mod stats;
+mod transport;
+
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_REQUEST_ATTEMPTS: u32 = 3;
+/// Legacy firmware doesn't answer the firmware info request at all, so don't hold up startup
+/// for long waiting on it
+pub const FIRMWARE_INFO_TIMEOUT: Duration = Duration::from_millis(500);
+/// Time between pings while waiting for a newly opened device to respond
+const FIRST_FRAME_PING_INTERVAL: Duration = Duration::from_millis(100);
+/// How often to log that the device still hasn't appeared while waiting for it
+const DEVICE_WAIT_LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// The serial task doesn't announce when it gives up, so check on it at this rate while waiting
+/// for the device to connect
+const CONNECTION_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Pings from the device which can be waiting for a response at once, any more are dropped
+const PONG_QUEUE_DEPTH: usize = 8;
+/// How long a device which was asked to restart may take to come back before losing it is
+/// treated as a fault again
+const RESET_WINDOW: Duration = Duration::from_secs(10);
+/// Row updates carry their length in a single byte, so wider rows are sent in segments of up to
+/// this many pixels, each carrying the column it starts at
+pub const MAX_ROW_SEGMENT_WIDTH: usize = u8::MAX as usize;
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_request_id() -> u64 {
+    NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+}
 
 #[derive(Debug)]
 enum SerialTaskRequest {
     SendMessage {
         msg: SerialMessage,
         response: oneshot::Sender<io::Result<()>>,
+        /// Where the status the device acknowledges the message with goes, if anybody is
+        /// waiting for it
+        ack: Option<oneshot::Sender<Status>>,
+        queued_at: Instant,
+        id: u64,
+    },
+    /// Several messages which are written to the device in a single write
+    SendBatch {
+        msgs: Vec<SerialMessage>,
+        response: oneshot::Sender<io::Result<()>>,
+        queued_at: Instant,
+        id: u64,
+    },
+    /// Writes nothing, but is only answered once everything queued ahead of it on the same lane
+    /// has been written
+    Flush {
+        lane: Lane,
+        response: oneshot::Sender<io::Result<()>>,
+        queued_at: Instant,
+        id: u64,
     },
 }
 
+impl SerialTaskRequest {
+    fn message(msg: SerialMessage, response: oneshot::Sender<io::Result<()>>) -> Self {
+        SerialTaskRequest::SendMessage {
+            msg,
+            response,
+            ack: None,
+            queued_at: Instant::now(),
+            id: next_request_id(),
+        }
+    }
+
+    fn acknowledged(
+        msg: SerialMessage,
+        response: oneshot::Sender<io::Result<()>>,
+        ack: oneshot::Sender<Status>,
+    ) -> Self {
+        SerialTaskRequest::SendMessage {
+            msg,
+            response,
+            ack: Some(ack),
+            queued_at: Instant::now(),
+            id: next_request_id(),
+        }
+    }
+
+    fn batch(msgs: Vec<SerialMessage>, response: oneshot::Sender<io::Result<()>>) -> Self {
+        SerialTaskRequest::SendBatch {
+            msgs,
+            response,
+            queued_at: Instant::now(),
+            id: next_request_id(),
+        }
+    }
+
+    fn flush(lane: Lane, response: oneshot::Sender<io::Result<()>>) -> Self {
+        SerialTaskRequest::Flush {
+            lane,
+            response,
+            queued_at: Instant::now(),
+            id: next_request_id(),
+        }
+    }
+
+    fn messages(&self) -> &[SerialMessage] {
+        match self {
+            SerialTaskRequest::SendMessage { msg, .. } => std::slice::from_ref(msg),
+            SerialTaskRequest::SendBatch { msgs, .. } => &msgs[..],
+            SerialTaskRequest::Flush { .. } => &[],
+        }
+    }
+
+    fn take_ack(&mut self) -> Option<oneshot::Sender<Status>> {
+        match self {
+            SerialTaskRequest::SendMessage { ack, .. } => ack.take(),
+            SerialTaskRequest::SendBatch { .. } | SerialTaskRequest::Flush { .. } => None,
+        }
+    }
+
+    /// Identifies the request in the logs of both the caller and the serial task.
+    fn id(&self) -> u64 {
+        let (SerialTaskRequest::SendMessage { id, .. }
+        | SerialTaskRequest::SendBatch { id, .. }
+        | SerialTaskRequest::Flush { id, .. }) = self;
+        *id
+    }
+
+    fn kind(&self) -> &str {
+        match self {
+            SerialTaskRequest::SendMessage { msg, .. } => msg.as_ref(),
+            SerialTaskRequest::SendBatch { .. } => "Batch",
+            SerialTaskRequest::Flush { .. } => "Flush",
+        }
+    }
+
+    fn queued_at(&self) -> Instant {
+        let (SerialTaskRequest::SendMessage { queued_at, .. }
+        | SerialTaskRequest::SendBatch { queued_at, .. }
+        | SerialTaskRequest::Flush { queued_at, .. }) = self;
+        *queued_at
+    }
+
+    /// Splits the request into the bytes to write to the device and the channel to report the
+    /// result of the write on.
+    fn encode(self, use_crc: bool) -> (Vec<u8>, oneshot::Sender<io::Result<()>>) {
+        match self {
+            SerialTaskRequest::SendMessage { msg, response, .. } => {
+                (encode_frame(msg.to_bytes(), use_crc), response)
+            }
+            SerialTaskRequest::SendBatch { msgs, response, .. } => (
+                msgs.into_iter()
+                    .flat_map(|msg| encode_frame(msg.to_bytes(), use_crc))
+                    .collect(),
+                response,
+            ),
+            SerialTaskRequest::Flush { response, .. } => (vec![], response),
+        }
+    }
+
+    fn respond(self, result: io::Result<()>) {
+        let (SerialTaskRequest::SendMessage { response, .. }
+        | SerialTaskRequest::SendBatch { response, .. }
+        | SerialTaskRequest::Flush { response, .. }) = self;
+        let _ = response.send(result);
+    }
+}
+
+/// The span the caller's side of a request is logged in, carrying the same request ID as the
+/// serial task's span for writing it.
+fn request_span(request: &SerialTaskRequest) -> tracing::Span {
+    tracing::trace_span!(
+        "serial_request",
+        request_id = request.id(),
+        msg_type = request.kind()
+    )
+}
+
+/// Starts the connection to a serial device. Shorthand for [`start_transport_task`] with a
+/// [`SerialTransport`].
 pub fn start_serial_task(
-    device_path: impl AsRef<Path>,
+    device: impl Into<DeviceSelector>,
+    config: SerialConfig,
     msg_tx: Sender<SerialMessage>,
     msg_rx: Receiver<SerialMessage>,
-) -> (SerialConnection, Box<dyn Future<Output = ()> + Send + Sync>) {
-    let (tx, rx) = async_channel::unbounded();
-    let device_path = device_path.as_ref().to_path_buf();
+) -> (
+    SerialConnection,
+    ShutdownHandle,
+    Box<dyn Future<Output = ()> + Send>,
+) {
+    let transport = SerialTransport::new(device, config.clone());
+    start_transport_task(Box::new(transport), config, msg_tx, msg_rx)
+}
 
-    let serial_future = serial_task(device_path, rx, msg_tx);
-    let ping_task = {
-        let tx = tx.clone();
-        async move {
-            loop {
-                tokio::time::sleep(Duration::from_millis(333)).await;
-                if let Err(err) =
-                    SerialConnection::send_message_inner(&tx, SerialMessage::Ping).await
-                {
-                    tracing::error!("Failed to send ping to device: {err}");
-                    break;
-                }
-            }
+pub fn start_transport_task(
+    transport: Box<dyn Transport>,
+    config: SerialConfig,
+    msg_tx: Sender<SerialMessage>,
+    msg_rx: Receiver<SerialMessage>,
+) -> (
+    SerialConnection,
+    ShutdownHandle,
+    Box<dyn Future<Output = ()> + Send>,
+) {
+    // Bounded so that a slow device applies backpressure to whatever is producing frames rather
+    // than letting latency grow without limit
+    let (tx, rx) = lanes::request_channel(config.send_queue_depth.max(1));
+
+    let event_subscribers = EventSubscribers::default();
+    let keepalive = config.keepalive.clone();
+    let health = ConnectionHealth::new(keepalive.clone(), event_subscribers.clone());
+    let ack_timeout = config.ack_timeout;
+    let retransmit = config.retransmit.clone();
+    let inbox_config = config.inbox.clone();
+    let stats = Arc::new(StatsRecorder::default());
+    let (capture, capture_task) = match config.capture_path.clone() {
+        Some(path) => {
+            let (capture, capture_task) = Capture::start(path);
+            (Some(capture), Some(capture_task))
+        }
+        None => (None, None),
+    };
+    let capture_task = async move {
+        if let Some(capture_task) = capture_task {
+            capture_task.await;
         }
     };
 
-    let message_inbox = MessageInbox::new(msg_rx.clone(), Some(Duration::from_secs(30)));
+    let (stopped_tx, stopped_rx) = watch::channel(false);
+    let shutdown_handle = ShutdownHandle {
+        actor_tx: tx.clone(),
+        stopped_rx,
+    };
+
+    let firmware_info = Arc::new(Mutex::new(FirmwareInfo::LEGACY));
+    let acks = Arc::new(AckTracker::default());
+    let serial_future = serial_task(
+        transport,
+        config,
+        rx,
+        msg_tx,
+        SerialTaskState {
+            health: health.clone(),
+            stats: stats.clone(),
+            firmware_info: firmware_info.clone(),
+            acks: acks.clone(),
+        },
+        capture,
+    );
+    let serial_future = async move {
+        serial_future.await;
+        stopped_tx.send_replace(true);
+    };
+
+    let message_inbox = MessageInbox::new(msg_rx, inbox_config);
     let inbox_handle = message_inbox.get_handle();
     let message_inbox_task = message_inbox.run();
+    let device_log_task = device_log::forward_device_logs(
+        inbox_handle.subscribe(|msg| matches!(msg, SerialMessage::DebugLog(_))),
+    );
+
+    let serial_conn = SerialConnection {
+        actor_tx: tx,
+        inbox_handle,
+        event_subscribers,
+        health,
+        ack_timeout,
+        retransmit,
+        stats,
+        firmware_info,
+        acks,
+    };
+    let ping_task = keepalive_task(serial_conn.clone(), keepalive);
 
     let serial_task = async move {
-        tokio::join!(serial_future, ping_task, message_inbox_task);
+        tokio::join!(
+            serial_future,
+            ping_task,
+            message_inbox_task,
+            capture_task,
+            device_log_task
+        );
     };
 
-    (
-        SerialConnection {
-            actor_tx: tx,
-            serial_message_rx: msg_rx,
-            inbox_handle,
-        },
-        Box::new(serial_task),
-    )
+    (serial_conn, shutdown_handle, Box::new(serial_task))
+}
+
+/// Periodically pings the device and records acknowledgements so the health of the connection
+/// can be tracked.
+async fn keepalive_task(serial_conn: SerialConnection, keepalive: KeepaliveConfig) {
+    if !keepalive.enabled {
+        tracing::debug!("Keepalive pings are disabled");
+        return;
+    }
+
+    let mut ping_interval = tokio::time::interval(keepalive.interval);
+    ping_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ping_interval.tick().await;
+    loop {
+        ping_interval.tick().await;
+        let ping_result = serial_conn
+            .request(
+                SerialMessage::Ping,
+                |msg| matches!(msg, SerialMessage::PingResponse).then_some(()),
+                keepalive.interval,
+            )
+            .await;
+        match ping_result {
+            Ok(()) => serial_conn.health.record_ping_ack(),
+            Err(err) if serial_conn.actor_tx.is_closed() => {
+                tracing::error!("Failed to send ping to device: {err}");
+                break;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotConnected => {
+                tracing::trace!("Skipped ping while the device is unavailable");
+            }
+            Err(err) => {
+                tracing::trace!("Ping was not acknowledged: {err}");
+                serial_conn.health.record_missed_ping();
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct SerialConnection {
-    actor_tx: Sender<SerialTaskRequest>,
-    serial_message_rx: Receiver<SerialMessage>,
+    actor_tx: RequestSender,
     inbox_handle: InboxHandle,
+    event_subscribers: EventSubscribers,
+    health: ConnectionHealth,
+    ack_timeout: Duration,
+    retransmit: Option<RetransmitConfig>,
+    stats: Arc<StatsRecorder>,
+    firmware_info: Arc<Mutex<FirmwareInfo>>,
+    acks: Arc<AckTracker>,
 }
 
 impl SerialConnection {
-    async fn send_message(&self, msg: SerialMessage) -> io::Result<()> {
+    /// Reports whether the device is responding to pings.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.health.state()
+    }
+
+    /// Subscribes to connection state changes of the device. A `Connected` event following a
+    /// `Disconnected` event means the device was reopened or started responding again, so its
+    /// state should be redrawn.
+    pub fn subscribe_events(&self) -> async_channel::Receiver<ConnectionEvent> {
+        self.event_subscribers.subscribe()
+    }
+
+    pub fn inbox_stats(&self) -> InboxStats {
+        self.inbox_handle.stats()
+    }
+
+    /// Waits until the device is connected, for when the serial task is started before the
+    /// device exists. Fails if the serial task gives up on the device first.
+    pub async fn wait_for_connection(&self) -> io::Result<()> {
+        let events = self.subscribe_events();
+        while self.connection_state() == ConnectionState::Disconnected {
+            if self.actor_tx.is_closed() {
+                return Err(io::ErrorKind::NotConnected.into());
+            }
+            let _ = tokio::time::timeout(CONNECTION_CHECK_INTERVAL, events.recv()).await;
+        }
+        Ok(())
+    }
+
+    /// The capabilities of the firmware found by the last call to
+    /// [`SerialConnection::get_firmware_info`], or the legacy capabilities if it hasn't been
+    /// called yet.
+    pub fn capabilities(&self) -> Capabilities {
+        self.firmware_info.lock().unwrap().capabilities
+    }
+
+    /// Asks the firmware for its version and capabilities and stores them to decide which
+    /// messages can be sent. Firmware which doesn't answer is assumed to be a legacy revision.
+    pub async fn get_firmware_info(&self) -> io::Result<FirmwareInfo> {
+        let firmware_info = match self
+            .request(
+                GetFirmwareInfo.into(),
+                |msg| GetFirmwareInfoResponse::try_from(msg.clone()).ok(),
+                FIRMWARE_INFO_TIMEOUT,
+            )
+            .await
+        {
+            Ok(response) => FirmwareInfo::from(response),
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                tracing::info!("Firmware did not report its version, assuming legacy capabilities");
+                FirmwareInfo::LEGACY
+            }
+            Err(err) => return Err(err),
+        };
+        *self.firmware_info.lock().unwrap() = firmware_info;
+        Ok(firmware_info)
+    }
+
+    /// Restarts the firmware. The device drops off while it reboots, and is reconnected to like
+    /// after any other disconnect.
+    pub async fn reset_device(&self) -> io::Result<()> {
+        self.require_capability(Capabilities::REMOTE_RESET, "remote resets")?;
+        self.health.expect_reset(RESET_WINDOW);
+        self.send_message(SerialMessage::ResetDevice).await
+    }
+
+    /// Restarts the device into its bootloader so new firmware can be flashed. The device won't
+    /// speak the protocol again until it has been flashed or reset.
+    pub async fn enter_bootloader(&self) -> io::Result<()> {
+        self.require_capability(Capabilities::REMOTE_RESET, "remote resets")?;
+        self.health.expect_reset(RESET_WINDOW);
+        self.send_message(SerialMessage::EnterBootloader).await
+    }
+
+    fn require_capability(&self, capability: Capabilities, operation: &str) -> io::Result<()> {
+        if self.capabilities().contains(capability) {
+            Ok(())
+        } else {
+            tracing::debug!("Firmware does not support {operation}");
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+
+    /// Number of row updates which were dropped before being written because a newer update to
+    /// the same row was queued behind them.
+    pub fn dropped_frame_count(&self) -> u64 {
+        self.stats.dropped_frames()
+    }
+
+    /// Traffic counters and send latency of the connection so far.
+    pub fn stats(&self) -> SerialStats {
+        self.stats.snapshot()
+    }
+
+    /// Sends any message as is and waits for it to be written, e.g. to try out new messages
+    /// during device bring-up. Unlike the typed helpers, this never retransmits.
+    pub async fn send_message(&self, msg: SerialMessage) -> io::Result<()> {
         Self::send_message_inner(&self.actor_tx, msg).await
     }
 
-    async fn send_message_inner(
-        actor_tx: &Sender<SerialTaskRequest>,
-        msg: SerialMessage,
-    ) -> io::Result<()> {
+    /// Sends a message which the device acknowledges, and waits briefly for it to answer. Fails
+    /// with a [`DeviceError`] if the device rejects the message, but one which isn't answered in
+    /// time is assumed to have been carried out. With retransmission, the message is instead
+    /// resent until the device accepts it, failing with a [`DeviceError`] if the device rejected
+    /// the last attempt or with `TimedOut` if it never answered.
+    async fn send_acknowledged_message(&self, msg: SerialMessage) -> io::Result<()> {
+        let (max_attempts, ack_timeout) = self
+            .retransmit
+            .as_ref()
+            .map_or((1, self.ack_timeout), |retransmit| {
+                (retransmit.max_attempts, retransmit.ack_timeout)
+            });
+
+        let request_kind = msg.as_ref().to_owned();
+        let mut last_error = None;
+        for attempt in 1..=max_attempts {
+            let (tx, rx) = oneshot::channel();
+            let (ack_tx, ack_rx) = oneshot::channel();
+            let request = SerialTaskRequest::acknowledged(msg.clone(), tx, ack_tx);
+            Self::send_request(&self.actor_tx, request, rx).await?;
+            match tokio::time::timeout(ack_timeout, ack_rx).await {
+                Ok(Ok(Status::Success)) => return Ok(()),
+                Ok(Ok(status)) => {
+                    tracing::debug!(
+                        "Device responded to {request_kind} with {status:?} on attempt {attempt}"
+                    );
+                    last_error = DeviceError::from_status(&status, &request_kind);
+                }
+                // Either the acknowledgement didn't arrive in time, or the device went away
+                // before sending it
+                Ok(Err(_)) | Err(_) => {
+                    tracing::debug!(
+                        "Device did not acknowledge {request_kind} on attempt {attempt}"
+                    );
+                    self.acks.forget_abandoned(&msg);
+                    last_error = None;
+                }
+            }
+        }
+
+        if self.retransmit.is_none() {
+            return last_error.map_or(Ok(()), |err| Err(err.into()));
+        }
+        tracing::warn!("Giving up on {request_kind} after {max_attempts} attempts");
+        // Report how the last attempt went, a rejection says more than a timeout
+        Err(last_error.map_or_else(|| io::ErrorKind::TimedOut.into(), Into::into))
+    }
+
+    async fn send_message_inner(actor_tx: &RequestSender, msg: SerialMessage) -> io::Result<()> {
         let (tx, rx) = oneshot::channel();
-        actor_tx
-            .send(SerialTaskRequest::SendMessage { msg, response: tx })
-            .await
-            .map_err(|err| {
+        Self::send_request(actor_tx, SerialTaskRequest::message(msg, tx), rx).await
+    }
+
+    async fn send_batch(&self, msgs: Vec<SerialMessage>) -> io::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        Self::send_request(&self.actor_tx, SerialTaskRequest::batch(msgs, tx), rx).await
+    }
+
+    async fn send_request(
+        actor_tx: &RequestSender,
+        request: SerialTaskRequest,
+        rx: oneshot::Receiver<io::Result<()>>,
+    ) -> io::Result<()> {
+        let span = request_span(&request);
+        async move {
+            tracing::trace!("Queueing request");
+            actor_tx.send(request).await.map_err(|err| {
                 tracing::error!("Failed to send message to serial task: {err}");
                 io::ErrorKind::NotConnected
             })?;
-        rx.await.map_err(|err| {
+            Self::await_send_response(rx).await
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Sends a message unless the send queue is full, in which case this fails immediately with
+    /// `WouldBlock`. Lets callers such as a renderer drop frames when the device can't keep up
+    /// instead of queueing stale ones.
+    pub async fn try_send_message(&self, msg: SerialMessage) -> io::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        let request = SerialTaskRequest::message(msg, tx);
+        let span = request_span(&request);
+        self.try_send_request(request)?;
+        Self::await_send_response(rx).instrument(span).await
+    }
+
+    fn try_send_request(&self, request: SerialTaskRequest) -> io::Result<()> {
+        self.actor_tx.try_send(request).map_err(|err| match err {
+            async_channel::TrySendError::Full(_) => io::ErrorKind::WouldBlock.into(),
+            async_channel::TrySendError::Closed(_) => {
+                tracing::error!("Failed to send message to serial task: channel closed");
+                io::ErrorKind::NotConnected.into()
+            }
+        })
+    }
+
+    /// Queues a message without waiting for it to be written. Fails with `WouldBlock` if the send
+    /// queue is full. Errors writing the message aren't reported, use
+    /// [`SerialConnection::flush`] to wait for queued messages to reach the device.
+    pub fn enqueue_message(&self, msg: SerialMessage) -> io::Result<()> {
+        self.try_send_request(SerialTaskRequest::message(msg, oneshot::channel().0))
+    }
+
+    /// Queues each of the messages on its own, so row updates among them can still be dropped
+    /// if they're superseded before being written.
+    fn enqueue_messages(&self, msgs: Vec<SerialMessage>) -> io::Result<()> {
+        msgs.into_iter()
+            .try_for_each(|msg| self.enqueue_message(msg))
+    }
+
+    fn enqueue_batch(&self, msgs: Vec<SerialMessage>) -> io::Result<()> {
+        self.try_send_request(SerialTaskRequest::batch(msgs, oneshot::channel().0))
+    }
+
+    /// Waits until everything queued so far has been written to the device. The device's answers
+    /// to what was queued aren't waited for, see [`SerialConnection::take_rejection`].
+    pub async fn flush(&self) -> io::Result<()> {
+        let (control_tx, control_rx) = oneshot::channel();
+        let (bulk_tx, bulk_rx) = oneshot::channel();
+        Self::send_request(
+            &self.actor_tx,
+            SerialTaskRequest::flush(Lane::Control, control_tx),
+            control_rx,
+        )
+        .await?;
+        Self::send_request(
+            &self.actor_tx,
+            SerialTaskRequest::flush(Lane::Bulk, bulk_tx),
+            bulk_rx,
+        )
+        .await
+    }
+
+    /// The first message nobody waited on, like a queued row, which the device has rejected
+    /// since the last time this was called. The device only answers once a message has been
+    /// written, so rejections of what was sent last may not have arrived yet.
+    pub fn take_rejection(&self) -> Option<DeviceError> {
+        self.acks.take_rejection()
+    }
+
+    async fn await_send_response(rx: oneshot::Receiver<io::Result<()>>) -> io::Result<()> {
+        let res = rx.await.map_err(|err| {
             tracing::error!("Failed to get response back for request: {err}");
             io::ErrorKind::UnexpectedEof
-        })?
+        })?;
+        tracing::trace!("Request completed: {res:?}");
+        res
+    }
+
+    /// Sends a message and waits for the first response to it which the matcher accepts. Only
+    /// messages received after the request was sent are considered, and a matched response is
+    /// consumed so concurrent requests of the same kind don't both claim it.
+    pub async fn request<R, F>(
+        &self,
+        msg: SerialMessage,
+        matcher: F,
+        timeout: Duration,
+    ) -> io::Result<R>
+    where
+        F: Fn(&SerialMessage) -> Option<R>,
+    {
+        let request_kind = msg.as_ref().to_owned();
+        let send_time = Instant::now();
+        self.send_message(msg).await?;
+        self.inbox_handle
+            .take_response_since(matcher, send_time, Some(timeout))
+            .await
+            .ok_or_else(|| {
+                tracing::debug!(
+                    "No response to {request_kind} within {}ms",
+                    timeout.as_millis()
+                );
+                io::ErrorKind::TimedOut.into()
+            })
     }
 
     pub async fn wait_for_message<F>(
@@ -119,46 +705,312 @@ impl SerialConnection {
             .check_for_message_since(matcher, start_time)
     }
 
+    /// Forwards every message received from now on which the matcher accepts, such as button
+    /// presses reported by the device.
+    pub fn subscribe<F>(&self, matcher: F) -> Receiver<SerialMessage>
+    where
+        F: Fn(&SerialMessage) -> bool + Send + 'static,
+    {
+        self.inbox_handle.subscribe(matcher)
+    }
+
+    /// Forwards a copy of every message received from now on. Messages still reach the inbox as
+    /// well, and arrive here in the same order they're added to it.
+    pub fn incoming(&self) -> Receiver<SerialMessage> {
+        self.subscribe(|_| true)
+    }
+
+    /// Collects the button presses and encoder turns the device reports from now on.
+    pub fn subscribe_input(&self) -> InputEvents {
+        InputEvents::new(self.subscribe(InputEvent::is_input_message))
+    }
+
+    /// Waits for a message of a specific kind, e.g. `wait_for::<GetDisplayInfoResponse>(..)`.
+    pub async fn wait_for<T>(&self, timeout: Option<Duration>) -> Option<T>
+    where
+        T: TryFrom<SerialMessage>,
+    {
+        self.inbox_handle.wait_for(timeout).await
+    }
+
+    /// Finds a message of a specific kind received no earlier than `start_time`.
+    pub fn check_for_since<T>(&self, start_time: Instant) -> Option<T>
+    where
+        T: TryFrom<SerialMessage>,
+    {
+        self.inbox_handle.check_for_since(start_time)
+    }
+
     pub async fn set_led_state(&self, new_state: bool) -> io::Result<()> {
-        self.send_message(SerialMessage::SetLedState(SetLedState { new_state }))
+        self.send_acknowledged_message(SerialMessage::SetLedState(SetLedState { new_state }))
             .await
     }
 
     pub async fn set_rgb_state(&self, (r, g, b): (u8, u8, u8)) -> io::Result<()> {
-        self.send_message(SerialMessage::SetRgbState(SetRgbState { r, g, b }))
+        self.send_acknowledged_message(SerialMessage::SetRgbState(SetRgbState { r, g, b }))
             .await
     }
 
-    pub async fn update_row(&self, row_number: u8, row_data: Vec<bool>) -> io::Result<()> {
-        let data = pack_bools_to_bytes(&row_data[..]);
-        self.send_message(SerialMessage::UpdateRow(UpdateRow {
-            row_number,
-            row_data_len: row_data.len() as u8,
-            row_data: data,
-        }))
-        .await
+    /// Sets the global brightness of the panel. Firmware which can't dim the panel ignores the
+    /// request rather than failing it, so apps don't need to check for support first.
+    pub async fn set_brightness(&self, level: u8) -> io::Result<()> {
+        if !self.capabilities().contains(Capabilities::BRIGHTNESS) {
+            tracing::warn!(
+                "Firmware does not support brightness control, ignoring brightness {level}"
+            );
+            return Ok(());
+        }
+        self.send_acknowledged_message(SerialMessage::SetBrightness(SetBrightness { level }))
+            .await
     }
 
-    pub async fn update_row_rgb(&self, row_number: u8, row_data: Vec<u16>) -> io::Result<()> {
-        self.send_message(SerialMessage::UpdateRowRgb(UpdateRowRgb {
-            row_number,
-            row_data_len: row_data.len() as u8,
-            row_data,
-        }))
-        .await
+    /// Takes anything which can be borrowed as a row, so rows can be sent straight out of a
+    /// screen buffer without being copied into a `Vec` first.
+    pub async fn update_row(&self, row_number: u8, row_data: impl AsRef<[bool]>) -> io::Result<()> {
+        for msg in update_row_msgs(row_number, row_data.as_ref())? {
+            self.send_acknowledged_message(msg).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn update_row_rgb(
+        &self,
+        row_number: u8,
+        row_data: impl AsRef<[u16]>,
+    ) -> io::Result<()> {
+        self.require_capability(Capabilities::RGB, "RGB row updates")?;
+        for msg in update_row_rgb_msgs(row_number, row_data.as_ref())? {
+            self.send_acknowledged_message(msg).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends a row to a display which takes eight bits per channel, with each pixel packed as
+    /// `0x00RRGGBB`.
+    pub async fn update_row_rgb888(
+        &self,
+        row_number: u8,
+        row_data: impl AsRef<[u32]>,
+    ) -> io::Result<()> {
+        self.require_capability(Capabilities::RGB, "RGB row updates")?;
+        for msg in update_row_rgb888_msgs(row_number, row_data.as_ref())? {
+            self.send_acknowledged_message(msg).await?;
+        }
+        Ok(())
+    }
+
+    /// Updates several rows with a single write to the device. The device's answers aren't
+    /// waited for, so rejected rows are reported by [`SerialConnection::take_rejection`] like
+    /// queued ones. When retransmission is configured each row still needs its own
+    /// acknowledgement, so the rows are sent one at a time instead.
+    pub async fn update_rows<R: AsRef<[bool]>>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        if self.retransmit.is_some() {
+            for (row_number, row_data) in rows {
+                self.update_row(*row_number, row_data).await?;
+            }
+            Ok(())
+        } else {
+            self.send_batch(row_msgs(rows, update_row_msgs)?).await
+        }
+    }
+
+    /// The RGB equivalent of [`SerialConnection::update_rows`].
+    pub async fn update_rows_rgb<R: AsRef<[u16]>>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.require_capability(Capabilities::RGB, "RGB row updates")?;
+        if self.retransmit.is_some() {
+            for (row_number, row_data) in rows {
+                self.update_row_rgb(*row_number, row_data).await?;
+            }
+            Ok(())
+        } else {
+            self.send_batch(row_msgs(rows, update_row_rgb_msgs)?).await
+        }
+    }
+
+    /// The 24 bit equivalent of [`SerialConnection::update_rows_rgb`].
+    pub async fn update_rows_rgb888<R: AsRef<[u32]>>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.require_capability(Capabilities::RGB, "RGB row updates")?;
+        if self.retransmit.is_some() {
+            for (row_number, row_data) in rows {
+                self.update_row_rgb888(*row_number, row_data).await?;
+            }
+            Ok(())
+        } else {
+            self.send_batch(row_msgs(rows, update_row_rgb888_msgs)?)
+                .await
+        }
+    }
+
+    /// Queues a row update without waiting for it to be written or acknowledged, see
+    /// [`SerialConnection::enqueue_message`].
+    pub fn try_update_row(&self, row_number: u8, row_data: impl AsRef<[bool]>) -> io::Result<()> {
+        self.enqueue_messages(update_row_msgs(row_number, row_data.as_ref())?)
+    }
+
+    pub fn try_update_row_rgb(
+        &self,
+        row_number: u8,
+        row_data: impl AsRef<[u16]>,
+    ) -> io::Result<()> {
+        self.require_capability(Capabilities::RGB, "RGB row updates")?;
+        self.enqueue_messages(update_row_rgb_msgs(row_number, row_data.as_ref())?)
+    }
+
+    pub fn try_update_row_rgb888(
+        &self,
+        row_number: u8,
+        row_data: impl AsRef<[u32]>,
+    ) -> io::Result<()> {
+        self.require_capability(Capabilities::RGB, "RGB row updates")?;
+        self.enqueue_messages(update_row_rgb888_msgs(row_number, row_data.as_ref())?)
+    }
+
+    /// Queues several row updates as a single write without waiting for it to complete.
+    pub fn try_update_rows<R: AsRef<[bool]>>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.enqueue_batch(row_msgs(rows, update_row_msgs)?)
+    }
+
+    pub fn try_update_rows_rgb<R: AsRef<[u16]>>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.require_capability(Capabilities::RGB, "RGB row updates")?;
+        self.enqueue_batch(row_msgs(rows, update_row_rgb_msgs)?)
+    }
+
+    pub fn try_update_rows_rgb888<R: AsRef<[u32]>>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.require_capability(Capabilities::RGB, "RGB row updates")?;
+        self.enqueue_batch(row_msgs(rows, update_row_rgb888_msgs)?)
+    }
+
+    /// Shows the rows written since the previous commit. Requires firmware which double buffers
+    /// the display, see [`Capabilities::DOUBLE_BUFFERING`].
+    pub async fn commit_render(&self) -> io::Result<()> {
+        self.require_capability(Capabilities::DOUBLE_BUFFERING, "committing renders")?;
+        self.send_acknowledged_message(SerialMessage::CommitRender(CommitRender))
+            .await
     }
 
     pub async fn get_display_info(&self) -> io::Result<GetDisplayInfoResponse> {
-        self.send_message(SerialMessage::GetDisplayInfo(GetDisplayInfo))
-            .await?;
-        while let Ok(msg) = self.serial_message_rx.recv().await {
-            if let SerialMessage::GetDisplayInfoResponse(inner) = msg {
-                return Ok(inner);
+        self.get_display_info_with_timeout(DEFAULT_RESPONSE_TIMEOUT, DEFAULT_REQUEST_ATTEMPTS)
+            .await
+    }
+
+    /// Requests the display info, waiting up to `timeout` for each of `attempts` requests.
+    pub async fn get_display_info_with_timeout(
+        &self,
+        timeout: Duration,
+        attempts: u32,
+    ) -> io::Result<GetDisplayInfoResponse> {
+        for attempt in 1..=attempts {
+            let response = self
+                .request(
+                    GetDisplayInfo.into(),
+                    |msg| GetDisplayInfoResponse::try_from(msg.clone()).ok(),
+                    timeout,
+                )
+                .await;
+
+            match response {
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => tracing::debug!(
+                    "Timed out waiting for display info on attempt {attempt} of {attempts}"
+                ),
+                res => return res,
             }
         }
 
-        Err(io::ErrorKind::ConnectionAborted.into())
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+/// A rough upper bound on how many full frames a second can be sent to `display` over a port
+/// with the given settings, worked out from the size of the framed row updates for its pixel
+/// format. Other traffic, like pings and the device's acknowledgements, isn't accounted for.
+/// `None` if the display is empty or too wide to send rows to.
+pub fn max_fps_hint(display: &DisplayConfiguration, config: &SerialConfig) -> Option<f32> {
+    let row_msgs = match display.pixel_representation {
+        PixelRepresentation::Monocolor => update_row_msgs(0, &vec![false; display.width]),
+        PixelRepresentation::RGB555 | PixelRepresentation::RGB565 => {
+            update_row_rgb_msgs(0, &vec![0; display.width])
+        }
+        PixelRepresentation::RGB888 => update_row_rgb888_msgs(0, &vec![0; display.width]),
+    }
+    .ok()?;
+    let row_len: usize = row_msgs
+        .into_iter()
+        .map(|msg| encode_frame(msg.to_bytes(), config.use_crc).len())
+        .sum();
+    let frame_len = row_len * display.height;
+    (frame_len > 0).then(|| config.byte_rate() / frame_len as f32)
+}
+
+/// The updates for each of `rows` in order, made by `row_update`.
+fn row_msgs<T, R: AsRef<[T]>>(
+    rows: &[(u8, R)],
+    row_update: impl Fn(u8, &[T]) -> io::Result<Vec<SerialMessage>>,
+) -> io::Result<Vec<SerialMessage>> {
+    let mut msgs = Vec::with_capacity(rows.len());
+    for (row_number, row_data) in rows {
+        msgs.extend(row_update(*row_number, row_data.as_ref())?);
     }
+    Ok(msgs)
+}
+
+fn update_row_msgs(row_number: u8, row_data: &[bool]) -> io::Result<Vec<SerialMessage>> {
+    Ok(row_segments(row_data)?
+        .map(|(column_offset, segment)| {
+            SerialMessage::UpdateRow(UpdateRow {
+                row_number,
+                column_offset,
+                row_data_len: segment.len() as u8,
+                row_data: pack_bools_to_bytes(segment),
+            })
+        })
+        .collect())
+}
+
+fn update_row_rgb_msgs(row_number: u8, row_data: &[u16]) -> io::Result<Vec<SerialMessage>> {
+    Ok(row_segments(row_data)?
+        .map(|(column_offset, segment)| {
+            SerialMessage::UpdateRowRgb(UpdateRowRgb {
+                row_number,
+                column_offset,
+                row_data_len: segment.len() as u8,
+                row_data: pack_rgb_to_bytes(segment),
+            })
+        })
+        .collect())
+}
+
+fn update_row_rgb888_msgs(row_number: u8, row_data: &[u32]) -> io::Result<Vec<SerialMessage>> {
+    Ok(row_segments(row_data)?
+        .map(|(column_offset, segment)| {
+            SerialMessage::UpdateRowRgb888(UpdateRowRgb888 {
+                row_number,
+                column_offset,
+                row_data_len: segment.len() as u8,
+                row_data: pack_rgb888_to_bytes(segment),
+            })
+        })
+        .collect())
+}
+
+/// Splits a row into segments of at most [`MAX_ROW_SEGMENT_WIDTH`] pixels, along with the
+/// column each starts at. Rows which fit in one update, empty ones included, are a single
+/// segment starting at column 0.
+fn row_segments<T>(row_data: &[T]) -> io::Result<impl Iterator<Item = (u16, &[T])>> {
+    let last_offset =
+        row_data.len().saturating_sub(1) / MAX_ROW_SEGMENT_WIDTH * MAX_ROW_SEGMENT_WIDTH;
+    if u16::try_from(last_offset).is_err() {
+        tracing::error!("Row of {} pixels is too wide to send", row_data.len());
+        return Err(io::ErrorKind::InvalidInput.into());
+    }
+    let segments = if row_data.is_empty() {
+        vec![row_data]
+    } else {
+        row_data.chunks(MAX_ROW_SEGMENT_WIDTH).collect()
+    };
+    Ok(segments
+        .into_iter()
+        .enumerate()
+        .map(|(idx, segment)| ((idx * MAX_ROW_SEGMENT_WIDTH) as u16, segment)))
 }
 
 #[derive(Clone, Debug)]
@@ -172,16 +1024,95 @@ impl SyncSerialConnection {
         Self { inner: conn, rt }
     }
 
+    /// Runs a future to completion on the connection's runtime. Blocking a runtime's worker
+    /// thread outright panics, so when this is called from inside a runtime the worker either
+    /// hands its other tasks off first, or the future is run from a separate thread.
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send,
+        F::Output: Send,
+    {
+        match tokio::runtime::Handle::try_current() {
+            Ok(current) if current.runtime_flavor() == RuntimeFlavor::CurrentThread => {
+                // There's no other worker to take over, so this can only make progress if the
+                // connection was created on a different runtime than the calling one
+                std::thread::scope(|scope| {
+                    scope
+                        .spawn(|| self.rt.block_on(future))
+                        .join()
+                        .expect("Blocking call panicked")
+                })
+            }
+            Ok(_) => tokio::task::block_in_place(|| self.rt.block_on(future)),
+            Err(_) => self.rt.block_on(future),
+        }
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        self.inner.connection_state()
+    }
+
+    pub fn subscribe_events(&self) -> async_channel::Receiver<ConnectionEvent> {
+        self.inner.subscribe_events()
+    }
+
+    pub fn dropped_frame_count(&self) -> u64 {
+        self.inner.dropped_frame_count()
+    }
+
+    pub fn stats(&self) -> SerialStats {
+        self.inner.stats()
+    }
+
+    pub fn inbox_stats(&self) -> InboxStats {
+        self.inner.inbox_stats()
+    }
+
+    pub fn wait_for_connection(&self) -> io::Result<()> {
+        self.block_on(async { self.inner.wait_for_connection().await })
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    pub fn get_firmware_info(&self) -> io::Result<FirmwareInfo> {
+        self.block_on(async { self.inner.get_firmware_info().await })
+    }
+
+    pub fn request<R, F>(&self, msg: SerialMessage, matcher: F, timeout: Duration) -> io::Result<R>
+    where
+        R: Send,
+        F: Fn(&SerialMessage) -> Option<R> + Send,
+    {
+        self.block_on(async { self.inner.request(msg, matcher, timeout).await })
+    }
+
+    pub fn send_message(&self, msg: SerialMessage) -> io::Result<()> {
+        self.block_on(async { self.inner.send_message(msg).await })
+    }
+
+    pub fn try_send_message(&self, msg: SerialMessage) -> io::Result<()> {
+        self.block_on(async { self.inner.try_send_message(msg).await })
+    }
+
+    pub fn reset_device(&self) -> io::Result<()> {
+        self.block_on(async { self.inner.reset_device().await })
+    }
+
+    pub fn enter_bootloader(&self) -> io::Result<()> {
+        self.block_on(async { self.inner.enter_bootloader().await })
+    }
+
     pub fn wait_for_message<F>(
         &self,
         matcher: F,
         timeout: Option<Duration>,
     ) -> Option<SerialMessage>
     where
-        F: Fn(&SerialMessage) -> bool,
+        F: Fn(&SerialMessage) -> bool + Send + Sync,
     {
-        self.rt
-            .block_on(async { self.inner.wait_for_message(matcher, timeout).await })
+        self.block_on(async { self.inner.wait_for_message(matcher, timeout).await })
     }
 
     pub fn check_for_message_since<F>(
@@ -195,123 +1126,904 @@ impl SyncSerialConnection {
         self.inner.check_for_message_since(matcher, start_time)
     }
 
+    /// Blocks on each message received from now on which the matcher accepts. The iterator ends
+    /// once the connection is shut down.
+    pub fn subscribe<F>(&self, matcher: F) -> impl Iterator<Item = SerialMessage>
+    where
+        F: Fn(&SerialMessage) -> bool + Send + 'static,
+    {
+        let rx = self.inner.subscribe(matcher);
+        std::iter::from_fn(move || rx.recv_blocking().ok())
+    }
+
+    /// Blocks on each message received from now on, see [`SerialConnection::incoming`].
+    pub fn incoming(&self) -> impl Iterator<Item = SerialMessage> {
+        self.subscribe(|_| true)
+    }
+
+    pub fn subscribe_input(&self) -> InputEvents {
+        self.inner.subscribe_input()
+    }
+
+    pub fn wait_for<T>(&self, timeout: Option<Duration>) -> Option<T>
+    where
+        T: TryFrom<SerialMessage> + Send,
+    {
+        self.block_on(async { self.inner.wait_for(timeout).await })
+    }
+
+    pub fn check_for_since<T>(&self, start_time: Instant) -> Option<T>
+    where
+        T: TryFrom<SerialMessage>,
+    {
+        self.inner.check_for_since(start_time)
+    }
+
     pub fn set_led_state(&self, new_state: bool) -> io::Result<()> {
-        self.rt
-            .block_on(async { self.inner.set_led_state(new_state).await })
+        self.block_on(async { self.inner.set_led_state(new_state).await })
     }
 
     pub fn set_rgb_state(&self, (r, g, b): (u8, u8, u8)) -> io::Result<()> {
-        self.rt
-            .block_on(async { self.inner.set_rgb_state((r, g, b)).await })
+        self.block_on(async { self.inner.set_rgb_state((r, g, b)).await })
+    }
+
+    pub fn set_brightness(&self, level: u8) -> io::Result<()> {
+        self.block_on(async { self.inner.set_brightness(level).await })
+    }
+
+    pub fn update_row(&self, row_number: u8, row_data: impl AsRef<[bool]>) -> io::Result<()> {
+        let row_data = row_data.as_ref();
+        self.block_on(async { self.inner.update_row(row_number, row_data).await })
+    }
+
+    pub fn update_row_rgb(&self, row_number: u8, row_data: impl AsRef<[u16]>) -> io::Result<()> {
+        let row_data = row_data.as_ref();
+        self.block_on(async { self.inner.update_row_rgb(row_number, row_data).await })
+    }
+
+    pub fn update_row_rgb888(&self, row_number: u8, row_data: impl AsRef<[u32]>) -> io::Result<()> {
+        let row_data = row_data.as_ref();
+        self.block_on(async { self.inner.update_row_rgb888(row_number, row_data).await })
+    }
+
+    pub fn update_rows<R: AsRef<[bool]> + Sync>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.block_on(async { self.inner.update_rows(rows).await })
+    }
+
+    pub fn update_rows_rgb<R: AsRef<[u16]> + Sync>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.block_on(async { self.inner.update_rows_rgb(rows).await })
+    }
+
+    pub fn update_rows_rgb888<R: AsRef<[u32]> + Sync>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.block_on(async { self.inner.update_rows_rgb888(rows).await })
+    }
+
+    pub fn try_update_row(&self, row_number: u8, row_data: impl AsRef<[bool]>) -> io::Result<()> {
+        self.inner.try_update_row(row_number, row_data)
+    }
+
+    pub fn try_update_row_rgb(
+        &self,
+        row_number: u8,
+        row_data: impl AsRef<[u16]>,
+    ) -> io::Result<()> {
+        self.inner.try_update_row_rgb(row_number, row_data)
+    }
+
+    pub fn try_update_row_rgb888(
+        &self,
+        row_number: u8,
+        row_data: impl AsRef<[u32]>,
+    ) -> io::Result<()> {
+        self.inner.try_update_row_rgb888(row_number, row_data)
+    }
+
+    pub fn try_update_rows<R: AsRef<[bool]>>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.inner.try_update_rows(rows)
+    }
+
+    pub fn try_update_rows_rgb<R: AsRef<[u16]>>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.inner.try_update_rows_rgb(rows)
+    }
+
+    pub fn try_update_rows_rgb888<R: AsRef<[u32]>>(&self, rows: &[(u8, R)]) -> io::Result<()> {
+        self.inner.try_update_rows_rgb888(rows)
     }
 
-    pub fn update_row(&self, row_number: u8, row_data: Vec<bool>) -> io::Result<()> {
-        self.rt
-            .block_on(async { self.inner.update_row(row_number, row_data).await })
+    pub fn flush(&self) -> io::Result<()> {
+        self.block_on(async { self.inner.flush().await })
     }
 
-    pub fn update_row_rgb(&self, row_number: u8, row_data: Vec<u16>) -> io::Result<()> {
-        self.rt
-            .block_on(async { self.inner.update_row_rgb(row_number, row_data).await })
+    pub fn take_rejection(&self) -> Option<DeviceError> {
+        self.inner.take_rejection()
+    }
+
+    pub fn commit_render(&self) -> io::Result<()> {
+        self.block_on(async { self.inner.commit_render().await })
     }
 
     pub fn get_display_info(&self) -> io::Result<GetDisplayInfoResponse> {
-        self.rt
-            .block_on(async { self.inner.get_display_info().await })
+        self.block_on(async { self.inner.get_display_info().await })
+    }
+
+    pub fn get_display_info_with_timeout(
+        &self,
+        timeout: Duration,
+        attempts: u32,
+    ) -> io::Result<GetDisplayInfoResponse> {
+        self.block_on(async {
+            self.inner
+                .get_display_info_with_timeout(timeout, attempts)
+                .await
+        })
     }
 }
 
+/// The parts of a connection's state which the serial task keeps up to date or depends on
+struct SerialTaskState {
+    health: ConnectionHealth,
+    stats: Arc<StatsRecorder>,
+    firmware_info: Arc<Mutex<FirmwareInfo>>,
+    acks: Arc<AckTracker>,
+}
+
 async fn serial_task(
-    device_path: PathBuf,
-    request_rx: Receiver<SerialTaskRequest>,
+    transport: Box<dyn Transport>,
+    config: SerialConfig,
+    request_rx: RequestReceiver,
     incoming_msg_tx: Sender<SerialMessage>,
+    state: SerialTaskState,
+    capture: Option<Capture>,
 ) {
     tracing::info!("Starting serial task");
-    let serial_port =
-        match tokio_serial::new(device_path.to_str().unwrap(), 230400).open_native_async() {
-            Ok(serial) => serial,
+    let SerialTaskState {
+        health,
+        stats,
+        firmware_info,
+        acks,
+    } = &state;
+    let mut reconnect_delay = INITIAL_RECONNECT_DELAY;
+    let mut has_connected = false;
+    let started_at = Instant::now();
+    let mut last_wait_log: Option<Instant> = None;
+
+    loop {
+        let connected = connect(&*transport, &config, &incoming_msg_tx, stats, firmware_info).await;
+        let (stream, frame_decoder) = match connected {
+            Ok(connected) => connected,
             Err(err) => {
-                tracing::error!(
-                    "Failed to open serial port {}: {err}",
-                    device_path.display()
+                if !has_connected {
+                    let Some(wait) = config.wait_for_device.as_ref().filter(|wait| {
+                        wait.timeout
+                            .is_none_or(|timeout| started_at.elapsed() < timeout)
+                    }) else {
+                        tracing::error!("Failed to connect to {transport}: {err}");
+                        return;
+                    };
+                    if last_wait_log
+                        .is_none_or(|logged_at| logged_at.elapsed() >= DEVICE_WAIT_LOG_INTERVAL)
+                    {
+                        tracing::info!("Waiting for {transport} to appear: {err}");
+                        last_wait_log = Some(Instant::now());
+                    }
+                    if reject_requests_for(&request_rx, wait.poll_interval)
+                        .await
+                        .is_err()
+                    {
+                        tracing::info!("Serial task request channel closed while waiting");
+                        return;
+                    }
+                    continue;
+                }
+                tracing::debug!(
+                    "Failed to reconnect to {transport}: {err}, retrying in {}ms",
+                    reconnect_delay.as_millis()
                 );
-                return;
+                health.record_reconnect_failed();
+                if reject_requests_for(&request_rx, reconnect_delay)
+                    .await
+                    .is_err()
+                {
+                    tracing::info!("Serial task request channel closed while reconnecting");
+                    return;
+                }
+                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                continue;
             }
         };
-    tracing::info!("Opened serial port: {}", device_path.display());
-    let (serial_rx, serial_tx) = tokio::io::split(serial_port);
+        has_connected = true;
+        reconnect_delay = INITIAL_RECONNECT_DELAY;
+        health.set_port_open(true);
+        // Frames only carry a CRC if the firmware agreed to them when connecting
+        let connection_config = SerialConfig {
+            use_crc: frame_decoder.use_crc(),
+            ..config.clone()
+        };
+        let (serial_rx, serial_tx) = tokio::io::split(stream);
+        let mut send_queue = SendQueue::new(config.coalesce_rows, stats.clone());
+        // Answers to the device's pings skip the request channels entirely so they can't be
+        // held up behind queued frames
+        let (pong_tx, pong_rx) = async_channel::bounded(PONG_QUEUE_DEPTH);
 
-    tokio::select! {
-        res = handle_requests(serial_tx, request_rx) => {
-            if let Err(err) = res {
-                tracing::error!("Serial task request handling exited with error: {err}");
-            } else {
-                tracing::info!("Serial task request handling exited");
+        tokio::select! {
+            res = handle_requests(
+                serial_tx,
+                &request_rx,
+                &pong_rx,
+                &mut send_queue,
+                &connection_config,
+                &state,
+                capture.as_ref(),
+            ) => {
+                if let Err(err) = res {
+                    if health.is_reset_expected() {
+                        tracing::debug!("Write failed while the device restarts: {err}");
+                    } else {
+                        tracing::error!("Serial task request handling exited with error: {err}");
+                    }
+                    if err
+                        .downcast_ref::<io::Error>()
+                        .is_some_and(|err| err.kind() == io::ErrorKind::TimedOut)
+                    {
+                        health.record_write_stall();
+                    }
+                } else {
+                    // The request channel only closes when shutting down or when every
+                    // connection has been dropped, and everything queued has been written
+                    tracing::info!("Serial task request handling exited");
+                    return;
+                }
+            },
+            res = handle_serial_msgs(
+                serial_rx,
+                frame_decoder,
+                &incoming_msg_tx,
+                &pong_tx,
+                &state,
+                capture.as_ref(),
+            ) => {
+                if let Err(err) = res {
+                    if health.is_reset_expected() {
+                        tracing::debug!("Read failed while the device restarts: {err}");
+                    } else {
+                        tracing::error!(
+                            "Serial task serial message handling exited with error: {err}"
+                        );
+                    }
+                } else {
+                    tracing::info!("Serial task serial message handling exited");
+                }
+                if incoming_msg_tx.is_closed() {
+                    return;
+                }
+            },
+        };
+
+        if health.is_reset_expected() {
+            tracing::info!("{transport} is restarting, reconnecting once it's back");
+        } else {
+            tracing::warn!("Lost connection to {transport}, attempting to reconnect");
+        }
+        send_queue.reject_all(io::ErrorKind::NotConnected);
+        // Whatever the device still owed acknowledgements for was lost along with it
+        acks.clear();
+        health.set_port_open(false);
+    }
+}
+
+/// Opens a stream to the device, waiting for it to start speaking the protocol if configured to.
+/// Returns the stream along with the decoder for the frames read from it, which holds whatever
+/// arrived after the frames waited for while connecting. Frames only carry a CRC if CRCs are
+/// enabled and the firmware reports supporting them.
+async fn connect(
+    transport: &dyn Transport,
+    config: &SerialConfig,
+    incoming_msg_tx: &Sender<SerialMessage>,
+    stats: &StatsRecorder,
+    firmware_info: &Mutex<FirmwareInfo>,
+) -> io::Result<(Box<dyn TransportStream>, FrameDecoder)> {
+    let mut stream = transport.connect().await?;
+    // Every connection starts out in plain COBS until CRCs have been negotiated
+    let mut frame_decoder = FrameDecoder::new(false);
+    if let Some(first_frame_timeout) = config.first_frame_timeout {
+        tokio::time::timeout(
+            first_frame_timeout,
+            wait_for_first_frame(&mut stream, &mut frame_decoder, incoming_msg_tx, stats),
+        )
+        .await
+        .map_err(|_| {
+            tracing::debug!("No valid frame from {transport} after opening it");
+            io::Error::from(io::ErrorKind::TimedOut)
+        })??;
+    }
+    if config.use_crc {
+        let use_crc = negotiate_crc(
+            &mut stream,
+            &mut frame_decoder,
+            incoming_msg_tx,
+            stats,
+            firmware_info,
+        )
+        .await?;
+        frame_decoder.set_use_crc(use_crc);
+    }
+    Ok((stream, frame_decoder))
+}
+
+/// Asks the firmware for its capabilities and, if it supports CRCs, switches it over to them.
+/// Returns whether frames carry a CRC from now on. Firmware which doesn't answer in time is
+/// assumed to be a legacy revision and spoken to in plain COBS, like firmware without CRCs.
+async fn negotiate_crc(
+    stream: &mut Box<dyn TransportStream>,
+    frame_decoder: &mut FrameDecoder,
+    incoming_msg_tx: &Sender<SerialMessage>,
+    stats: &StatsRecorder,
+    firmware_info: &Mutex<FirmwareInfo>,
+) -> io::Result<bool> {
+    let request = encode_frame(SerialMessage::from(GetFirmwareInfo).to_bytes(), false);
+    stream.write_all(&request[..]).await?;
+    let info = match tokio::time::timeout(
+        FIRMWARE_INFO_TIMEOUT,
+        wait_for_firmware_info(stream, frame_decoder, incoming_msg_tx, stats),
+    )
+    .await
+    {
+        Ok(response) => FirmwareInfo::from(response?),
+        Err(_) => FirmwareInfo::LEGACY,
+    };
+    *firmware_info.lock().unwrap() = info;
+    if !info.capabilities.contains(Capabilities::CRC) {
+        tracing::info!("Firmware does not support CRCs, falling back to plain COBS framing");
+        return Ok(false);
+    }
+    let enable_crc = encode_frame(SerialMessage::EnableCrc.to_bytes(), false);
+    stream.write_all(&enable_crc[..]).await?;
+    tracing::debug!("Switched to CRC framing");
+    Ok(true)
+}
+
+/// Reads plain frames until the firmware answers a firmware info request, forwarding everything
+/// else it sends in the meantime. Frames which arrived along with the answer were sent before
+/// the firmware could have switched to CRCs, so they're forwarded as well.
+async fn wait_for_firmware_info(
+    stream: &mut Box<dyn TransportStream>,
+    frame_decoder: &mut FrameDecoder,
+    incoming_msg_tx: &Sender<SerialMessage>,
+    stats: &StatsRecorder,
+) -> io::Result<GetFirmwareInfoResponse> {
+    let mut response = None;
+    loop {
+        let n = stream.read_buf(frame_decoder.buffer_mut()).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        stats.record_read(n);
+        while let Some(decoded_data) = frame_decoder.next_frame() {
+            let msg = split_sequence_number(&decoded_data[..])
+                .and_then(|(_seq, data)| SerialMessage::try_from_bytes(data));
+            match msg.map(GetFirmwareInfoResponse::try_from) {
+                Ok(Ok(info)) => response = Some(info),
+                Ok(Err(msg)) => {
+                    let _ = incoming_msg_tx.send(msg).await;
+                }
+                Err(_) => stats.record_decode_errors(1),
             }
-        },
-        res = handle_serial_msgs(serial_rx, incoming_msg_tx) => {
-            if let Err(err) = res {
-                tracing::error!("Serial task serial message handling exited with error: {err}");
-            } else {
-                tracing::info!("Serial task serial message handling exited");
+        }
+        stats.record_decode_errors(frame_decoder.take_invalid_frames());
+        if let Some(response) = response {
+            return Ok(response);
+        }
+    }
+}
+
+/// Pings the device until it answers with any valid message, which is forwarded like any other.
+async fn wait_for_first_frame(
+    stream: &mut Box<dyn TransportStream>,
+    frame_decoder: &mut FrameDecoder,
+    incoming_msg_tx: &Sender<SerialMessage>,
+    stats: &StatsRecorder,
+) -> io::Result<()> {
+    let ping = encode_frame(SerialMessage::Ping.to_bytes(), false);
+    let mut ping_interval = tokio::time::interval(FIRST_FRAME_PING_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ping_interval.tick() => stream.write_all(&ping[..]).await?,
+            res = stream.read_buf(frame_decoder.buffer_mut()) => {
+                let n = res?;
+                if n == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                stats.record_read(n);
+                let mut received_frame = false;
+                while let Some(decoded_data) = frame_decoder.next_frame() {
+                    let msg = split_sequence_number(&decoded_data[..])
+                        .and_then(|(_seq, data)| SerialMessage::try_from_bytes(data));
+                    if let Ok(msg) = msg {
+                        let _ = incoming_msg_tx.send(msg).await;
+                        received_frame = true;
+                    } else {
+                        stats.record_decode_errors(1);
+                    }
+                }
+                // Boards which reset on open often print noise first, which shouldn't go
+                // uncounted just because it arrived before the first valid frame
+                stats.record_decode_errors(frame_decoder.take_invalid_frames());
+                if received_frame {
+                    tracing::debug!("Device is speaking the protocol");
+                    return Ok(());
+                }
             }
-        },
-    };
+        }
+    }
+}
+
+/// Fails any requests made while the device is unavailable until the delay elapses. Returns an
+/// error if the request channel has closed and the serial task should stop.
+async fn reject_requests_for(
+    request_rx: &RequestReceiver,
+    delay: Duration,
+) -> Result<(), async_channel::RecvError> {
+    let deadline = tokio::time::Instant::now() + delay;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => break Ok(()),
+            req = request_rx.recv() => {
+                tracing::trace!("Rejecting request while disconnected");
+                req?.respond(Err(io::ErrorKind::NotConnected.into()));
+            },
+        }
+    }
 }
 
 async fn handle_requests(
-    mut serial_tx: WriteHalf<SerialStream>,
-    request_rx: Receiver<SerialTaskRequest>,
+    mut serial_tx: WriteHalf<Box<dyn TransportStream>>,
+    request_rx: &RequestReceiver,
+    pong_rx: &Receiver<Instant>,
+    send_queue: &mut SendQueue,
+    config: &SerialConfig,
+    state: &SerialTaskState,
+    capture: Option<&Capture>,
 ) -> anyhow::Result<()> {
-    while let Ok(msg) = request_rx.recv().await {
-        match msg {
-            SerialTaskRequest::SendMessage { msg, response } => {
-                let payload = msg.to_bytes();
-                let mut payload = cobs::encode_vec(&payload[..]);
-                payload.push(0x00);
-                let _ = response.send(serial_tx.write_all(&payload[..]).await);
+    let SerialTaskState { stats, acks, .. } = state;
+    let pong = encode_frame(SerialMessage::PingResponse.to_bytes(), config.use_crc);
+    let write_timeout = config.write_timeout;
+    loop {
+        if send_queue.is_empty() {
+            tokio::select! {
+                biased;
+                Ok(received_at) = pong_rx.recv() => {
+                    if let Err(err) = write_pong(
+                        &mut serial_tx,
+                        &pong[..],
+                        received_at,
+                        write_timeout,
+                        stats,
+                        capture,
+                    )
+                    .await
+                    {
+                        send_queue.reject_all(err.kind());
+                        return Err(err.into());
+                    }
+                    continue;
+                }
+                request = request_rx.recv() => {
+                    let Ok(request) = request else {
+                        tokio::time::timeout(write_timeout, serial_tx.flush())
+                            .await
+                            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+                        return Ok(());
+                    };
+                    send_queue.push(request);
+                }
+            }
+        }
+        while let Ok(received_at) = pong_rx.try_recv() {
+            if let Err(err) = write_pong(
+                &mut serial_tx,
+                &pong[..],
+                received_at,
+                write_timeout,
+                stats,
+                capture,
+            )
+            .await
+            {
+                send_queue.reject_all(err.kind());
+                return Err(err.into());
+            }
+        }
+        // Pull in everything which queued up during the last write so superseded rows can be
+        // dropped before they reach the wire
+        while let Ok(request) = request_rx.try_recv() {
+            send_queue.push(request);
+        }
+
+        if let Some(mut request) = send_queue.pop() {
+            stats.record_messages(&request);
+            // The device can answer before the write is over, so its acknowledgements have to
+            // be expected before then
+            let ack = request.take_ack();
+            acks.expect(request.messages(), ack);
+            let queued_at = request.queued_at();
+            let span = tracing::trace_span!(
+                "serial_write",
+                request_id = request.id(),
+                msg_type = request.kind(),
+                bytes = tracing::field::Empty,
+                queue_wait_us = queued_at.elapsed().as_micros() as u64,
+            );
+            let (payload, response) = request.encode(config.use_crc);
+            span.record("bytes", payload.len());
+            let res = write_with_timeout(&mut serial_tx, &payload[..], write_timeout)
+                .instrument(span.clone())
+                .await;
+            let _entered = span.enter();
+            if let Err(err) = res {
+                let _ = response.send(Err(err.kind().into()));
+                send_queue.reject_all(err.kind());
+                return Err(err.into());
             }
+            if !payload.is_empty() {
+                stats.record_write(payload.len(), queued_at);
+            }
+            if let Some(capture) = capture {
+                capture.record(Direction::ToDevice, &payload[..]);
+            }
+            tracing::trace!("Wrote request");
+            let _ = response.send(Ok(()));
         }
     }
+}
 
+/// Answers a ping which the device sent at `received_at`.
+async fn write_pong(
+    serial_tx: &mut WriteHalf<Box<dyn TransportStream>>,
+    pong: &[u8],
+    received_at: Instant,
+    write_timeout: Duration,
+    stats: &StatsRecorder,
+    capture: Option<&Capture>,
+) -> io::Result<()> {
+    write_with_timeout(serial_tx, pong, write_timeout).await?;
+    stats.record_write(pong.len(), received_at);
+    stats.record_ping_answered();
+    if let Some(capture) = capture {
+        capture.record(Direction::ToDevice, pong);
+    }
     Ok(())
 }
 
+/// Writes the bytes to the device, failing with `TimedOut` if it stops accepting data. Some USB
+/// CDC stacks stall without ever erroring, which would otherwise hang the serial task.
+async fn write_with_timeout(
+    serial_tx: &mut WriteHalf<Box<dyn TransportStream>>,
+    bytes: &[u8],
+    write_timeout: Duration,
+) -> io::Result<()> {
+    tokio::time::timeout(write_timeout, serial_tx.write_all(bytes))
+        .await
+        .map_err(|_| {
+            tracing::warn!(
+                "Write of {} bytes to the device did not complete within {}ms",
+                bytes.len(),
+                write_timeout.as_millis()
+            );
+            io::Error::from(io::ErrorKind::TimedOut)
+        })?
+}
+
+/// Queues pings from the device to be answered, hands acknowledgements to the messages they
+/// answer, and forwards everything else to the inbox.
+async fn dispatch_message(
+    msg: SerialMessage,
+    incoming_msg_tx: &Sender<SerialMessage>,
+    pong_tx: &Sender<Instant>,
+    acks: &AckTracker,
+) -> anyhow::Result<()> {
+    if let SerialMessage::Ping = msg {
+        tracing::trace!("Device sent a ping");
+        if pong_tx.try_send(Instant::now()).is_err() {
+            tracing::debug!("Dropped a ping from the device, too many are waiting for a response");
+        }
+        return Ok(());
+    }
+    if acks.resolve(&msg) {
+        tracing::trace!("Device acknowledged a message: {msg:?}");
+        return Ok(());
+    }
+    tracing::debug!("Decoded a message: {msg:?}");
+    incoming_msg_tx.send(msg).await.map_err(|err| {
+        tracing::error!("Failed to forward deserialized device message: {err}");
+        err.into()
+    })
+}
+
+/// Reads messages from the device until the stream ends. `frame_decoder` is the one used while
+/// connecting, as it may hold more than what was waited for then.
 async fn handle_serial_msgs(
-    mut serial_rx: ReadHalf<SerialStream>,
-    incoming_msg_tx: Sender<SerialMessage>,
+    mut serial_rx: ReadHalf<Box<dyn TransportStream>>,
+    mut frame_decoder: FrameDecoder,
+    incoming_msg_tx: &Sender<SerialMessage>,
+    pong_tx: &Sender<Instant>,
+    state: &SerialTaskState,
+    capture: Option<&Capture>,
 ) -> anyhow::Result<()> {
-    let mut incoming_serial_buffer = Vec::with_capacity(1024);
+    let SerialTaskState {
+        stats,
+        firmware_info,
+        acks,
+        ..
+    } = state;
+    let mut sequence_tracker = SequenceTracker::default();
     loop {
-        match serial_rx.read_buf(&mut incoming_serial_buffer).await {
+        // A single read can contain several frames, forward all of them before waiting for more
+        // data
+        while let Some(decoded_data) = frame_decoder.next_frame() {
+            let decode_started = Instant::now();
+            let msg = match split_sequence_number(&decoded_data[..]) {
+                // Repeats are only dropped once the firmware has said it numbers its messages
+                Ok((Some(seq), _data))
+                    if firmware_info
+                        .lock()
+                        .unwrap()
+                        .capabilities
+                        .contains(Capabilities::SEQUENCING)
+                        && !sequence_tracker.accept(seq, stats) =>
+                {
+                    continue;
+                }
+                Ok((_seq, data)) => SerialMessage::try_from_bytes(data),
+                Err(err) => Err(err),
+            };
+            match msg {
+                Ok(msg) => {
+                    let span = tracing::trace_span!(
+                        "serial_receive",
+                        msg_type = msg.as_ref(),
+                        decode_us = decode_started.elapsed().as_micros() as u64,
+                    );
+                    dispatch_message(msg, incoming_msg_tx, pong_tx, acks)
+                        .instrument(span)
+                        .await?;
+                }
+                Err(err) => {
+                    tracing::debug!("Failed to deserialize device message: {err}");
+                    stats.record_decode_errors(1);
+                }
+            }
+        }
+        stats.record_decode_errors(frame_decoder.take_invalid_frames());
+
+        match serial_rx.read_buf(frame_decoder.buffer_mut()).await {
+            Ok(0) => {
+                tracing::debug!("Serial port reached end of stream");
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
             Ok(n) => {
                 tracing::trace!("Received {n} bytes from the serial port");
-                if let Ok(decoded_data) = cobs::decode_vec(&incoming_serial_buffer[..]) {
-                    tracing::trace!(
-                        "Decoded a payload of {} bytes from buffer of {} bytes",
-                        decoded_data.len(),
-                        incoming_serial_buffer.len()
-                    );
-                    if let Ok(msg) = SerialMessage::try_from_bytes(&decoded_data[..]) {
-                        tracing::debug!("Decoded a message: {msg:?}");
-                        if let Err(err) = incoming_msg_tx.send(msg).await {
-                            tracing::error!("Failed to forward deserialized device message: {err}");
-                            return Err(err.into());
-                        }
-                    }
-                    let (encoded_len, _) = incoming_serial_buffer
-                        .iter()
-                        .enumerate()
-                        .find(|(_idx, elem)| **elem == 0x00)
-                        .expect("Need a terminator to have a valid COBS payload");
-                    incoming_serial_buffer =
-                        Vec::from_iter(incoming_serial_buffer.into_iter().skip(encoded_len + 1));
+                stats.record_read(n);
+                if let Some(capture) = capture {
+                    let buffer = frame_decoder.buffer_mut();
+                    capture.record(Direction::FromDevice, &buffer[buffer.len() - n..]);
                 }
             }
             Err(err) => {
-                tracing::error!("Failed to read data from the serial port: {err}");
+                tracing::debug!("Failed to read data from the serial port: {err}");
                 return Err(err.into());
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display_config(
+        width: usize,
+        height: usize,
+        pixel_representation: PixelRepresentation,
+    ) -> DisplayConfiguration {
+        DisplayConfiguration {
+            width,
+            height,
+            pixel_representation,
+            orientation: Default::default(),
+            max_fps_hint: None,
+        }
+    }
+
+    /// Connects to a mock device with the display and configuration given, returning the
+    /// connection along with the device so what it received can be checked.
+    fn connect_to_mock_device(
+        display_config: DisplayConfiguration,
+        device_supports_crc: bool,
+        config: SerialConfig,
+    ) -> (SerialConnection, ShutdownHandle, MockDevice) {
+        let (device, transport) = MockDevice::new(display_config, device_supports_crc);
+        tokio::spawn({
+            let device = device.clone();
+            async move { device.run().await }
+        });
+        let (tx, rx) = async_channel::unbounded();
+        let (serial_conn, shutdown_handle, serial_task) =
+            start_transport_task(Box::new(transport), config, tx, rx);
+        tokio::spawn(Box::into_pin(serial_task));
+        (serial_conn, shutdown_handle, device)
+    }
+
+    fn connect_with_crc(
+        device_supports_crc: bool,
+    ) -> (SerialConnection, ShutdownHandle, MockDevice) {
+        connect_to_mock_device(
+            display_config(8, 2, PixelRepresentation::Monocolor),
+            device_supports_crc,
+            SerialConfig {
+                use_crc: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn enabled_crc(device: &MockDevice) -> bool {
+        device
+            .received_messages()
+            .iter()
+            .any(|msg| matches!(msg, SerialMessage::EnableCrc))
+    }
+
+    #[tokio::test]
+    async fn switches_to_crc_framing_when_the_firmware_supports_it() {
+        let (serial_conn, _shutdown_handle, device) = connect_with_crc(true);
+        // The device drops frames without a valid CRC once switched, so it only answers if
+        // both ends agree on the framing
+        let display_info = serial_conn.get_display_info().await.unwrap();
+        assert_eq!((display_info.width, display_info.height), (8, 2));
+        assert!(enabled_crc(&device));
+        assert!(serial_conn.capabilities().contains(Capabilities::CRC));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_plain_framing_when_the_firmware_lacks_crcs() {
+        let (serial_conn, _shutdown_handle, device) = connect_with_crc(false);
+        let display_info = serial_conn.get_display_info().await.unwrap();
+        assert_eq!((display_info.width, display_info.height), (8, 2));
+        assert!(!enabled_crc(&device));
+        assert_eq!(serial_conn.stats().decode_errors, 0);
+    }
+
+    #[tokio::test]
+    async fn frames_arriving_along_with_the_firmware_info_are_kept() {
+        let press = |id| {
+            let msg = SerialMessage::ReportInput(ReportInput {
+                kind: InputKind::ButtonPressed,
+                id,
+                value: 0,
+            });
+            encode_frame(msg.to_bytes(), false)
+        };
+        let (transport, peer) = LoopbackTransport::new();
+        tokio::spawn(async move {
+            let mut stream = peer.accept().await.unwrap();
+            let mut frame_decoder = FrameDecoder::new(false);
+            while frame_decoder.next_frame().is_none() {
+                stream.read_buf(frame_decoder.buffer_mut()).await.unwrap();
+            }
+            // The answer, a button press, and the start of another press all in one write
+            let info = SerialMessage::from(GetFirmwareInfoResponse {
+                version_major: 0,
+                version_minor: 1,
+                version_patch: 0,
+                capabilities: Capabilities::LEGACY,
+            });
+            let second_press = press(1);
+            let mut bytes = encode_frame(info.to_bytes(), false);
+            bytes.extend(press(0));
+            bytes.extend(&second_press[..2]);
+            stream.write_all(&bytes[..]).await.unwrap();
+            stream.write_all(&second_press[2..]).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let (tx, rx) = async_channel::unbounded();
+        let config = SerialConfig {
+            use_crc: true,
+            ..Default::default()
+        };
+        let (serial_conn, _shutdown_handle, serial_task) =
+            start_transport_task(Box::new(transport), config, tx, rx);
+        tokio::spawn(Box::into_pin(serial_task));
+
+        for id in [0, 1] {
+            let pressed = serial_conn
+                .wait_for_message(
+                    |msg| matches!(msg, SerialMessage::ReportInput(input) if input.id == id),
+                    Some(Duration::from_secs(1)),
+                )
+                .await;
+            assert!(pressed.is_some(), "Press of button {id} was lost");
+        }
+    }
+
+    /// The column each update starts at and how many pixels it carries.
+    fn segments(msgs: &[SerialMessage]) -> Vec<(u16, u8)> {
+        msgs.iter()
+            .map(|msg| match msg {
+                SerialMessage::UpdateRow(inner) => (inner.column_offset, inner.row_data_len),
+                SerialMessage::UpdateRowRgb(inner) => (inner.column_offset, inner.row_data_len),
+                SerialMessage::UpdateRowRgb888(inner) => (inner.column_offset, inner.row_data_len),
+                other => panic!("{other:?} isn't a row update"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_rows_wider_than_a_segment() {
+        let cases = [
+            (255, vec![(0, 255)]),
+            (256, vec![(0, 255), (255, 1)]),
+            (512, vec![(0, 255), (255, 255), (510, 2)]),
+        ];
+        for (width, expected) in cases {
+            let mono = update_row_msgs(3, &vec![true; width]).unwrap();
+            let rgb = update_row_rgb_msgs(3, &vec![0x7fff; width]).unwrap();
+            let rgb888 = update_row_rgb888_msgs(3, &vec![0xffffff; width]).unwrap();
+            assert_eq!(segments(&mono), expected, "Monocolor row of {width} pixels");
+            assert_eq!(segments(&rgb), expected, "RGB row of {width} pixels");
+            assert_eq!(segments(&rgb888), expected, "RGB888 row of {width} pixels");
+        }
+    }
+
+    #[test]
+    fn row_segments_survive_encoding() {
+        let row = (0..512).map(|column| column as u16).collect::<Vec<_>>();
+        let decoded = update_row_rgb_msgs(7, &row)
+            .unwrap()
+            .into_iter()
+            .map(|msg| SerialMessage::try_from_bytes(&msg.to_bytes()[..]).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(segments(&decoded), [(0, 255), (255, 255), (510, 2)]);
+        let mut reassembled = vec![0; row.len()];
+        for msg in decoded {
+            let SerialMessage::UpdateRowRgb(segment) = msg else {
+                panic!("{msg:?} isn't an RGB row update");
+            };
+            assert_eq!(segment.row_number, 7);
+            let start = usize::from(segment.column_offset);
+            for (column, pixel) in (start..).zip(segment.pixels()) {
+                reassembled[column] = pixel;
+            }
+        }
+        assert_eq!(reassembled, row);
+    }
+
+    #[tokio::test]
+    async fn wide_rows_reach_the_device_intact() {
+        for width in [255, 256, 512] {
+            let (serial_conn, _shutdown_handle, device) = connect_to_mock_device(
+                display_config(width, 2, PixelRepresentation::RGB888),
+                false,
+                Default::default(),
+            );
+            let row = (0..width as u32).collect::<Vec<_>>();
+            serial_conn.update_row_rgb888(1, &row).await.unwrap();
+            assert_eq!(device.framebuffer()[1], row, "Row of {width} pixels");
+
+            let mono = (0..width).map(|column| column % 3 == 0).collect::<Vec<_>>();
+            let (serial_conn, _shutdown_handle, device) = connect_to_mock_device(
+                display_config(width, 2, PixelRepresentation::Monocolor),
+                false,
+                Default::default(),
+            );
+            serial_conn.update_rows(&[(0, &mono)]).await.unwrap();
+            // Batches are only waited on until they're written, the device answering a request
+            // means it has handled them too
+            serial_conn.get_display_info().await.unwrap();
+            let lit = device.framebuffer()[0]
+                .iter()
+                .map(|&pixel| pixel != 0)
+                .collect::<Vec<_>>();
+            assert_eq!(lit, mono, "Monocolor row of {width} pixels");
+        }
+    }
+}
@@ -1,80 +1,168 @@
-use async_channel::{Receiver, Sender};
+use super::config::InboxConfig;
+use async_channel::{Receiver, Sender, TrySendError};
 use megabit_serial_protocol::SerialMessage;
 use std::{
     collections::VecDeque,
-    sync::{Arc, Mutex, Weak},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
     time::{Duration, Instant},
 };
+use tokio::sync::watch;
 
+const SUBSCRIBER_QUEUE_DEPTH: usize = 32;
+
+type MessageMatcher = Box<dyn Fn(&SerialMessage) -> bool + Send>;
+type Subscribers = Mutex<Vec<(MessageMatcher, Sender<SerialMessage>)>>;
+
+#[derive(Debug)]
 pub enum HandleNotification {
     NewMessages,
     ClosedConnection,
 }
 
+/// Counts of the messages the inbox has dropped, and of the ones it currently holds
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InboxStats {
+    pub stored: usize,
+    pub evicted: u64,
+    pub expired: u64,
+}
+
+#[derive(Debug, Default)]
+struct InboxCounters {
+    evicted: AtomicU64,
+    expired: AtomicU64,
+}
+
 pub struct MessageInbox {
     msg_rx: Receiver<SerialMessage>,
     msg_queue: Arc<Mutex<VecDeque<(Instant, SerialMessage)>>>,
-    notification_tx: Sender<HandleNotification>,
-    notification_rx: Receiver<HandleNotification>,
-    msg_expiration_duration: Option<Duration>,
+    notification_tx: watch::Sender<HandleNotification>,
+    config: InboxConfig,
+    subscribers: Arc<Subscribers>,
+    counters: Arc<InboxCounters>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct InboxHandle {
     msg_queue: Weak<Mutex<VecDeque<(Instant, SerialMessage)>>>,
-    notification_rx: Receiver<HandleNotification>,
+    notification_rx: watch::Receiver<HandleNotification>,
+    subscribers: Weak<Subscribers>,
+    counters: Arc<InboxCounters>,
+}
+
+impl std::fmt::Debug for InboxHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InboxHandle")
+            .field("msg_queue", &self.msg_queue)
+            .field("notification_rx", &self.notification_rx)
+            .finish_non_exhaustive()
+    }
 }
 
 impl MessageInbox {
-    pub fn new(msg_rx: Receiver<SerialMessage>, msg_expiration_age: Option<Duration>) -> Self {
-        let (tx, rx) = async_channel::bounded(1);
+    pub fn new(msg_rx: Receiver<SerialMessage>, config: InboxConfig) -> Self {
+        // A watch channel wakes every waiting handle rather than just one of them, and never
+        // blocks the inbox when nobody is waiting
+        let (tx, _rx) = watch::channel(HandleNotification::NewMessages);
         Self {
             msg_rx,
             msg_queue: Arc::new(Mutex::new(VecDeque::new())),
             notification_tx: tx,
-            notification_rx: rx,
-            msg_expiration_duration: msg_expiration_age,
+            config,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            counters: Arc::default(),
         }
     }
 
     pub fn get_handle(&self) -> InboxHandle {
         InboxHandle {
             msg_queue: Arc::downgrade(&self.msg_queue),
-            notification_rx: self.notification_rx.clone(),
+            notification_rx: self.notification_tx.subscribe(),
+            subscribers: Arc::downgrade(&self.subscribers),
+            counters: self.counters.clone(),
         }
     }
 
     pub async fn run(self) {
         while let Ok(msg) = self.msg_rx.recv().await {
+            self.publish(&msg);
             {
                 let mut msg_queue = self.msg_queue.lock().unwrap();
                 msg_queue.push_back((std::time::Instant::now(), msg));
 
-                if let Some(expiration_age) = self.msg_expiration_duration {
-                    while let Some((receive_time, _msg)) = msg_queue.get(0) {
-                        if *receive_time + expiration_age > std::time::Instant::now() {
+                if let Some(expiration_age) = self.config.retention {
+                    while let Some((receive_time, _msg)) = msg_queue.front() {
+                        if *receive_time + expiration_age <= std::time::Instant::now() {
                             let _ = msg_queue.pop_front();
+                            self.counters.expired.fetch_add(1, Ordering::Relaxed);
                         } else {
                             break;
                         }
                     }
                 }
+                if let Some(max_messages) = self.config.max_messages {
+                    while msg_queue.len() > max_messages {
+                        let _ = msg_queue.pop_front();
+                        self.counters.evicted.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
             }
-            let _ = self
-                .notification_tx
-                .send(HandleNotification::NewMessages)
-                .await;
+            self.notification_tx
+                .send_replace(HandleNotification::NewMessages);
         }
 
-        let _ = self
-            .notification_tx
-            .send(HandleNotification::ClosedConnection)
-            .await;
+        self.notification_tx
+            .send_replace(HandleNotification::ClosedConnection);
         tracing::debug!("Stopping message inbox");
     }
+
+    fn publish(&self, msg: &SerialMessage) {
+        self.subscribers.lock().unwrap().retain(|(matcher, tx)| {
+            match matcher(msg).then(|| tx.try_send(msg.clone())) {
+                Some(Err(TrySendError::Closed(_))) => false,
+                Some(Err(TrySendError::Full(_))) => {
+                    tracing::warn!("Subscriber is not keeping up, dropping {}", msg.as_ref());
+                    true
+                }
+                Some(Ok(())) | None => true,
+            }
+        });
+    }
 }
 
 impl InboxHandle {
+    pub fn stats(&self) -> InboxStats {
+        InboxStats {
+            stored: self
+                .msg_queue
+                .upgrade()
+                .map_or(0, |msg_queue| msg_queue.lock().expect("Mutex locks").len()),
+            evicted: self.counters.evicted.load(Ordering::Relaxed),
+            expired: self.counters.expired.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Forwards every message received from now on which the matcher accepts. Messages are still
+    /// stored for the other lookups as well. A subscriber which falls more than a few messages
+    /// behind misses messages, and is removed once its receiver is dropped.
+    pub fn subscribe<F>(&self, matcher: F) -> Receiver<SerialMessage>
+    where
+        F: Fn(&SerialMessage) -> bool + Send + 'static,
+    {
+        let (tx, rx) = async_channel::bounded(SUBSCRIBER_QUEUE_DEPTH);
+        if let Some(subscribers) = self.subscribers.upgrade() {
+            let mut subscribers = subscribers.lock().unwrap();
+            subscribers.retain(|(_matcher, tx)| !tx.is_closed());
+            subscribers.push((Box::new(matcher), tx));
+        } else {
+            tracing::debug!("Message inbox has been deleted, subscription will be empty");
+        }
+        rx
+    }
+
     pub async fn wait_for_message<F>(
         &self,
         matcher: F,
@@ -82,40 +170,99 @@ impl InboxHandle {
     ) -> Option<SerialMessage>
     where
         F: Fn(&SerialMessage) -> bool,
+    {
+        self.wait_for_matching_message(
+            |msg| matcher(msg).then(|| msg.clone()),
+            None,
+            timeout,
+            false,
+        )
+        .await
+    }
+
+    /// Waits for a message of a specific kind, e.g. `wait_for::<GetDisplayInfoResponse>(..)`.
+    pub async fn wait_for<T>(&self, timeout: Option<Duration>) -> Option<T>
+    where
+        T: TryFrom<SerialMessage>,
+    {
+        self.wait_for_matching_message(|msg| T::try_from(msg.clone()).ok(), None, timeout, false)
+            .await
+    }
+
+    /// Waits for the first message received no earlier than `start_time` which the matcher maps
+    /// to a response. The matched message is removed from the inbox so that concurrent requests
+    /// of the same kind each consume a separate response.
+    pub async fn take_response_since<F, R>(
+        &self,
+        matcher: F,
+        start_time: Instant,
+        timeout: Option<Duration>,
+    ) -> Option<R>
+    where
+        F: Fn(&SerialMessage) -> Option<R>,
+    {
+        self.wait_for_matching_message(matcher, Some(start_time), timeout, true)
+            .await
+    }
+
+    async fn wait_for_matching_message<F, R>(
+        &self,
+        matcher: F,
+        start_time: Option<Instant>,
+        timeout: Option<Duration>,
+        remove_match: bool,
+    ) -> Option<R>
+    where
+        F: Fn(&SerialMessage) -> Option<R>,
     {
         let timeout_instant = timeout.map(|duration| std::time::Instant::now() + duration);
+        let mut notification_rx = self.notification_rx.clone();
         loop {
-            let msg = if let Some(msg_queue) = self.msg_queue.upgrade() {
-                let queue = msg_queue.lock().expect("Mutex locks");
-                let msg = queue.iter().find_map(|(_received_time, msg)| {
-                    if matcher(msg) {
-                        Some(msg.clone())
-                    } else {
-                        None
-                    }
-                });
-                msg
+            // Anything sent after this point will wake the wait below, so messages arriving
+            // while the queue is being searched can't be missed
+            drop(notification_rx.borrow_and_update());
+            let matched = if let Some(msg_queue) = self.msg_queue.upgrade() {
+                let mut queue = msg_queue.lock().expect("Mutex locks");
+                let matched = queue
+                    .iter()
+                    .enumerate()
+                    .find_map(|(idx, (received_time, msg))| {
+                        if start_time.is_none_or(|start_time| *received_time >= start_time) {
+                            matcher(msg).map(|matched| (idx, matched))
+                        } else {
+                            None
+                        }
+                    });
+                if let (true, Some((idx, _))) = (remove_match, &matched) {
+                    let _ = queue.remove(*idx);
+                }
+                matched.map(|(_idx, matched)| matched)
             } else {
                 tracing::debug!("Message inbox has been deleted, no messages to search");
                 break None;
             };
 
-            if let Some(msg) = msg {
-                break Some(msg);
+            if let Some(matched) = matched {
+                break Some(matched);
             } else {
-                let notification = if let Some(timeout_instant) = timeout_instant {
+                let changed = if let Some(timeout_instant) = timeout_instant {
                     let time_left = timeout_instant - std::time::Instant::now();
-                    match tokio::time::timeout(time_left, self.notification_rx.recv()).await {
+                    match tokio::time::timeout(time_left, notification_rx.changed()).await {
                         Ok(res) => res,
                         Err(_) => break None,
                     }
                 } else {
-                    self.notification_rx.recv_blocking()
+                    notification_rx.changed().await
                 };
 
-                match notification {
-                    Ok(HandleNotification::NewMessages) => continue,
-                    Ok(HandleNotification::ClosedConnection) | Err(_) => {
+                match changed.map(|()| {
+                    matches!(
+                        *notification_rx.borrow_and_update(),
+                        HandleNotification::NewMessages
+                    )
+                }) {
+                    Ok(true) => continue,
+                    Ok(false) | Err(_) => {
                         tracing::debug!(
                             "Notification channel from inbox closed, no messages to search"
                         );
@@ -147,4 +294,95 @@ impl InboxHandle {
             None
         }
     }
+
+    /// Finds a message of a specific kind received no earlier than `start_time`.
+    pub fn check_for_since<T>(&self, start_time: Instant) -> Option<T>
+    where
+        T: TryFrom<SerialMessage>,
+    {
+        let msg_queue = self.msg_queue.upgrade()?;
+        let msg_queue = msg_queue.lock().expect("Mutex locks");
+        msg_queue.iter().find_map(|(receive_time, msg)| {
+            if *receive_time >= start_time {
+                T::try_from(msg.clone()).ok()
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use megabit_serial_protocol::{GetDisplayInfoResponse, PixelRepresentation};
+
+    fn display_info() -> SerialMessage {
+        SerialMessage::GetDisplayInfoResponse(GetDisplayInfoResponse {
+            width: 32,
+            height: 16,
+            pixel_representation: PixelRepresentation::Monocolor,
+        })
+    }
+
+    #[tokio::test]
+    async fn responses_arriving_before_the_wait_begins_are_found() {
+        let (msg_tx, msg_rx) = async_channel::unbounded();
+        let inbox = MessageInbox::new(msg_rx, InboxConfig::default());
+        let handle = inbox.get_handle();
+        tokio::spawn(inbox.run());
+
+        // The request has been sent, and the device answers before anybody starts waiting
+        let sent_at = Instant::now();
+        msg_tx.try_send(display_info()).unwrap();
+        while handle.stats().stored == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let timeout = Some(Duration::from_millis(100));
+        let waited = handle
+            .wait_for_message(
+                |msg| matches!(msg, SerialMessage::GetDisplayInfoResponse(_)),
+                timeout,
+            )
+            .await;
+        assert!(waited.is_some());
+        let taken = handle
+            .take_response_since(
+                |msg| GetDisplayInfoResponse::try_from(msg.clone()).ok(),
+                sent_at,
+                timeout,
+            )
+            .await;
+        assert_eq!(taken.map(|info| (info.width, info.height)), Some((32, 16)));
+    }
+
+    #[tokio::test]
+    async fn flooding_the_inbox_keeps_it_bounded() {
+        const FLOOD: u64 = 1_000_000;
+        let config = InboxConfig::default();
+        let max_messages = config.max_messages.unwrap();
+        let (msg_tx, msg_rx) = async_channel::unbounded();
+        let inbox = MessageInbox::new(msg_rx, config);
+        let handle = inbox.get_handle();
+        tokio::spawn(inbox.run());
+
+        for _ in 0..FLOOD {
+            msg_tx.try_send(SerialMessage::PingResponse).unwrap();
+        }
+        // Once the last message can be found the inbox has been through all of them
+        msg_tx.try_send(SerialMessage::ReportButtonPress).unwrap();
+        let last = handle
+            .wait_for_message(
+                |msg| matches!(msg, SerialMessage::ReportButtonPress),
+                Some(Duration::from_secs(60)),
+            )
+            .await;
+        assert!(last.is_some());
+
+        let stats = handle.stats();
+        assert_eq!(stats.stored, max_messages);
+        assert_eq!(stats.evicted, FLOOD + 1 - max_messages as u64);
+        assert_eq!(stats.expired, 0);
+    }
 }
@@ -0,0 +1,203 @@
+use super::{lanes::Lane, stats::StatsRecorder, SerialTaskRequest};
+use megabit_serial_protocol::{SerialMessage, Status};
+use std::{collections::VecDeque, io, mem::Discriminant, sync::Arc};
+
+/// Control requests written in a row before a waiting bulk request gets its turn
+const MAX_CONTROL_BURST: usize = 4;
+
+/// Requests which have been taken off the actor channels but not yet written to the device.
+/// Control requests are written ahead of bulk ones, but only [`MAX_CONTROL_BURST`] at a time so
+/// frame data keeps moving. With coalescing enabled, a row update replaces any update for the
+/// same row which is still waiting to be written, since only the most recent contents of a row
+/// matter. Updates never replace one queued ahead of a commit though, as the commit has to show
+/// the frame the row was part of.
+#[derive(Debug)]
+pub struct SendQueue {
+    control: VecDeque<SerialTaskRequest>,
+    bulk: VecDeque<SerialTaskRequest>,
+    control_burst: usize,
+    coalesce_rows: bool,
+    stats: Arc<StatsRecorder>,
+}
+
+impl SendQueue {
+    pub fn new(coalesce_rows: bool, stats: Arc<StatsRecorder>) -> Self {
+        Self {
+            control: VecDeque::new(),
+            bulk: VecDeque::new(),
+            control_burst: 0,
+            coalesce_rows,
+            stats,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.control.is_empty() && self.bulk.is_empty()
+    }
+
+    pub fn push(&mut self, request: SerialTaskRequest) {
+        if Lane::of(&request) == Lane::Control {
+            self.control.push_back(request);
+            return;
+        }
+        if let (true, Some(key)) = (self.coalesce_rows, row_key(&request)) {
+            let frame_start = self
+                .bulk
+                .iter()
+                .rposition(is_commit)
+                .map_or(0, |commit| commit + 1);
+            let superseded = self
+                .bulk
+                .range(frame_start..)
+                .position(|pending| row_key(pending) == Some(key));
+            if let Some(mut superseded) =
+                superseded.and_then(|idx| self.bulk.remove(frame_start + idx))
+            {
+                tracing::trace!("Dropping superseded update to row {}", key.1);
+                self.stats.record_dropped_frame();
+                // The caller only cares that the row ends up with the latest contents, and one
+                // waiting for an acknowledgement mustn't take its absence as the row being lost
+                // and send the stale contents again
+                if let Some(ack) = superseded.take_ack() {
+                    let _ = ack.send(Status::Success);
+                }
+                superseded.respond(Ok(()));
+            }
+        }
+        // Newer data is always appended so it can't be written ahead of anything queued earlier
+        self.bulk.push_back(request);
+    }
+
+    pub fn pop(&mut self) -> Option<SerialTaskRequest> {
+        let bulk_waiting = !self.bulk.is_empty();
+        if !self.control.is_empty() && (!bulk_waiting || self.control_burst < MAX_CONTROL_BURST) {
+            self.control_burst += 1;
+            self.control.pop_front()
+        } else {
+            self.control_burst = 0;
+            self.bulk.pop_front()
+        }
+    }
+
+    /// Fails every pending request, e.g. after the device has gone away.
+    pub fn reject_all(&mut self, kind: io::ErrorKind) {
+        for request in self.control.drain(..).chain(self.bulk.drain(..)) {
+            request.respond(Err(kind.into()));
+        }
+    }
+}
+
+fn is_commit(request: &SerialTaskRequest) -> bool {
+    request
+        .messages()
+        .iter()
+        .any(|msg| matches!(msg, SerialMessage::CommitRender(_)))
+}
+
+/// Batches are never coalesced, only individual row updates.
+/// Segments of wide rows only supersede the segment starting at the same column.
+fn row_key(request: &SerialTaskRequest) -> Option<(Discriminant<SerialMessage>, u8, u16)> {
+    let SerialTaskRequest::SendMessage { msg, .. } = request else {
+        return None;
+    };
+    let (row_number, column_offset) = match msg {
+        SerialMessage::UpdateRow(inner) => (inner.row_number, inner.column_offset),
+        SerialMessage::UpdateRowRgb(inner) => (inner.row_number, inner.column_offset),
+        SerialMessage::UpdateRowRgb888(inner) => (inner.row_number, inner.column_offset),
+        _ => return None,
+    };
+    Some((std::mem::discriminant(msg), row_number, column_offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use megabit_serial_protocol::{CommitRender, UpdateRow};
+    use tokio::sync::oneshot;
+
+    fn send(queue: &mut SendQueue, msg: SerialMessage) -> oneshot::Receiver<io::Result<()>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        queue.push(SerialTaskRequest::message(msg, response_tx));
+        response_rx
+    }
+
+    /// A row update whose contents tell the updates to the same row apart
+    fn row(row_number: u8, contents: u8) -> SerialMessage {
+        SerialMessage::UpdateRow(UpdateRow {
+            row_number,
+            column_offset: 0,
+            row_data_len: 1,
+            row_data: vec![contents],
+        })
+    }
+
+    fn commit() -> SerialMessage {
+        SerialMessage::CommitRender(CommitRender)
+    }
+
+    /// Messages are compared by how they're encoded
+    fn encoded<const N: usize>(msgs: [SerialMessage; N]) -> Vec<Vec<u8>> {
+        msgs.into_iter().map(SerialMessage::to_bytes).collect()
+    }
+
+    /// Everything in the queue in the order it's written
+    fn drain(queue: &mut SendQueue) -> Vec<Vec<u8>> {
+        std::iter::from_fn(|| queue.pop())
+            .flat_map(|request| request.messages().to_vec())
+            .map(SerialMessage::to_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn coalesces_updates_to_the_same_row() {
+        let stats = Arc::new(StatsRecorder::default());
+        let mut queue = SendQueue::new(true, stats.clone());
+        let mut superseded = send(&mut queue, row(0, 0));
+        send(&mut queue, row(1, 0));
+        send(&mut queue, row(0, 1));
+
+        assert!(matches!(superseded.try_recv(), Ok(Ok(()))));
+        assert_eq!(drain(&mut queue), encoded([row(1, 0), row(0, 1)]));
+        assert_eq!(stats.dropped_frames(), 1);
+    }
+
+    #[test]
+    fn superseded_updates_are_acknowledged_for_their_waiters() {
+        let mut queue = SendQueue::new(true, Arc::default());
+        let (response_tx, mut response_rx) = oneshot::channel();
+        let (ack_tx, mut ack_rx) = oneshot::channel();
+        queue.push(SerialTaskRequest::acknowledged(
+            row(0, 0),
+            response_tx,
+            ack_tx,
+        ));
+        send(&mut queue, row(0, 1));
+
+        assert!(matches!(response_rx.try_recv(), Ok(Ok(()))));
+        assert!(matches!(ack_rx.try_recv(), Ok(Status::Success)));
+        assert_eq!(drain(&mut queue), encoded([row(0, 1)]));
+    }
+
+    #[test]
+    fn rows_are_not_coalesced_across_a_commit() {
+        let stats = Arc::new(StatsRecorder::default());
+        let mut queue = SendQueue::new(true, stats.clone());
+        let mut committed = send(&mut queue, row(0, 0));
+        send(&mut queue, commit());
+        send(&mut queue, row(0, 1));
+        // Rows after the commit still coalesce with each other
+        send(&mut queue, row(0, 2));
+
+        assert!(committed.try_recv().is_err());
+        assert_eq!(drain(&mut queue), encoded([row(0, 0), commit(), row(0, 2)]));
+        assert_eq!(stats.dropped_frames(), 1);
+    }
+
+    #[test]
+    fn rows_are_not_coalesced_without_it_enabled() {
+        let mut queue = SendQueue::new(false, Arc::default());
+        send(&mut queue, row(0, 0));
+        send(&mut queue, row(0, 1));
+        assert_eq!(drain(&mut queue), encoded([row(0, 0), row(0, 1)]));
+    }
+}
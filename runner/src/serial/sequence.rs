@@ -0,0 +1,48 @@
+use super::stats::StatsRecorder;
+use std::collections::VecDeque;
+
+/// Number of the most recent sequence numbers a duplicate is looked for in
+const SEQUENCE_WINDOW: usize = 64;
+
+/// Detects repeated and missing messages from the sequence numbers the device puts on them.
+/// The device restarts its numbering when it resets, so a tracker only lasts for a single
+/// connection.
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    newest: Option<u16>,
+    recent: VecDeque<u16>,
+}
+
+impl SequenceTracker {
+    /// Whether a message with this sequence number should be kept. Messages which were already
+    /// received, e.g. because the device retransmitted them, are dropped, and skipped sequence
+    /// numbers are counted as gaps.
+    pub fn accept(&mut self, seq: u16, stats: &StatsRecorder) -> bool {
+        if self.newest == Some(seq) || self.recent.contains(&seq) {
+            tracing::debug!("Dropping duplicate message with sequence number {seq}");
+            stats.record_duplicate();
+            return false;
+        }
+        if self.recent.len() == SEQUENCE_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(seq);
+
+        let Some(newest) = self.newest else {
+            self.newest = Some(seq);
+            return true;
+        };
+        // Sequence numbers wrap, so anything in the half of the range behind the newest message
+        // is a late arrival rather than a jump forward
+        let ahead = seq.wrapping_sub(newest);
+        if ahead < u16::MAX / 2 {
+            self.newest = Some(seq);
+            let missed = ahead - 1;
+            if missed > 0 {
+                tracing::debug!("Missed {missed} messages before sequence number {seq}");
+                stats.record_sequence_gap(missed.into());
+            }
+        }
+        true
+    }
+}
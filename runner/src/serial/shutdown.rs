@@ -0,0 +1,56 @@
+use super::{
+    lanes::RequestSender, update_row_msgs, update_row_rgb888_msgs, update_row_rgb_msgs,
+    SerialTaskRequest,
+};
+use crate::display::DisplayConfiguration;
+use megabit_serial_protocol::{PixelRepresentation, SerialMessage, SetLedState};
+use tokio::sync::{oneshot, watch};
+
+/// Stops the serial task cleanly so the device isn't left showing a half-written frame.
+#[derive(Debug)]
+pub struct ShutdownHandle {
+    pub(super) actor_tx: RequestSender,
+    pub(super) stopped_rx: watch::Receiver<bool>,
+}
+
+impl ShutdownHandle {
+    /// Stops accepting new requests, writes everything which is already queued followed by the
+    /// `goodbye` messages, and then closes the connection to the device. This waits on the
+    /// device, so callers should put a deadline on it in case the device has stopped responding.
+    pub async fn shutdown(self, goodbye: Vec<SerialMessage>) {
+        if !goodbye.is_empty() {
+            let (response, _response_rx) = oneshot::channel();
+            let _ = self
+                .actor_tx
+                .send(SerialTaskRequest::batch(goodbye, response))
+                .await;
+        }
+        self.actor_tx.close();
+
+        let mut stopped_rx = self.stopped_rx;
+        let _ = stopped_rx.wait_for(|stopped| *stopped).await;
+        tracing::info!("Serial task shut down");
+    }
+}
+
+/// Messages which clear every row of the display and turn off the status LED.
+pub fn blank_display_sequence(display_config: &DisplayConfiguration) -> Vec<SerialMessage> {
+    (0..display_config.height)
+        .filter_map(|row_number| {
+            let row_number = u8::try_from(row_number).ok()?;
+            match display_config.pixel_representation {
+                PixelRepresentation::Monocolor => {
+                    update_row_msgs(row_number, &vec![false; display_config.width]).ok()
+                }
+                PixelRepresentation::RGB555 | PixelRepresentation::RGB565 => {
+                    update_row_rgb_msgs(row_number, &vec![0; display_config.width]).ok()
+                }
+                PixelRepresentation::RGB888 => {
+                    update_row_rgb888_msgs(row_number, &vec![0; display_config.width]).ok()
+                }
+            }
+        })
+        .flatten()
+        .chain([SerialMessage::SetLedState(SetLedState { new_state: false })])
+        .collect()
+}
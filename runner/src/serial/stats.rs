@@ -0,0 +1,177 @@
+use super::SerialTaskRequest;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Number of the most recent send latencies which the latency summary is computed over
+const LATENCY_WINDOW: usize = 1024;
+
+/// A snapshot of the traffic counters of a connection, see [`SerialConnection::stats`].
+///
+/// [`SerialConnection::stats`]: super::SerialConnection::stats
+#[derive(Clone, Debug, Default)]
+pub struct SerialStats {
+    /// Messages written to the device, keyed by message type
+    pub messages_sent: BTreeMap<String, u64>,
+    pub bytes_written: u64,
+    pub bytes_read: u64,
+    /// Frames from the device which couldn't be decoded into a message
+    pub decode_errors: u64,
+    /// Row updates dropped before being written because a newer update replaced them
+    pub dropped_frames: u64,
+    /// Pings from the device which were responded to
+    pub pings_answered: u64,
+    /// Messages from the device dropped because their sequence number had already been seen
+    pub duplicates_dropped: u64,
+    /// Messages from the device which never arrived, going by gaps in the sequence numbers
+    pub sequence_gaps: u64,
+    /// Time from a request being queued to its bytes being written, over the most recent sends
+    pub send_latency: Option<LatencySummary>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    /// Summarizes a set of latency samples, or `None` if there are none.
+    pub fn from_samples(mut samples: Vec<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+        Some(LatencySummary {
+            samples: samples.len(),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: percentile(100),
+        })
+    }
+}
+
+impl fmt::Display for SerialStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sent {} messages ({} bytes), read {} bytes, {} decode errors, {} dropped frames, \
+             {} pings answered, {} duplicates dropped, {} sequence gaps",
+            self.messages_sent.values().sum::<u64>(),
+            self.bytes_written,
+            self.bytes_read,
+            self.decode_errors,
+            self.dropped_frames,
+            self.pings_answered,
+            self.duplicates_dropped,
+            self.sequence_gaps,
+        )?;
+        if let Some(latency) = &self.send_latency {
+            write!(
+                f,
+                ", send latency p50 {:?} p90 {:?} p99 {:?} max {:?}",
+                latency.p50, latency.p90, latency.p99, latency.max
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The live counters shared between the serial task and the connection handles
+#[derive(Debug, Default)]
+pub struct StatsRecorder {
+    bytes_written: AtomicU64,
+    bytes_read: AtomicU64,
+    decode_errors: AtomicU64,
+    dropped_frames: AtomicU64,
+    pings_answered: AtomicU64,
+    duplicates_dropped: AtomicU64,
+    sequence_gaps: AtomicU64,
+    sends: Mutex<SendStats>,
+}
+
+#[derive(Debug, Default)]
+struct SendStats {
+    messages_sent: BTreeMap<String, u64>,
+    latencies: VecDeque<Duration>,
+}
+
+impl StatsRecorder {
+    /// Counts the messages of a request which is about to be written to the device.
+    pub fn record_messages(&self, request: &SerialTaskRequest) {
+        let mut sends = self.sends.lock().unwrap();
+        for msg in request.messages() {
+            *sends
+                .messages_sent
+                .entry(msg.as_ref().to_owned())
+                .or_default() += 1;
+        }
+    }
+
+    /// Records a completed write of a request which was queued at `queued_at`.
+    pub fn record_write(&self, bytes: usize, queued_at: Instant) {
+        self.bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        let mut sends = self.sends.lock().unwrap();
+        if sends.latencies.len() == LATENCY_WINDOW {
+            sends.latencies.pop_front();
+        }
+        sends.latencies.push_back(queued_at.elapsed());
+    }
+
+    pub fn record_read(&self, bytes: usize) {
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_decode_errors(&self, count: u64) {
+        self.decode_errors.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped_frame(&self) {
+        self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_ping_answered(&self) {
+        self.pings_answered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_duplicate(&self) {
+        self.duplicates_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_sequence_gap(&self, missed: u64) {
+        self.sequence_gaps.fetch_add(missed, Ordering::Relaxed);
+    }
+
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> SerialStats {
+        let sends = self.sends.lock().unwrap();
+        let send_latency = LatencySummary::from_samples(sends.latencies.iter().copied().collect());
+
+        SerialStats {
+            messages_sent: sends.messages_sent.clone(),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+            dropped_frames: self.dropped_frames(),
+            pings_answered: self.pings_answered.load(Ordering::Relaxed),
+            duplicates_dropped: self.duplicates_dropped.load(Ordering::Relaxed),
+            sequence_gaps: self.sequence_gaps.load(Ordering::Relaxed),
+            send_latency,
+        }
+    }
+}
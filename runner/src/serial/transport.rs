@@ -0,0 +1,280 @@
+use super::{
+    capture::{self, CaptureRecord, Direction},
+    config::SerialConfig,
+    discovery::DeviceSelector,
+};
+use std::{fmt, future::Future, io, path::PathBuf, pin::Pin, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream},
+    net::TcpStream,
+};
+use tokio_serial::{SerialPort, SerialPortBuilderExt};
+
+const LOOPBACK_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A byte stream to the device which frames are written to and read from
+pub trait TransportStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> TransportStream for T {}
+
+pub type ConnectFuture<'a> =
+    Pin<Box<dyn Future<Output = io::Result<Box<dyn TransportStream>>> + Send + 'a>>;
+
+/// A way of reaching the device. The framing and message handling on top of the stream are the
+/// same regardless of transport.
+pub trait Transport: fmt::Display + Send + Sync {
+    /// Opens a new stream to the device. Called again to reconnect whenever the previous stream
+    /// is lost.
+    fn connect(&self) -> ConnectFuture<'_>;
+}
+
+/// A tty serial device, such as the USB CDC port of the display coprocessor
+#[derive(Clone, Debug)]
+pub struct SerialTransport {
+    device: DeviceSelector,
+    config: SerialConfig,
+}
+
+impl SerialTransport {
+    pub fn new(device: impl Into<DeviceSelector>, config: SerialConfig) -> Self {
+        Self {
+            device: device.into(),
+            config,
+        }
+    }
+}
+
+impl fmt::Display for SerialTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.device.fmt(f)
+    }
+}
+
+impl Transport for SerialTransport {
+    fn connect(&self) -> ConnectFuture<'_> {
+        let device = self.device.clone();
+        let config = self.config.clone();
+        let open_port = move || -> io::Result<Box<dyn TransportStream>> {
+            let device_path = device.resolve()?;
+            if !matches!(device, DeviceSelector::Path(_)) {
+                tracing::info!("Found {device} at {}", device_path.display());
+            }
+            let mut serial_port =
+                tokio_serial::new(device_path.to_string_lossy(), config.baud_rate)
+                    .flow_control(config.flow_control)
+                    .parity(config.parity)
+                    .stop_bits(config.stop_bits)
+                    .open_native_async()?;
+            if let Some(dtr) = config.dtr {
+                serial_port.write_data_terminal_ready(dtr)?;
+            }
+            if let Some(rts) = config.rts {
+                serial_port.write_request_to_send(rts)?;
+            }
+            tracing::info!(
+                "Opened serial port {} at {} baud",
+                device_path.display(),
+                config.baud_rate
+            );
+            Ok(Box::new(serial_port) as Box<dyn TransportStream>)
+        };
+        let open_timeout = self.config.open_timeout;
+        let settle_delay = self.config.settle_delay;
+        Box::pin(async move {
+            let serial_port =
+                match tokio::time::timeout(open_timeout, tokio::task::spawn_blocking(open_port))
+                    .await
+                {
+                    Ok(Ok(res)) => res?,
+                    Ok(Err(err)) => return Err(io::Error::other(err)),
+                    Err(_) => return Err(io::ErrorKind::TimedOut.into()),
+                };
+            if !settle_delay.is_zero() {
+                tracing::debug!(
+                    "Waiting {}ms for the device to settle",
+                    settle_delay.as_millis()
+                );
+                tokio::time::sleep(settle_delay).await;
+            }
+            Ok(serial_port)
+        })
+    }
+}
+
+/// A network bridge which exposes the device over a TCP socket
+#[derive(Clone, Debug)]
+pub struct TcpTransport {
+    pub host: String,
+    pub port: u16,
+    pub connect_timeout: Duration,
+}
+
+impl TcpTransport {
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            connect_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+impl fmt::Display for TcpTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+impl Transport for TcpTransport {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let stream = tokio::time::timeout(
+                self.connect_timeout,
+                TcpStream::connect((self.host.as_str(), self.port)),
+            )
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+            // Frames are small and latency sensitive, don't let them sit in the socket buffer
+            stream.set_nodelay(true)?;
+            tracing::info!("Connected to {self}");
+            Ok(Box::new(stream) as Box<dyn TransportStream>)
+        })
+    }
+}
+
+/// An in-process stream for tests. Each connection creates a new pipe whose other end is handed
+/// to the paired [`LoopbackPeer`], which plays the part of the device.
+#[derive(Debug)]
+pub struct LoopbackTransport {
+    peer_tx: async_channel::Sender<DuplexStream>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LoopbackPeer {
+    peer_rx: async_channel::Receiver<DuplexStream>,
+}
+
+impl LoopbackTransport {
+    pub fn new() -> (Self, LoopbackPeer) {
+        let (peer_tx, peer_rx) = async_channel::unbounded();
+        (Self { peer_tx }, LoopbackPeer { peer_rx })
+    }
+}
+
+impl LoopbackPeer {
+    /// Waits for the host to connect, returning the device end of the stream. Returns `None`
+    /// once the transport has been dropped.
+    pub async fn accept(&self) -> Option<DuplexStream> {
+        self.peer_rx.recv().await.ok()
+    }
+}
+
+impl fmt::Display for LoopbackTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "loopback")
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let (host, device) = tokio::io::duplex(LOOPBACK_BUFFER_SIZE);
+            self.peer_tx
+                .send(device)
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused))?;
+            Ok(Box::new(host) as Box<dyn TransportStream>)
+        })
+    }
+}
+
+/// Plays back the device's side of a session recorded with a capture file. Writes from the host
+/// are discarded. Playback starts when the host first writes to the stream so that responses line
+/// up with the requests which prompted them, and then follows the original timing scaled by
+/// `speed`.
+#[derive(Clone, Debug)]
+pub struct ReplayTransport {
+    path: PathBuf,
+    speed: f64,
+}
+
+impl ReplayTransport {
+    pub fn new(path: PathBuf, speed: f64) -> Self {
+        Self { path, speed }
+    }
+}
+
+impl fmt::Display for ReplayTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "replay of {}", self.path.display())
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn connect(&self) -> ConnectFuture<'_> {
+        Box::pin(async move {
+            let records = Arc::new(capture::read_capture(&self.path)?);
+            tracing::info!(
+                "Replaying {} records from {}",
+                records.len(),
+                self.path.display()
+            );
+            let (host, device) = tokio::io::duplex(LOOPBACK_BUFFER_SIZE);
+            tokio::spawn(replay(records, device, self.speed));
+            Ok(Box::new(host) as Box<dyn TransportStream>)
+        })
+    }
+}
+
+async fn replay(records: Arc<Vec<CaptureRecord>>, device: DuplexStream, speed: f64) {
+    let (mut device_rx, mut device_tx) = tokio::io::split(device);
+    let (first_write_tx, first_write_rx) = tokio::sync::oneshot::channel();
+
+    let discard_writes = async move {
+        let mut first_write_tx = Some(first_write_tx);
+        let mut buffer = [0u8; 1024];
+        while let Ok(n @ 1..) = device_rx.read(&mut buffer).await {
+            tracing::trace!("Discarding {n} bytes written during replay");
+            if let Some(first_write_tx) = first_write_tx.take() {
+                let _ = first_write_tx.send(tokio::time::Instant::now());
+            }
+        }
+    };
+
+    let play_records = async move {
+        // Align the capture's clock with the stream's so that the first request the host sends
+        // happens at the same point as the first request in the capture
+        let first_write_offset = records
+            .iter()
+            .find(|record| record.direction == Direction::ToDevice)
+            .map(|record| record.timestamp);
+        let mut start_time = tokio::time::Instant::now();
+        let mut first_write_rx = Some(first_write_rx);
+
+        for record in records
+            .iter()
+            .filter(|record| record.direction == Direction::FromDevice)
+        {
+            if let Some(offset) = first_write_offset.filter(|offset| record.timestamp >= *offset) {
+                if let Some(first_write_rx) = first_write_rx.take() {
+                    let Ok(first_write_time) = first_write_rx.await else {
+                        return;
+                    };
+                    start_time = first_write_time - offset.div_f64(speed);
+                }
+            }
+            tokio::time::sleep_until(start_time + record.timestamp.div_f64(speed)).await;
+            if device_tx.write_all(&record.data[..]).await.is_err() {
+                return;
+            }
+        }
+        tracing::info!("Replay finished");
+        // Keep the stream open so the host doesn't reconnect and start the replay over
+        std::future::pending::<()>().await;
+    };
+
+    tokio::select! {
+        _ = discard_writes => tracing::debug!("Host closed the replay stream"),
+        _ = play_records => {},
+    }
+}
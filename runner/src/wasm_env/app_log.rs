@@ -0,0 +1,95 @@
+use std::{fmt, time::Instant};
+
+/// Lines an app can log in a burst before it's held to the steady rate
+const LOG_BURST: f64 = 50.0;
+/// Lines per second an app can keep on logging
+const LOG_LINES_PER_SEC: f64 = 10.0;
+/// What secrets are replaced with in logged lines
+const SECRET_MASK: &str = "********";
+
+/// Re-emits an app's log lines through `tracing` under the `wasm` target, with the app's name
+/// in an `app` field so lines from different apps can be told apart. Apps logging faster than
+/// they're allowed to have their lines dropped, with a count of how many once they slow down.
+/// Secrets from the app's config are masked out of everything logged.
+pub struct AppLog {
+    app_name: String,
+    secrets: Vec<String>,
+    /// Lines the app can log right now, topped up over time to at most a burst
+    allowance: f64,
+    last_line_time: Instant,
+    dropped: usize,
+}
+
+impl AppLog {
+    pub fn new(app_name: impl Into<String>) -> Self {
+        AppLog {
+            app_name: app_name.into(),
+            secrets: Vec::new(),
+            allowance: LOG_BURST,
+            last_line_time: Instant::now(),
+            dropped: 0,
+        }
+    }
+
+    /// Masks each of `secrets` wherever it shows up in what's logged.
+    pub fn with_secrets(mut self, secrets: Vec<String>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// `text` with the secrets masked out.
+    fn mask(&self, text: &str) -> String {
+        self.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, SECRET_MASK)
+        })
+    }
+
+    /// Logs a line from the app, at levels from 0 for trace up to 4 for error.
+    pub fn log(&mut self, level: u32, line: &str) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_line_time).as_secs_f64();
+        self.allowance = (self.allowance + elapsed * LOG_LINES_PER_SEC).min(LOG_BURST);
+        self.last_line_time = now;
+        if self.allowance < 1.0 {
+            self.dropped += 1;
+            return;
+        }
+        self.allowance -= 1.0;
+
+        let line = self.mask(line);
+        let app = &self.app_name;
+        if self.dropped > 0 {
+            let dropped = self.dropped;
+            tracing::warn!(target: "wasm", app = %app, "Dropped {dropped} lines logged too fast");
+            self.dropped = 0;
+        }
+        match level {
+            0 => tracing::trace!(target: "wasm", app = %app, "{line}"),
+            1 => tracing::debug!(target: "wasm", app = %app, "{line}"),
+            2 => tracing::info!(target: "wasm", app = %app, "{line}"),
+            3 => tracing::warn!(target: "wasm", app = %app, "{line}"),
+            4 => tracing::error!(target: "wasm", app = %app, "{line}"),
+            level => {
+                tracing::error!(target: "wasm", app = %app, "Logged at bad level {level}: {line}")
+            }
+        }
+    }
+
+    /// Logs an error from calling into the app, which isn't held back by the rate limit.
+    pub fn call_failed(&self, function: &str, err: &anyhow::Error) {
+        let app = &self.app_name;
+        let err = self.mask(&format!("{err:#}"));
+        tracing::error!(target: "wasm", app = %app, "Calling {function} failed: {err}");
+    }
+}
+
+impl fmt::Debug for AppLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppLog")
+            .field("app_name", &self.app_name)
+            .field("secrets", &self.secrets.len())
+            .field("allowance", &self.allowance)
+            .field("dropped", &self.dropped)
+            .finish_non_exhaustive()
+    }
+}
@@ -1,59 +1,252 @@
+use super::Capability;
+use crate::display::{DisplayConfiguration, TransitionKind};
 use serde::Deserialize;
 use std::{
-    io::{self, Read},
+    io,
     path::{Path, PathBuf},
     time::Duration,
 };
 
+/// Manifest file names, in the order they're looked for
+pub(super) const MANIFEST_FILE_NAMES: [&str; 2] = ["manifest.toml", "manifest.json"];
+
 #[derive(Debug, Clone)]
 pub struct AppManifest {
     pub path: PathBuf,
     pub app_name: String,
+    pub version: Option<String>,
     pub app_bin_path: PathBuf,
     pub refresh_period: Option<Duration>,
+    /// The width and height the app is written for, if it should be scaled up to the display
+    pub virtual_resolution: Option<(usize, usize)>,
+    /// How to switch to the app, if not the runner's default
+    pub transition: Option<TransitionKind>,
+    pub transition_duration: Option<Duration>,
+    /// What the app asks to be allowed to do beyond drawing
+    pub capabilities: Vec<Capability>,
+    /// What to seed the app's random numbers with, if they should be the same on every run
+    pub seed: Option<u64>,
+    /// Whether the app only works on RGB displays
+    pub needs_rgb: bool,
+    /// The smallest width and height the app's part of the display can be for it to work
+    pub min_display_size: Option<(usize, usize)>,
+    /// Hosts the app may connect to
+    pub network_allowlist: Vec<String>,
+    /// Most the app may keep in its key-value store, if not the runner's default
+    pub kv_quota_bytes: Option<usize>,
+    /// Most time each call into the app may take, if not the runner's default
+    pub execution_budget: Option<Duration>,
+    /// Most memory the app may use in 64 KiB pages, if not the runner's default
+    pub max_memory_pages: Option<u32>,
+    /// Most the app may extend its turn in the rotation by altogether
+    pub max_slot_extension: Duration,
+    /// Whether the app is left out of the rotation for a while after saying it's idle
+    pub skip_if_idle: bool,
+    /// Settings of the app's own, handed to it as JSON, with `${VAR}` in strings replaced by
+    /// the environment variable `VAR`
+    pub config: serde_json::Map<String, serde_json::Value>,
+    /// Keys in `config` whose values are kept out of the logs
+    pub secret_config_keys: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 struct ManifestSchema {
     name: String,
+    version: Option<String>,
     bin: String,
+    #[serde(alias = "refresh_interval_ms")]
     refresh_period_ms: Option<u32>,
+    virtual_resolution: Option<(usize, usize)>,
+    transition: Option<String>,
+    transition_ms: Option<u32>,
+    #[serde(default, alias = "permissions")]
+    capabilities: Vec<Capability>,
+    seed: Option<u64>,
+    #[serde(default)]
+    needs_rgb: bool,
+    min_display_size: Option<(usize, usize)>,
+    #[serde(default)]
+    network_allowlist: Vec<String>,
+    kv_quota_bytes: Option<usize>,
+    execution_budget_ms: Option<u32>,
+    max_memory_pages: Option<u32>,
+    #[serde(default)]
+    max_slot_extension_ms: u32,
+    #[serde(default)]
+    skip_if_idle: bool,
+    #[serde(default)]
+    config: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    secret_config_keys: Vec<String>,
 }
 
 impl AppManifest {
+    /// Reads the manifest in `manifest_dir`, which is `manifest.toml` or else `manifest.json`.
     pub fn open(manifest_dir: impl AsRef<Path>) -> io::Result<Self> {
-        let mut manifest_filepath = manifest_dir.as_ref().to_path_buf();
-        manifest_filepath.push("manifest.json");
-        let mut manifest_file = std::fs::File::open(&manifest_filepath).map_err(|err| {
+        let manifest_filepath = MANIFEST_FILE_NAMES
+            .iter()
+            .map(|name| manifest_dir.as_ref().join(name))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                tracing::error!(
+                    "Failed to find manifest.toml or manifest.json in {}",
+                    manifest_dir.as_ref().display()
+                );
+                io::Error::from(io::ErrorKind::NotFound)
+            })?;
+        let manifest_contents = std::fs::read_to_string(&manifest_filepath).map_err(|err| {
             tracing::error!("Failed to open manifest file: {err}");
             err
         })?;
-        let mut manifest_contents = String::new();
-        manifest_file.read_to_string(&mut manifest_contents)?;
 
-        if let Ok(manifest) = serde_json::from_str::<ManifestSchema>(&mut manifest_contents) {
-            if manifest.bin.contains('/') || manifest.bin.contains('\\') {
-                tracing::error!("Invalid binary filename: {}", &manifest.bin);
+        let parsed = if manifest_filepath
+            .extension()
+            .is_some_and(|ext| ext == "toml")
+        {
+            toml::from_str::<ManifestSchema>(&manifest_contents).map_err(|err| err.to_string())
+        } else {
+            serde_json::from_str::<ManifestSchema>(&manifest_contents)
+                .map_err(|err| err.to_string())
+        };
+        let manifest = match parsed {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                tracing::error!(
+                    "Failed to parse manifest at path {}: {err}",
+                    manifest_filepath.display()
+                );
                 return Err(io::ErrorKind::InvalidData.into());
             }
+        };
+        if manifest.bin.contains('/') || manifest.bin.contains('\\') {
+            tracing::error!("Invalid binary filename: {}", &manifest.bin);
+            return Err(io::ErrorKind::InvalidData.into());
+        }
 
-            let mut bin_path = manifest_dir.as_ref().to_path_buf();
-            bin_path.push(manifest.bin);
+        let transition = match manifest.transition.as_deref().map(str::parse) {
+            Some(Ok(transition)) => Some(transition),
+            Some(Err(err)) => {
+                tracing::error!("Invalid transition: {err}");
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            None => None,
+        };
 
-            Ok(AppManifest {
-                path: manifest_filepath,
-                app_name: manifest.name,
-                app_bin_path: bin_path,
-                refresh_period: manifest
-                    .refresh_period_ms
-                    .map(|duration| Duration::from_millis(duration.into())),
+        let mut config = manifest.config;
+        for (key, value) in config.iter_mut() {
+            if let Err(err) = interpolate_env(value) {
+                tracing::error!("Invalid config value {key}: {err}");
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+        }
+
+        let mut bin_path = manifest_dir.as_ref().to_path_buf();
+        bin_path.push(manifest.bin);
+
+        Ok(AppManifest {
+            path: manifest_filepath,
+            app_name: manifest.name,
+            version: manifest.version,
+            app_bin_path: bin_path,
+            refresh_period: manifest
+                .refresh_period_ms
+                .map(|duration| Duration::from_millis(duration.into())),
+            virtual_resolution: manifest.virtual_resolution,
+            transition,
+            transition_duration: manifest
+                .transition_ms
+                .map(|duration| Duration::from_millis(duration.into())),
+            capabilities: manifest.capabilities,
+            seed: manifest.seed,
+            needs_rgb: manifest.needs_rgb,
+            min_display_size: manifest.min_display_size,
+            network_allowlist: manifest.network_allowlist,
+            kv_quota_bytes: manifest.kv_quota_bytes,
+            execution_budget: manifest
+                .execution_budget_ms
+                .map(|duration| Duration::from_millis(duration.into())),
+            max_memory_pages: manifest.max_memory_pages,
+            max_slot_extension: Duration::from_millis(manifest.max_slot_extension_ms.into()),
+            skip_if_idle: manifest.skip_if_idle,
+            config,
+            secret_config_keys: manifest.secret_config_keys,
+        })
+    }
+
+    /// The values of the config keys marked secret, as they'd show up in what the app logs.
+    /// Strings are taken as they are and anything else as JSON.
+    pub fn secret_config_values(&self) -> Vec<String> {
+        self.secret_config_keys
+            .iter()
+            .filter_map(|key| self.config.get(key))
+            .map(|value| match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
             })
-        } else {
-            tracing::error!(
-                "Failed to parse manifest at path: {}",
-                manifest_filepath.display()
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    /// Fails with the reason if the app can't run on `display` with a `width` by `height` part
+    /// of it.
+    pub fn check_requirements(
+        &self,
+        display: &DisplayConfiguration,
+        (width, height): (usize, usize),
+    ) -> anyhow::Result<()> {
+        if self.needs_rgb && !display.is_rgb() {
+            anyhow::bail!(
+                "{} needs an RGB display but this one is monocolor",
+                self.app_name
             );
-            Err(io::ErrorKind::InvalidData.into())
         }
+        if let Some((min_width, min_height)) = self.min_display_size {
+            if width < min_width || height < min_height {
+                anyhow::bail!(
+                    "{} needs at least {min_width}x{min_height} pixels but only has \
+                     {width}x{height}",
+                    self.app_name
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replaces `${VAR}` in the strings in `value`, however deeply nested, with the environment
+/// variable `VAR`. Fails if any of them aren't set, rather than handing the app a setting it
+/// can't use.
+fn interpolate_env(value: &mut serde_json::Value) -> Result<(), String> {
+    match value {
+        serde_json::Value::String(text) => {
+            let mut interpolated = String::with_capacity(text.len());
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("${") {
+                interpolated.push_str(&rest[..start]);
+                let after = &rest[start + 2..];
+                let end = after
+                    .find('}')
+                    .ok_or_else(|| format!("unterminated ${{ in {text:?}"))?;
+                let name = &after[..end];
+                let var = std::env::var(name)
+                    .map_err(|err| format!("can't use environment variable {name}: {err}"))?;
+                interpolated.push_str(&var);
+                rest = &after[end + 1..];
+            }
+            interpolated.push_str(rest);
+            *text = interpolated;
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                interpolate_env(value)?;
+            }
+        }
+        serde_json::Value::Object(values) => {
+            for value in values.values_mut() {
+                interpolate_env(value)?;
+            }
+        }
+        _ => {}
     }
+    Ok(())
 }
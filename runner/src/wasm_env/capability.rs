@@ -0,0 +1,185 @@
+use serde::Deserialize;
+use std::{fmt, io, str::FromStr};
+
+/// Something beyond drawing which an app has to declare in its manifest before its host
+/// functions will do it, and which the runner can deny to every app whatever they declare
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Making HTTP requests with extism's `http_request`, to the hosts in the app's
+    /// `network_allowlist`
+    Network,
+    /// Keeping keys and values which last across runs with the `kv_*` functions
+    Storage,
+    /// Setting the board's status LED and RGB LED, which otherwise show the runner's own health
+    Led,
+    /// Taking button presses and encoder turns from the device with `poll_input_events`
+    Input,
+    /// Reading the clock with `get_monotonic_ms`, `get_unix_time_ms` and `get_local_time`
+    Time,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::Network,
+        Capability::Storage,
+        Capability::Led,
+        Capability::Input,
+        Capability::Time,
+    ];
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::Network => write!(f, "network"),
+            Capability::Storage => write!(f, "storage"),
+            Capability::Led => write!(f, "led"),
+            Capability::Input => write!(f, "input"),
+            Capability::Time => write!(f, "time"),
+        }
+    }
+}
+
+impl FromStr for Capability {
+    type Err = io::Error;
+
+    /// Parses names as they're written in manifests, like `storage`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Capability::ALL
+            .into_iter()
+            .find(|capability| capability.to_string() == s)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Unknown capability {s}, expected one of network, storage, led, input \
+                         or time"
+                    ),
+                )
+            })
+    }
+}
+
+/// Why an app isn't allowed a capability
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenialReason {
+    /// The app's manifest doesn't declare it
+    Undeclared,
+    /// The runner denies it to every app
+    DeniedByRunner,
+}
+
+/// What host functions needing a capability the app isn't allowed fail with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PermissionDenied {
+    /// The host function the app called
+    pub function: &'static str,
+    pub capability: Capability,
+    pub reason: DenialReason,
+}
+
+impl fmt::Display for PermissionDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let PermissionDenied {
+            function,
+            capability,
+            reason,
+        } = self;
+        match reason {
+            DenialReason::Undeclared => write!(
+                f,
+                "Permission denied, {function} needs the {capability} capability, which the \
+                 app's manifest doesn't declare"
+            ),
+            DenialReason::DeniedByRunner => write!(
+                f,
+                "Permission denied, {function} needs the {capability} capability, which the \
+                 runner denies to every app"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PermissionDenied {}
+
+/// The capabilities an app is allowed, which are those its manifest declares less any the
+/// runner denies
+#[derive(Debug, Clone, Default)]
+pub struct CapabilityGrants {
+    declared: Vec<Capability>,
+    denied: Vec<Capability>,
+}
+
+impl CapabilityGrants {
+    pub fn new(declared: Vec<Capability>, denied: Vec<Capability>) -> Self {
+        CapabilityGrants { declared, denied }
+    }
+
+    /// Whether the app is allowed `capability`, and if not why not.
+    pub fn check(&self, capability: Capability) -> Result<(), DenialReason> {
+        if self.denied.contains(&capability) {
+            Err(DenialReason::DeniedByRunner)
+        } else if !self.declared.contains(&capability) {
+            Err(DenialReason::Undeclared)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn is_granted(&self, capability: Capability) -> bool {
+        self.check(capability).is_ok()
+    }
+
+    /// The capabilities the app is allowed.
+    pub fn granted(&self) -> Vec<Capability> {
+        Capability::ALL
+            .into_iter()
+            .filter(|&capability| self.is_granted(capability))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_declared_capabilities_are_granted() {
+        let grants = CapabilityGrants::new(vec![Capability::Time, Capability::Storage], vec![]);
+        assert_eq!(grants.check(Capability::Time), Ok(()));
+        assert_eq!(grants.check(Capability::Storage), Ok(()));
+        assert_eq!(
+            grants.check(Capability::Network),
+            Err(DenialReason::Undeclared)
+        );
+        assert_eq!(grants.granted(), [Capability::Storage, Capability::Time]);
+    }
+
+    #[test]
+    fn the_runner_denying_a_capability_wins_over_declaring_it() {
+        let grants = CapabilityGrants::new(
+            vec![Capability::Time, Capability::Led],
+            vec![Capability::Led, Capability::Input],
+        );
+        assert_eq!(grants.check(Capability::Time), Ok(()));
+        assert_eq!(
+            grants.check(Capability::Led),
+            Err(DenialReason::DeniedByRunner)
+        );
+        // Denied capabilities are reported as denied even when they weren't declared
+        assert_eq!(
+            grants.check(Capability::Input),
+            Err(DenialReason::DeniedByRunner)
+        );
+        assert_eq!(grants.granted(), [Capability::Time]);
+    }
+
+    #[test]
+    fn nothing_is_granted_by_default() {
+        let grants = CapabilityGrants::default();
+        for capability in Capability::ALL {
+            assert_eq!(grants.check(capability), Err(DenialReason::Undeclared));
+        }
+    }
+}
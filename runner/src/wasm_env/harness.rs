@@ -0,0 +1,218 @@
+use super::{read_trace, write_trace, AppOptions, HostCall, HostCallTrace, WasmAppRunner};
+use crate::{
+    display::{DisplayConfiguration, MockPanel, ScreenBuffer},
+    serial::MockDevice,
+};
+use std::{cell::RefCell, io, path::Path, rc::Rc, time::Duration};
+
+/// What apps' random numbers are seeded with in the harness unless the options give a seed, so
+/// they're the same on every run
+pub const HARNESS_SEED: u64 = 0;
+/// How long each frame is taken to last for apps without a refresh period
+const DEFAULT_FRAME_PERIOD: Duration = Duration::from_secs(1);
+/// Host functions whose results depend on when they're called, which are left out when
+/// comparing traces
+const TIME_DEPENDENT_FUNCTIONS: [&str; 4] = [
+    "get_monotonic_ms",
+    "get_unix_time_ms",
+    "get_local_time",
+    "get_slot_time_remaining_ms",
+];
+/// Names of the files golden runs are kept in
+const GOLDEN_TRACE_FILE_NAME: &str = "trace.jsonl";
+#[cfg(feature = "image")]
+const GOLDEN_SCREEN_FILE_NAME: &str = "screen.png";
+
+/// Runs an app on its own against a [`MockDevice`], with the same host functions the runner
+/// gives it and every call it makes to them recorded, so it can be checked without a display.
+///
+/// The app is set up in frame 0, and each frame after that updates it as if its refresh period
+/// had passed, however long the frame really took. Along with its random numbers being seeded
+/// with [`HARNESS_SEED`], this means apps which don't read the clock make the same calls and
+/// draw the same thing on every run, which can be checked against a golden run with
+/// [`AppHarness::check_golden`].
+pub struct AppHarness {
+    runner: WasmAppRunner,
+    trace: HostCallTrace,
+    /// The last frame the app was run in
+    frame: u32,
+    /// What the app's display is on, which is dropped after the app
+    panel: MockPanel,
+}
+
+impl AppHarness {
+    /// Loads the app in `app_path` onto a display with `display_config`, with `options` applied
+    /// to it, and sets it up.
+    pub fn load(
+        app_path: impl AsRef<Path>,
+        display_config: DisplayConfiguration,
+        options: AppOptions,
+    ) -> anyhow::Result<Self> {
+        let panel = MockPanel::new(display_config)?;
+        let display = Rc::new(RefCell::new(panel.display()?));
+
+        let trace = HostCallTrace::new();
+        let options = AppOptions {
+            seed: options.seed.or(Some(HARNESS_SEED)),
+            trace_host_calls: Some(trace.clone()),
+            ..options
+        };
+        let mut runner = WasmAppRunner::load(app_path, display, &options)?;
+        runner
+            .setup_app()
+            .map_err(|err| err.context("Failed to set up the app"))?;
+        Ok(AppHarness {
+            runner,
+            trace,
+            frame: 0,
+            panel,
+        })
+    }
+
+    /// Runs the app for `frames` more frames, stopping at the first one it fails in.
+    pub fn run_frames(&mut self, frames: u32) -> anyhow::Result<()> {
+        for _ in 0..frames {
+            self.frame += 1;
+            self.trace.set_frame(self.frame);
+            let elapsed = self.runner.refresh_period().unwrap_or(DEFAULT_FRAME_PERIOD);
+            self.runner
+                .update_app_by(elapsed)
+                .map_err(|err| err.context(format!("The app failed in frame {}", self.frame)))?;
+        }
+        Ok(())
+    }
+
+    pub fn runner(&self) -> &WasmAppRunner {
+        &self.runner
+    }
+
+    /// The device the app's display is on, e.g. to check what was sent to it.
+    pub fn device(&self) -> &MockDevice {
+        self.panel.device()
+    }
+
+    /// Every call the app has made to the runner's host functions so far.
+    pub fn trace(&self) -> Vec<HostCall> {
+        self.trace.calls()
+    }
+
+    /// What the app has drawn so far, whether or not it has rendered it.
+    pub fn screen_buffer(&self) -> anyhow::Result<ScreenBuffer> {
+        self.runner.screen_buffer()
+    }
+
+    /// What the app has drawn so far as a PNG, see [`ScreenBuffer::to_png`].
+    #[cfg(feature = "image")]
+    pub fn screen_png(&self, scale: u32) -> anyhow::Result<Vec<u8>> {
+        Ok(self.screen_buffer()?.to_png(scale, false)?)
+    }
+
+    /// Keeps the app's trace, and with the image feature what it has drawn, in `dir` as the
+    /// golden run to check later runs against, replacing whatever was there.
+    pub fn write_golden(&self, dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        let mut trace = Vec::new();
+        write_trace(&self.trace(), &mut trace)?;
+        std::fs::write(dir.join(GOLDEN_TRACE_FILE_NAME), trace)?;
+        #[cfg(feature = "image")]
+        std::fs::write(dir.join(GOLDEN_SCREEN_FILE_NAME), self.screen_png(1)?)?;
+        Ok(())
+    }
+
+    /// Checks the app's trace, and with the image feature what it has drawn, against the golden
+    /// run kept in `dir` by [`AppHarness::write_golden`]. Returns each way they differ, which
+    /// is nothing if they match.
+    pub fn check_golden(&self, dir: impl AsRef<Path>) -> anyhow::Result<Vec<String>> {
+        let dir = dir.as_ref();
+        let golden_trace_path = dir.join(GOLDEN_TRACE_FILE_NAME);
+        let golden_trace = std::fs::File::open(&golden_trace_path)
+            .and_then(|file| read_trace(io::BufReader::new(file)))
+            .map_err(|err| {
+                anyhow::anyhow!(
+                    "Failed to read the golden trace at {}: {err}",
+                    golden_trace_path.display()
+                )
+            })?;
+        let mut differences = Vec::new();
+        differences.extend(compare_traces(&golden_trace, &self.trace()));
+        #[cfg(feature = "image")]
+        {
+            let golden_screen_path = dir.join(GOLDEN_SCREEN_FILE_NAME);
+            if golden_screen_path.exists() {
+                let golden_screen = std::fs::read(&golden_screen_path)?;
+                differences.extend(compare_screen(&golden_screen, &self.screen_buffer()?)?);
+            }
+        }
+        Ok(differences)
+    }
+}
+
+/// Describes the first call in `actual` which differs from the one made at the same point in
+/// `golden`, or `None` if they make the same calls. When the calls were made and how long they
+/// took aren't compared, and neither are the results of functions which tell the time.
+pub fn compare_traces(golden: &[HostCall], actual: &[HostCall]) -> Option<String> {
+    for (index, (expected, call)) in golden.iter().zip(actual).enumerate() {
+        let same_result = TIME_DEPENDENT_FUNCTIONS.contains(&call.function.as_str())
+            || (expected.result == call.result && expected.error == call.error);
+        if expected.frame != call.frame
+            || expected.function != call.function
+            || expected.args != call.args
+            || !same_result
+        {
+            return Some(format!(
+                "Call {index} was {} rather than {}",
+                describe_call(call),
+                describe_call(expected)
+            ));
+        }
+    }
+    (golden.len() != actual.len()).then(|| {
+        format!(
+            "The app made {} host calls rather than {}",
+            actual.len(),
+            golden.len()
+        )
+    })
+}
+
+fn describe_call(call: &HostCall) -> String {
+    let outcome = match (&call.result, &call.error) {
+        (_, Some(err)) => format!("failing with {err}"),
+        (Some(result), None) => format!("giving back {result:?}"),
+        (None, None) => "giving back nothing".to_owned(),
+    };
+    format!(
+        "{}({:?}) in frame {}, {outcome}",
+        call.function, call.args, call.frame
+    )
+}
+
+/// Describes how `screen` differs from the golden PNG of it, or `None` if they're the same.
+#[cfg(feature = "image")]
+fn compare_screen(golden_png: &[u8], screen: &ScreenBuffer) -> anyhow::Result<Option<String>> {
+    let golden =
+        ::image::load_from_memory_with_format(golden_png, ::image::ImageFormat::Png)?.to_rgb8();
+    let actual = screen.to_image(1, false)?;
+    if golden.dimensions() != actual.dimensions() {
+        return Ok(Some(format!(
+            "The screen is {}x{} rather than {}x{}",
+            actual.width(),
+            actual.height(),
+            golden.width(),
+            golden.height()
+        )));
+    }
+    let differing = golden
+        .enumerate_pixels()
+        .zip(actual.pixels())
+        .filter(|((_, _, expected), pixel)| expected != pixel)
+        .map(|((x, y, _), _)| (x, y))
+        .collect::<Vec<_>>();
+    Ok(differing.first().map(|(x, y)| {
+        format!(
+            "{} pixels differ from the golden screen, the first at ({x}, {y})",
+            differing.len()
+        )
+    }))
+}
@@ -0,0 +1,8 @@
+/// The value under `key` in the app's config as JSON, after a byte saying whether there is one,
+/// 1 if so and 0 if not, the same as `kv_get`.
+pub fn get(config: &serde_json::Value, key: &str) -> Result<Vec<u8>, extism::Error> {
+    Ok(match config.get(key) {
+        Some(value) => [&[1], serde_json::to_vec(value)?.as_slice()].concat(),
+        None => vec![0],
+    })
+}
@@ -1,59 +1,644 @@
-use super::super::ScreenBuffer;
-use crate::{
-    display::{DisplayConfiguration, MonocolorPalette},
-    serial::SyncSerialConnection,
+use crate::display::{
+    BufferRegion, ColorCorrection, CompositeDisplay, DecodedImage, DisplayConfiguration,
+    DitherMode, Font, MonocolorPalette, PixelValue, RegionBounds, RenderedExtent, Rgb555,
+    ScreenBuffer, ScrollMode,
 };
 
-pub fn write_region(
+/// Largest number of pixels an app may ask for an image to be scaled to
+const MAX_SCALED_IMAGE_PIXELS: u64 = 1 << 20;
+
+/// First byte of the rows passed to `render` when they're given as a tagged selection rather
+/// than a plain list. The byte after it picks the kind of selection: 0 for every row, 1 for an
+/// inclusive range given as its first and last rows, and 2 for a list of rows.
+const ROW_SELECTION_TAG: u8 = 0xff;
+const SELECT_ALL_ROWS: u8 = 0;
+const SELECT_ROW_RANGE: u8 = 1;
+const SELECT_ROW_LIST: u8 = 2;
+
+/// Format bytes at the start of what `read_region` returns
+const READ_REGION_MONO: u8 = 0;
+const READ_REGION_RGB: u8 = 1;
+
+/// The part of the screen buffer an app draws on, which is all of it unless the app has been
+/// given a region.
+pub fn app_region(
     screen_buffer: &mut ScreenBuffer,
+    bounds: Option<RegionBounds>,
+) -> Result<BufferRegion<'_>, extism::Error> {
+    Ok(match bounds {
+        Some(bounds) => screen_buffer.region(bounds.x, bounds.y, bounds.width, bounds.height)?,
+        None => screen_buffer.full_region(),
+    })
+}
+
+pub fn write_region(
+    region: &mut BufferRegion,
     position_x: u32,
     position_y: u32,
     width: u32,
     height: u32,
     buffer_data: Vec<u8>,
 ) -> Result<(), extism::Error> {
-    for row in position_y..(position_y + height) {
-        for col in position_x..(position_x + width) {
-            let idx = (col - position_x) + (width * (row - position_y));
-            screen_buffer.set_cell(
-                row as usize,
-                col as usize,
-                (buffer_data[(idx / 8) as usize] & (1 << (idx % 8))) != 0,
-            )?;
+    // Everything is checked before the first pixel is written so a bad region can't leave a
+    // partial write behind
+    check_region_fits(region, (position_x, position_y), (width, height))?;
+    let expected_len = (u64::from(width) * u64::from(height)).div_ceil(8);
+    if (buffer_data.len() as u64) < expected_len {
+        return Err(extism::Error::msg(format!(
+            "A {width}x{height} region needs {expected_len} bytes, got {}",
+            buffer_data.len()
+        )));
+    }
+
+    let values = (0..width as usize * height as usize)
+        .map(|idx| (buffer_data[idx / 8] & (1 << (idx % 8))) != 0)
+        .collect::<Vec<_>>();
+    region.write_region(
+        position_x as usize,
+        position_y as usize,
+        width as usize,
+        height as usize,
+        &values[..],
+    )?;
+    Ok(())
+}
+
+/// Reads back a `width` by `height` rectangle with its top left corner at (`x`, `y`), as a byte
+/// giving the format followed by the pixels laid out the way they're written. That's 0 and then
+/// packed bits like `write_region` takes on monocolor displays, and 1 and then little endian
+/// RGB555 colors like `write_region_rgb` takes on RGB displays. Fails if the rectangle doesn't
+/// fit on the app's display.
+pub fn read_region(
+    region: &mut BufferRegion,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+) -> Result<Vec<u8>, extism::Error> {
+    check_region_fits(region, (x, y), (width, height))?;
+    let pixels = (y as usize..(y + height) as usize)
+        .flat_map(|row| (x as usize..(x + width) as usize).map(move |col| (row, col)))
+        .map(|(row, col)| region.get_pixel(row, col))
+        .collect::<Result<Vec<_>, _>>()?;
+    if region.is_rgb() {
+        let colors = pixels.into_iter().flat_map(|value| match value {
+            PixelValue::Rgb(color) => color.raw().to_le_bytes(),
+            PixelValue::Mono(_) => [0; 2],
+        });
+        return Ok([READ_REGION_RGB].into_iter().chain(colors).collect());
+    }
+    let mut bits = vec![0u8; pixels.len().div_ceil(8)];
+    for (index, value) in pixels.into_iter().enumerate() {
+        if value == PixelValue::Mono(true) {
+            bits[index / 8] |= 1 << (index % 8);
         }
     }
+    Ok([READ_REGION_MONO].into_iter().chain(bits).collect())
+}
+
+/// Fails if a `width` by `height` rectangle at (`x`, `y`) doesn't fit on the app's display.
+fn check_region_fits(
+    region: &BufferRegion,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+) -> Result<(), extism::Error> {
+    let (right, bottom) = (
+        u64::from(x) + u64::from(width),
+        u64::from(y) + u64::from(height),
+    );
+    if right > region.width() as u64 || bottom > region.height() as u64 {
+        return Err(extism::Error::msg(format!(
+            "A {width}x{height} region at ({x}, {y}) doesn't fit on the {}x{} display",
+            region.width(),
+            region.height()
+        )));
+    }
+    Ok(())
+}
+
+/// Copies a rectangle of RGB555 colors, sent by the app as little endian `u16`s row by row.
+pub fn write_region_rgb(
+    region: &mut BufferRegion,
+    position_x: u32,
+    position_y: u32,
+    width: u32,
+    height: u32,
+    buffer_data: Vec<u8>,
+) -> Result<(), extism::Error> {
+    let expected_len = width as usize * height as usize * 2;
+    if buffer_data.len() != expected_len {
+        return Err(extism::Error::msg(format!(
+            "A {width}x{height} RGB region needs {expected_len} bytes, got {}",
+            buffer_data.len()
+        )));
+    }
+    let colors = buffer_data
+        .chunks_exact(2)
+        .map(|bytes| Rgb555::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+        .collect::<Vec<_>>();
+    region.write_region_rgb(
+        position_x as usize,
+        position_y as usize,
+        width as usize,
+        height as usize,
+        &colors[..],
+    )?;
+    Ok(())
+}
+
+pub fn set_pixel(
+    region: &mut BufferRegion,
+    x: u32,
+    y: u32,
+    color: Rgb555,
+) -> Result<(), extism::Error> {
+    region.set_pixel_rgb(y as usize, x as usize, color)?;
+    Ok(())
+}
+
+/// Draws text with one of the built-in fonts, 0 for small and 1 for large. The position is taken
+/// as signed so apps can scroll text in from off the left or top of the display.
+pub fn draw_text(
+    region: &mut BufferRegion,
+    x: u32,
+    y: u32,
+    text: String,
+    color: Rgb555,
+    font: u32,
+) -> Result<RenderedExtent, extism::Error> {
+    let font = match font {
+        0 => Font::Small,
+        1 => Font::Large,
+        _ => return Err(extism::Error::msg(format!("Unknown font: {font}"))),
+    };
+    Ok(region.draw_text(x as i32, y as i32, &text, color, font)?)
+}
+
+/// Draws big seven segment digits for clocks and counters, returning the width drawn so apps can
+/// right-align them. The position is taken as signed like for text.
+pub fn draw_seven_segment(
+    region: &mut BufferRegion,
+    (x, y): (u32, u32),
+    digit_height: u32,
+    value: String,
+    color: Rgb555,
+) -> Result<usize, extism::Error> {
+    Ok(region.draw_seven_segment(x as i32, y as i32, digit_height, &value, color)?)
+}
+
+/// Shapes are drawn in RGB555 like `set_pixel`, with positions taken as signed so shapes can hang
+/// off any edge of the display.
+pub fn draw_line(
+    region: &mut BufferRegion,
+    (x0, y0): (u32, u32),
+    (x1, y1): (u32, u32),
+    color: Rgb555,
+) -> Result<(), extism::Error> {
+    region.draw_line(
+        x0 as i32,
+        y0 as i32,
+        x1 as i32,
+        y1 as i32,
+        PixelValue::Rgb(color),
+    );
+    Ok(())
+}
+
+pub fn draw_rect(
+    region: &mut BufferRegion,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+    color: Rgb555,
+    filled: bool,
+) -> Result<(), extism::Error> {
+    if filled {
+        region.fill_rect(x as i32, y as i32, width, height, PixelValue::Rgb(color));
+    } else {
+        region.draw_rect(x as i32, y as i32, width, height, PixelValue::Rgb(color));
+    }
+    Ok(())
+}
+
+pub fn draw_circle(
+    region: &mut BufferRegion,
+    (center_x, center_y): (u32, u32),
+    radius: u32,
+    color: Rgb555,
+) -> Result<(), extism::Error> {
+    region.draw_circle(
+        center_x as i32,
+        center_y as i32,
+        radius,
+        PixelValue::Rgb(color),
+    );
+    Ok(())
+}
+
+/// Draws a progress bar `fraction` of the way full, in `foreground` on `background`.
+pub fn draw_progress_bar(
+    region: &mut BufferRegion,
+    (x, y): (u32, u32),
+    size: (u32, u32),
+    fraction: f32,
+    colors: (Rgb555, Rgb555),
+    border: bool,
+) -> Result<(), extism::Error> {
+    region.draw_progress_bar((x as i32, y as i32), size, fraction, colors, border);
+    Ok(())
+}
+
+/// Draws a line graph of `values`, sent as little-endian `f32`s. Either end of the range may be
+/// NaN to have it fit the values.
+pub fn draw_sparkline(
+    region: &mut BufferRegion,
+    ((x, y), size): ((u32, u32), (u32, u32)),
+    values: Vec<u8>,
+    (range_min, range_max): (f32, f32),
+    color: Rgb555,
+    fill: bool,
+) -> Result<(), extism::Error> {
+    if !values.len().is_multiple_of(4) {
+        return Err(extism::Error::msg(format!(
+            "Sparkline values must be 4 byte floats, got {} bytes",
+            values.len()
+        )));
+    }
+    let values = values
+        .chunks_exact(4)
+        .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+        .collect::<Vec<_>>();
+    let range = (
+        (!range_min.is_nan()).then_some(range_min),
+        (!range_max.is_nan()).then_some(range_max),
+    );
+    region.draw_sparkline((x as i32, y as i32), size, &values[..], range, color, fill)?;
+    Ok(())
+}
+
+/// Shifts the app's whole display, wrapping around unless `fill` is set, in which case the space
+/// left behind is filled with `fill_color`.
+pub fn scroll(
+    region: &mut BufferRegion,
+    (dx, dy): (u32, u32),
+    fill: bool,
+    fill_color: Rgb555,
+) -> Result<(), extism::Error> {
+    let mode = if fill {
+        ScrollMode::Fill(PixelValue::Rgb(fill_color))
+    } else {
+        ScrollMode::Wrap
+    };
+    region.scroll(dx as i32, dy as i32, mode);
+    Ok(())
+}
+
+/// Decodes a PNG, BMP, or GIF image sent by the app and draws it at (`x`, `y`), scaled to
+/// `width` by `height` unless both are 0. The position is taken as signed like for shapes.
+pub fn draw_image(
+    region: &mut BufferRegion,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+    image_data: Vec<u8>,
+) -> Result<(), extism::Error> {
+    if u64::from(width) * u64::from(height) > MAX_SCALED_IMAGE_PIXELS {
+        return Err(extism::Error::msg(format!(
+            "Can't scale an image to {width}x{height}, it may be at most \
+             {MAX_SCALED_IMAGE_PIXELS} pixels"
+        )));
+    }
+    let size = (width != 0 || height != 0).then_some((width as usize, height as usize));
+    let image = decode_image(&image_data[..], size)?;
+    region.draw_image(x as i32, y as i32, &image);
     Ok(())
 }
 
+#[cfg(feature = "image")]
+fn decode_image(
+    image_data: &[u8],
+    size: Option<(usize, usize)>,
+) -> Result<DecodedImage, extism::Error> {
+    DecodedImage::decode(image_data, size)
+        .map_err(|err| extism::Error::msg(format!("Failed to decode image: {err}")))
+}
+
+#[cfg(not(feature = "image"))]
+fn decode_image(
+    _image_data: &[u8],
+    _size: Option<(usize, usize)>,
+) -> Result<DecodedImage, extism::Error> {
+    Err(extism::Error::msg(
+        "The runner was built without image support, enable the image feature",
+    ))
+}
+
+/// Draws `contents` as a QR code with its quiet zone's top left corner at (`x`, `y`), each
+/// module `module_size` pixels square.
+pub fn draw_qr(
+    region: &mut BufferRegion,
+    (x, y): (u32, u32),
+    contents: String,
+    module_size: u32,
+) -> Result<(), extism::Error> {
+    draw_qr_code(region, (x as usize, y as usize), &contents, module_size)
+}
+
+#[cfg(feature = "qr")]
+fn draw_qr_code(
+    region: &mut BufferRegion,
+    (x, y): (usize, usize),
+    data: &str,
+    module_size: u32,
+) -> Result<(), extism::Error> {
+    region
+        .draw_qr(x, y, data, module_size)
+        .map_err(|err| extism::Error::msg(format!("Failed to draw QR code: {err}")))
+}
+
+#[cfg(not(feature = "qr"))]
+fn draw_qr_code(
+    _region: &mut BufferRegion,
+    _position: (usize, usize),
+    _data: &str,
+    _module_size: u32,
+) -> Result<(), extism::Error> {
+    Err(extism::Error::msg(
+        "The runner was built without QR code support, enable the qr feature",
+    ))
+}
+
+/// The rows an app asked to render, sorted from the top down without any repeats, or `None` for
+/// every row which has changed since it was last sent. Apps either send a plain list of rows,
+/// with an empty list meaning the changed rows, or a selection starting with
+/// [`ROW_SELECTION_TAG`]. Fails naming the first row which isn't on the app's `height` row
+/// display.
+pub fn select_rows(rows: Vec<u8>, height: usize) -> Result<Option<Vec<u8>>, extism::Error> {
+    let mut rows = match rows[..] {
+        [] => return Ok(None),
+        [ROW_SELECTION_TAG, SELECT_ALL_ROWS] => {
+            return Ok(Some(
+                (0..height)
+                    .filter_map(|row| u8::try_from(row).ok())
+                    .collect(),
+            ))
+        }
+        [ROW_SELECTION_TAG, SELECT_ROW_RANGE, first, last] if first <= last => {
+            (first..=last).collect()
+        }
+        [ROW_SELECTION_TAG, SELECT_ROW_RANGE, first, last] => {
+            return Err(extism::Error::msg(format!(
+                "Can't render rows {first} to {last}, the first row is after the last"
+            )))
+        }
+        [ROW_SELECTION_TAG, SELECT_ROW_LIST, ..] => rows[2..].to_vec(),
+        [ROW_SELECTION_TAG, ..] => {
+            return Err(extism::Error::msg(format!(
+                "Unknown selection of rows to render: {rows:?}"
+            )))
+        }
+        _ => rows,
+    };
+    rows.sort_unstable();
+    rows.dedup();
+    if let Some(row) = rows.iter().find(|&&row| usize::from(row) >= height) {
+        return Err(extism::Error::msg(format!(
+            "Can't render row {row}, the display only has {height} rows"
+        )));
+    }
+    Ok(Some(rows))
+}
+
+/// Sends the given rows of the app's region to the display, or every row which has changed
+/// since it was last sent if no rows are given.
 pub fn render(
-    screen_buffer: &ScreenBuffer,
-    serial_conn: SyncSerialConnection,
+    display: &mut CompositeDisplay,
+    region: Option<RegionBounds>,
     rows: Vec<u8>,
 ) -> Result<(), extism::Error> {
-    for row_number in rows {
-        if screen_buffer.is_rgb() {
-            let row_data = screen_buffer.get_row_rgb(row_number as usize)?;
-            serial_conn.update_row_rgb(row_number, row_data)?;
-        } else {
-            let row_data = screen_buffer.get_row(row_number as usize)?;
-            serial_conn.update_row(row_number, row_data)?;
-        }
+    if rows.is_empty() {
+        display.render_dirty()?;
+    } else {
+        let rows = app_region(&mut display.screen_buffer_mut(), region)?.buffer_rows(&rows[..]);
+        display.render(&rows[..])?;
     }
+    Ok(())
+}
+
+pub fn render_dirty(display: &mut CompositeDisplay) -> Result<(), extism::Error> {
+    display.render_dirty()?;
+    Ok(())
+}
 
+/// Blanks the app's region of the screen buffer, and sends it to the display straight away if
+/// `render` is set.
+pub fn clear_display(
+    display: &mut CompositeDisplay,
+    region: Option<RegionBounds>,
+    render: bool,
+) -> Result<(), extism::Error> {
+    let Some(region) = region else {
+        display.screen_buffer_mut().clear();
+        if render {
+            display.redraw()?;
+        }
+        return Ok(());
+    };
+    let rows = {
+        let mut screen_buffer = display.screen_buffer_mut();
+        let mut app_region = app_region(&mut screen_buffer, Some(region))?;
+        app_region.clear();
+        let rows = (0..region.height)
+            .filter_map(|row| u8::try_from(row).ok())
+            .collect::<Vec<_>>();
+        app_region.buffer_rows(&rows[..])
+    };
+    if render {
+        display.render(&rows[..])?;
+    }
     Ok(())
 }
 
 pub fn set_monocolor_palette(
     screen_buffer: &mut ScreenBuffer,
-    on_color: u16,
-    off_color: u16,
+    on_color: Rgb555,
+    off_color: Rgb555,
 ) -> Result<(), extism::Error> {
     screen_buffer.set_palette(MonocolorPalette::new(on_color, off_color))?;
     Ok(())
 }
 
+/// Picks how colors are drawn on a monocolor display: 0 thresholds, 1 uses ordered dithering,
+/// and 2 uses Floyd-Steinberg error diffusion.
+pub fn set_dither_mode(screen_buffer: &mut ScreenBuffer, mode: u32) -> Result<(), extism::Error> {
+    let mode = match mode {
+        0 => DitherMode::Threshold,
+        1 => DitherMode::Bayer4x4,
+        2 => DitherMode::FloydSteinberg,
+        _ => return Err(extism::Error::msg(format!("Unknown dither mode: {mode}"))),
+    };
+    screen_buffer.set_dither_mode(mode);
+    Ok(())
+}
+
+/// Sets the gamma applied to RGB colors sent to the display, given in hundredths so 220 is a
+/// gamma of 2.2. The brightness scaling is kept as it was.
+pub fn set_gamma(screen_buffer: &mut ScreenBuffer, gamma: u32) -> Result<(), extism::Error> {
+    let brightness = screen_buffer
+        .color_correction()
+        .map_or(1.0, |correction| correction.brightness());
+    let correction = ColorCorrection::new(gamma as f32 / 100.0, brightness)
+        .map_err(|_| extism::Error::msg(format!("Invalid gamma: {gamma}")))?;
+    screen_buffer.set_color_correction(Some(correction));
+    Ok(())
+}
+
+/// Scales RGB colors sent to the display by a percentage, on top of any gamma correction and
+/// the panel's own brightness setting.
+pub fn set_rgb_brightness(
+    screen_buffer: &mut ScreenBuffer,
+    percent: u32,
+) -> Result<(), extism::Error> {
+    let gamma = screen_buffer
+        .color_correction()
+        .map_or(1.0, |correction| correction.gamma());
+    let correction = ColorCorrection::new(gamma, percent as f32 / 100.0).map_err(|_| {
+        extism::Error::msg(format!(
+            "Brightness must be a percentage from 0 to 100, got {percent}"
+        ))
+    })?;
+    screen_buffer.set_color_correction(Some(correction));
+    Ok(())
+}
+
+pub fn set_brightness(display: &CompositeDisplay, level: u32) -> Result<(), extism::Error> {
+    let level = u8::try_from(level).unwrap_or_else(|_| {
+        tracing::warn!("App requested brightness {level}, clamping to {}", u8::MAX);
+        u8::MAX
+    });
+    display.set_brightness(level)?;
+    Ok(())
+}
+
+/// Describes the display as the app sees it, which is the size of its virtual screen or region
+/// if it has one.
 pub fn get_display_info(
-    screen_buffer: &ScreenBuffer,
+    display: &CompositeDisplay,
+    app_size: Option<(usize, usize)>,
 ) -> Result<DisplayConfiguration, extism::Error> {
-    Ok(screen_buffer.display_config())
+    let config = display.display_config();
+    Ok(match app_size {
+        Some((width, height)) => DisplayConfiguration {
+            width,
+            height,
+            ..config
+        },
+        None => config,
+    })
+}
+
+/// Copies a `width` by `height` rectangle of an offscreen buffer onto what the app draws on,
+/// see [`ScreenBuffer::blit_from`].
+pub fn blit(
+    region: &mut BufferRegion,
+    src: &ScreenBuffer,
+    (src_x, src_y): (u32, u32),
+    (width, height): (u32, u32),
+    (dest_x, dest_y): (u32, u32),
+) -> Result<(), extism::Error> {
+    let src_rect = RegionBounds {
+        x: src_x as usize,
+        y: src_y as usize,
+        width: width as usize,
+        height: height as usize,
+    };
+    region.blit_from(src, src_rect, dest_x as i32, dest_y as i32);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit_pixels(screen_buffer: &ScreenBuffer) -> usize {
+        screen_buffer
+            .rows()
+            .unwrap()
+            .map(|(_row, bits)| bits.iter().filter(|&&lit| lit).count())
+            .sum()
+    }
+
+    #[test]
+    fn write_region_sets_packed_bits_row_by_row() {
+        let mut screen_buffer = ScreenBuffer::new(8, 4, None);
+        let mut region = screen_buffer.full_region();
+        // Bits 0 to 2 are the first row and 3 to 5 the second
+        write_region(&mut region, 2, 1, 3, 2, vec![0b0010_1001]).unwrap();
+        assert_eq!(
+            screen_buffer.get_row(1).unwrap(),
+            [false, false, true, false, false, false, false, false]
+        );
+        assert_eq!(
+            screen_buffer.get_row(2).unwrap(),
+            [false, false, true, false, true, false, false, false]
+        );
+    }
+
+    #[test]
+    fn write_region_refuses_short_buffers_without_writing() {
+        let mut screen_buffer = ScreenBuffer::new(16, 4, None);
+        let mut region = screen_buffer.full_region();
+        // 16x4 needs 8 bytes
+        let err = write_region(&mut region, 0, 0, 16, 4, vec![0xff; 7]).unwrap_err();
+        assert!(err.to_string().contains("needs 8 bytes, got 7"), "{err}");
+        assert!(write_region(&mut region, 0, 0, 16, 4, vec![]).is_err());
+        assert_eq!(lit_pixels(&screen_buffer), 0);
+    }
+
+    #[test]
+    fn write_region_refuses_regions_off_the_display_without_writing() {
+        let mut screen_buffer = ScreenBuffer::new(16, 4, None);
+        let mut region = screen_buffer.full_region();
+        for (position, size) in [
+            ((0, 0), (17, 4)),
+            ((0, 0), (16, 5)),
+            ((15, 0), (2, 1)),
+            ((0, 4), (1, 1)),
+            // Would wrap around if added up as u32s
+            ((u32::MAX, 0), (2, 1)),
+            ((0, 1), (1, u32::MAX)),
+        ] {
+            let err = write_region(
+                &mut region,
+                position.0,
+                position.1,
+                size.0,
+                size.1,
+                vec![0xff; 64],
+            )
+            .unwrap_err();
+            assert!(err.to_string().contains("doesn't fit"), "{err}");
+        }
+        assert_eq!(lit_pixels(&screen_buffer), 0);
+    }
+
+    #[test]
+    fn write_region_of_nothing_writes_nothing() {
+        let mut screen_buffer = ScreenBuffer::new(16, 4, None);
+        let mut region = screen_buffer.full_region();
+        write_region(&mut region, 0, 0, 0, 0, vec![]).unwrap();
+        write_region(&mut region, 16, 4, 0, 0, vec![]).unwrap();
+        write_region(&mut region, 3, 2, 0, 2, vec![0xff]).unwrap();
+        write_region(&mut region, 3, 2, 5, 0, vec![]).unwrap();
+        assert_eq!(lit_pixels(&screen_buffer), 0);
+    }
+
+    #[test]
+    fn write_region_is_relative_to_the_apps_region() {
+        let mut screen_buffer = ScreenBuffer::new(16, 4, None);
+        let mut region = screen_buffer.region(8, 2, 8, 2).unwrap();
+        assert!(write_region(&mut region, 0, 0, 9, 1, vec![0xff; 2]).is_err());
+        write_region(&mut region, 7, 1, 1, 1, vec![0x01]).unwrap();
+        assert_eq!(lit_pixels(&screen_buffer), 1);
+        assert_eq!(
+            screen_buffer.get_pixel(3, 15).unwrap(),
+            PixelValue::Mono(true)
+        );
+    }
 }
@@ -1,10 +1,24 @@
 use crate::wasm_env::KvStore;
 
 pub fn write(kv_store: &mut KvStore, key: String, data: Vec<u8>) -> Result<(), extism::Error> {
-    kv_store.insert(key, data);
+    kv_store.set(key, data)?;
     Ok(())
 }
 
 pub fn read(kv_store: &KvStore, key: String) -> Result<Vec<u8>, extism::Error> {
-    Ok(kv_store.get(&key).unwrap_or(&vec![]).clone())
+    Ok(kv_store.get(&key).unwrap_or_default().to_vec())
+}
+
+/// The value under `key` after a byte saying whether there is one, 1 if so and 0 if not, so
+/// empty values can be told apart from missing ones.
+pub fn get(kv_store: &KvStore, key: String) -> Result<Vec<u8>, extism::Error> {
+    Ok(match kv_store.get(&key) {
+        Some(value) => [&[1], value].concat(),
+        None => vec![0],
+    })
+}
+
+pub fn delete(kv_store: &mut KvStore, key: String) -> Result<(), extism::Error> {
+    kv_store.delete(&key)?;
+    Ok(())
 }
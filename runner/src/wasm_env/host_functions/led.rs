@@ -0,0 +1,23 @@
+use crate::display::CompositeDisplay;
+
+pub fn set_led_state(display: &CompositeDisplay, on: u32) -> Result<(), extism::Error> {
+    display.set_led_state(on != 0)?;
+    Ok(())
+}
+
+/// Sets the RGB LED, with each channel taken from 0 to 255.
+pub fn set_rgb_state(
+    display: &CompositeDisplay,
+    (r, g, b): (u32, u32, u32),
+) -> Result<(), extism::Error> {
+    let channel = |value: u32| {
+        u8::try_from(value).map_err(|_| {
+            extism::Error::msg(format!(
+                "RGB LED channels go from 0 to {}, got {value}",
+                u8::MAX
+            ))
+        })
+    };
+    display.set_rgb_state((channel(r)?, channel(g)?, channel(b)?))?;
+    Ok(())
+}
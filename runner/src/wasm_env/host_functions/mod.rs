@@ -1,112 +1,683 @@
-use super::PersistentData;
+use super::{Capability, CapabilityGrants, HostCallTrace, PermissionDenied, PersistentData};
+use crate::display::{BufferRegion, CompositeDisplay, Rgb555};
 use extism::UserData;
+use std::time::{Duration, Instant};
 
+mod config;
 mod display;
 mod kv_store;
+mod led;
+mod offscreen;
+mod time;
 
-pub fn with_host_functions<'a>(
-    builder: extism::PluginBuilder<'a>,
-    user_data: &UserData<PersistentData>,
-) -> extism::PluginBuilder<'a> {
-    with_screen_functions(with_kv_functions(builder, user_data), user_data).with_function(
-        "log",
-        [extism::PTR, extism::PTR],
-        [extism::PTR],
-        extism::UserData::new(()),
-        log,
-    )
+/// A host function, all of whose arguments and whose result are handles to extism's memory
+struct HostFunction {
+    name: &'static str,
+    args: usize,
+    call: HostFn,
 }
 
-pub fn with_screen_functions<'a>(
-    builder: extism::PluginBuilder<'a>,
+type HostFn = fn(
+    &mut extism::CurrentPlugin,
+    &[extism::Val],
+    &mut [extism::Val],
+    UserData<PersistentData>,
+) -> Result<(), extism::Error>;
+
+/// What's registered with extism for each host function, which may be the function itself, a
+/// stand-in which denies it, or either of those recording their calls
+type BoxedHostFn = Box<
+    dyn Fn(
+            &mut extism::CurrentPlugin,
+            &[extism::Val],
+            &mut [extism::Val],
+            UserData<PersistentData>,
+        ) -> Result<(), extism::Error>
+        + Send
+        + Sync,
+>;
+
+/// Every host function, grouped with the capability apps need to be allowed to use them, or
+/// `None` for the ones every app gets
+const HOST_FUNCTIONS: [(Option<Capability>, &[HostFunction]); 9] = [
+    (None, &SCREEN_FUNCTIONS),
+    (Some(Capability::Storage), &KV_FUNCTIONS),
+    (Some(Capability::Led), &LED_FUNCTIONS),
+    (Some(Capability::Time), &TIME_FUNCTIONS),
+    (None, &RANDOM_FUNCTIONS),
+    (None, &CONFIG_FUNCTIONS),
+    (Some(Capability::Input), &INPUT_FUNCTIONS),
+    (None, &SCHEDULE_FUNCTIONS),
+    (None, &LOG_FUNCTIONS),
+];
+
+/// Registers every host function with `builder`. Those needing a capability the app isn't
+/// allowed are registered in name only, so apps which import them still load, and fail with
+/// [`PermissionDenied`] whenever they're called. With `trace`, every call the app makes is
+/// added to it, including those which were denied.
+pub fn with_host_functions<'a>(
+    mut builder: extism::PluginBuilder<'a>,
     user_data: &UserData<PersistentData>,
+    capabilities: &CapabilityGrants,
+    trace: Option<&HostCallTrace>,
 ) -> extism::PluginBuilder<'a> {
+    time::start_monotonic_clock();
+    for (capability, functions) in HOST_FUNCTIONS {
+        let denied = capability
+            .and_then(|capability| Some((capability, capabilities.check(capability).err()?)));
+        for function in functions {
+            let call: BoxedHostFn = match denied {
+                None => Box::new(function.call),
+                Some((capability, reason)) => {
+                    let err = PermissionDenied {
+                        function: function.name,
+                        capability,
+                        reason,
+                    };
+                    Box::new(move |_, _, _, _| Err(err.into()))
+                }
+            };
+            let call = match trace {
+                Some(trace) => recorded(function.name, call, trace.clone()),
+                None => call,
+            };
+            builder = builder.with_function(
+                function.name,
+                vec![extism::PTR; function.args],
+                [extism::PTR],
+                user_data.clone(),
+                call,
+            );
+        }
+    }
     builder
-        .with_function(
-            "write_region",
-            [
-                extism::PTR,
-                extism::PTR,
-                extism::PTR,
-                extism::PTR,
-                extism::PTR,
-            ],
-            [extism::PTR],
-            user_data.clone(),
-            write_region,
-        )
-        .with_function(
-            "render",
-            [extism::PTR],
-            [extism::PTR],
-            user_data.clone(),
-            render,
-        )
-        .with_function(
-            "set_monocolor_palette",
-            [extism::PTR, extism::PTR],
-            [extism::PTR],
-            user_data.clone(),
-            set_monocolor_palette,
-        )
-        .with_function(
-            "get_display_info",
-            [],
-            [extism::PTR],
-            user_data.clone(),
-            get_display_info,
-        )
 }
 
-pub fn with_kv_functions<'a>(
-    builder: extism::PluginBuilder<'a>,
-    user_data: &UserData<PersistentData>,
-) -> extism::PluginBuilder<'a> {
-    builder
-        .with_function(
-            "kv_store_read",
-            [extism::PTR],
-            [extism::PTR],
-            user_data.clone(),
-            kv_store_read,
-        )
-        .with_function(
-            "kv_store_write",
-            [extism::PTR, extism::PTR],
-            [extism::PTR],
-            user_data.clone(),
-            kv_store_write,
-        )
+/// Wraps `call` so that each call to it is added to `trace`, along with the bytes the app
+/// passed it and what it gave back.
+fn recorded(function: &'static str, call: BoxedHostFn, trace: HostCallTrace) -> BoxedHostFn {
+    Box::new(move |plugin, inputs, outputs, user_data| {
+        let args = inputs
+            .iter()
+            .map(|input| memory_bytes(plugin, input))
+            .collect();
+        let at = trace.elapsed();
+        let result = call(plugin, inputs, outputs, user_data);
+        let recorded_result = match &result {
+            Ok(()) => Ok(outputs
+                .first()
+                .map(|output| memory_bytes(plugin, output))
+                .unwrap_or_default()),
+            Err(err) => Err(err.to_string()),
+        };
+        trace.record(function, args, recorded_result, at);
+        result
+    })
+}
+
+/// A copy of the block of the app's memory which `val` is a handle to, or nothing if it isn't
+/// one.
+fn memory_bytes(plugin: &mut extism::CurrentPlugin, val: &extism::Val) -> Vec<u8> {
+    plugin
+        .memory_from_val(val)
+        .and_then(|handle| plugin.memory_bytes(handle).ok())
+        .map(<[u8]>::to_vec)
+        .unwrap_or_default()
+}
+/// Drawing on and setting up the app's part of the display
+const SCREEN_FUNCTIONS: [HostFunction; 29] = [
+    HostFunction {
+        name: "write_region",
+        args: 5,
+        call: write_region,
+    },
+    HostFunction {
+        name: "write_region_rgb",
+        args: 5,
+        call: write_region_rgb,
+    },
+    HostFunction {
+        name: "read_region",
+        args: 4,
+        call: read_region,
+    },
+    HostFunction {
+        name: "set_pixel",
+        args: 3,
+        call: set_pixel,
+    },
+    HostFunction {
+        name: "draw_text",
+        args: 5,
+        call: draw_text,
+    },
+    HostFunction {
+        name: "draw_seven_segment",
+        args: 5,
+        call: draw_seven_segment,
+    },
+    HostFunction {
+        name: "draw_line",
+        args: 5,
+        call: draw_line,
+    },
+    HostFunction {
+        name: "draw_rect",
+        args: 5,
+        call: draw_rect,
+    },
+    HostFunction {
+        name: "fill_rect",
+        args: 5,
+        call: fill_rect,
+    },
+    HostFunction {
+        name: "draw_circle",
+        args: 4,
+        call: draw_circle,
+    },
+    HostFunction {
+        name: "draw_progress_bar",
+        args: 8,
+        call: draw_progress_bar,
+    },
+    HostFunction {
+        name: "draw_sparkline",
+        args: 9,
+        call: draw_sparkline,
+    },
+    HostFunction {
+        name: "scroll",
+        args: 4,
+        call: scroll,
+    },
+    HostFunction {
+        name: "draw_image",
+        args: 5,
+        call: draw_image,
+    },
+    HostFunction {
+        name: "draw_qr",
+        args: 4,
+        call: draw_qr,
+    },
+    HostFunction {
+        name: "create_offscreen_buffer",
+        args: 2,
+        call: create_offscreen_buffer,
+    },
+    HostFunction {
+        name: "destroy_buffer",
+        args: 1,
+        call: destroy_buffer,
+    },
+    HostFunction {
+        name: "set_draw_target",
+        args: 1,
+        call: set_draw_target,
+    },
+    HostFunction {
+        name: "blit",
+        args: 7,
+        call: blit,
+    },
+    HostFunction {
+        name: "render",
+        args: 1,
+        call: render,
+    },
+    HostFunction {
+        name: "render_dirty",
+        args: 0,
+        call: render_dirty,
+    },
+    HostFunction {
+        name: "clear_display",
+        args: 1,
+        call: clear_display,
+    },
+    HostFunction {
+        name: "set_monocolor_palette",
+        args: 2,
+        call: set_monocolor_palette,
+    },
+    HostFunction {
+        name: "set_dither_mode",
+        args: 1,
+        call: set_dither_mode,
+    },
+    HostFunction {
+        name: "set_gamma",
+        args: 1,
+        call: set_gamma,
+    },
+    HostFunction {
+        name: "set_rgb_brightness",
+        args: 1,
+        call: set_rgb_brightness,
+    },
+    HostFunction {
+        name: "get_display_info",
+        args: 0,
+        call: get_display_info,
+    },
+    HostFunction {
+        name: "display_changed",
+        args: 0,
+        call: display_changed,
+    },
+    HostFunction {
+        name: "set_brightness",
+        args: 1,
+        call: set_brightness,
+    },
+];
+
+/// Keeping keys and values which last across runs
+const KV_FUNCTIONS: [HostFunction; 5] = [
+    HostFunction {
+        name: "kv_store_read",
+        args: 1,
+        call: kv_store_read,
+    },
+    HostFunction {
+        name: "kv_store_write",
+        args: 2,
+        call: kv_store_write,
+    },
+    HostFunction {
+        name: "kv_get",
+        args: 1,
+        call: kv_get,
+    },
+    HostFunction {
+        name: "kv_set",
+        args: 2,
+        call: kv_set,
+    },
+    HostFunction {
+        name: "kv_delete",
+        args: 1,
+        call: kv_delete,
+    },
+];
+
+/// Setting the board's LEDs
+const LED_FUNCTIONS: [HostFunction; 2] = [
+    HostFunction {
+        name: "set_led_state",
+        args: 1,
+        call: set_led_state,
+    },
+    HostFunction {
+        name: "set_rgb_state",
+        args: 3,
+        call: set_rgb_state,
+    },
+];
+
+/// Reading the clock
+const TIME_FUNCTIONS: [HostFunction; 3] = [
+    HostFunction {
+        name: "get_monotonic_ms",
+        args: 0,
+        call: get_monotonic_ms,
+    },
+    HostFunction {
+        name: "get_unix_time_ms",
+        args: 0,
+        call: get_unix_time_ms,
+    },
+    HostFunction {
+        name: "get_local_time",
+        args: 0,
+        call: get_local_time,
+    },
+];
+
+const RANDOM_FUNCTIONS: [HostFunction; 2] = [
+    HostFunction {
+        name: "random_bytes",
+        args: 1,
+        call: random_bytes,
+    },
+    HostFunction {
+        name: "random_u32_range",
+        args: 2,
+        call: random_u32_range,
+    },
+];
+
+const CONFIG_FUNCTIONS: [HostFunction; 1] = [HostFunction {
+    name: "get_config",
+    args: 1,
+    call: get_config,
+}];
+
+/// Taking input from the device
+const INPUT_FUNCTIONS: [HostFunction; 1] = [HostFunction {
+    name: "poll_input_events",
+    args: 0,
+    call: poll_input_events,
+}];
+
+/// Asking when to be run and for how long
+const SCHEDULE_FUNCTIONS: [HostFunction; 6] = [
+    HostFunction {
+        name: "set_refresh_interval",
+        args: 1,
+        call: set_refresh_interval,
+    },
+    HostFunction {
+        name: "get_slot_duration_ms",
+        args: 0,
+        call: get_slot_duration_ms,
+    },
+    HostFunction {
+        name: "get_slot_time_remaining_ms",
+        args: 0,
+        call: get_slot_time_remaining_ms,
+    },
+    HostFunction {
+        name: "request_slot_extension",
+        args: 1,
+        call: request_slot_extension,
+    },
+    HostFunction {
+        name: "yield_slot",
+        args: 0,
+        call: yield_slot,
+    },
+    HostFunction {
+        name: "set_idle",
+        args: 1,
+        call: set_idle,
+    },
+];
+
+const LOG_FUNCTIONS: [HostFunction; 1] = [HostFunction {
+    name: "log",
+    args: 2,
+    call: log,
+}];
+
+pub fn redraw(user_data: &UserData<PersistentData>) -> Result<(), extism::Error> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let mut composite = data.display.borrow_mut();
+    present(&data, &mut composite, vec![])?;
+    composite.force_full_render()?;
+    Ok(())
+}
+
+/// Runs `draw` on what the app draws on, which is the offscreen buffer it has picked if any, or
+/// else its virtual screen if it has one and its part of the display otherwise.
+fn draw_on_app<T>(
+    data: &PersistentData,
+    draw: impl FnOnce(&mut BufferRegion) -> Result<T, extism::Error>,
+) -> Result<T, extism::Error> {
+    if let Some(handle) = data.draw_target {
+        return draw(&mut data.offscreen.get(handle)?.write().full_region());
+    }
+    match &data.virtual_screen {
+        Some(virtual_screen) => draw(&mut virtual_screen.buffer.write().full_region()),
+        None => {
+            let mut composite = data.display.borrow_mut();
+            let mut screen_buffer = composite.screen_buffer_mut();
+            draw(&mut display::app_region(&mut screen_buffer, data.region)?)
+        }
+    }
+}
+
+/// Scales the app's virtual screen, if it has one, onto its part of the display. Returns the
+/// rows of that part which the given rows of the virtual screen were drawn on, or the rows as
+/// they were without a virtual screen.
+pub fn present(
+    data: &PersistentData,
+    composite: &mut CompositeDisplay,
+    rows: Vec<u8>,
+) -> Result<Vec<u8>, extism::Error> {
+    let Some(virtual_screen) = &data.virtual_screen else {
+        return Ok(rows);
+    };
+    // The virtual screen is locked before the display's screen buffer, see SharedScreenBuffer
+    let virtual_buffer = virtual_screen.buffer.read();
+    let mut screen_buffer = composite.screen_buffer_mut();
+    let mut region = display::app_region(&mut screen_buffer, data.region)?;
+    virtual_buffer.scale_into(&mut region, &virtual_screen.mapping);
+    Ok(rows
+        .into_iter()
+        .flat_map(|row| virtual_screen.mapping.physical_rows(usize::from(row)))
+        .filter_map(|row| u8::try_from(row).ok())
+        .collect())
 }
 
 extism::host_fn!(pub write_region(user_data: PersistentData; position_x: u32, position_y: u32, width: u32, height: u32, buffer_data: Vec<u8>) {
     let data = user_data.get()?;
     let data = data.lock().unwrap();
-    let mut screen_buffer = data.screen_buffer.borrow_mut();
-    display::write_region(&mut screen_buffer, position_x, position_y, width, height, buffer_data)
+    draw_on_app(&data, |region| display::write_region(region, position_x, position_y, width, height, buffer_data))
+});
+
+extism::host_fn!(pub write_region_rgb(user_data: PersistentData; position_x: u32, position_y: u32, width: u32, height: u32, buffer_data: Vec<u8>) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::write_region_rgb(region, position_x, position_y, width, height, buffer_data))
+});
+
+extism::host_fn!(pub read_region(user_data: PersistentData; x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::read_region(region, (x, y), (width, height)))
+});
+
+extism::host_fn!(pub set_pixel(user_data: PersistentData; x: u32, y: u32, color: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::set_pixel(region, x, y, Rgb555::from((color & 0xffff) as u16)))
+});
+
+extism::host_fn!(pub draw_text(user_data: PersistentData; x: u32, y: u32, text: String, color: u32, font: u32) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let extent = draw_on_app(&data, |region| display::draw_text(region, x, y, text, Rgb555::from((color & 0xffff) as u16), font))?;
+    Ok([(extent.width as u32).to_be_bytes(), (extent.height as u32).to_be_bytes()].concat())
+});
+
+extism::host_fn!(pub draw_seven_segment(user_data: PersistentData; x: u32, y: u32, digit_height: u32, value: String, color: u32) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let width = draw_on_app(&data, |region| display::draw_seven_segment(region, (x, y), digit_height, value, Rgb555::from((color & 0xffff) as u16)))?;
+    Ok((width as u32).to_be_bytes().to_vec())
+});
+
+extism::host_fn!(pub draw_line(user_data: PersistentData; x0: u32, y0: u32, x1: u32, y1: u32, color: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::draw_line(region, (x0, y0), (x1, y1), Rgb555::from((color & 0xffff) as u16)))
+});
+
+extism::host_fn!(pub draw_rect(user_data: PersistentData; x: u32, y: u32, width: u32, height: u32, color: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::draw_rect(region, (x, y), (width, height), Rgb555::from((color & 0xffff) as u16), false))
+});
+
+extism::host_fn!(pub fill_rect(user_data: PersistentData; x: u32, y: u32, width: u32, height: u32, color: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::draw_rect(region, (x, y), (width, height), Rgb555::from((color & 0xffff) as u16), true))
+});
+
+extism::host_fn!(pub draw_circle(user_data: PersistentData; center_x: u32, center_y: u32, radius: u32, color: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::draw_circle(region, (center_x, center_y), radius, Rgb555::from((color & 0xffff) as u16)))
+});
+
+extism::host_fn!(pub draw_progress_bar(user_data: PersistentData; x: u32, y: u32, width: u32, height: u32, fraction: f32, foreground: u32, background: u32, border: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let colors = (Rgb555::from((foreground & 0xffff) as u16), Rgb555::from((background & 0xffff) as u16));
+    draw_on_app(&data, |region| display::draw_progress_bar(region, (x, y), (width, height), fraction, colors, border != 0))
+});
+
+extism::host_fn!(pub draw_sparkline(user_data: PersistentData; x: u32, y: u32, width: u32, height: u32, values: Vec<u8>, range_min: f32, range_max: f32, color: u32, fill: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::draw_sparkline(region, ((x, y), (width, height)), values, (range_min, range_max), Rgb555::from((color & 0xffff) as u16), fill != 0))
+});
+
+extism::host_fn!(pub scroll(user_data: PersistentData; dx: u32, dy: u32, fill: u32, fill_color: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::scroll(region, (dx, dy), fill != 0, Rgb555::from((fill_color & 0xffff) as u16)))
+});
+
+extism::host_fn!(pub draw_image(user_data: PersistentData; x: u32, y: u32, width: u32, height: u32, image_data: Vec<u8>) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::draw_image(region, (x, y), (width, height), image_data))
+});
+
+extism::host_fn!(pub draw_qr(user_data: PersistentData; x: u32, y: u32, contents: String, module_size: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    draw_on_app(&data, |region| display::draw_qr(region, (x, y), contents, module_size))
+});
+
+extism::host_fn!(pub create_offscreen_buffer(user_data: PersistentData; width: u32, height: u32) -> Vec<u8> {
+    let data = user_data.get()?;
+    let mut data = data.lock().unwrap();
+    let palette = data.with_app_buffer(|screen_buffer| screen_buffer.palette());
+    let handle = offscreen::create(&mut data.offscreen, (width, height), palette)?;
+    Ok(handle.to_be_bytes().to_vec())
+});
+
+extism::host_fn!(pub destroy_buffer(user_data: PersistentData; handle: u32) {
+    let data = user_data.get()?;
+    let mut data = data.lock().unwrap();
+    offscreen::destroy(&mut data.offscreen, handle)?;
+    if data.draw_target == Some(handle) {
+        data.draw_target = None;
+    }
+    Ok(())
+});
+
+extism::host_fn!(pub set_draw_target(user_data: PersistentData; handle: u32) {
+    let data = user_data.get()?;
+    let mut data = data.lock().unwrap();
+    data.draw_target = offscreen::draw_target(&data.offscreen, handle)?;
+    Ok(())
+});
+
+extism::host_fn!(pub blit(user_data: PersistentData; handle: u32, src_x: u32, src_y: u32, width: u32, height: u32, dest_x: u32, dest_y: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let src = offscreen::blit_source(&data.offscreen, handle, data.draw_target)?;
+    draw_on_app(&data, |region| display::blit(region, &src.read(), (src_x, src_y), (width, height), (dest_x, dest_y)))
 });
 
 extism::host_fn!(pub render(user_data: PersistentData; rows_to_update: Vec<u8>) {
     let data = user_data.get()?;
     let data = data.lock().unwrap();
-    let screen_buffer = data.screen_buffer.borrow();
-    let serial_conn = data.serial_conn.clone();
-    display::render(&screen_buffer, serial_conn, rows_to_update)
+    let rows = match display::select_rows(rows_to_update, data.app_size().1)? {
+        // Nothing would be sent, but an empty list means every changed row to display::render
+        Some(rows) if rows.is_empty() => return Ok(()),
+        Some(rows) => rows,
+        None => vec![],
+    };
+    let mut composite = data.display.borrow_mut();
+    let rows = present(&data, &mut composite, rows)?;
+    if data.renders_held {
+        return Ok(());
+    }
+    display::render(&mut composite, data.region, rows)
+});
+
+extism::host_fn!(pub render_dirty(user_data: PersistentData;) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let mut composite = data.display.borrow_mut();
+    present(&data, &mut composite, vec![])?;
+    if data.renders_held {
+        return Ok(());
+    }
+    display::render_dirty(&mut composite)
+});
+
+extism::host_fn!(pub clear_display(user_data: PersistentData; render: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    if let Some(virtual_screen) = &data.virtual_screen {
+        virtual_screen.buffer.write().clear();
+    }
+    let mut composite = data.display.borrow_mut();
+    present(&data, &mut composite, vec![])?;
+    display::clear_display(&mut composite, data.region, render != 0 && !data.renders_held)
 });
 
 extism::host_fn!(pub set_monocolor_palette(user_data: PersistentData; on_color: u32, off_color: u32) {
     let data = user_data.get()?;
     let data = data.lock().unwrap();
-    let mut screen_buffer = data.screen_buffer.borrow_mut();
-    display::set_monocolor_palette(&mut screen_buffer, (on_color & 0xffff) as u16, (off_color & 0xffff) as u16)
+    data.with_app_buffer(|screen_buffer| display::set_monocolor_palette(screen_buffer, Rgb555::from((on_color & 0xffff) as u16), Rgb555::from((off_color & 0xffff) as u16)))
+});
+
+extism::host_fn!(pub set_dither_mode(user_data: PersistentData; mode: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    data.with_app_buffer(|screen_buffer| display::set_dither_mode(screen_buffer, mode))
+});
+
+extism::host_fn!(pub set_gamma(user_data: PersistentData; gamma: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let mut composite = data.display.borrow_mut();
+    let mut screen_buffer = composite.screen_buffer_mut();
+    display::set_gamma(&mut screen_buffer, gamma)
+});
+
+extism::host_fn!(pub set_rgb_brightness(user_data: PersistentData; percent: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let mut composite = data.display.borrow_mut();
+    let mut screen_buffer = composite.screen_buffer_mut();
+    display::set_rgb_brightness(&mut screen_buffer, percent)
+});
+
+extism::host_fn!(pub display_changed(user_data: PersistentData;) -> Vec<u8> {
+    let data = user_data.get()?;
+    let mut data = data.lock().unwrap();
+    Ok(vec![u8::from(std::mem::take(&mut data.display_changed))])
 });
 
 extism::host_fn!(pub get_display_info(user_data: PersistentData;) -> Vec<u8> {
     let data = user_data.get()?;
     let data = data.lock().unwrap();
-    let screen_buffer = data.screen_buffer.borrow();
-    let config = display::get_display_info(&screen_buffer)?;
-    Ok([&(config.width as u32).to_be_bytes()[..], &(config.height as u32).to_be_bytes()[..], &(if config.is_rgb { 1u8 } else {0u8 }).to_be_bytes()[..]].concat())
+    let composite = data.display.borrow();
+    let app_size = match &data.virtual_screen {
+        Some(virtual_screen) => {
+            let config = virtual_screen.buffer.read().display_config();
+            Some((config.width, config.height))
+        }
+        None => data.region.map(|region| (region.width, region.height)),
+    };
+    let config = display::get_display_info(&composite, app_size)?;
+    // Apps built before the pixel format and frame rate hint were added only read the first
+    // nine bytes, so new fields go on the end. An unknown frame rate is sent as 0.
+    Ok([
+        &(config.width as u32).to_be_bytes()[..],
+        &(config.height as u32).to_be_bytes()[..],
+        &(if config.is_rgb() { 1u8 } else {0u8 }).to_be_bytes()[..],
+        &[config.pixel_representation as u8][..],
+        &config.max_fps_hint.unwrap_or(0.0).to_be_bytes()[..],
+    ].concat())
+});
+
+extism::host_fn!(pub set_brightness(user_data: PersistentData; level: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let composite = data.display.borrow();
+    display::set_brightness(&composite, level)
 });
 
 extism::host_fn!(pub kv_store_read(user_data: PersistentData; key: String) -> Vec<u8> {
@@ -123,23 +694,143 @@ extism::host_fn!(pub kv_store_write(user_data: PersistentData; key: String, valu
     kv_store::write(&mut kv_store, key, value)
 });
 
-extism::host_fn!(pub log(level: u32, line: String) {
-    host::log(level, line)
+extism::host_fn!(pub kv_get(user_data: PersistentData; key: String) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let kv_store = data.kv_store.borrow();
+    kv_store::get(&kv_store, key)
 });
 
-mod host {
-    pub fn log(level: u32, line: String) -> Result<(), extism::Error> {
-        match level {
-            0 => tracing::trace!("{line}"),
-            1 => tracing::debug!("{line}"),
-            2 => tracing::info!("{line}"),
-            3 => tracing::warn!("{line}"),
-            4 => tracing::error!("{line}"),
-            level => {
-                tracing::error!("Got a log call from app with invalid log level: {level}");
-            }
-        }
+extism::host_fn!(pub get_config(user_data: PersistentData; key: String) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    config::get(&data.config, &key)
+});
 
-        Ok(())
-    }
+extism::host_fn!(pub poll_input_events(user_data: PersistentData;) -> Vec<u8> {
+    let data = user_data.get()?;
+    let mut data = data.lock().unwrap();
+    let events = data.input.drain(..).collect::<Vec<_>>();
+    Ok(serde_json::to_vec(&events)?)
+});
+
+extism::host_fn!(pub kv_set(user_data: PersistentData; key: String, value: Vec<u8>) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let mut kv_store = data.kv_store.borrow_mut();
+    kv_store::write(&mut kv_store, key, value)
+});
+
+extism::host_fn!(pub kv_delete(user_data: PersistentData; key: String) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let mut kv_store = data.kv_store.borrow_mut();
+    kv_store::delete(&mut kv_store, key)
+});
+
+extism::host_fn!(pub set_led_state(user_data: PersistentData; on: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let composite = data.display.borrow();
+    led::set_led_state(&composite, on)
+});
+
+extism::host_fn!(pub set_rgb_state(user_data: PersistentData; r: u32, g: u32, b: u32) {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let composite = data.display.borrow();
+    led::set_rgb_state(&composite, (r, g, b))
+});
+
+extism::host_fn!(pub get_monotonic_ms(user_data: PersistentData;) -> Vec<u8> {
+    Ok(time::get_monotonic_ms().to_be_bytes().to_vec())
+});
+
+extism::host_fn!(pub get_unix_time_ms(user_data: PersistentData;) -> Vec<u8> {
+    Ok(time::get_unix_time_ms()?.to_be_bytes().to_vec())
+});
+
+extism::host_fn!(pub get_local_time(user_data: PersistentData;) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    time::get_local_time(&data.time_zone)
+});
+
+extism::host_fn!(pub set_refresh_interval(user_data: PersistentData; interval_ms: u32) -> Vec<u8> {
+    let data = user_data.get()?;
+    let mut data = data.lock().unwrap();
+    let refresh_period = data.clamp_refresh_period(Duration::from_millis(interval_ms.into()));
+    data.refresh_period = Some(refresh_period);
+    let refresh_period_ms = u32::try_from(refresh_period.as_millis()).unwrap_or(u32::MAX);
+    Ok(refresh_period_ms.to_be_bytes().to_vec())
+});
+
+extism::host_fn!(pub get_slot_duration_ms(user_data: PersistentData;) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let duration = data.slot.as_ref().and_then(|slot| slot.borrow().duration());
+    Ok(slot_millis(duration).to_be_bytes().to_vec())
+});
+
+extism::host_fn!(pub get_slot_time_remaining_ms(user_data: PersistentData;) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let remaining = data
+        .slot
+        .as_ref()
+        .and_then(|slot| slot.borrow().remaining(Instant::now()));
+    Ok(slot_millis(remaining).to_be_bytes().to_vec())
+});
+
+extism::host_fn!(pub request_slot_extension(user_data: PersistentData; extension_ms: u32) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let extension = Duration::from_millis(extension_ms.into());
+    let granted = data
+        .slot
+        .as_ref()
+        .is_none_or(|slot| slot.borrow_mut().extend(extension));
+    Ok(vec![u8::from(granted)])
+});
+
+extism::host_fn!(pub yield_slot(user_data: PersistentData;) -> Vec<u8> {
+    let data = user_data.get()?;
+    let data = data.lock().unwrap();
+    let yielded = data
+        .slot
+        .as_ref()
+        .is_some_and(|slot| slot.borrow_mut().yield_at(Instant::now()));
+    Ok(vec![u8::from(yielded)])
+});
+
+extism::host_fn!(pub set_idle(user_data: PersistentData; idle: u32) {
+    let data = user_data.get()?;
+    data.lock().unwrap().idle = idle != 0;
+    Ok(())
+});
+
+extism::host_fn!(pub random_bytes(user_data: PersistentData; len: u32) -> Vec<u8> {
+    let data = user_data.get()?;
+    let mut data = data.lock().unwrap();
+    Ok(data.rng.bytes(len as usize)?)
+});
+
+extism::host_fn!(pub random_u32_range(user_data: PersistentData; low: u32, high: u32) -> Vec<u8> {
+    let data = user_data.get()?;
+    let mut data = data.lock().unwrap();
+    Ok(data.rng.u32_in_range(low, high)?.to_be_bytes().to_vec())
+});
+
+extism::host_fn!(pub log(user_data: PersistentData; level: u32, line: String) {
+    let data = user_data.get()?;
+    let mut data = data.lock().unwrap();
+    data.log.log(level, &line);
+    Ok(())
+});
+
+/// `duration` in milliseconds, with turns which last forever as `u64::MAX`.
+fn slot_millis(duration: Option<Duration>) -> u64 {
+    duration.map_or(u64::MAX, |duration| {
+        u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+    })
 }
@@ -0,0 +1,43 @@
+use crate::{
+    display::{MonocolorPalette, SharedScreenBuffer},
+    wasm_env::OffscreenBuffers,
+};
+
+pub fn create(
+    offscreen: &mut OffscreenBuffers,
+    (width, height): (u32, u32),
+    palette: Option<MonocolorPalette>,
+) -> Result<u32, extism::Error> {
+    Ok(offscreen.create(width as usize, height as usize, palette)?)
+}
+
+pub fn destroy(offscreen: &mut OffscreenBuffers, handle: u32) -> Result<(), extism::Error> {
+    Ok(offscreen.destroy(handle)?)
+}
+
+/// What the app's drawing goes to after it picks `handle`, which is its screen for 0 and an
+/// offscreen buffer otherwise.
+pub fn draw_target(
+    offscreen: &OffscreenBuffers,
+    handle: u32,
+) -> Result<Option<u32>, extism::Error> {
+    if handle == 0 {
+        return Ok(None);
+    }
+    offscreen.get(handle)?;
+    Ok(Some(handle))
+}
+
+/// The offscreen buffer to copy from, which can't be the one being drawn on.
+pub fn blit_source(
+    offscreen: &OffscreenBuffers,
+    handle: u32,
+    draw_target: Option<u32>,
+) -> Result<&SharedScreenBuffer, extism::Error> {
+    if draw_target == Some(handle) {
+        return Err(extism::Error::msg(format!(
+            "Can't blit offscreen buffer {handle} onto itself"
+        )));
+    }
+    Ok(offscreen.get(handle)?)
+}
@@ -0,0 +1,45 @@
+use jiff::{tz::TimeZone, Timestamp};
+use std::{sync::LazyLock, time::Instant};
+
+/// What monotonic time is measured from, which is when the first app was loaded
+static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Starts the monotonic clock if no app has started it yet.
+pub fn start_monotonic_clock() {
+    LazyLock::force(&START);
+}
+
+/// Milliseconds since the first app was loaded, which never goes backwards even if the system
+/// clock is changed.
+pub fn get_monotonic_ms() -> u64 {
+    START.elapsed().as_millis() as u64
+}
+
+/// Milliseconds since the Unix epoch by the system clock.
+pub fn get_unix_time_ms() -> Result<u64, extism::Error> {
+    let now = Timestamp::now().as_millisecond();
+    u64::try_from(now)
+        .map_err(|_| extism::Error::msg(format!("The system clock is before 1970, at {now} ms")))
+}
+
+/// The date and time now in `time_zone`, as the year as a `u16`, then the month, day, hour,
+/// minute, and second as a byte each, then the weekday as a byte from 1 for Monday to 7 for
+/// Sunday, then the offset from UTC in seconds as an `i32`. Numbers are big endian.
+pub fn get_local_time(time_zone: &TimeZone) -> Result<Vec<u8>, extism::Error> {
+    let now = Timestamp::now().to_zoned(time_zone.clone());
+    let year = u16::try_from(now.year())
+        .map_err(|_| extism::Error::msg(format!("Can't give the year {} to apps", now.year())))?;
+    Ok([
+        &year.to_be_bytes()[..],
+        &[
+            now.month() as u8,
+            now.day() as u8,
+            now.hour() as u8,
+            now.minute() as u8,
+            now.second() as u8,
+            now.weekday().to_monday_one_offset() as u8,
+        ][..],
+        &now.offset().seconds().to_be_bytes()[..],
+    ]
+    .concat())
+}
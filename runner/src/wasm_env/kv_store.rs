@@ -0,0 +1,299 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Longest key an app may use, in bytes
+const MAX_KEY_BYTES: usize = 256;
+/// Largest value an app may store under one key
+const MAX_VALUE_BYTES: usize = 64 * 1024;
+/// Most an app may store altogether by default, counting both keys and values
+pub const DEFAULT_KV_QUOTA: usize = 1024 * 1024;
+
+/// An app's keys and values, kept in a file of its own so they last across restarts of the
+/// runner, or only in memory if there's nowhere to keep them. Each change is written to a new
+/// file which then replaces the old one, so a crash partway through a write leaves the store as
+/// it was before.
+#[derive(Debug)]
+pub struct KvStore {
+    entries: BTreeMap<String, Vec<u8>>,
+    /// The file the store is kept in, if it's kept at all
+    path: Option<PathBuf>,
+    bytes_used: usize,
+    /// Most the store may hold, counting both keys and values
+    quota: usize,
+}
+
+impl KvStore {
+    /// A store which is lost when the app is.
+    pub fn in_memory() -> Self {
+        KvStore {
+            entries: BTreeMap::new(),
+            path: None,
+            bytes_used: 0,
+            quota: DEFAULT_KV_QUOTA,
+        }
+    }
+
+    /// Opens the store of the app called `app_name` in `state_dir`, which is created if it
+    /// doesn't exist. Apps with different names never see each other's keys.
+    pub fn open(state_dir: impl AsRef<Path>, app_name: &str) -> io::Result<Self> {
+        fs::create_dir_all(state_dir.as_ref())?;
+        let path = state_dir.as_ref().join(store_file_name(app_name));
+        let entries = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice::<BTreeMap<String, Vec<u8>>>(&contents)
+                .map_err(|err| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Failed to parse the store at {}: {err}", path.display()),
+                    )
+                })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err),
+        };
+        let bytes_used = entries
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum();
+        Ok(KvStore {
+            entries,
+            path: Some(path),
+            bytes_used,
+            quota: DEFAULT_KV_QUOTA,
+        })
+    }
+
+    /// Limits the store to `quota` bytes, counting both keys and values. A store already over
+    /// its quota keeps what it has, but can't take anything more until enough is deleted.
+    pub fn with_quota(mut self, quota: usize) -> Self {
+        if self.bytes_used > quota {
+            tracing::warn!(
+                "The store holds {} bytes, more than its quota of {quota}",
+                self.bytes_used
+            );
+        }
+        self.quota = quota;
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    /// Stores `value` under `key`, replacing whatever was there. Fails without changing anything
+    /// if the key or value is too big or the store would go over its quota.
+    pub fn set(&mut self, key: String, value: Vec<u8>) -> io::Result<()> {
+        if key.len() > MAX_KEY_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Keys can be at most {MAX_KEY_BYTES} bytes, got {}",
+                    key.len()
+                ),
+            ));
+        }
+        if value.len() > MAX_VALUE_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Values can be at most {MAX_VALUE_BYTES} bytes, got {} for {key}",
+                    value.len()
+                ),
+            ));
+        }
+        let replaced = self
+            .entries
+            .get(&key)
+            .map_or(0, |old| key.len() + old.len());
+        let bytes_used = self.bytes_used - replaced + key.len() + value.len();
+        if bytes_used > self.quota {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!(
+                    "Storing {} bytes under {key} would take the app to {bytes_used} of the \
+                     {} bytes it's allowed",
+                    value.len(),
+                    self.quota
+                ),
+            ));
+        }
+        let old = self.entries.insert(key.clone(), value);
+        if let Err(err) = self.save() {
+            match old {
+                Some(old) => self.entries.insert(key, old),
+                None => self.entries.remove(&key),
+            };
+            return Err(err);
+        }
+        self.bytes_used = bytes_used;
+        Ok(())
+    }
+
+    /// Removes `key`, returning whether it was there.
+    pub fn delete(&mut self, key: &str) -> io::Result<bool> {
+        let Some(old) = self.entries.remove(key) else {
+            return Ok(false);
+        };
+        if let Err(err) = self.save() {
+            self.entries.insert(key.to_owned(), old);
+            return Err(err);
+        }
+        self.bytes_used -= key.len() + old.len();
+        Ok(true)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let contents = serde_json::to_vec(&self.entries)?;
+        let temp_path = path.with_extension("json.tmp");
+        let mut file = fs::File::create(&temp_path)?;
+        io::Write::write_all(&mut file, &contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        // The rename itself only lasts through a crash once the directory has been synced
+        if let Some(dir) = path.parent() {
+            fs::File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of the file an app's store is kept in. Lowercase letters, digits, and dashes are
+/// kept as they are and every other byte of the name is written as `_` and its two hex digits,
+/// so no two names share a file, even on filesystems which ignore case, and none can escape the
+/// state directory.
+fn store_file_name(app_name: &str) -> String {
+    let mut name = String::with_capacity(app_name.len());
+    for byte in app_name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' => name.push(char::from(byte)),
+            _ => name.push_str(&format!("_{byte:02x}")),
+        }
+    }
+    format!("{name}.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A state directory of the test's own, removed when the test is done
+    struct StateDir(PathBuf);
+
+    impl StateDir {
+        fn new(test_name: &str) -> Self {
+            StateDir(
+                std::env::temp_dir().join(format!("megabit-kv-{test_name}-{}", std::process::id())),
+            )
+        }
+    }
+
+    impl Drop for StateDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn apps_cannot_see_each_others_keys() {
+        let state_dir = StateDir::new("isolation");
+        let mut counter = KvStore::open(&state_dir.0, "Counter").unwrap();
+        let mut clock = KvStore::open(&state_dir.0, "Clock").unwrap();
+        counter.set("count".to_owned(), vec![41]).unwrap();
+        clock.set("format".to_owned(), b"24h".to_vec()).unwrap();
+        assert_eq!(clock.get("count"), None);
+        assert_eq!(counter.get("format"), None);
+
+        // Nor once the stores are reopened
+        let counter = KvStore::open(&state_dir.0, "Counter").unwrap();
+        let clock = KvStore::open(&state_dir.0, "Clock").unwrap();
+        assert_eq!(counter.get("count"), Some(&[41][..]));
+        assert_eq!(counter.get("format"), None);
+        assert_eq!(clock.get("format"), Some(&b"24h"[..]));
+        assert_eq!(clock.get("count"), None);
+    }
+
+    #[test]
+    fn stores_are_limited_to_their_quota() {
+        let state_dir = StateDir::new("quota");
+        let mut store = KvStore::open(&state_dir.0, "Clock")
+            .unwrap()
+            .with_quota(100);
+        store.set("a".to_owned(), vec![0; 49]).unwrap();
+        store.set("b".to_owned(), vec![0; 49]).unwrap();
+        let err = store.set("c".to_owned(), vec![0]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+        assert_eq!(store.get("c"), None);
+
+        // Replacing a value only counts the difference
+        store.set("a".to_owned(), vec![1; 49]).unwrap();
+        assert_eq!(
+            store.set("a".to_owned(), vec![0; 50]).unwrap_err().kind(),
+            io::ErrorKind::StorageFull
+        );
+        assert_eq!(store.get("a"), Some(&[1; 49][..]));
+
+        // Deleting makes room again, and what was stored still counts once the store is reopened
+        assert!(store.delete("b").unwrap());
+        store.set("c".to_owned(), vec![0]).unwrap();
+        let mut store = KvStore::open(&state_dir.0, "Clock")
+            .unwrap()
+            .with_quota(100);
+        assert_eq!(
+            store.set("d".to_owned(), vec![0; 49]).unwrap_err().kind(),
+            io::ErrorKind::StorageFull
+        );
+    }
+
+    #[test]
+    fn keys_and_values_are_limited_in_size() {
+        let mut store = KvStore::in_memory();
+        let err = store
+            .set("k".repeat(MAX_KEY_BYTES + 1), vec![0])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = store
+            .set("big".to_owned(), vec![0; MAX_VALUE_BYTES + 1])
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(store.get("big"), None);
+
+        store.set("k".repeat(MAX_KEY_BYTES), vec![0]).unwrap();
+        store
+            .set("big".to_owned(), vec![0; MAX_VALUE_BYTES])
+            .unwrap();
+    }
+
+    #[test]
+    fn store_file_names_never_collide() {
+        let names = [
+            "a.b", "a/b", "a_b", "a_2eb", "A.b", "a.B", "ab", "a b", "a\\b", "é",
+        ];
+        let file_names = names.map(store_file_name);
+        for (index, file_name) in file_names.iter().enumerate() {
+            assert!(
+                !file_names[index + 1..].contains(file_name),
+                "{} shares {file_name} with another app",
+                names[index]
+            );
+        }
+        assert_eq!(store_file_name("a.b"), "a_2eb.json");
+        assert_eq!(store_file_name("a/b"), "a_2fb.json");
+    }
+
+    #[test]
+    fn store_file_names_stay_in_the_state_directory() {
+        for name in ["..", "../other", "/etc/passwd", "", "."] {
+            let file_name = store_file_name(name);
+            assert_eq!(
+                Path::new(&file_name).components().count(),
+                1,
+                "{name} became {file_name}"
+            );
+        }
+        assert_eq!(store_file_name("clock-2"), "clock-2.json");
+    }
+}
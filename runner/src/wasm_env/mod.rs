@@ -1,83 +1,927 @@
-use self::host_functions::with_host_functions;
-use crate::{
-    display::{DisplayConfiguration, ScreenBuffer, DEFAULT_MONO_PALETTE},
-    serial::SyncSerialConnection,
+//! Runs wasm apps built with extism, which draw on the display through the runner's host
+//! functions.
+//!
+//! Apps go through a lifecycle made up of the functions they export:
+//!
+//! 1. `setup`, called once after the app is loaded with JSON input like
+//!    `{"width": 32, "height": 16, "rgb": false, "config": {...}}` giving the size of the screen
+//!    the app draws on, whether it's RGB, and the `config` table from its manifest
+//! 2. `update`, called every refresh period with the milliseconds since the app was set up or
+//!    last updated as a little-endian `u64`, which is how extism's PDKs take a `u64` input
+//! 3. `teardown`, called once before the app is dropped, whether it's being switched away from,
+//!    reloaded, restarted after crashing, or the runner is shutting down
+//!
+//! `setup` and `teardown` are optional. Apps written before `update` existed export `run`
+//! instead, which is called with no input.
+//!
+//! The refresh period starts out as `refresh_period_ms` from the app's manifest, if it gives one,
+//! and can be changed at any time with `set_refresh_interval`, which takes milliseconds and gives
+//! back the period the app will actually be run at as a big endian `u32`, since periods are kept
+//! within [`AppOptions::min_refresh_period`] and [`AppOptions::max_refresh_period`]. Apps without
+//! a refresh period are only set up, until they set one.
+//!
+//! Apps in a rotation can ask how long their turn lasts with `get_slot_duration_ms` and how
+//! much of it is left with `get_slot_time_remaining_ms`, both as a big endian `u64` which is
+//! `u64::MAX` for apps which run forever, and can ask for more time with
+//! `request_slot_extension`, which gives back 1 if the extension was granted and 0 if it would
+//! take the app past the `max_slot_extension_ms` in its manifest. They can give up the rest of
+//! their turn with `yield_slot`, which takes effect once the call they make it from returns and
+//! gives back 0 if the app has to see its turn out because every app before it gave up theirs.
+//! Apps with `skip_if_idle` in their manifest can say they have nothing to show with
+//! `set_idle`, and are skipped in the rotation for a while after a turn which ends with them
+//! idle, unless there's nothing else to show.
+//!
+//! The `config` table is read from the manifest each time the app is loaded, so changes to it
+//! take effect the next time the app is reloaded, restarted or rotated back to. Apps can also
+//! read it as JSON from extism's config under `config`, or a key at a time with `get_config`.
+//!
+//! Host functions beyond drawing, scheduling, configuration and random numbers belong to a
+//! [`Capability`] which apps have to declare in their manifest's `capabilities`, and which the
+//! runner can deny to every app with [`AppOptions::denied_capabilities`]. Apps can import host
+//! functions they aren't allowed and still load, but calling one fails the call with
+//! [`PermissionDenied`].
+//!
+//! Button presses and encoder turns from the device are queued for the app being shown, which
+//! takes them with `poll_input_events` as a JSON array like
+//! `[{"type": "button_pressed", "button": 0}, {"type": "encoder_turned", "encoder": 0,
+//! "steps": -2}]`. Input arriving while no app is being shown is dropped, as is whatever an app
+//! hasn't taken by the time it's switched away from. The runner may keep a button for itself,
+//! see [`AppScheduler::set_next_app_button`].
+
+use self::host_functions::{present, redraw, with_host_functions};
+use crate::display::{
+    CompositeDisplay, DisplayConfiguration, Font, MonocolorPalette, RegionBounds, Rgb555,
+    ScaleMapping, ScreenBuffer, SharedScreenBuffer, Transition, TransitionKind,
 };
+use crate::serial::InputEvent;
+use app_log::AppLog;
 use app_manifest::AppManifest;
-use std::{cell::RefCell, collections::BTreeMap, path::Path, rc::Rc, time::Duration};
+pub use capability::{Capability, CapabilityGrants, DenialReason, PermissionDenied};
+#[cfg(feature = "test-support")]
+pub use harness::{compare_traces, AppHarness, HARNESS_SEED};
+use jiff::tz::TimeZone;
+pub use kv_store::{KvStore, DEFAULT_KV_QUOTA};
+pub use module_cache::ModuleCache;
+pub(crate) use offscreen::OffscreenBuffers;
+pub use random::AppRng;
+pub use scheduler::{AppRotation, AppScheduler, AppStats, RotationEntry};
+pub use slot::Slot;
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+pub use trace::{read_trace, write_trace, HostCall, HostCallTrace};
 
+mod app_log;
 mod app_manifest;
+mod capability;
+#[cfg(feature = "test-support")]
+mod harness;
 mod host_functions;
+mod kv_store;
+mod module_cache;
+mod offscreen;
+mod random;
+mod scheduler;
+mod slot;
+mod trace;
+mod watcher;
 
-pub type KvStore = BTreeMap<String, Vec<u8>>;
+/// Most time each call into an app may take, unless its manifest or the runner say otherwise.
+/// Time spent in host functions counts towards it, but a call is only stopped once it's back
+/// in the app's own code, so one stuck in a slow host function carries on until that returns.
+pub const DEFAULT_EXECUTION_BUDGET: Duration = Duration::from_millis(500);
+/// How many calls in a row an app may run over its execution budget before it's taken to be
+/// misbehaving
+const MAX_BUDGET_VIOLATIONS: u32 = 3;
+/// Most memory an app may use in 64 KiB pages, 64 MiB, unless its manifest or the runner say
+/// otherwise. Only memory the app grows into once it has been loaded counts towards it, which
+/// includes what extism allocates for it, and not what the module starts out with.
+pub const DEFAULT_MAX_MEMORY_PAGES: u32 = 1024;
+const WASM_PAGE_BYTES: usize = 64 * 1024;
+/// What extism reports calls it stopped for running too long or out of memory as
+const TIMEOUT_ERROR: &str = "timeout";
+const OUT_OF_MEMORY_ERROR: &str = "oom";
+/// Shortest and longest refresh periods apps may run at, unless the runner says otherwise. Apps
+/// asking for periods outside of these, in their manifest or with `set_refresh_interval`, are
+/// run at the nearest one.
+pub const DEFAULT_MIN_REFRESH_PERIOD: Duration = Duration::from_millis(16);
+pub const DEFAULT_MAX_REFRESH_PERIOD: Duration = Duration::from_secs(60);
+/// Most input events kept for an app which isn't taking them, after which the oldest are dropped
+const MAX_QUEUED_INPUT: usize = 64;
 
 struct PersistentData {
-    screen_buffer: Rc<RefCell<ScreenBuffer>>,
+    display: Rc<RefCell<CompositeDisplay>>,
     kv_store: Rc<RefCell<KvStore>>,
-    serial_conn: SyncSerialConnection,
+    /// The part of the display the app draws on, or all of it if not set
+    region: Option<RegionBounds>,
+    /// What the app draws on instead of the display, if it runs at its own resolution
+    virtual_screen: Option<VirtualScreen>,
+    /// Whether the app's renders only update the screen buffer without sending anything, e.g.
+    /// while it draws the first frame of a transition
+    renders_held: bool,
+    /// Whether the display has changed size since the app last checked
+    display_changed: bool,
+    offscreen: OffscreenBuffers,
+    /// The offscreen buffer the app's drawing goes to instead of its screen, if it has picked
+    /// one
+    draw_target: Option<u32>,
+    /// The time zone local times are given to the app in
+    time_zone: TimeZone,
+    rng: AppRng,
+    log: AppLog,
+    /// The `config` table from the app's manifest
+    config: serde_json::Value,
+    /// Input from the device the app hasn't taken yet
+    input: VecDeque<InputEvent>,
+    /// How often the app is run, if it is at all
+    refresh_period: Option<Duration>,
+    /// The shortest and longest refresh periods the app may ask for
+    refresh_period_range: (Duration, Duration),
+    /// The app's turn in the rotation, or `None` if it isn't part of one and runs forever
+    slot: Option<Rc<RefCell<Slot>>>,
+    /// Whether the app has said it has nothing to show
+    idle: bool,
+}
+
+/// A screen at the resolution an app was written for, scaled onto the app's part of the display
+/// whenever it renders
+struct VirtualScreen {
+    buffer: SharedScreenBuffer,
+    mapping: ScaleMapping,
 }
 
 impl PersistentData {
-    fn new(serial_conn: SyncSerialConnection, display_cfg: DisplayConfiguration) -> Self {
-        let screen_buffer = Rc::new(RefCell::new(ScreenBuffer::new(
-            display_cfg.width,
-            display_cfg.height,
-            if display_cfg.is_rgb {
-                Some(DEFAULT_MONO_PALETTE)
-            } else {
-                None
-            },
-        )));
-        let kv_store = Rc::new(RefCell::new(BTreeMap::new()));
+    fn new(
+        display: Rc<RefCell<CompositeDisplay>>,
+        region: Option<RegionBounds>,
+        kv_store: KvStore,
+        rng: AppRng,
+        log: AppLog,
+        config: serde_json::Value,
+    ) -> Self {
+        let kv_store = Rc::new(RefCell::new(kv_store));
 
         PersistentData {
-            screen_buffer,
+            display,
             kv_store,
-            serial_conn,
+            region,
+            virtual_screen: None,
+            renders_held: false,
+            display_changed: false,
+            offscreen: OffscreenBuffers::new(),
+            draw_target: None,
+            time_zone: TimeZone::system(),
+            rng,
+            log,
+            config,
+            input: VecDeque::new(),
+            refresh_period: None,
+            refresh_period_range: (DEFAULT_MIN_REFRESH_PERIOD, DEFAULT_MAX_REFRESH_PERIOD),
+            slot: None,
+            idle: false,
+        }
+    }
+
+    /// `period` brought within the range of refresh periods the app may run at.
+    fn clamp_refresh_period(&self, period: Duration) -> Duration {
+        let (min, max) = self.refresh_period_range;
+        period.max(min).min(max)
+    }
+
+    /// The width and height of the app's part of the display.
+    fn display_size(&self) -> (usize, usize) {
+        let config = self.display.borrow().display_config();
+        self.region.map_or((config.width, config.height), |region| {
+            (region.width, region.height)
+        })
+    }
+
+    /// The width and height of what the app draws on, which is its virtual screen if it has one.
+    fn app_size(&self) -> (usize, usize) {
+        match &self.virtual_screen {
+            Some(virtual_screen) => {
+                let config = virtual_screen.buffer.read().display_config();
+                (config.width, config.height)
+            }
+            None => self.display_size(),
+        }
+    }
+
+    /// The buffer the app draws on, which is its virtual screen if it has one.
+    fn with_app_buffer<T>(&self, f: impl FnOnce(&mut ScreenBuffer) -> T) -> T {
+        match &self.virtual_screen {
+            Some(virtual_screen) => f(&mut virtual_screen.buffer.write()),
+            None => f(&mut self.display.borrow_mut().screen_buffer_mut()),
         }
     }
 }
 
+/// Settings the runner gives every app it loads, on top of what the apps' manifests say
+#[derive(Debug, Clone, Default)]
+pub struct AppOptions {
+    /// The part of the display apps draw on, or all of it if not set
+    pub region: Option<RegionBounds>,
+    /// The resolution apps run at, overriding their manifests
+    pub virtual_resolution: Option<(usize, usize)>,
+    /// The time zone apps get local times in, if not the system's
+    pub time_zone: Option<TimeZone>,
+    /// What to seed apps' random numbers with, overriding their manifests
+    pub seed: Option<u64>,
+    /// Where apps' key-value stores are kept, or only in memory if not set
+    pub state_dir: Option<PathBuf>,
+    /// Most time each call into an app may take unless its manifest gives its own, or
+    /// [`DEFAULT_EXECUTION_BUDGET`] if not set
+    pub execution_budget: Option<Duration>,
+    /// Most memory an app may use in 64 KiB pages unless its manifest gives its own, or
+    /// [`DEFAULT_MAX_MEMORY_PAGES`] if not set
+    pub max_memory_pages: Option<u32>,
+    /// Shortest refresh period apps may run at, or [`DEFAULT_MIN_REFRESH_PERIOD`] if not set
+    pub min_refresh_period: Option<Duration>,
+    /// Longest refresh period apps may run at, or [`DEFAULT_MAX_REFRESH_PERIOD`] if not set
+    pub max_refresh_period: Option<Duration>,
+    /// Capabilities no app is allowed, whatever its manifest declares
+    pub denied_capabilities: Vec<Capability>,
+    /// Where apps are kept once they've been compiled so they load faster the next time, or
+    /// wherever wasmtime's own settings say if not set
+    pub module_cache: Option<ModuleCache>,
+    /// Where to record every call apps make to the runner's host functions, if anywhere
+    pub trace_host_calls: Option<HostCallTrace>,
+}
+
 pub struct WasmAppRunner {
     app: extism::Plugin,
+    user_data: extism::UserData<PersistentData>,
     name: String,
-    refresh_period: Option<Duration>,
+    transition: Option<TransitionKind>,
+    transition_duration: Option<Duration>,
+    version: Option<String>,
+    network_allowlist: Vec<String>,
+    capabilities: CapabilityGrants,
+    kv_quota: usize,
+    execution_budget: Duration,
+    /// How many calls in a row have been stopped for running over the execution budget
+    budget_violations: u32,
+    max_memory_pages: u32,
+    max_slot_extension: Duration,
+    skip_if_idle: bool,
+    /// Whether the app's last call failed because it ran out of memory
+    out_of_memory: bool,
+    exports: LifecycleExports,
+    /// When the app was last set up or updated
+    last_update: Option<Instant>,
+    torn_down: bool,
+}
+
+/// Which of the optional parts of the lifecycle an app exports
+#[derive(Debug, Clone, Copy)]
+struct LifecycleExports {
+    setup: bool,
+    /// Whether the app exports `update`, rather than only `run` like apps from before it existed
+    update: bool,
+    teardown: bool,
 }
 
 impl WasmAppRunner {
-    pub fn new(
+    pub fn new(app_path: impl AsRef<Path>, display: CompositeDisplay) -> anyhow::Result<Self> {
+        WasmAppRunner::with_region(app_path, Rc::new(RefCell::new(display)), None)
+    }
+
+    /// Runs an app which only sees and draws on `region` of the display, if given. Several
+    /// runners can share a display this way, see [`WasmAppRunner::display`], with renders from
+    /// any of them sending every changed row. Regions may overlap, in which case whichever app
+    /// drew last wins.
+    pub fn with_region(
+        app_path: impl AsRef<Path>,
+        display: Rc<RefCell<CompositeDisplay>>,
+        region: Option<RegionBounds>,
+    ) -> anyhow::Result<Self> {
+        let options = AppOptions {
+            region,
+            ..Default::default()
+        };
+        WasmAppRunner::load(app_path, display, &options)
+    }
+
+    /// Loads the app in `app_path` with `options` applied to it.
+    pub fn load(
         app_path: impl AsRef<Path>,
-        serial_conn: SyncSerialConnection,
-        display_cfg: DisplayConfiguration,
+        display: Rc<RefCell<CompositeDisplay>>,
+        options: &AppOptions,
     ) -> anyhow::Result<Self> {
+        let region = options.region;
+        let display_config = display.borrow().display_config();
+        if let Some(region) = region {
+            check_region_fits(region, &display_config)?;
+        }
         let app_manifest = AppManifest::open(app_path)?;
-        let wasm_app_bin = extism::Wasm::file(app_manifest.app_bin_path);
-        let user_data = extism::UserData::new(PersistentData::new(serial_conn, display_cfg));
-        let manifest = extism::Manifest::new([wasm_app_bin]);
-        let plugin = with_host_functions(extism::PluginBuilder::new(manifest), &user_data)
-            .with_wasi(true)
-            .build()?;
-
-        Ok(WasmAppRunner {
+        tracing::debug!("Loaded app manifest: {}", app_manifest.path.display());
+        let app_size = region.map_or((display_config.width, display_config.height), |region| {
+            (region.width, region.height)
+        });
+        app_manifest.check_requirements(&display_config, app_size)?;
+        let wasm_app_bin = extism::Wasm::file(&app_manifest.app_bin_path);
+        let rng = match app_manifest.seed {
+            Some(seed) => AppRng::new(seed),
+            None => AppRng::from_entropy()?,
+        };
+        tracing::debug!("Seeded the app's random numbers with {}", rng.seed());
+        let kv_quota = app_manifest.kv_quota_bytes.unwrap_or(DEFAULT_KV_QUOTA);
+        let log =
+            AppLog::new(&app_manifest.app_name).with_secrets(app_manifest.secret_config_values());
+        // The app can also read its settings with extism's config functions, as JSON under
+        // "config", or a key at a time with get_config
+        let config = serde_json::Value::Object(app_manifest.config);
+        let extism_config = config.to_string();
+        let mut data = PersistentData::new(
+            display,
+            region,
+            KvStore::in_memory().with_quota(kv_quota),
+            rng,
+            log,
+            config,
+        );
+        data.refresh_period_range = (
+            options
+                .min_refresh_period
+                .unwrap_or(DEFAULT_MIN_REFRESH_PERIOD),
+            options
+                .max_refresh_period
+                .unwrap_or(DEFAULT_MAX_REFRESH_PERIOD),
+        );
+        data.refresh_period = app_manifest
+            .refresh_period
+            .map(|period| data.clamp_refresh_period(period));
+        let user_data = extism::UserData::new(data);
+        let execution_budget = app_manifest
+            .execution_budget
+            .or(options.execution_budget)
+            .unwrap_or(DEFAULT_EXECUTION_BUDGET);
+        let max_memory_pages = app_manifest
+            .max_memory_pages
+            .or(options.max_memory_pages)
+            .unwrap_or(DEFAULT_MAX_MEMORY_PAGES);
+        let capabilities = CapabilityGrants::new(
+            app_manifest.capabilities,
+            options.denied_capabilities.clone(),
+        );
+        tracing::debug!("Allowed the app {:?}", capabilities.granted());
+        let mut manifest = extism::Manifest::new([wasm_app_bin])
+            .with_config_key("config", extism_config)
+            .with_timeout(execution_budget)
+            .with_memory_max(max_memory_pages);
+        // Without any allowed hosts extism turns down every request the app makes
+        match capabilities.check(Capability::Network) {
+            Ok(()) => {
+                manifest =
+                    manifest.with_allowed_hosts(app_manifest.network_allowlist.iter().cloned());
+            }
+            Err(reason) if !app_manifest.network_allowlist.is_empty() => tracing::warn!(
+                "App {} has a network_allowlist but can't make requests to it: {reason:?}",
+                app_manifest.app_name
+            ),
+            Err(_) => {}
+        }
+        let mut builder = extism::PluginBuilder::new(manifest);
+        if let Some(module_cache) = &options.module_cache {
+            builder = builder.with_cache_config(module_cache.config_path());
+        }
+        let compile_started_at = Instant::now();
+        let mut plugin = with_host_functions(
+            builder,
+            &user_data,
+            &capabilities,
+            options.trace_host_calls.as_ref(),
+        )
+        .with_wasi(true)
+        .build()?;
+        tracing::debug!(
+            "Compiled app {} in {:?}",
+            app_manifest.app_name,
+            compile_started_at.elapsed()
+        );
+        let exports = LifecycleExports {
+            setup: plugin.function_exists("setup"),
+            update: plugin.function_exists("update"),
+            teardown: plugin.function_exists("teardown"),
+        };
+
+        let mut runner = WasmAppRunner {
             app: plugin,
+            user_data,
             name: app_manifest.app_name,
-            refresh_period: app_manifest.refresh_period,
-        })
+            transition: app_manifest.transition,
+            transition_duration: app_manifest.transition_duration,
+            version: app_manifest.version,
+            network_allowlist: app_manifest.network_allowlist,
+            capabilities,
+            kv_quota,
+            execution_budget,
+            budget_violations: 0,
+            max_memory_pages,
+            max_slot_extension: app_manifest.max_slot_extension,
+            skip_if_idle: app_manifest.skip_if_idle,
+            out_of_memory: false,
+            exports,
+            last_update: None,
+            torn_down: false,
+        };
+        if let Some(resolution) = options
+            .virtual_resolution
+            .or(app_manifest.virtual_resolution)
+        {
+            runner.set_virtual_resolution(Some(resolution))?;
+        }
+        if let Some(time_zone) = options.time_zone.clone() {
+            runner.set_time_zone(time_zone)?;
+        }
+        if let Some(seed) = options.seed {
+            runner.set_seed(seed)?;
+        }
+        if let Some(state_dir) = &options.state_dir {
+            runner.set_state_dir(state_dir)?;
+        }
+        Ok(runner)
+    }
+
+    /// Has the app draw on a screen of the given width and height, which is scaled up to fit
+    /// its part of the display as well as it can by whole multiples and centered, or draw on
+    /// the display directly if `None`. The app should be told before it's set up, as it will
+    /// start out with a blank screen either way.
+    pub fn set_virtual_resolution(
+        &mut self,
+        resolution: Option<(usize, usize)>,
+    ) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        let mut data = data.lock().unwrap();
+        let Some((width, height)) = resolution else {
+            data.virtual_screen = None;
+            return Ok(());
+        };
+        let palette = data.display.borrow().screen_buffer().palette();
+        let app_size = data.display_size();
+        let mapping = ScaleMapping::new((width, height), app_size)?;
+        tracing::info!(
+            "Scaling the app's {width}x{height} screen by {} onto its {}x{} display",
+            mapping.factor(),
+            app_size.0,
+            app_size.1
+        );
+        data.virtual_screen = Some(VirtualScreen {
+            buffer: SharedScreenBuffer::new(ScreenBuffer::new(width, height, palette)),
+            mapping,
+        });
+        Ok(())
+    }
+
+    /// Gives the app local times in `time_zone` rather than the system's time zone, e.g. on
+    /// devices whose system time zone is wrong.
+    pub fn set_time_zone(&mut self, time_zone: TimeZone) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        data.lock().unwrap().time_zone = time_zone;
+        Ok(())
+    }
+
+    /// Most the app's manifest lets it extend its turn in the rotation by.
+    pub fn max_slot_extension(&self) -> Duration {
+        self.max_slot_extension
+    }
+
+    /// Whether the app's manifest asks for it to be left out of the rotation while it's idle.
+    pub fn skip_if_idle(&self) -> bool {
+        self.skip_if_idle
+    }
+
+    /// Whether the app has said with `set_idle` that it has nothing to show.
+    pub fn is_idle(&self) -> bool {
+        self.user_data
+            .get()
+            .is_ok_and(|data| data.lock().unwrap().idle)
+    }
+
+    /// Tells the app about its turn in the rotation, which it can ask how long is left of and
+    /// extend. Apps without one are told they run forever.
+    pub fn set_slot(&mut self, slot: Rc<RefCell<Slot>>) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        data.lock().unwrap().slot = Some(slot);
+        Ok(())
+    }
+
+    /// Queues input from the device for the app to take the next time it polls for it.
+    pub fn queue_input(&mut self, event: InputEvent) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        let mut data = data.lock().unwrap();
+        if data.input.len() >= MAX_QUEUED_INPUT {
+            data.input.pop_front();
+        }
+        data.input.push_back(event);
+        Ok(())
+    }
+
+    /// Drops whatever input the app hasn't taken yet, e.g. when it's switched away from.
+    pub fn clear_input(&mut self) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        data.lock().unwrap().input.clear();
+        Ok(())
+    }
+
+    /// Keeps the app's keys and values in a file in `state_dir` named after the app, so they
+    /// last across restarts, loading whatever it stored last time.
+    pub fn set_state_dir(&mut self, state_dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        let kv_store = KvStore::open(state_dir, &self.name)?.with_quota(self.kv_quota);
+        let data = self.user_data.get()?;
+        *data.lock().unwrap().kv_store.borrow_mut() = kv_store;
+        Ok(())
+    }
+
+    /// Restarts the app's random numbers from `seed`, so they're the same on every run.
+    pub fn set_seed(&mut self, seed: u64) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        data.lock().unwrap().rng = AppRng::new(seed);
+        Ok(())
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// What the app is allowed to do beyond drawing.
+    pub fn capabilities(&self) -> &CapabilityGrants {
+        &self.capabilities
+    }
+
+    /// Hosts the app's manifest says it may connect to, which it can only do if it's allowed
+    /// [`Capability::Network`].
+    pub fn network_allowlist(&self) -> &[String] {
+        &self.network_allowlist
+    }
+
+    /// How often the app asks to be run, from its manifest or whatever it has since set with
+    /// `set_refresh_interval`, or `None` if it's only set up.
     pub fn refresh_period(&self) -> Option<Duration> {
-        self.refresh_period
+        let data = self.user_data.get().ok()?;
+        let refresh_period = data.lock().unwrap().refresh_period;
+        refresh_period
+    }
+
+    /// How to switch to the app, which is `default` with the kind and duration of transition
+    /// replaced by the app's own if its manifest gives them.
+    pub fn transition(&self, default: Transition) -> Transition {
+        Transition {
+            kind: self.transition.unwrap_or(default.kind),
+            duration: self.transition_duration.unwrap_or(default.duration),
+            ..default
+        }
     }
 
+    /// Sets the app up, telling it the size of its screen and its settings, if it exports
+    /// `setup`.
     pub fn setup_app(&mut self) -> anyhow::Result<()> {
-        self.app.call::<_, ()>("setup", ())
+        self.last_update = Some(Instant::now());
+        if !self.exports.setup {
+            return Ok(());
+        }
+        let input = {
+            let data = self.user_data.get()?;
+            let data = data.lock().unwrap();
+            let (width, height) = data.app_size();
+            serde_json::json!({
+                "width": width,
+                "height": height,
+                "rgb": data.display.borrow().display_config().is_rgb(),
+                "config": data.config,
+            })
+        };
+        self.call("setup", serde_json::to_vec(&input)?)
     }
 
+    /// Sets the app up and runs it once without sending anything to the display, then animates
+    /// the display from `outgoing` over to what the app drew. The app stays paused until the
+    /// transition has finished. With a cut the app is only set up, and shows up whenever it
+    /// first renders as usual.
+    pub fn setup_with_transition(
+        &mut self,
+        outgoing: &ScreenBuffer,
+        transition: Transition,
+    ) -> anyhow::Result<()> {
+        if transition.frame_count() == 0 {
+            return self.setup_app();
+        }
+        self.set_renders_held(true)?;
+        let first_frame = self.setup_app().and_then(|()| self.run_app_once());
+        self.set_renders_held(false)?;
+        first_frame?;
+
+        let data = self.user_data.get()?;
+        let data = data.lock().unwrap();
+        let mut composite = data.display.borrow_mut();
+        present(&data, &mut composite, vec![])?;
+        let incoming = composite.shared_screen_buffer().read_snapshot();
+        if !composite.play_transition(&transition, outgoing, &incoming)? {
+            tracing::info!("The display couldn't keep up with the transition, cut to the app");
+        }
+        Ok(())
+    }
+
+    /// Puts back a screen saved with [`WasmAppRunner::save_screen`] and animates the display
+    /// from `outgoing` over to it, for switching back to an app which was kept running while
+    /// another was shown. With a cut it's sent straight to the display. If it can't be restored
+    /// the app starts out from a blank screen instead and the error is returned.
+    pub fn resume_with_transition(
+        &mut self,
+        outgoing: &ScreenBuffer,
+        saved: &[u8],
+        transition: Transition,
+    ) -> anyhow::Result<()> {
+        if transition.frame_count() == 0 {
+            return self.restore_screen(saved);
+        }
+        let data = self.user_data.get()?;
+        let data = data.lock().unwrap();
+        let restored =
+            data.with_app_buffer(|screen_buffer| screen_buffer.restore_from_bytes(saved));
+        let mut composite = data.display.borrow_mut();
+        present(&data, &mut composite, vec![])?;
+        let incoming = composite.shared_screen_buffer().read_snapshot();
+        if !composite.play_transition(&transition, outgoing, &incoming)? {
+            tracing::info!("The display couldn't keep up with the transition, cut to the app");
+        }
+        Ok(restored?)
+    }
+
+    fn set_renders_held(&mut self, renders_held: bool) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        data.lock().unwrap().renders_held = renders_held;
+        Ok(())
+    }
+
+    /// Updates the app with how long it has been since it was last updated, or runs it if it
+    /// only exports `run`.
     pub fn run_app_once(&mut self) -> anyhow::Result<()> {
-        self.app.call::<_, ()>("run", ())
+        let elapsed = self
+            .last_update
+            .map_or(Duration::ZERO, |last_update| last_update.elapsed());
+        self.update_app_by(elapsed)
+    }
+
+    /// Updates the app as if `elapsed` had passed since it was last updated, whatever the time
+    /// really is, e.g. to run it frame by frame in tests. Apps which only export `run` are run.
+    pub fn update_app_by(&mut self, elapsed: Duration) -> anyhow::Result<()> {
+        self.last_update = Some(Instant::now());
+        if !self.exports.update {
+            return self.call("run", Vec::new());
+        }
+        let elapsed_ms = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+        self.call("update", elapsed_ms.to_le_bytes().to_vec())
+    }
+
+    /// Lets the app clean up before it's dropped, if it exports `teardown`. Only the first call
+    /// does anything, so every path an app is dropped on can call it, and it's called when the
+    /// runner is dropped if it hasn't been already. Apps which have crashed may not be able to
+    /// tear down, in which case the error is returned.
+    pub fn teardown(&mut self) -> anyhow::Result<()> {
+        if std::mem::replace(&mut self.torn_down, true) || !self.exports.teardown {
+            return Ok(());
+        }
+        self.call("teardown", Vec::new())
+    }
+
+    /// Whether the app's last call was stopped for running over its execution budget.
+    pub fn ran_over_budget(&self) -> bool {
+        self.budget_violations > 0
+    }
+
+    /// Whether the app has run over its execution budget on so many calls in a row that it
+    /// shouldn't be given another go.
+    pub fn is_misbehaving(&self) -> bool {
+        self.budget_violations >= MAX_BUDGET_VIOLATIONS
+    }
+
+    /// Whether the app's last call failed because it tried to use more than its memory limit.
+    pub fn ran_out_of_memory(&self) -> bool {
+        self.out_of_memory
+    }
+
+    /// Most memory the app may use, in bytes.
+    pub fn memory_limit(&self) -> usize {
+        self.max_memory_pages as usize * WASM_PAGE_BYTES
+    }
+
+    /// Calls one of the app's exported functions, logging any error under the app's name. Calls
+    /// which run over the app's execution budget are stopped and fail, as do calls which try to
+    /// use more than its memory limit.
+    fn call(&mut self, function: &str, input: Vec<u8>) -> anyhow::Result<()> {
+        let result = self.app.call::<_, ()>(function, input);
+        let root_cause = result
+            .as_ref()
+            .err()
+            .map(|err| err.root_cause().to_string());
+        let timed_out = root_cause.as_deref() == Some(TIMEOUT_ERROR);
+        self.out_of_memory = root_cause.as_deref() == Some(OUT_OF_MEMORY_ERROR);
+        if timed_out {
+            self.budget_violations += 1;
+        } else {
+            self.budget_violations = 0;
+        }
+        let result = result.map_err(|err| {
+            if timed_out {
+                err.context(format!(
+                    "{function} ran over the app's {}ms execution budget",
+                    self.execution_budget.as_millis()
+                ))
+            } else if self.out_of_memory {
+                err.context(format!(
+                    "{function} ran out of memory, the app may use at most {} KiB",
+                    self.memory_limit() / 1024
+                ))
+            } else {
+                err
+            }
+        });
+        if let Err(err) = &result {
+            let data = self.user_data.get()?;
+            data.lock().unwrap().log.call_failed(function, err);
+        }
+        result
+    }
+
+    /// The display the app draws on, for handing to other runners with their own regions.
+    pub fn display(&self) -> anyhow::Result<Rc<RefCell<CompositeDisplay>>> {
+        let data = self.user_data.get()?;
+        let data = data.lock().unwrap();
+        Ok(data.display.clone())
+    }
+
+    /// A copy of what the app has drawn so far.
+    pub fn screen_buffer(&self) -> anyhow::Result<ScreenBuffer> {
+        let data = self.user_data.get()?;
+        let data = data.lock().unwrap();
+        let screen_buffer = data.display.borrow().shared_screen_buffer();
+        Ok(screen_buffer.read_snapshot())
+    }
+
+    /// Changes the colors on and off pixels are shown in, sending the rows which were recolored
+    /// straight away.
+    pub fn set_palette(&mut self, palette: MonocolorPalette) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        let data = data.lock().unwrap();
+        data.with_app_buffer(|screen_buffer| screen_buffer.set_palette(palette))?;
+        let mut composite = data.display.borrow_mut();
+        present(&data, &mut composite, vec![])?;
+        composite.render_dirty()?;
+        Ok(())
+    }
+
+    /// Blanks the app's part of the display, and its virtual screen if it has one, without
+    /// sending anything, so it starts out from an empty screen rather than whatever was shown
+    /// before it.
+    pub fn clear_screen(&mut self) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        let data = data.lock().unwrap();
+        if let Some(virtual_screen) = &data.virtual_screen {
+            virtual_screen.buffer.write().clear();
+        }
+        let mut composite = data.display.borrow_mut();
+        let mut screen_buffer = composite.screen_buffer_mut();
+        match data.region {
+            Some(region) => screen_buffer
+                .region(region.x, region.y, region.width, region.height)?
+                .clear(),
+            None => screen_buffer.clear(),
+        }
+        Ok(())
+    }
+
+    /// Replaces the app's part of the display with its name and `reason` it crashed, e.g. while
+    /// it waits to be restarted, and sends it to the display. Its virtual screen, if it has
+    /// one, is left as it was.
+    pub fn show_crash_screen(&mut self, reason: &str) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        let data = data.lock().unwrap();
+        let mut composite = data.display.borrow_mut();
+        {
+            let mut screen_buffer = composite.screen_buffer_mut();
+            let mut region = match data.region {
+                Some(region) => {
+                    screen_buffer.region(region.x, region.y, region.width, region.height)?
+                }
+                None => screen_buffer.full_region(),
+            };
+            region.clear();
+            // Red doesn't always survive being dithered onto a monocolor display
+            let reason_color = if region.is_rgb() {
+                Rgb555::RED
+            } else {
+                Rgb555::WHITE
+            };
+            region.draw_text(0, 0, &self.name, Rgb555::WHITE, Font::Small)?;
+            region.draw_text(0, 8, reason, reason_color, Font::Small)?;
+        }
+        composite.render_dirty()?;
+        Ok(())
+    }
+
+    /// Saves what the app has drawn, for putting back with [`WasmAppRunner::restore_screen`]
+    /// when switching back to it from another app. Apps with a virtual screen have it saved at
+    /// their own resolution.
+    pub fn save_screen(&self) -> anyhow::Result<Vec<u8>> {
+        let data = self.user_data.get()?;
+        let data = data.lock().unwrap();
+        Ok(data.with_app_buffer(|screen_buffer| screen_buffer.to_bytes()))
+    }
+
+    /// Puts back a screen saved with [`WasmAppRunner::save_screen`] and sends it to the
+    /// display. If it can't be restored the display is cleared instead and the error returned.
+    pub fn restore_screen(&mut self, saved: &[u8]) -> anyhow::Result<()> {
+        let data = self.user_data.get()?;
+        let data = data.lock().unwrap();
+        let restored =
+            data.with_app_buffer(|screen_buffer| screen_buffer.restore_from_bytes(saved));
+        let mut composite = data.display.borrow_mut();
+        present(&data, &mut composite, vec![])?;
+        composite.redraw()?;
+        Ok(restored?)
+    }
+
+    /// Fits the app to the display again after it has changed size, see
+    /// [`CompositeDisplay::reconfigure_panel`], and sends everything to it. Apps with a virtual
+    /// screen are scaled onto the new size, and apps which check the `display_changed` host
+    /// function are told so they can lay themselves out again. Fails if the app's region no
+    /// longer fits on the display or its virtual screen no longer fits in its part of it.
+    pub fn display_reconfigured(&mut self) -> anyhow::Result<()> {
+        {
+            let data = self.user_data.get()?;
+            let mut data = data.lock().unwrap();
+            if let Some(region) = data.region {
+                check_region_fits(region, &data.display.borrow().display_config())?;
+            }
+            let app_size = data.display_size();
+            if let Some(virtual_screen) = &mut data.virtual_screen {
+                let config = virtual_screen.buffer.read().display_config();
+                virtual_screen.mapping =
+                    ScaleMapping::new((config.width, config.height), app_size)?;
+            }
+            data.display_changed = true;
+        }
+        self.redraw()
+    }
+
+    /// Sends the full contents of the screen buffer to the display, e.g. after the device has
+    /// reconnected and lost its state.
+    pub fn redraw(&mut self) -> anyhow::Result<()> {
+        redraw(&self.user_data)
+    }
+}
+
+impl Drop for WasmAppRunner {
+    fn drop(&mut self) {
+        if let Err(err) = self.teardown() {
+            tracing::debug!("App {} couldn't tear down: {err}", self.name);
+        }
+    }
+}
+
+/// A few words on why a call into an app failed, short enough to show on the display.
+pub fn crash_reason(err: &anyhow::Error) -> String {
+    if let Some(denied) = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<PermissionDenied>())
+    {
+        return format!("No {} permission", denied.capability);
+    }
+    let root_cause = err.root_cause().to_string();
+    match root_cause.as_str() {
+        OUT_OF_MEMORY_ERROR => "Out of memory".to_owned(),
+        TIMEOUT_ERROR => "Too slow".to_owned(),
+        // Traps read like "wasm trap: wasm `unreachable` instruction executed"
+        _ => root_cause
+            .trim_start_matches("wasm trap: ")
+            .trim_start_matches("wasm ")
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned(),
+    }
+}
+
+fn check_region_fits(region: RegionBounds, config: &DisplayConfiguration) -> anyhow::Result<()> {
+    if region.right() > config.width || region.bottom() > config.height {
+        anyhow::bail!(
+            "A {}x{} region at ({}, {}) doesn't fit on the {}x{} display",
+            region.width,
+            region.height,
+            region.x,
+            region.y,
+            config.width,
+            config.height
+        );
     }
+    Ok(())
 }
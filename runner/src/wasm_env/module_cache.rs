@@ -0,0 +1,116 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Name of the wasmtime cache config the runner writes into the cache directory
+const CONFIG_FILE_NAME: &str = "wasmtime-cache.toml";
+/// Where in the cache directory wasmtime keeps compiled apps. wasmtime cleans up whatever it
+/// doesn't recognise in there, so the config is kept outside it
+const COMPILED_DIR_NAME: &str = "compiled";
+
+/// A directory apps are kept in once they've been compiled, so they only have to be compiled
+/// the first time they're loaded rather than every time their turn in the rotation comes round.
+///
+/// Compiled apps are serialized by wasmtime's own compilation cache, keyed by a hash of the
+/// app's wasm together with the version and settings of the compiler, so apps are compiled
+/// afresh whenever they or the runner change. Compiled apps which can't be read back, e.g.
+/// because the file is corrupt, are also compiled afresh and replace what was cached.
+#[derive(Debug, Clone)]
+pub struct ModuleCache {
+    dir: PathBuf,
+    config_path: PathBuf,
+}
+
+impl ModuleCache {
+    /// Keeps compiled apps in `dir`, which is created if it doesn't exist.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref().join(COMPILED_DIR_NAME))?;
+        // wasmtime only takes an absolute cache directory
+        let dir = dir.as_ref().canonicalize()?;
+        let compiled_dir = dir.join(COMPILED_DIR_NAME);
+        let mut cache = toml::Table::new();
+        cache.insert("enabled".to_owned(), true.into());
+        cache.insert(
+            "directory".to_owned(),
+            compiled_dir
+                .to_str()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} isn't valid UTF-8", compiled_dir.display()),
+                    )
+                })?
+                .into(),
+        );
+        let mut config = toml::Table::new();
+        config.insert("cache".to_owned(), cache.into());
+        let config_path = dir.join(CONFIG_FILE_NAME);
+        std::fs::write(&config_path, config.to_string())?;
+        Ok(ModuleCache { dir, config_path })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The wasmtime cache config pointing at the cache directory, for extism.
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An app which doesn't do anything, as there only has to be something to compile
+    const APP: &str = r#"(module (func (export "run") (result i32) i32.const 0))"#;
+
+    fn compile(module_cache: &ModuleCache) -> Result<extism::Plugin, extism::Error> {
+        let manifest = extism::Manifest::new([extism::Wasm::data(APP)]);
+        extism::PluginBuilder::new(manifest)
+            .with_cache_config(module_cache.config_path())
+            .build()
+    }
+
+    /// Every file wasmtime has kept compiled apps in, leaving out the stats and lock files it
+    /// keeps alongside them.
+    fn compiled_files(module_cache: &ModuleCache) -> Vec<PathBuf> {
+        let mut dirs = vec![module_cache.dir().join(COMPILED_DIR_NAME)];
+        let mut files = vec![];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().is_none()
+                    && !path.file_name().unwrap().to_string_lossy().starts_with('.')
+                {
+                    files.push(path);
+                }
+            }
+        }
+        files
+    }
+
+    #[test]
+    fn corrupt_compiled_apps_are_compiled_afresh() {
+        let dir = std::env::temp_dir().join(format!("megabit-module-cache-{}", std::process::id()));
+        let module_cache = ModuleCache::open(&dir).unwrap();
+        compile(&module_cache).unwrap();
+        let cached = compiled_files(&module_cache);
+        assert!(!cached.is_empty(), "Nothing was kept in the module cache");
+
+        for path in &cached {
+            std::fs::write(path, b"not a compiled app").unwrap();
+        }
+        let result = compile(&module_cache);
+        let replaced = cached.iter().all(|path| {
+            std::fs::read(path).is_ok_and(|contents| contents != b"not a compiled app")
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+        assert!(replaced, "The corrupt compiled app was kept in the cache");
+    }
+}
@@ -0,0 +1,103 @@
+use crate::display::{MonocolorPalette, ScreenBuffer, SharedScreenBuffer};
+use std::{collections::BTreeMap, io};
+
+/// Most memory an app's offscreen buffers may take up between them, in bytes
+const MAX_OFFSCREEN_BYTES: usize = 256 * 1024;
+
+/// Screen buffers an app draws on without them being shown, for copying onto its screen later.
+/// Each app has its own, looked up by handles which start from 1 and aren't reused. Buffers the
+/// app never destroys are freed along with the app.
+#[derive(Debug)]
+pub struct OffscreenBuffers {
+    buffers: BTreeMap<u32, SharedScreenBuffer>,
+    next_handle: u32,
+    bytes_used: usize,
+}
+
+impl OffscreenBuffers {
+    pub fn new() -> Self {
+        OffscreenBuffers {
+            buffers: BTreeMap::new(),
+            next_handle: 1,
+            bytes_used: 0,
+        }
+    }
+
+    /// Adds a blank `width` by `height` buffer, RGB with the given palette if there is one, and
+    /// returns its handle. Fails if the buffer is empty or would take the app over its memory
+    /// limit.
+    pub fn create(
+        &mut self,
+        width: usize,
+        height: usize,
+        palette: Option<MonocolorPalette>,
+    ) -> io::Result<u32> {
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Can't create an empty {width}x{height} offscreen buffer"),
+            ));
+        }
+        let bytes = buffer_bytes(width, height, palette.is_some());
+        if bytes > MAX_OFFSCREEN_BYTES - self.bytes_used {
+            return Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                format!(
+                    "A {width}x{height} offscreen buffer needs {bytes} bytes but only {} of the \
+                     {MAX_OFFSCREEN_BYTES} allowed are left",
+                    MAX_OFFSCREEN_BYTES - self.bytes_used
+                ),
+            ));
+        }
+        let handle = self.next_handle;
+        self.next_handle = self
+            .next_handle
+            .checked_add(1)
+            .ok_or(io::ErrorKind::OutOfMemory)?;
+        self.buffers.insert(
+            handle,
+            SharedScreenBuffer::new(ScreenBuffer::new(width, height, palette)),
+        );
+        self.bytes_used += bytes;
+        Ok(handle)
+    }
+
+    pub fn get(&self, handle: u32) -> io::Result<&SharedScreenBuffer> {
+        self.buffers.get(&handle).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("There's no offscreen buffer {handle}"),
+            )
+        })
+    }
+
+    pub fn destroy(&mut self, handle: u32) -> io::Result<()> {
+        let buffer = self.buffers.remove(&handle).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("There's no offscreen buffer {handle} to destroy"),
+            )
+        })?;
+        let config = buffer.read().display_config();
+        self.bytes_used -= buffer_bytes(config.width, config.height, config.is_rgb());
+        Ok(())
+    }
+}
+
+impl Drop for OffscreenBuffers {
+    fn drop(&mut self) {
+        if !self.buffers.is_empty() {
+            tracing::debug!(
+                "Freeing {} offscreen buffers the app didn't destroy",
+                self.buffers.len()
+            );
+        }
+    }
+}
+
+/// Roughly how much memory a buffer takes up. RGB buffers keep track of which of their pixels
+/// are palette colors as well as the colors themselves.
+fn buffer_bytes(width: usize, height: usize, is_rgb: bool) -> usize {
+    let pixel_bytes = if is_rgb { 3 } else { 1 };
+    width.saturating_mul(height).saturating_mul(pixel_bytes)
+}
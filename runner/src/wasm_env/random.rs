@@ -0,0 +1,157 @@
+use std::io;
+
+/// Most bytes an app can ask for at once
+const MAX_RANDOM_BYTES: usize = 64 * 1024;
+
+/// A SplitMix64 generator for an app's random numbers. It's defined here rather than taken from a
+/// crate so the numbers a seed gives never change between versions of the runner, which keeps
+/// runs with a fixed seed reproducible.
+///
+/// Each app instance has its own generator, so two instances never share a stream, even when
+/// they're given the same seed.
+#[derive(Debug, Clone)]
+pub struct AppRng {
+    seed: u64,
+    state: u64,
+}
+
+impl AppRng {
+    pub fn new(seed: u64) -> Self {
+        AppRng { seed, state: seed }
+    }
+
+    /// Seeds a generator from the OS's entropy source.
+    pub fn from_entropy() -> io::Result<Self> {
+        let mut seed = [0; 8];
+        getrandom::getrandom(&mut seed).map_err(io::Error::other)?;
+        Ok(AppRng::new(u64::from_le_bytes(seed)))
+    }
+
+    /// The seed the generator started from, for reproducing a run.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+        value ^ (value >> 31)
+    }
+
+    /// `len` random bytes. Fails if more than 64 KiB are asked for at once.
+    pub fn bytes(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        if len > MAX_RANDOM_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Can't give more than {MAX_RANDOM_BYTES} random bytes at once, {len} were \
+                     asked for"
+                ),
+            ));
+        }
+        let mut bytes = Vec::with_capacity(len.next_multiple_of(8));
+        while bytes.len() < len {
+            bytes.extend_from_slice(&self.next_u64().to_le_bytes());
+        }
+        bytes.truncate(len);
+        Ok(bytes)
+    }
+
+    /// A number from `low` up to but not including `high`, with every number equally likely.
+    /// Fails if the range is empty.
+    pub fn u32_in_range(&mut self, low: u32, high: u32) -> io::Result<u32> {
+        if low >= high {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Can't pick a random number from the empty range {low}..{high}"),
+            ));
+        }
+        let span = u64::from(high - low);
+        // Numbers past the last whole multiple of the span are drawn again, or the low end of
+        // the range would come up slightly more often
+        let limit = u64::MAX - u64::MAX % span;
+        loop {
+            let value = self.next_u64();
+            if value < limit {
+                return Ok(low + (value % span) as u32);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(rng: &mut AppRng) -> Vec<u64> {
+        (0..1000).map(|_| rng.next_u64()).collect()
+    }
+
+    #[test]
+    fn seeds_give_the_same_numbers_every_time() {
+        assert_eq!(stream(&mut AppRng::new(42)), stream(&mut AppRng::new(42)));
+        assert_ne!(stream(&mut AppRng::new(42)), stream(&mut AppRng::new(43)));
+        // The reference SplitMix64 output, which runs with a fixed seed depend on never changing
+        let mut rng = AppRng::new(0);
+        assert_eq!(rng.next_u64(), 0xe220a8397b1dcdaf);
+        assert_eq!(rng.next_u64(), 0x6e789e6aa1b965f4);
+        assert_eq!(rng.seed(), 0);
+    }
+
+    #[test]
+    fn generators_do_not_share_a_stream() {
+        // Like two instances of an app given the same seed
+        let mut untouched = AppRng::new(42);
+        let expected = stream(&mut untouched.clone());
+        let mut busy = AppRng::new(42);
+        stream(&mut busy);
+        assert_eq!(stream(&mut untouched), expected);
+
+        let (mut first, mut second) = (
+            AppRng::from_entropy().unwrap(),
+            AppRng::from_entropy().unwrap(),
+        );
+        assert_ne!(first.seed(), second.seed());
+        assert_ne!(stream(&mut first), stream(&mut second));
+    }
+
+    #[test]
+    fn ranges_are_evenly_covered() {
+        let mut rng = AppRng::new(7);
+        let mut counts = [0usize; 6];
+        for _ in 0..60_000 {
+            counts[(rng.u32_in_range(10, 16).unwrap() - 10) as usize] += 1;
+        }
+        assert!(
+            counts.iter().all(|&count| (9000..11000).contains(&count)),
+            "{counts:?}"
+        );
+        assert_eq!(rng.u32_in_range(0, 1).unwrap(), 0);
+        assert!(rng.u32_in_range(u32::MAX - 1, u32::MAX).is_ok());
+    }
+
+    #[test]
+    fn empty_ranges_are_rejected() {
+        let mut rng = AppRng::new(7);
+        for (low, high) in [(5, 5), (6, 5), (u32::MAX, 0)] {
+            let err = rng.u32_in_range(low, high).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{low}..{high}");
+        }
+    }
+
+    #[test]
+    fn bytes_are_limited_to_64_kib_at_once() {
+        let mut rng = AppRng::new(7);
+        for len in [0, 1, 13, MAX_RANDOM_BYTES] {
+            assert_eq!(rng.bytes(len).unwrap().len(), len);
+        }
+        let err = rng.bytes(MAX_RANDOM_BYTES + 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        // The bytes come from the same stream as the numbers
+        let mut first = AppRng::new(3);
+        let mut second = AppRng::new(3);
+        assert_eq!(first.bytes(8).unwrap(), second.next_u64().to_le_bytes());
+    }
+}
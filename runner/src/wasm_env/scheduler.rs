@@ -0,0 +1,833 @@
+use super::{crash_reason, watcher::AppWatcher, AppOptions, Slot, WasmAppRunner};
+use crate::{
+    display::{CompositeDisplay, Transition},
+    serial::InputEvent,
+};
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// How often to check in on an app which doesn't ask to be run periodically
+const IDLE_PERIOD: Duration = Duration::from_millis(100);
+/// How long to wait before restarting an app which has crashed, doubled each time it crashes
+/// again while it's being shown
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+/// How many times an app may crash while it's being shown before it's given up on until its
+/// next turn
+const MAX_CRASHES: u32 = 3;
+/// How long the next app button has to be held to switch apps
+const LONG_PRESS: Duration = Duration::from_secs(1);
+/// How long apps which are left out of the rotation while idle are skipped for after a turn
+/// which ended with them idle, before being given another to check whether they still are
+const IDLE_RECHECK: Duration = Duration::from_secs(5 * 60);
+
+/// The apps to cycle through, in order
+#[derive(Debug, Clone)]
+pub struct AppRotation {
+    pub entries: Vec<RotationEntry>,
+}
+
+/// An app in the rotation and how long it's shown for each time round
+#[derive(Debug, Clone, PartialEq)]
+pub struct RotationEntry {
+    /// Directory containing the app's manifest
+    pub path: PathBuf,
+    /// How long the app is shown before moving on to the next, or forever if not set
+    pub duration: Option<Duration>,
+    /// Whether the app is shown at all, so it can be taken out of the rotation without losing
+    /// its place
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RotationSchema {
+    apps: Vec<RotationEntrySchema>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RotationEntrySchema {
+    path: PathBuf,
+    duration_secs: f64,
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl AppRotation {
+    /// A rotation of just the one app, shown forever.
+    pub fn single(app_path: impl Into<PathBuf>) -> Self {
+        AppRotation {
+            entries: vec![RotationEntry {
+                path: app_path.into(),
+                duration: None,
+                enabled: true,
+            }],
+        }
+    }
+
+    /// Reads a rotation from a TOML file listing the apps in the order they're shown, like
+    ///
+    /// ```toml
+    /// [[apps]]
+    /// path = "clock"
+    /// duration_secs = 30
+    ///
+    /// [[apps]]
+    /// path = "weather"
+    /// duration_secs = 20
+    /// enabled = false
+    /// ```
+    ///
+    /// Relative paths are taken from the directory the file is in.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|err| {
+            anyhow::anyhow!("Failed to read the rotation at {}: {err}", path.display())
+        })?;
+        let rotation = toml::from_str::<RotationSchema>(&contents).map_err(|err| {
+            anyhow::anyhow!("Failed to parse the rotation at {}: {err}", path.display())
+        })?;
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        let entries = rotation
+            .apps
+            .into_iter()
+            .map(|app| {
+                let duration = Duration::try_from_secs_f64(app.duration_secs)
+                    .ok()
+                    .filter(|duration| !duration.is_zero())
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "{} has a duration of {}s, it must be more than 0",
+                            app.path.display(),
+                            app.duration_secs
+                        )
+                    })?;
+                Ok(RotationEntry {
+                    path: base_dir.join(app.path),
+                    duration: Some(duration),
+                    enabled: app.enabled,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(AppRotation { entries })
+    }
+}
+
+/// Counts of how an app has fared over every time it has been shown
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AppStats {
+    /// Times the app has crashed, including by running out of memory
+    pub crashes: u64,
+    /// Times the app was restarted after crashing
+    pub restarts: u64,
+    /// Times the app was run after having been run before without crashing in between
+    pub frames: u64,
+    /// Time from the start of each of those runs back to the start of the one before
+    pub frame_time: Duration,
+}
+
+impl AppStats {
+    /// How many times a second the app has been run on average while it was running
+    /// periodically, if it ever has.
+    pub fn fps(&self) -> Option<f64> {
+        (!self.frame_time.is_zero()).then(|| self.frames as f64 / self.frame_time.as_secs_f64())
+    }
+}
+
+impl fmt::Display for AppStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} crashes, {} restarts", self.crashes, self.restarts)?;
+        if let Some(fps) = self.fps() {
+            write!(f, ", {fps:.1} fps")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the button kept for switching apps is in being pressed
+#[derive(Debug, Clone, Copy)]
+enum NextAppButton {
+    Released,
+    /// Held down since then, and not yet for long enough to switch apps
+    Pressed(Instant),
+    /// Held down for long enough that apps have been switched, and not let go of yet
+    LongPressed,
+}
+
+/// The app being shown and its turn on the display
+struct CurrentApp {
+    runner: WasmAppRunner,
+    path: PathBuf,
+    /// Shared with the app, which can ask about and extend it
+    slot: Rc<RefCell<Slot>>,
+    /// Watches the app's files if it's reloaded when they change
+    watcher: Option<AppWatcher>,
+    /// When to restart the app, if it has crashed
+    restart_at: Option<Instant>,
+    /// How many times the app has crashed since it was switched to
+    crash_count: u32,
+    /// When the app was last run, unless it has crashed or been reloaded since
+    last_frame_at: Option<Instant>,
+    /// When the app is next due to run, if it runs periodically
+    next_frame_at: Option<Instant>,
+}
+
+/// An app kept running in the background after being switched away from, so it can be switched
+/// back to without loading and setting it up again
+struct WarmApp {
+    runner: WasmAppRunner,
+    path: PathBuf,
+    /// What the app had drawn when it was switched away from
+    screen: Vec<u8>,
+}
+
+/// Cycles the display through a rotation of apps, showing each for its own amount of time.
+/// Only the app being shown is loaded, with each app loaded afresh when its turn comes round
+/// and torn down when it's over, unless a few are kept warm, see
+/// [`AppScheduler::set_warm_instances`]. Apps which fail to load are skipped until their next turn.
+/// Apps which crash show an error screen and are restarted after a backoff, and are skipped
+/// until their next turn if they keep crashing. Apps can give up the rest of their turn, unless
+/// every app before them has just done the same, so a rotation of apps which all give up their
+/// turns straight away still shows each for its full turn in between rather than switching
+/// between them as fast as they can be loaded.
+pub struct AppScheduler {
+    display: Rc<RefCell<CompositeDisplay>>,
+    rotation: AppRotation,
+    options: AppOptions,
+    /// How to switch between apps which don't choose their own transition
+    default_transition: Transition,
+    current: Option<CurrentApp>,
+    /// Where in the rotation to start looking for the next app
+    next_index: usize,
+    /// Whether apps are reloaded when their files change
+    hot_reload: bool,
+    /// Keyed by app name
+    stats: BTreeMap<String, AppStats>,
+    /// The button which switches to the next app when held down, if any
+    next_app_button: Option<u8>,
+    next_app_button_state: NextAppButton,
+    /// How many turns in a row have been given up by the app they were for
+    yields_in_a_row: usize,
+    /// When each app which is left out of the rotation while idle last finished a turn idle
+    idle_since: BTreeMap<PathBuf, Instant>,
+    /// Apps kept running after being switched away from, least recently shown first
+    warm: VecDeque<WarmApp>,
+    max_warm: usize,
+}
+
+impl AppScheduler {
+    pub fn new(
+        display: Rc<RefCell<CompositeDisplay>>,
+        rotation: AppRotation,
+        options: AppOptions,
+        default_transition: Transition,
+    ) -> Self {
+        AppScheduler {
+            display,
+            rotation,
+            options,
+            default_transition,
+            current: None,
+            next_index: 0,
+            hot_reload: false,
+            stats: BTreeMap::new(),
+            next_app_button: None,
+            next_app_button_state: NextAppButton::Released,
+            yields_in_a_row: 0,
+            idle_since: BTreeMap::new(),
+            warm: VecDeque::new(),
+            max_warm: 0,
+        }
+    }
+
+    /// Reloads the app being shown whenever its manifest or wasm file changes, e.g. while it's
+    /// being developed. Takes effect from the next app switched to.
+    pub fn set_hot_reload(&mut self, hot_reload: bool) {
+        self.hot_reload = hot_reload;
+    }
+
+    /// Keeps up to `count` apps running in the background after they've been switched away
+    /// from, rather than tearing them down, so switching back to them puts back what they'd
+    /// drawn straight away instead of loading and setting them up again. The least recently
+    /// shown is torn down once there are more. Apps which crashed or had to be moved on from
+    /// are always torn down, as is every app while hot reloading, so changes to apps aren't
+    /// missed while they're in the background.
+    pub fn set_warm_instances(&mut self, count: usize) {
+        self.max_warm = count;
+        self.drop_warm(|_| true);
+    }
+
+    /// Keeps `button` for the runner, switching to the next app whenever it's held down for a
+    /// second whether or not the app being shown takes input. Presses which are let go of
+    /// sooner are passed on to the app once they're let go of. Firmware which only reports
+    /// presses, rather than presses and releases, can't hold buttons down.
+    pub fn set_next_app_button(&mut self, button: Option<u8>) {
+        self.next_app_button = button;
+        self.next_app_button_state = NextAppButton::Released;
+    }
+
+    /// Passes input from the device on to the app being shown, unless it's for the next app
+    /// button. Input is dropped if no app is being shown.
+    pub fn handle_input(&mut self, event: InputEvent, now: Instant) {
+        let events = match (event, self.next_app_button) {
+            (InputEvent::ButtonPressed { button }, Some(next_app_button))
+                if button == next_app_button =>
+            {
+                self.next_app_button_state = NextAppButton::Pressed(now);
+                vec![]
+            }
+            (InputEvent::ButtonReleased { button }, Some(next_app_button))
+                if button == next_app_button =>
+            {
+                match std::mem::replace(&mut self.next_app_button_state, NextAppButton::Released) {
+                    NextAppButton::Pressed(_) => vec![InputEvent::ButtonPressed { button }, event],
+                    NextAppButton::Released | NextAppButton::LongPressed => vec![],
+                }
+            }
+            _ => vec![event],
+        };
+        let Some(current) = &mut self.current else {
+            tracing::trace!("Dropped input with no app running: {event:?}");
+            return;
+        };
+        for event in events {
+            if let Err(err) = current.runner.queue_input(event) {
+                tracing::warn!("Failed to pass input on to the app: {err}");
+            }
+        }
+    }
+
+    /// The app being shown, if any.
+    pub fn app(&mut self) -> Option<&mut WasmAppRunner> {
+        self.current.as_mut().map(|current| &mut current.runner)
+    }
+
+    pub fn display(&self) -> Rc<RefCell<CompositeDisplay>> {
+        self.display.clone()
+    }
+
+    /// How each app which has been shown has fared, keyed by app name.
+    pub fn stats(&self) -> &BTreeMap<String, AppStats> {
+        &self.stats
+    }
+
+    /// How long to wait from `now` before calling [`AppScheduler::poll`] and
+    /// [`AppScheduler::run_once`] again. Apps are run every refresh period counting from when
+    /// they were last due to run rather than from when they finished, so the time they take to
+    /// update and render doesn't make them drift. There's no wait at all after a run which went
+    /// over, so the app can catch up, unless it's a whole period behind, in which case the
+    /// frames it missed are skipped. The wait never goes past the end of the app's turn, so
+    /// apps are switched away from when they've been told they will be.
+    pub fn time_until_next_frame(&self, now: Instant) -> Duration {
+        let Some(current) = &self.current else {
+            return IDLE_PERIOD;
+        };
+        let until_next_frame = match current.next_frame_at {
+            Some(next_frame_at) => next_frame_at.saturating_duration_since(now),
+            None => self.refresh_period(),
+        };
+        match current.slot.borrow().remaining(now) {
+            Some(remaining) => until_next_frame.min(remaining),
+            None => until_next_frame,
+        }
+    }
+
+    /// The app's refresh period if it has one, or how often to check in on it if not.
+    pub fn refresh_period(&self) -> Duration {
+        self.current
+            .as_ref()
+            .and_then(|current| current.runner.refresh_period())
+            .unwrap_or(IDLE_PERIOD)
+    }
+
+    /// Switches to the next app if the current one's time is up, or if nothing is being shown,
+    /// and reloads the current one if its files have changed or it's due to be restarted after
+    /// crashing, or if the next app button has been held down. Fails if no app in the rotation
+    /// could be started.
+    pub fn poll(&mut self, now: Instant) -> anyhow::Result<()> {
+        if let NextAppButton::Pressed(pressed_at) = self.next_app_button_state {
+            if now.duration_since(pressed_at) >= LONG_PRESS {
+                tracing::info!("Next app button held down, switching apps");
+                self.next_app_button_state = NextAppButton::LongPressed;
+                self.switch_to_next(false)?;
+            }
+        }
+        let restart_due = self
+            .current
+            .as_ref()
+            .and_then(|current| current.restart_at)
+            .is_some_and(|restart_at| now >= restart_at);
+        if restart_due {
+            self.restart_current()?;
+        }
+        let files_changed = self
+            .current
+            .as_mut()
+            .and_then(|current| current.watcher.as_mut())
+            .is_some_and(|watcher| watcher.poll(now));
+        if files_changed {
+            self.reload_current();
+        }
+        let time_is_up = match &self.current {
+            Some(current) => current.slot.borrow().is_over(now),
+            None => true,
+        };
+        if time_is_up {
+            let yielded = self
+                .current
+                .as_ref()
+                .filter(|current| current.slot.borrow().was_yielded());
+            if let Some(current) = yielded {
+                tracing::info!("App {} gave up the rest of its turn", current.runner.name());
+                self.yields_in_a_row += 1;
+            } else {
+                self.yields_in_a_row = 0;
+            }
+            self.switch_to_next(false)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the app once if it asks to be run periodically, restarting it after a while if it
+    /// crashes and moving on to the next app if it keeps running over its execution budget.
+    /// Fails if the app had to be moved on from and no other app in the rotation could be
+    /// started.
+    pub fn run_once(&mut self) -> anyhow::Result<()> {
+        let Some(current) = &mut self.current else {
+            return Ok(());
+        };
+        let refresh_period = current.runner.refresh_period();
+        let (Some(refresh_period), None) = (refresh_period, current.restart_at) else {
+            // Waiting on the app to be restarted or to set a refresh period
+            current.next_frame_at = None;
+            return Ok(());
+        };
+        let now = Instant::now();
+        current.next_frame_at = Some(match current.next_frame_at {
+            Some(due) if now.saturating_duration_since(due) < refresh_period => {
+                due + refresh_period
+            }
+            _ => now + refresh_period,
+        });
+        let last_frame_at = current.last_frame_at.take();
+        match current.runner.run_app_once() {
+            Ok(()) => {
+                current.last_frame_at = Some(now);
+                if let Some(last_frame_at) = last_frame_at {
+                    let stats = self
+                        .stats
+                        .entry(current.runner.name().to_owned())
+                        .or_default();
+                    stats.frames += 1;
+                    stats.frame_time += now.duration_since(last_frame_at);
+                }
+            }
+            Err(_) if current.runner.is_misbehaving() => {
+                tracing::error!(
+                    "App {} keeps running over its execution budget, moving on",
+                    current.runner.name()
+                );
+                self.switch_to_next(true)?;
+            }
+            // Apps which run over their budget only once may just have been held up, e.g. by a
+            // slow render, so they aren't counted as crashing
+            Err(_) if current.runner.ran_over_budget() => {}
+            Err(err) => self.crashed(&crash_reason(&err))?,
+        }
+        Ok(())
+    }
+
+    /// Fits the app to the display again after it has changed size, moving on to the next app
+    /// if it no longer fits. Apps kept running in the background are torn down, as they were
+    /// set up for the old size. Fails if no app in the rotation could be started.
+    pub fn display_reconfigured(&mut self) -> anyhow::Result<()> {
+        self.drop_warm(|_| false);
+        let Some(current) = &mut self.current else {
+            return Ok(());
+        };
+        if let Err(err) = current.runner.display_reconfigured() {
+            tracing::error!(
+                "App {} can't run on the new display: {err}, moving on",
+                current.runner.name()
+            );
+            self.switch_to_next(true)?;
+        }
+        Ok(())
+    }
+
+    /// Tears down the app being shown and any kept running in the background, e.g. when the
+    /// runner is shutting down.
+    pub fn stop(&mut self) {
+        if let Some(mut current) = self.current.take() {
+            tear_down(&mut current.runner);
+        }
+        self.drop_warm(|_| false);
+    }
+
+    /// Replaces the rotation, e.g. after its file has been edited. The app being shown carries
+    /// on with its new duration if it's still in the rotation, counting from when it was
+    /// switched to, and otherwise the next app is switched to straight away.
+    pub fn set_rotation(&mut self, rotation: AppRotation) -> anyhow::Result<()> {
+        self.rotation = rotation;
+        let entries = self.rotation.entries.clone();
+        self.drop_warm(|path| {
+            entries
+                .iter()
+                .any(|entry| entry.enabled && entry.path == path)
+        });
+        let Some(current) = &mut self.current else {
+            self.next_index = 0;
+            return Ok(());
+        };
+        let position = self
+            .rotation
+            .entries
+            .iter()
+            .position(|entry| entry.enabled && entry.path == current.path);
+        match position {
+            Some(index) => {
+                current
+                    .slot
+                    .borrow_mut()
+                    .set_duration(self.rotation.entries[index].duration);
+                self.next_index = index + 1;
+            }
+            None => {
+                self.next_index = 0;
+                self.switch_to_next(true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Tears down the app being shown and starts the next enabled app in the rotation, trying
+    /// the ones after it in turn if it fails to load. Apps which are idle are only tried once
+    /// every other app has been. The current app keeps going if it's the only one enabled,
+    /// unless `skip_current` is set because it can't carry on.
+    fn switch_to_next(&mut self, skip_current: bool) -> anyhow::Result<()> {
+        let now = Instant::now();
+        if let Some(current) = &self.current {
+            if current.runner.skip_if_idle() && current.runner.is_idle() {
+                tracing::info!(
+                    "App {} is idle, skipping it for {IDLE_RECHECK:?}",
+                    current.runner.name()
+                );
+                self.idle_since.insert(current.path.clone(), now);
+            } else {
+                self.idle_since.remove(&current.path);
+            }
+        }
+        let count = self.rotation.entries.len();
+        let (awake, idle): (Vec<_>, Vec<_>) = (0..count)
+            .map(|offset| (self.next_index + offset) % count)
+            .filter(|&index| self.rotation.entries[index].enabled)
+            .partition(|&index| !self.is_idle(&self.rotation.entries[index].path, now));
+        for index in awake.into_iter().chain(idle) {
+            let entry = self.rotation.entries[index].clone();
+            if let Some(current) = &self.current {
+                if current.path == entry.path {
+                    if skip_current {
+                        continue;
+                    }
+                    let slot = self.new_slot(&entry, current.runner.max_slot_extension());
+                    *current.slot.borrow_mut() = slot;
+                    self.next_index = index + 1;
+                    return Ok(());
+                }
+            }
+            match self.start(&entry, !skip_current) {
+                Ok(()) => {
+                    self.next_index = index + 1;
+                    return Ok(());
+                }
+                Err(err) => tracing::error!(
+                    "Failed to start the app in {}: {err:#}, skipping it",
+                    entry.path.display()
+                ),
+            }
+        }
+        self.stop();
+        anyhow::bail!("None of the apps in the rotation could be started")
+    }
+
+    /// Tears down the apps kept running in the background which `keep` turns down, along with
+    /// the least recently shown of those it keeps if there are too many.
+    fn drop_warm(&mut self, keep: impl Fn(&Path) -> bool) {
+        let mut kept = VecDeque::new();
+        for mut warm in std::mem::take(&mut self.warm) {
+            if keep(&warm.path) {
+                kept.push_back(warm);
+            } else {
+                tear_down(&mut warm.runner);
+            }
+        }
+        while kept.len() > self.max_warm {
+            if let Some(mut warm) = kept.pop_front() {
+                tear_down(&mut warm.runner);
+            }
+        }
+        self.warm = kept;
+    }
+
+    /// Switches away from the app being shown, keeping it running in the background if
+    /// `keep_warm` is set and there's room for it, or tearing it down otherwise.
+    fn put_away_current(&mut self, keep_warm: bool) {
+        let Some(mut current) = self.current.take() else {
+            return;
+        };
+        if !keep_warm || self.max_warm == 0 || self.hot_reload || current.restart_at.is_some() {
+            tear_down(&mut current.runner);
+            return;
+        }
+        let saved = current.runner.save_screen().and_then(|screen| {
+            current.runner.clear_input()?;
+            Ok(screen)
+        });
+        match saved {
+            Ok(screen) => {
+                self.warm.push_back(WarmApp {
+                    runner: current.runner,
+                    path: current.path,
+                    screen,
+                });
+                self.drop_warm(|_| true);
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to keep app {} running in the background: {err}",
+                    current.runner.name()
+                );
+                tear_down(&mut current.runner);
+            }
+        }
+    }
+
+    /// Whether the app in `path` is being left out of the rotation at `now` for being idle.
+    fn is_idle(&self, path: &Path, now: Instant) -> bool {
+        self.idle_since
+            .get(path)
+            .is_some_and(|&idle_since| now.duration_since(idle_since) < IDLE_RECHECK)
+    }
+
+    /// A turn for the app in `entry` starting now, which can be given up unless every enabled
+    /// app has just given up theirs.
+    fn new_slot(&self, entry: &RotationEntry, max_extension: Duration) -> Slot {
+        let mut slot = Slot::new(Instant::now(), entry.duration, max_extension);
+        let enabled = self
+            .rotation
+            .entries
+            .iter()
+            .filter(|entry| entry.enabled)
+            .count();
+        slot.set_can_yield(self.yields_in_a_row < enabled);
+        slot
+    }
+
+    /// Replaces the app being shown with a fresh instance loaded from its files, which is set
+    /// up on a blank screen. The old instance is only torn down once the new one is set up, so
+    /// if the new one can't be loaded or set up the old one is kept running with its screen put
+    /// back as it was.
+    fn reload_current(&mut self) {
+        let Some(current) = &mut self.current else {
+            return;
+        };
+        tracing::info!("{} changed, reloading it", current.path.display());
+        let saved = current.runner.save_screen();
+        match instantiate(
+            &current.path,
+            self.display.clone(),
+            &self.options,
+            &current.slot,
+        ) {
+            Ok(runner) => {
+                match runner.version() {
+                    Some(version) => tracing::info!("Reloaded app: {} {version}", runner.name()),
+                    None => tracing::info!("Reloaded app: {}", runner.name()),
+                }
+                tear_down(&mut std::mem::replace(&mut current.runner, runner));
+                current.restart_at = None;
+                current.last_frame_at = None;
+            }
+            Err(err) => {
+                tracing::error!(
+                    "FAILED TO RELOAD {}, keeping the old version of {} running: {err:#}",
+                    current.path.display(),
+                    current.runner.name()
+                );
+                if let Err(err) = saved.and_then(|saved| current.runner.restore_screen(&saved)) {
+                    tracing::warn!("Failed to put the app's screen back: {err}");
+                }
+            }
+        }
+    }
+
+    /// Shows that the app being shown has crashed and schedules it to be restarted, backing off
+    /// further each time it crashes again, or moves on to the next app if it has crashed too
+    /// many times. Fails if no other app in the rotation could be started.
+    fn crashed(&mut self, reason: &str) -> anyhow::Result<()> {
+        let Some(current) = &mut self.current else {
+            return Ok(());
+        };
+        current.crash_count += 1;
+        let name = current.runner.name().to_owned();
+        self.stats.entry(name.clone()).or_default().crashes += 1;
+        if current.crash_count >= MAX_CRASHES {
+            tracing::error!(
+                "App {name} crashed ({reason}) {MAX_CRASHES} times in a row, giving up on it \
+                 until its next turn"
+            );
+            return self.switch_to_next(true);
+        }
+        let backoff = RESTART_BACKOFF
+            .saturating_mul(2u32.saturating_pow(current.crash_count - 1))
+            .min(MAX_RESTART_BACKOFF);
+        tracing::warn!("App {name} crashed ({reason}), restarting it in {backoff:?}");
+        tear_down(&mut current.runner);
+        if let Err(err) = current.runner.show_crash_screen(reason) {
+            tracing::warn!("Failed to show that the app crashed: {err}");
+        }
+        current.restart_at = Some(Instant::now() + backoff);
+        Ok(())
+    }
+
+    /// Replaces the crashed app being shown with a fresh instance, set up on a blank screen.
+    /// Fails if it crashed again and no other app in the rotation could be started.
+    fn restart_current(&mut self) -> anyhow::Result<()> {
+        let Some(current) = &mut self.current else {
+            return Ok(());
+        };
+        tracing::info!("Restarting app {}", current.runner.name());
+        self.stats
+            .entry(current.runner.name().to_owned())
+            .or_default()
+            .restarts += 1;
+        match instantiate(
+            &current.path,
+            self.display.clone(),
+            &self.options,
+            &current.slot,
+        ) {
+            Ok(runner) => {
+                current.runner = runner;
+                current.restart_at = None;
+                Ok(())
+            }
+            Err(err) => {
+                tracing::error!("Failed to restart app {}: {err:#}", current.runner.name());
+                self.crashed(&crash_reason(&err))
+            }
+        }
+    }
+
+    /// Loads the app in `entry` and sets it up, or switches back to it if it was kept running
+    /// in the background, animating the display over to it from whatever was shown before, and
+    /// makes it the current app. The outgoing app is put away before the new one is loaded,
+    /// and kept running in the background if `keep_outgoing_warm` is set and there's room.
+    /// Fails if the app can't be loaded, while apps which crash while being set up are shown
+    /// as crashed and restarted like any other crash.
+    fn start(&mut self, entry: &RotationEntry, keep_outgoing_warm: bool) -> anyhow::Result<()> {
+        let outgoing = self.display.borrow().shared_screen_buffer().read_snapshot();
+        // Taken out before the outgoing app is put away, so it can't make room for it
+        let warm = self
+            .warm
+            .iter()
+            .position(|warm| warm.path == entry.path)
+            .and_then(|index| self.warm.remove(index));
+        self.put_away_current(keep_outgoing_warm);
+        let (mut runner, saved_screen) = match warm {
+            Some(warm) => {
+                tracing::info!("Switching back to app: {}", warm.runner.name());
+                (warm.runner, Some(warm.screen))
+            }
+            None => {
+                let runner = WasmAppRunner::load(&entry.path, self.display.clone(), &self.options)?;
+                match runner.version() {
+                    Some(version) => tracing::info!("Running app: {} {version}", runner.name()),
+                    None => tracing::info!("Running app: {}", runner.name()),
+                }
+                (runner, None)
+            }
+        };
+        self.stats.entry(runner.name().to_owned()).or_default();
+        let slot = Rc::new(RefCell::new(
+            self.new_slot(entry, runner.max_slot_extension()),
+        ));
+        runner.set_slot(slot.clone())?;
+        let transition = runner.transition(self.default_transition);
+        let setup = match saved_screen {
+            Some(screen) => {
+                if let Err(err) = runner.resume_with_transition(&outgoing, &screen, transition) {
+                    tracing::warn!("Failed to put the app's screen back: {err}");
+                }
+                Ok(())
+            }
+            None => {
+                runner.clear_screen()?;
+                runner.setup_with_transition(&outgoing, transition)
+            }
+        };
+        // The app's turn starts once it's on the display
+        slot.borrow_mut().set_started_at(Instant::now());
+        let watcher = if self.hot_reload {
+            AppWatcher::new(&entry.path)
+                .inspect_err(|err| {
+                    tracing::warn!(
+                        "Failed to watch {} for changes, it won't be reloaded: {err}",
+                        entry.path.display()
+                    )
+                })
+                .ok()
+        } else {
+            None
+        };
+        self.current = Some(CurrentApp {
+            runner,
+            path: entry.path.clone(),
+            slot,
+            watcher,
+            restart_at: None,
+            crash_count: 0,
+            last_frame_at: None,
+            next_frame_at: None,
+        });
+        match setup {
+            Ok(()) => Ok(()),
+            Err(err) => self.crashed(&crash_reason(&err)),
+        }
+    }
+}
+
+/// Loads a new instance of the app in `path` and sets it up on a blank screen, carrying on with
+/// the turn in `slot`.
+fn instantiate(
+    path: &Path,
+    display: Rc<RefCell<CompositeDisplay>>,
+    options: &AppOptions,
+    slot: &Rc<RefCell<Slot>>,
+) -> anyhow::Result<WasmAppRunner> {
+    let mut runner = WasmAppRunner::load(path, display, options)?;
+    // The app's manifest may have changed how much it can extend its turn by
+    slot.borrow_mut()
+        .set_max_extension(runner.max_slot_extension());
+    runner.set_slot(slot.clone())?;
+    runner.clear_screen()?;
+    runner.setup_app()?;
+    Ok(runner)
+}
+
+/// Tears `runner` down, logging rather than failing if it can't, e.g. because it has crashed.
+fn tear_down(runner: &mut WasmAppRunner) {
+    if let Err(err) = runner.teardown() {
+        tracing::warn!("App {} failed to tear down: {err}", runner.name());
+    }
+}
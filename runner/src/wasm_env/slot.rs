@@ -0,0 +1,163 @@
+use std::time::{Duration, Instant};
+
+/// An app's turn on the display, which the scheduler switches away from once it's over and the
+/// app can ask about and extend through its host functions. Shared between the two so they
+/// agree on when the switch happens.
+#[derive(Debug, Clone)]
+pub struct Slot {
+    started_at: Instant,
+    /// How long the turn was given for, or forever if not set
+    duration: Option<Duration>,
+    /// How much longer the app has asked for and been given
+    extended_by: Duration,
+    /// Most the app may extend its turn by altogether
+    max_extension: Duration,
+    /// When the app gave up the rest of its turn, if it has
+    yielded_at: Option<Instant>,
+    /// Whether the app may give up the rest of its turn
+    can_yield: bool,
+}
+
+impl Slot {
+    pub fn new(started_at: Instant, duration: Option<Duration>, max_extension: Duration) -> Self {
+        Slot {
+            started_at,
+            duration,
+            extended_by: Duration::ZERO,
+            max_extension,
+            yielded_at: None,
+            can_yield: true,
+        }
+    }
+
+    /// How long the turn lasts including any extensions, or `None` if it lasts forever.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration.map(|duration| duration + self.extended_by)
+    }
+
+    /// When the turn is over, or `None` if it lasts forever.
+    pub fn ends_at(&self) -> Option<Instant> {
+        self.yielded_at
+            .or_else(|| self.duration().map(|duration| self.started_at + duration))
+    }
+
+    /// How much of the turn is left at `now`, or `None` if it lasts forever.
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.ends_at()
+            .map(|ends_at| ends_at.saturating_duration_since(now))
+    }
+
+    pub fn is_over(&self, now: Instant) -> bool {
+        self.ends_at().is_some_and(|ends_at| now >= ends_at)
+    }
+
+    /// Starts the turn over from `started_at`, keeping any extensions, e.g. once the app has
+    /// finished transitioning in.
+    pub fn set_started_at(&mut self, started_at: Instant) {
+        self.started_at = started_at;
+    }
+
+    /// Changes how long the turn was given for, e.g. after the rotation has been edited, keeping
+    /// any extensions.
+    pub fn set_duration(&mut self, duration: Option<Duration>) {
+        self.duration = duration;
+    }
+
+    pub fn set_max_extension(&mut self, max_extension: Duration) {
+        self.max_extension = max_extension;
+    }
+
+    /// Lets the app give up the rest of its turn or not.
+    pub fn set_can_yield(&mut self, can_yield: bool) {
+        self.can_yield = can_yield;
+    }
+
+    /// Ends the turn at `now` if the app may give up the rest of it, returning whether it was.
+    pub fn yield_at(&mut self, now: Instant) -> bool {
+        if self.can_yield && self.yielded_at.is_none() {
+            self.yielded_at = Some(now);
+        }
+        self.yielded_at.is_some()
+    }
+
+    /// Whether the turn was ended early by the app giving up the rest of it.
+    pub fn was_yielded(&self) -> bool {
+        self.yielded_at.is_some()
+    }
+
+    /// Makes the turn last `extension` longer if that keeps it within the most it may be
+    /// extended by, returning whether it was. Turns which last forever don't need extending, so
+    /// they're always taken to have been.
+    pub fn extend(&mut self, extension: Duration) -> bool {
+        if self.duration.is_none() {
+            return true;
+        }
+        let extended_by = self.extended_by + extension;
+        if extended_by > self.max_extension {
+            return false;
+        }
+        self.extended_by = extended_by;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn remaining_time_counts_down_to_the_end_of_the_turn() {
+        let start = Instant::now();
+        let slot = Slot::new(start, Some(10 * SECOND), Duration::ZERO);
+        assert_eq!(slot.remaining(start), Some(10 * SECOND));
+        assert_eq!(slot.remaining(start + 4 * SECOND), Some(6 * SECOND));
+        assert!(!slot.is_over(start + 4 * SECOND));
+        assert_eq!(slot.remaining(start + 11 * SECOND), Some(Duration::ZERO));
+        assert!(slot.is_over(start + 10 * SECOND));
+
+        let forever = Slot::new(start, None, Duration::ZERO);
+        assert_eq!(forever.remaining(start + 1000 * SECOND), None);
+        assert!(!forever.is_over(start + 1000 * SECOND));
+    }
+
+    #[test]
+    fn turns_are_only_extended_up_to_the_limit() {
+        let start = Instant::now();
+        let mut slot = Slot::new(start, Some(10 * SECOND), 5 * SECOND);
+        assert!(slot.extend(3 * SECOND));
+        assert!(!slot.extend(3 * SECOND));
+        assert!(slot.extend(2 * SECOND));
+        assert!(!slot.extend(Duration::from_millis(1)));
+        assert_eq!(slot.duration(), Some(15 * SECOND));
+        assert_eq!(slot.ends_at(), Some(start + 15 * SECOND));
+
+        // Extensions are kept when the turn starts over or is given for longer
+        slot.set_started_at(start + SECOND);
+        slot.set_duration(Some(20 * SECOND));
+        assert_eq!(slot.ends_at(), Some(start + 26 * SECOND));
+
+        let mut forever = Slot::new(start, None, Duration::ZERO);
+        assert!(forever.extend(SECOND));
+        assert_eq!(forever.duration(), None);
+    }
+
+    #[test]
+    fn yielding_ends_the_turn_if_allowed() {
+        let start = Instant::now();
+        let mut slot = Slot::new(start, Some(10 * SECOND), Duration::ZERO);
+        slot.set_can_yield(false);
+        assert!(!slot.yield_at(start + SECOND));
+        assert!(!slot.was_yielded());
+        assert_eq!(slot.ends_at(), Some(start + 10 * SECOND));
+
+        slot.set_can_yield(true);
+        assert!(slot.yield_at(start + 2 * SECOND));
+        // Yielding again doesn't move the end of the turn
+        assert!(slot.yield_at(start + 3 * SECOND));
+        assert!(slot.was_yielded());
+        assert!(slot.is_over(start + 2 * SECOND));
+        assert_eq!(slot.remaining(start + SECOND), Some(SECOND));
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, BufRead, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A call an app made to one of the runner's host functions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostCall {
+    /// Which of the app's frames the call was made in, as set with [`HostCallTrace::set_frame`]
+    pub frame: u32,
+    pub function: String,
+    /// The bytes the app passed for each argument, which for numbers are as extism encodes
+    /// them
+    pub args: Vec<Vec<u8>>,
+    /// What the function gave back to the app, if the call succeeded
+    pub result: Option<Vec<u8>>,
+    /// What the call failed with, if it did
+    pub error: Option<String>,
+    /// Microseconds from the trace being started to the call being made
+    pub at_us: u64,
+    /// Microseconds the call took
+    pub duration_us: u64,
+}
+
+/// Every call an app makes to the runner's host functions, in the order they're made. Clones
+/// add to the same trace, so one can be handed to the app through
+/// [`AppOptions::trace_host_calls`](super::AppOptions::trace_host_calls) and read back from
+/// another.
+#[derive(Debug, Clone)]
+pub struct HostCallTrace {
+    inner: Arc<Mutex<TraceState>>,
+}
+
+#[derive(Debug)]
+struct TraceState {
+    started_at: Instant,
+    frame: u32,
+    calls: Vec<HostCall>,
+}
+
+impl HostCallTrace {
+    pub fn new() -> Self {
+        HostCallTrace {
+            inner: Arc::new(Mutex::new(TraceState {
+                started_at: Instant::now(),
+                frame: 0,
+                calls: Vec::new(),
+            })),
+        }
+    }
+
+    /// Marks the calls made from now on as being made in `frame`.
+    pub fn set_frame(&self, frame: u32) {
+        self.inner.lock().unwrap().frame = frame;
+    }
+
+    /// The calls made so far.
+    pub fn calls(&self) -> Vec<HostCall> {
+        self.inner.lock().unwrap().calls.clone()
+    }
+
+    /// How long it has been since the trace was started.
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.inner.lock().unwrap().started_at.elapsed()
+    }
+
+    /// Adds a call to `function` made `at` into the trace, which finished just now.
+    pub(crate) fn record(
+        &self,
+        function: &str,
+        args: Vec<Vec<u8>>,
+        result: Result<Vec<u8>, String>,
+        at: Duration,
+    ) {
+        let mut state = self.inner.lock().unwrap();
+        let duration = state.started_at.elapsed().saturating_sub(at);
+        let frame = state.frame;
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(err) => (None, Some(err)),
+        };
+        state.calls.push(HostCall {
+            frame,
+            function: function.to_owned(),
+            args,
+            result,
+            error,
+            at_us: micros(at),
+            duration_us: micros(duration),
+        });
+    }
+}
+
+impl Default for HostCallTrace {
+    fn default() -> Self {
+        HostCallTrace::new()
+    }
+}
+
+/// Writes `calls` as JSON lines, one call to a line, which keeps traces easy to diff.
+pub fn write_trace(calls: &[HostCall], mut writer: impl Write) -> io::Result<()> {
+    for call in calls {
+        serde_json::to_writer(&mut writer, call)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// Reads calls written by [`write_trace`].
+pub fn read_trace(reader: impl BufRead) -> io::Result<Vec<HostCall>> {
+    reader
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+fn micros(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
@@ -0,0 +1,75 @@
+use super::app_manifest::MANIFEST_FILE_NAMES;
+use notify::{
+    event::{AccessKind, AccessMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use std::{
+    path::Path,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+/// How long an app's files have to be left alone after changing before it's reloaded, so a
+/// file being copied over in several writes is only reloaded once it's all there
+const DEBOUNCE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Watches an app's directory for changes to its manifest or wasm file. The directory is
+/// watched rather than the files themselves so files which are replaced rather than written
+/// over, as `scp` and most editors do, are still noticed.
+pub struct AppWatcher {
+    /// Kept so the directory stays watched
+    _watcher: RecommendedWatcher,
+    changes: mpsc::Receiver<()>,
+    /// When the app's files last changed, if they have since it was last reloaded
+    last_change: Option<Instant>,
+}
+
+impl AppWatcher {
+    pub fn new(app_dir: impl AsRef<Path>) -> notify::Result<Self> {
+        let (tx, changes) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                // Files being looked at, including by the runner loading the app, aren't changes
+                let is_change = match event.kind {
+                    EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+                    EventKind::Access(_) => false,
+                    _ => true,
+                };
+                if is_change && event.paths.iter().any(|path| is_app_file(path)) {
+                    let _ = tx.send(());
+                }
+            })?;
+        watcher.watch(app_dir.as_ref(), RecursiveMode::NonRecursive)?;
+        Ok(AppWatcher {
+            _watcher: watcher,
+            changes,
+            last_change: None,
+        })
+    }
+
+    /// Whether the app's files have changed and then been left alone for long enough that it
+    /// should be reloaded. Only returns true once for each burst of changes.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if self.changes.try_iter().count() > 0 {
+            self.last_change = Some(now);
+        }
+        match self.last_change {
+            Some(last_change) if now.duration_since(last_change) >= DEBOUNCE_PERIOD => {
+                self.last_change = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether `path` is a file the app is loaded from, i.e. its manifest or a wasm file.
+fn is_app_file(path: &Path) -> bool {
+    let is_manifest = path
+        .file_name()
+        .is_some_and(|name| MANIFEST_FILE_NAMES.iter().any(|manifest| name == *manifest));
+    is_manifest || path.extension().is_some_and(|ext| ext == "wasm")
+}
@@ -0,0 +1,178 @@
+//! Runs apps in the test harness and checks that they're only allowed the capabilities they've
+//! been granted, and checks them against the golden runs kept in `tests/golden`, which are made
+//! with:
+//!
+//! cargo run --bin megabit-runner --features test-support,image -- test-app examples/counter_app \
+//!     --frames 5 --display-size 32x16 --golden tests/golden/counter_app --update-golden
+
+use megabit_runner::{
+    display::{DisplayConfiguration, PixelRepresentation},
+    wasm_env::{crash_reason, AppHarness, AppOptions, Capability, DenialReason, PermissionDenied},
+};
+use std::path::{Path, PathBuf};
+
+/// How many frames the golden run of the counter app lasted after it was set up
+const GOLDEN_FRAMES: u32 = 5;
+
+/// An app which reads the clock every time it runs
+const CLOCK_APP: &str = r#"(module
+  (import "extism:host/user" "get_monotonic_ms" (func $get_monotonic_ms (result i64)))
+  (func (export "run") (result i32)
+    (drop (call $get_monotonic_ms))
+    i32.const 0))"#;
+
+fn display_config() -> DisplayConfiguration {
+    DisplayConfiguration {
+        width: 32,
+        height: 16,
+        pixel_representation: PixelRepresentation::Monocolor,
+        orientation: Default::default(),
+        max_fps_hint: None,
+    }
+}
+
+fn counter_app(frames: u32) -> AppHarness {
+    let counter_app = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/counter_app");
+    let mut harness = AppHarness::load(counter_app, display_config(), Default::default()).unwrap();
+    harness.run_frames(frames).unwrap();
+    harness
+}
+
+/// Runs the clock app for a frame with its manifest declaring `capabilities` and the runner
+/// denying `denied_capabilities` to every app. Each test has its own copy of the app, named
+/// `test_name`.
+fn run_clock_app(
+    test_name: &str,
+    capabilities: &str,
+    denied_capabilities: Vec<Capability>,
+) -> anyhow::Result<()> {
+    let dir =
+        std::env::temp_dir().join(format!("megabit-clock-{test_name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("clock.wat"), CLOCK_APP).unwrap();
+    std::fs::write(
+        dir.join("manifest.json"),
+        format!(r#"{{"name": "Clock", "bin": "clock.wat", "capabilities": {capabilities}}}"#),
+    )
+    .unwrap();
+    let options = AppOptions {
+        denied_capabilities,
+        ..Default::default()
+    };
+    // Apps without a capability still load, only failing once they use it
+    let result = AppHarness::load(&dir, display_config(), options)
+        .unwrap()
+        .run_frames(1);
+    std::fs::remove_dir_all(&dir).unwrap();
+    result
+}
+
+/// Why the clock app wasn't allowed to read the clock, checking that it crashed for that reason.
+fn clock_denied_for(result: anyhow::Result<()>) -> DenialReason {
+    let err = result.expect_err("The app read the clock without being allowed to");
+    let denied = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<PermissionDenied>())
+        .unwrap_or_else(|| panic!("The app failed for another reason: {err:#}"));
+    assert_eq!(denied.function, "get_monotonic_ms");
+    assert_eq!(denied.capability, Capability::Time);
+    assert_eq!(crash_reason(&err), "No time permission");
+    denied.reason
+}
+
+#[test]
+fn undeclared_capabilities_are_denied() {
+    assert_eq!(
+        clock_denied_for(run_clock_app("undeclared", "[]", vec![])),
+        DenialReason::Undeclared
+    );
+}
+
+#[test]
+fn capabilities_denied_by_the_runner_are_denied_whatever_the_manifest_says() {
+    assert_eq!(
+        clock_denied_for(run_clock_app(
+            "denied",
+            r#"["time"]"#,
+            vec![Capability::Time]
+        )),
+        DenialReason::DeniedByRunner
+    );
+}
+
+#[test]
+fn granted_capabilities_are_allowed() {
+    run_clock_app("granted", r#"["time"]"#, vec![Capability::Storage]).unwrap();
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/counter_app")
+}
+
+#[test]
+fn host_calls_are_recorded_frame_by_frame() {
+    let harness = counter_app(GOLDEN_FRAMES);
+    let trace = harness.trace();
+    assert_eq!(trace.len(), GOLDEN_FRAMES as usize);
+    for (index, call) in trace.iter().enumerate() {
+        // The app lights the pixel along from the last each frame, passing its column first
+        assert_eq!(call.function, "set_pixel");
+        assert_eq!(call.frame as usize, index + 1);
+        assert_eq!(call.args[0], (index as u32).to_le_bytes());
+        assert_eq!(call.error, None);
+    }
+    let lit = harness.screen_buffer().unwrap().get_row(0).unwrap();
+    assert_eq!(
+        lit.iter().filter(|&&lit| lit).count(),
+        GOLDEN_FRAMES as usize
+    );
+}
+
+#[test]
+fn the_app_matches_its_golden_run() {
+    let differences = counter_app(GOLDEN_FRAMES)
+        .check_golden(golden_dir())
+        .unwrap();
+    assert_eq!(differences, Vec::<String>::new());
+}
+
+#[test]
+fn running_for_longer_differs_from_the_golden_run() {
+    let differences = counter_app(GOLDEN_FRAMES + 1)
+        .check_golden(golden_dir())
+        .unwrap();
+    assert_eq!(
+        differences,
+        [
+            "The app made 6 host calls rather than 5",
+            "1 pixels differ from the golden screen, the first at (5, 0)",
+        ]
+    );
+}
+
+#[test]
+fn a_call_differing_from_the_golden_run_is_described() {
+    let dir = std::env::temp_dir().join(format!("megabit-golden-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // The golden run without its screen, with the third call lighting a different pixel
+    let golden_trace = std::fs::read_to_string(golden_dir().join("trace.jsonl")).unwrap();
+    let trace = golden_trace.replacen("[[2,0,0,0]", "[[9,0,0,0]", 1);
+    assert_ne!(trace, golden_trace);
+    std::fs::write(dir.join("trace.jsonl"), trace).unwrap();
+
+    let differences = counter_app(GOLDEN_FRAMES).check_golden(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let differences = differences.unwrap();
+    assert_eq!(differences.len(), 1);
+    assert!(
+        differences[0].starts_with("Call 2 was set_pixel([[2, 0, 0, 0]"),
+        "{differences:?}"
+    );
+    assert!(differences[0].contains("rather than set_pixel([[9, 0, 0, 0]"));
+}
+
+#[test]
+fn a_missing_golden_run_is_an_error() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/missing");
+    assert!(counter_app(1).check_golden(dir).is_err());
+}
@@ -0,0 +1,227 @@
+//! Renders through a full display stack onto a mock device and checks what reaches it.
+
+use megabit_runner::{
+    display::{
+        CompositeDisplay, DisplayConfiguration, MockPanel, PanelLayout, PixelRepresentation,
+        Rgb555, ScreenBuffer,
+    },
+    serial::{Capabilities, DeviceError, SerialConfig},
+};
+use megabit_serial_protocol::SerialMessage;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+fn display_config(pixel_representation: PixelRepresentation) -> DisplayConfiguration {
+    DisplayConfiguration {
+        width: 16,
+        height: 8,
+        pixel_representation,
+        orientation: Default::default(),
+        max_fps_hint: None,
+    }
+}
+
+/// What the device should be showing for the screen buffer, in its own pixel format.
+fn expected_framebuffer(screen_buffer: &ScreenBuffer) -> Vec<Vec<u32>> {
+    let (_, height) = screen_buffer.physical_size();
+    (0..height)
+        .map(|row| {
+            if screen_buffer.is_rgb() {
+                let row = screen_buffer.get_physical_row_rgb(row).unwrap();
+                row.into_iter().map(u32::from).collect()
+            } else {
+                let row = screen_buffer.get_physical_row(row).unwrap();
+                row.into_iter().map(u32::from).collect()
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn monocolor_render_reaches_the_device() {
+    let panel = MockPanel::new(display_config(PixelRepresentation::Monocolor)).unwrap();
+    let mut display = panel.display().unwrap();
+    {
+        let mut screen_buffer = display.screen_buffer_mut();
+        for col in 0..16 {
+            screen_buffer.set_cell(col % 8, col, true).unwrap();
+        }
+        screen_buffer.set_cell(7, 0, true).unwrap();
+    }
+    display.render_dirty().unwrap();
+    panel.sync().unwrap();
+
+    let framebuffer = panel.device().framebuffer();
+    assert_eq!(framebuffer, expected_framebuffer(&display.screen_buffer()));
+    assert_eq!(framebuffer[0][0], 1);
+    assert_eq!(framebuffer[0][1], 0);
+}
+
+#[test]
+fn rgb_render_reaches_the_device() {
+    let panel = MockPanel::new(display_config(PixelRepresentation::RGB555)).unwrap();
+    let mut display = panel.display().unwrap();
+    {
+        let mut screen_buffer = display.screen_buffer_mut();
+        for row in 0..8 {
+            for col in 0..16 {
+                let color = Rgb555::from_raw(((row * 16 + col) as u16 * 0x0101) & 0x7fff);
+                screen_buffer.set_pixel_rgb(row, col, color).unwrap();
+            }
+        }
+    }
+    display.render_dirty().unwrap();
+    panel.sync().unwrap();
+
+    assert_eq!(
+        panel.device().framebuffer(),
+        expected_framebuffer(&display.screen_buffer())
+    );
+}
+
+#[test]
+fn only_changed_rows_are_sent_again() {
+    let panel = MockPanel::new(display_config(PixelRepresentation::Monocolor)).unwrap();
+    let mut display = panel.display().unwrap();
+    display.redraw().unwrap();
+    panel.sync().unwrap();
+    let sent_before = row_updates(&panel.device().received_messages());
+
+    display.screen_buffer_mut().set_cell(3, 5, true).unwrap();
+    display.render_dirty().unwrap();
+    panel.sync().unwrap();
+
+    let received = panel.device().received_messages();
+    assert_eq!(row_updates(&received) - sent_before, 1);
+    assert_eq!(panel.device().framebuffer()[3][5], 1);
+}
+
+fn row_updates(msgs: &[SerialMessage]) -> usize {
+    msgs.iter()
+        .filter(|msg| matches!(msg, SerialMessage::UpdateRow(_)))
+        .count()
+}
+
+#[test]
+fn double_buffered_renders_commit_after_their_rows() {
+    let panel = MockPanel::with_capabilities(
+        display_config(PixelRepresentation::Monocolor),
+        Capabilities::LEGACY | Capabilities::DOUBLE_BUFFERING,
+        SerialConfig::default(),
+    )
+    .unwrap();
+    let mut display = panel.display().unwrap();
+    for frame in 0..3 {
+        display.screen_buffer_mut().fill(frame % 2 == 0);
+        display.redraw().unwrap();
+    }
+    panel.sync().unwrap();
+
+    // Each frame's rows are all written before the commit which shows them
+    let kinds = panel
+        .device()
+        .received_messages()
+        .into_iter()
+        .filter_map(|msg| match msg {
+            SerialMessage::UpdateRow(inner) => Some(Some(inner.row_number)),
+            SerialMessage::CommitRender(_) => Some(None),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let frame = (0..8).map(Some).chain([None]).collect::<Vec<_>>();
+    assert_eq!(kinds, frame.repeat(3));
+    assert_eq!(
+        panel.device().framebuffer(),
+        expected_framebuffer(&display.screen_buffer())
+    );
+}
+
+#[test]
+fn renders_without_double_buffering_are_never_committed() {
+    let panel = MockPanel::new(display_config(PixelRepresentation::Monocolor)).unwrap();
+    let mut display = panel.display().unwrap();
+    display.screen_buffer_mut().fill(true);
+    display.redraw().unwrap();
+    panel.sync().unwrap();
+
+    let received = panel.device().received_messages();
+    assert!(received
+        .iter()
+        .all(|msg| !matches!(msg, SerialMessage::CommitRender(_))));
+    assert_eq!(row_updates(&received), 8);
+}
+
+#[test]
+fn renders_report_rows_the_device_rejects() {
+    let panel = MockPanel::new(display_config(PixelRepresentation::Monocolor)).unwrap();
+    // The display thinks the panel is twice as tall as the device behind it really is
+    let mut display = CompositeDisplay::new(
+        vec![(
+            panel.serial_conn().clone(),
+            DisplayConfiguration {
+                height: 16,
+                ..display_config(PixelRepresentation::Monocolor)
+            },
+        )],
+        PanelLayout::Horizontal,
+        Default::default(),
+    )
+    .unwrap();
+
+    // The device answers after the rows are written, so the rejection can come out of the
+    // render after the one which sent the rows
+    let err = display.redraw().err().unwrap_or_else(|| {
+        panel.sync().unwrap();
+        display.redraw().unwrap_err()
+    });
+    let err = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<DeviceError>());
+    assert_eq!(
+        err,
+        Some(&DeviceError::Rejected {
+            request: "UpdateRow".to_owned()
+        })
+    );
+}
+
+#[test]
+fn snapshots_never_catch_a_frame_half_drawn() {
+    let panel = MockPanel::new(display_config(PixelRepresentation::Monocolor)).unwrap();
+    let mut display = panel.display().unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+    let observers = (0..2)
+        .map(|_| {
+            let screen_buffer = display.shared_screen_buffer();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                // At least one snapshot is taken, however quickly the frames are drawn
+                loop {
+                    // Every frame fills the whole buffer, so a snapshot which isn't all one
+                    // value was taken partway through drawing
+                    let snapshot = screen_buffer.read_snapshot();
+                    let mut pixels = snapshot
+                        .rows()
+                        .unwrap()
+                        .flat_map(|(_, row)| row.iter().copied());
+                    let first = pixels.next();
+                    assert!(pixels.all(|pixel| Some(pixel) == first));
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for frame in 0..200 {
+        display.screen_buffer_mut().fill(frame % 2 == 0);
+        display.render_dirty().unwrap();
+    }
+    done.store(true, Ordering::Relaxed);
+    for observer in observers {
+        observer.join().unwrap();
+    }
+}
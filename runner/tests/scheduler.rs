@@ -0,0 +1,558 @@
+//! Runs apps through the scheduler, on a mock panel, and checks it deals with ones which
+//! misbehave without holding up the rest of the rotation, that it paces and times their turns,
+//! hands input to the app being shown, and keeps apps it switches away from warm.
+
+use megabit_runner::{
+    display::{CompositeDisplay, DisplayConfiguration, MockPanel, PixelRepresentation},
+    serial::{InputEvent, InputEvents, MockDevice},
+    wasm_env::{AppOptions, AppRotation, AppScheduler, ModuleCache, RotationEntry, WasmAppRunner},
+};
+use megabit_serial_protocol::{InputKind, ReportInput, SerialMessage};
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// How long to run the scheduler for when checking what it does over time
+const RUN_FOR: Duration = Duration::from_millis(1500);
+
+fn panel() -> MockPanel {
+    MockPanel::new(DisplayConfiguration {
+        width: 32,
+        height: 16,
+        pixel_representation: PixelRepresentation::Monocolor,
+        orientation: Default::default(),
+        max_fps_hint: None,
+    })
+    .unwrap()
+}
+
+fn display(panel: &MockPanel) -> Rc<RefCell<CompositeDisplay>> {
+    Rc::new(RefCell::new(panel.display().unwrap()))
+}
+
+/// The directory of one of the example apps
+fn app(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("examples")
+        .join(name)
+}
+
+/// A scheduler showing each of `apps` in turn, for `slot` each.
+fn scheduler(
+    display: Rc<RefCell<CompositeDisplay>>,
+    apps: &[&str],
+    slot: Duration,
+) -> AppScheduler {
+    let rotation = AppRotation {
+        entries: apps
+            .iter()
+            .map(|name| RotationEntry {
+                path: app(name),
+                duration: Some(slot),
+                enabled: true,
+            })
+            .collect(),
+    };
+    AppScheduler::new(display, rotation, Default::default(), Default::default())
+}
+
+fn shown_app(scheduler: &mut AppScheduler) -> Option<String> {
+    scheduler.app().map(|app| app.name().to_owned())
+}
+
+fn lit_pixels(display: &RefCell<CompositeDisplay>) -> usize {
+    display
+        .borrow()
+        .screen_buffer()
+        .rows()
+        .unwrap()
+        .flat_map(|(_, row)| row.iter().copied())
+        .filter(|&pixel| pixel)
+        .count()
+}
+
+#[test]
+fn calls_running_over_budget_are_stopped() {
+    let panel = panel();
+    let options = AppOptions {
+        execution_budget: Some(Duration::from_secs(10)),
+        ..Default::default()
+    };
+    let mut runner = WasmAppRunner::load(app("spin_app"), display(&panel), &options).unwrap();
+    runner.setup_app().unwrap();
+    for _ in 0..3 {
+        let start_time = Instant::now();
+        assert!(runner.run_app_once().is_err());
+        // The manifest's own budget of 200ms wins over the runner's
+        assert!(start_time.elapsed() < Duration::from_secs(2));
+        assert!(runner.ran_over_budget());
+    }
+    assert!(runner.is_misbehaving());
+}
+
+#[test]
+fn apps_which_keep_running_over_budget_are_moved_on_from() {
+    let panel = panel();
+    let mut scheduler = scheduler(
+        display(&panel),
+        &["spin_app", "quiet_app"],
+        Duration::from_secs(60),
+    );
+    scheduler.poll(Instant::now()).unwrap();
+    let mut runs = 0;
+    while shown_app(&mut scheduler).as_deref() == Some("Spin") {
+        assert!(runs < 10, "The scheduler never moved on from the app");
+        scheduler.run_once().unwrap();
+        runs += 1;
+    }
+    assert_eq!(runs, 3);
+    scheduler.run_once().unwrap();
+}
+
+#[test]
+fn crashing_apps_are_shown_restarted_and_moved_on_from() {
+    let panel = panel();
+    let display = display(&panel);
+    let mut scheduler = scheduler(
+        display.clone(),
+        &["crash_app", "quiet_app"],
+        Duration::from_secs(60),
+    );
+    scheduler.poll(Instant::now()).unwrap();
+
+    // Backs off for 1s after the first crash and 2s after the second, then gives up on the third
+    for backoff in [Duration::from_secs(1), Duration::from_secs(2)] {
+        scheduler.run_once().unwrap();
+        assert!(
+            lit_pixels(&display) > 0,
+            "Nothing was shown when the app crashed"
+        );
+        scheduler.poll(Instant::now()).unwrap();
+        let restarts = scheduler.stats()["Crash"].restarts;
+        std::thread::sleep(backoff + Duration::from_millis(50));
+        scheduler.poll(Instant::now()).unwrap();
+        assert_eq!(scheduler.stats()["Crash"].restarts, restarts + 1);
+    }
+    scheduler.run_once().unwrap();
+    assert_eq!(shown_app(&mut scheduler).as_deref(), Some("Quiet"));
+    let stats = scheduler.stats()["Crash"];
+    assert_eq!((stats.crashes, stats.restarts), (3, 2));
+}
+
+#[test]
+fn apps_running_out_of_memory_are_shown_and_restarted() {
+    let panel = panel();
+    let display = display(&panel);
+    let mut scheduler = AppScheduler::new(
+        display.clone(),
+        AppRotation::single(app("hog_app")),
+        Default::default(),
+        Default::default(),
+    );
+    scheduler.poll(Instant::now()).unwrap();
+    // The manifest's limit of 64 pages
+    assert_eq!(
+        scheduler.app().map(|app| app.memory_limit()),
+        Some(64 * 64 * 1024)
+    );
+
+    // The app grows by 16 pages each run, so four runs use up its limit and the fifth takes it
+    // over
+    let mut runs = 0;
+    while !scheduler.app().is_some_and(|app| app.ran_out_of_memory()) {
+        assert!(runs < 10, "The app never ran out of memory");
+        scheduler.run_once().unwrap();
+        runs += 1;
+    }
+    assert_eq!(runs, 5);
+    assert!(
+        lit_pixels(&display) > 0,
+        "Nothing was shown when the app crashed"
+    );
+
+    // It isn't run again until it's been restarted
+    scheduler.run_once().unwrap();
+    assert!(scheduler.app().is_some_and(|app| app.ran_out_of_memory()));
+    std::thread::sleep(Duration::from_millis(1100));
+    scheduler.poll(Instant::now()).unwrap();
+    assert!(scheduler.app().is_some_and(|app| !app.ran_out_of_memory()));
+    scheduler.run_once().unwrap();
+}
+
+#[test]
+fn apps_yielding_on_their_own_dont_busy_loop() {
+    let panel = panel();
+    let mut scheduler = scheduler(display(&panel), &["yield_app"], Duration::from_millis(300));
+    let start_time = Instant::now();
+    let mut iterations = 0;
+    while start_time.elapsed() < RUN_FOR {
+        scheduler.poll(Instant::now()).unwrap();
+        scheduler.run_once().unwrap();
+        std::thread::sleep(scheduler.time_until_next_frame(Instant::now()));
+        iterations += 1;
+    }
+    assert_eq!(shown_app(&mut scheduler).as_deref(), Some("Yield"));
+    // Switching apps without waiting would go round far more often than this
+    assert!(
+        iterations <= 50,
+        "Went round {iterations} times in {RUN_FOR:?}"
+    );
+}
+
+#[test]
+fn idle_apps_are_skipped() {
+    let panel = panel();
+    let mut scheduler = scheduler(
+        display(&panel),
+        &["yield_app", "quiet_app"],
+        Duration::from_millis(300),
+    );
+    scheduler.poll(Instant::now()).unwrap();
+    assert_eq!(shown_app(&mut scheduler).as_deref(), Some("Yield"));
+    scheduler.run_once().unwrap();
+    scheduler.poll(Instant::now()).unwrap();
+    assert_eq!(shown_app(&mut scheduler).as_deref(), Some("Quiet"));
+
+    // The idle app isn't come back to while there's another to show
+    let start_time = Instant::now();
+    while start_time.elapsed() < RUN_FOR {
+        std::thread::sleep(scheduler.time_until_next_frame(Instant::now()));
+        scheduler.poll(Instant::now()).unwrap();
+        scheduler.run_once().unwrap();
+        assert_eq!(shown_app(&mut scheduler).as_deref(), Some("Quiet"));
+    }
+}
+
+#[test]
+fn extended_turns_are_switched_away_from_when_over() {
+    let panel = panel();
+    let display = display(&panel);
+    let slot = Duration::from_secs(1);
+    let mut scheduler = scheduler(display.clone(), &["slot_app", "quiet_app"], slot);
+    scheduler.poll(Instant::now()).unwrap();
+    let shown_at = Instant::now();
+    scheduler.run_once().unwrap();
+    // The app shows whether it was only granted the first of its two 500ms extensions, the
+    // second taking it over its manifest's limit
+    assert!(top_left_lit(&display));
+
+    // Paced like the runner's own loop, timing the switch from when it was decided on rather
+    // than after transitioning to the next app
+    let mut switched_after = Duration::ZERO;
+    while shown_app(&mut scheduler).as_deref() == Some("Slot") {
+        assert!(
+            shown_at.elapsed() < slot * 2,
+            "The app was never switched away from"
+        );
+        std::thread::sleep(scheduler.time_until_next_frame(Instant::now()));
+        switched_after = shown_at.elapsed();
+        scheduler.poll(Instant::now()).unwrap();
+        scheduler.run_once().unwrap();
+    }
+    let expected = slot + Duration::from_millis(500);
+    assert!(
+        switched_after >= expected - Duration::from_millis(20)
+            && switched_after < expected + Duration::from_millis(50),
+        "The app was switched away from after {switched_after:?} rather than {expected:?}"
+    );
+}
+
+/// The shortest refresh period allowed in the pacing tests, which the app asks to go faster than
+const MIN_REFRESH_PERIOD: Duration = Duration::from_millis(25);
+
+fn pacing_scheduler(display: Rc<RefCell<CompositeDisplay>>) -> AppScheduler {
+    let options = AppOptions {
+        min_refresh_period: Some(MIN_REFRESH_PERIOD),
+        ..Default::default()
+    };
+    AppScheduler::new(
+        display,
+        AppRotation::single(app("pacing_app")),
+        options,
+        Default::default(),
+    )
+}
+
+#[test]
+fn frames_are_paced_without_drifting() {
+    let panel = panel();
+    let mut scheduler = pacing_scheduler(display(&panel));
+    scheduler.poll(Instant::now()).unwrap();
+    let refresh_period = scheduler.app().and_then(|app| app.refresh_period());
+    assert_eq!(refresh_period, Some(MIN_REFRESH_PERIOD));
+
+    // Paced like the runner's own loop, with time spent on each frame as if rendering, which
+    // would only manage 1 / 35ms without keeping to a schedule
+    let start_time = Instant::now();
+    while start_time.elapsed() < RUN_FOR {
+        scheduler.poll(Instant::now()).unwrap();
+        scheduler.run_once().unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        std::thread::sleep(scheduler.time_until_next_frame(Instant::now()));
+    }
+    let fps = scheduler.stats()["Pacing"].fps().unwrap();
+    let expected = 1.0 / MIN_REFRESH_PERIOD.as_secs_f64();
+    assert!(
+        (fps - expected).abs() < expected * 0.05,
+        "The app ran at {fps:.1} fps rather than {expected:.1}"
+    );
+}
+
+#[test]
+fn frames_which_overrun_are_caught_up_on_or_skipped() {
+    let panel = panel();
+    let mut scheduler = pacing_scheduler(display(&panel));
+    scheduler.poll(Instant::now()).unwrap();
+    scheduler.run_once().unwrap();
+
+    // A frame running over is followed straight away by the next, which keeps to the schedule
+    std::thread::sleep(MIN_REFRESH_PERIOD + Duration::from_millis(10));
+    assert_eq!(
+        scheduler.time_until_next_frame(Instant::now()),
+        Duration::ZERO
+    );
+    scheduler.run_once().unwrap();
+    assert!(scheduler.time_until_next_frame(Instant::now()) <= Duration::from_millis(15));
+
+    // Once a whole period behind, the missed frames are skipped rather than run back to back
+    std::thread::sleep(MIN_REFRESH_PERIOD * 3);
+    scheduler.run_once().unwrap();
+    assert!(scheduler.time_until_next_frame(Instant::now()) > MIN_REFRESH_PERIOD / 2);
+}
+
+/// The button which switches apps when held down
+const NEXT_APP_BUTTON: u8 = 1;
+
+/// Has `device` report `messages` and waits for the runner to receive them.
+fn report(
+    device: &MockDevice,
+    input: &InputEvents,
+    messages: Vec<SerialMessage>,
+) -> Vec<InputEvent> {
+    let expected = messages
+        .iter()
+        .map(|msg| InputEvent::from_message(msg).len())
+        .sum::<usize>();
+    for msg in messages {
+        device.report(msg);
+    }
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut events = vec![];
+    while events.len() < expected {
+        assert!(
+            Instant::now() < deadline,
+            "Only received {events:?} from the device"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+        events.extend(input.drain());
+    }
+    events
+}
+
+fn button(kind: InputKind, id: u8) -> SerialMessage {
+    SerialMessage::ReportInput(ReportInput { kind, id, value: 0 })
+}
+
+fn top_left_lit(display: &RefCell<CompositeDisplay>) -> bool {
+    display
+        .borrow()
+        .screen_buffer()
+        .rows()
+        .unwrap()
+        .next()
+        .is_some_and(|(_, row)| row[0])
+}
+
+#[test]
+fn input_reaches_the_app_being_shown_once() {
+    let panel = panel();
+    let input = panel.serial_conn().subscribe_input();
+    let display = display(&panel);
+    let mut scheduler = scheduler(
+        display.clone(),
+        &["input_app", "quiet_app"],
+        Duration::from_secs(60),
+    );
+    scheduler.poll(Instant::now()).unwrap();
+
+    let events = report(
+        panel.device(),
+        &input,
+        vec![
+            button(InputKind::ButtonPressed, 0),
+            button(InputKind::ButtonReleased, 0),
+            SerialMessage::ReportInput(ReportInput {
+                kind: InputKind::EncoderTurned,
+                id: 0,
+                value: -2,
+            }),
+            // Older firmware only reports presses of its one button
+            SerialMessage::ReportButtonPress,
+        ],
+    );
+    assert_eq!(
+        events,
+        [
+            InputEvent::ButtonPressed { button: 0 },
+            InputEvent::ButtonReleased { button: 0 },
+            InputEvent::EncoderTurned {
+                encoder: 0,
+                steps: -2,
+            },
+            InputEvent::ButtonPressed { button: 0 },
+            InputEvent::ButtonReleased { button: 0 },
+        ]
+    );
+    for event in events {
+        scheduler.handle_input(event, Instant::now());
+    }
+    scheduler.run_once().unwrap();
+    assert!(top_left_lit(&display), "The app didn't get the input");
+    scheduler.run_once().unwrap();
+    assert!(!top_left_lit(&display), "The app got the same input twice");
+}
+
+#[test]
+fn holding_down_the_next_app_button_switches_apps() {
+    let panel = panel();
+    let input = panel.serial_conn().subscribe_input();
+    let display = display(&panel);
+    let mut scheduler = scheduler(
+        display.clone(),
+        &["input_app", "quiet_app"],
+        Duration::from_secs(60),
+    );
+    scheduler.set_next_app_button(Some(NEXT_APP_BUTTON));
+    scheduler.poll(Instant::now()).unwrap();
+    // Has the device report the next app button being pressed or let go of at `at`
+    let press = |scheduler: &mut AppScheduler, kind, at| {
+        for event in report(panel.device(), &input, vec![button(kind, NEXT_APP_BUTTON)]) {
+            scheduler.handle_input(event, at);
+        }
+    };
+
+    // A short press only reaches the app once it's let go of
+    let pressed_at = Instant::now();
+    press(&mut scheduler, InputKind::ButtonPressed, pressed_at);
+    scheduler.run_once().unwrap();
+    assert!(!top_left_lit(&display));
+    let released_at = pressed_at + Duration::from_millis(200);
+    press(&mut scheduler, InputKind::ButtonReleased, released_at);
+    scheduler.run_once().unwrap();
+    assert!(top_left_lit(&display), "The app didn't get a short press");
+
+    // Holding it down switches apps without the app getting it
+    let pressed_at = Instant::now();
+    press(&mut scheduler, InputKind::ButtonPressed, pressed_at);
+    scheduler
+        .poll(pressed_at + Duration::from_millis(500))
+        .unwrap();
+    assert_eq!(shown_app(&mut scheduler).as_deref(), Some("Input"));
+    scheduler
+        .poll(pressed_at + Duration::from_millis(1100))
+        .unwrap();
+    assert_eq!(shown_app(&mut scheduler).as_deref(), Some("Quiet"));
+
+    // Letting go of it doesn't switch again
+    let released_at = pressed_at + Duration::from_millis(1500);
+    press(&mut scheduler, InputKind::ButtonReleased, released_at);
+    scheduler.poll(Instant::now()).unwrap();
+    assert_eq!(shown_app(&mut scheduler).as_deref(), Some("Quiet"));
+}
+
+/// Runs the counting app a few times, then shows each of `others` in turn before switching back
+/// to it and running it once more, and gives back how many runs it has shown.
+fn counter_runs_after_switching_back(warm_instances: usize, others: &[&str]) -> usize {
+    const RUNS_BEFORE_SWITCHING: usize = 3;
+    let panel = panel();
+    let display = display(&panel);
+    let slot = Duration::from_secs(60);
+    let apps = [&["counter_app"], others].concat();
+    let mut scheduler = scheduler(display.clone(), &apps, slot);
+    scheduler.set_warm_instances(warm_instances);
+    let start_time = Instant::now();
+    scheduler.poll(start_time).unwrap();
+    for _ in 0..RUNS_BEFORE_SWITCHING {
+        scheduler.run_once().unwrap();
+    }
+    for turn in 1..=others.len() as u32 {
+        scheduler.poll(start_time + slot * 2 * turn).unwrap();
+        assert_ne!(shown_app(&mut scheduler).as_deref(), Some("Counter"));
+    }
+    scheduler
+        .poll(start_time + slot * 2 * (others.len() as u32 + 1))
+        .unwrap();
+    assert_eq!(shown_app(&mut scheduler).as_deref(), Some("Counter"));
+    scheduler.run_once().unwrap();
+    let lit = display
+        .borrow()
+        .screen_buffer()
+        .rows()
+        .unwrap()
+        .next()
+        .map_or(0, |(_, row)| row.iter().filter(|&&pixel| pixel).count());
+    scheduler.stop();
+    lit
+}
+
+#[test]
+fn apps_kept_warm_carry_on_where_they_left_off() {
+    assert_eq!(counter_runs_after_switching_back(1, &["quiet_app"]), 4);
+}
+
+#[test]
+fn apps_not_kept_warm_start_over() {
+    assert_eq!(counter_runs_after_switching_back(0, &["quiet_app"]), 1);
+}
+
+#[test]
+fn the_app_put_away_longest_ago_stops_being_kept_warm() {
+    assert_eq!(
+        counter_runs_after_switching_back(1, &["quiet_app", "input_app"]),
+        1
+    );
+    assert_eq!(
+        counter_runs_after_switching_back(2, &["quiet_app", "input_app"]),
+        4
+    );
+}
+
+/// Every file under `dir`.
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(files_in(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
+
+#[test]
+fn compiled_apps_are_kept_in_the_module_cache() {
+    let panel = panel();
+    let cache_dir =
+        std::env::temp_dir().join(format!("megabit-scheduler-cache-{}", std::process::id()));
+    let module_cache = ModuleCache::open(&cache_dir).unwrap();
+    let options = AppOptions {
+        module_cache: Some(module_cache.clone()),
+        ..Default::default()
+    };
+    // The second load is from the cache
+    let loaded = (0..2)
+        .map(|_| WasmAppRunner::load(app("counter_app"), display(&panel), &options).map(drop))
+        .collect::<Result<Vec<_>, _>>();
+    let cached = files_in(module_cache.dir())
+        .into_iter()
+        .filter(|path| path != module_cache.config_path())
+        .count();
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+    loaded.unwrap();
+    assert!(cached > 0, "Nothing was kept in the module cache");
+}
@@ -0,0 +1,307 @@
+//! Drives the serial stack against a mock device over the loopback transport.
+
+use megabit_runner::{
+    display::{DisplayConfiguration, PixelRepresentation},
+    serial::{
+        self, ConnectionEvent, DeviceError, KeepaliveConfig, MockDevice, RetransmitConfig,
+        SerialConfig, SerialConnection, SyncSerialConnection, Transport, TransportStream,
+    },
+};
+use megabit_serial_protocol::{SerialMessage, SetLedState};
+use std::{fmt, future::Future, io, pin::Pin, sync::Mutex, time::Duration};
+use tokio::io::DuplexStream;
+
+fn display_config() -> DisplayConfiguration {
+    DisplayConfiguration {
+        width: 8,
+        height: 8,
+        pixel_representation: PixelRepresentation::Monocolor,
+        orientation: Default::default(),
+        max_fps_hint: None,
+    }
+}
+
+/// Sets up a connection to a mock device without starting either, so requests can be queued
+/// before anything is written. The returned task runs both once spawned.
+fn unstarted_connection() -> (
+    SerialConnection,
+    serial::ShutdownHandle,
+    MockDevice,
+    Pin<Box<dyn Future<Output = ()> + Send>>,
+) {
+    unstarted_connection_with(SerialConfig::default())
+}
+
+/// Like [`unstarted_connection`], but connecting with `serial_config`.
+fn unstarted_connection_with(
+    serial_config: SerialConfig,
+) -> (
+    SerialConnection,
+    serial::ShutdownHandle,
+    MockDevice,
+    Pin<Box<dyn Future<Output = ()> + Send>>,
+) {
+    let (device, transport) = MockDevice::new(display_config(), false);
+    let (tx, rx) = async_channel::unbounded();
+    let (serial_conn, shutdown_handle, serial_task) =
+        serial::start_transport_task(Box::new(transport), serial_config, tx, rx);
+    let task = {
+        let device = device.clone();
+        let serial_task = Box::into_pin(serial_task);
+        Box::pin(async move {
+            tokio::join!(device.run(), serial_task);
+        })
+    };
+    (serial_conn, shutdown_handle, device, task)
+}
+
+#[tokio::test]
+async fn control_messages_go_first_without_starving_frame_data() {
+    let (serial_conn, _shutdown_handle, device, task) = unstarted_connection();
+    for row in 0..6 {
+        serial_conn.try_update_row(row, [true; 8]).unwrap();
+    }
+    for _ in 0..6 {
+        serial_conn
+            .enqueue_message(SerialMessage::SetLedState(SetLedState { new_state: true }))
+            .unwrap();
+    }
+    tokio::spawn(task);
+    serial_conn.flush().await.unwrap();
+    // The device answers in order, so it has handled everything written before this
+    serial_conn.get_display_info().await.unwrap();
+
+    let lanes = device
+        .received_messages()
+        .iter()
+        .filter_map(|msg| match msg {
+            SerialMessage::UpdateRow(_) => Some('B'),
+            SerialMessage::SetLedState(_) => Some('C'),
+            _ => None,
+        })
+        .collect::<String>();
+    // Up to four control messages are written at a time while frame data is waiting
+    assert_eq!(lanes, "CCCCBCCBBBBB");
+}
+
+#[tokio::test]
+async fn row_updates_stay_in_order() {
+    let (serial_conn, _shutdown_handle, device, task) = unstarted_connection();
+    tokio::spawn(task);
+    for row in 0..8u8 {
+        let data = (0..8).map(|col| col == row).collect::<Vec<_>>();
+        serial_conn.try_update_row(row, data).unwrap();
+    }
+    serial_conn.flush().await.unwrap();
+    serial_conn.get_display_info().await.unwrap();
+
+    let rows = device
+        .received_messages()
+        .into_iter()
+        .filter_map(|msg| match msg {
+            SerialMessage::UpdateRow(inner) => Some(inner.row_number),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rows, (0..8).collect::<Vec<_>>());
+    let framebuffer = device.framebuffer();
+    for (row, pixels) in framebuffer.iter().enumerate() {
+        let lit = pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, &pixel)| pixel != 0)
+            .map(|(col, _)| col)
+            .collect::<Vec<_>>();
+        assert_eq!(lit, [row]);
+    }
+}
+
+fn rejected_row() -> DeviceError {
+    DeviceError::Rejected {
+        request: "UpdateRow".to_owned(),
+    }
+}
+
+fn device_error(err: &io::Error) -> Option<&DeviceError> {
+    err.get_ref()
+        .and_then(|err| err.downcast_ref::<DeviceError>())
+}
+
+#[tokio::test]
+async fn rejections_are_reported_without_retransmission() {
+    let (serial_conn, _shutdown_handle, _device, task) = unstarted_connection();
+    tokio::spawn(task);
+
+    // The device only has eight rows
+    let err = serial_conn.update_row(8, [true; 8]).await.unwrap_err();
+    assert_eq!(device_error(&err), Some(&rejected_row()));
+    serial_conn.update_row(7, [true; 8]).await.unwrap();
+    // Both were waited on, so there's nothing left to report
+    assert_eq!(serial_conn.take_rejection(), None);
+}
+
+#[tokio::test]
+async fn rejections_of_queued_rows_are_kept_for_later() {
+    let (serial_conn, _shutdown_handle, _device, task) = unstarted_connection();
+    tokio::spawn(task);
+
+    // Nothing waits to hear from the device about queued rows
+    serial_conn.try_update_row(8, [true; 8]).unwrap();
+    serial_conn.flush().await.unwrap();
+    serial_conn.get_display_info().await.unwrap();
+    assert_eq!(serial_conn.take_rejection(), Some(rejected_row()));
+    assert_eq!(serial_conn.take_rejection(), None);
+}
+
+#[tokio::test]
+async fn retransmitted_messages_are_not_answered_by_batched_acks() {
+    let (serial_conn, _shutdown_handle, _device, task) = unstarted_connection_with(SerialConfig {
+        retransmit: Some(RetransmitConfig::default()),
+        ..Default::default()
+    });
+    tokio::spawn(task);
+
+    // Rows the device rejects, whose acknowledgements arrive while the next row is waited on
+    for row in 8..12 {
+        serial_conn.try_update_row(row, [true; 8]).unwrap();
+    }
+    serial_conn.update_row(0, [true; 8]).await.unwrap();
+    assert_eq!(serial_conn.take_rejection(), Some(rejected_row()));
+
+    let err = serial_conn.update_row(8, [true; 8]).await.unwrap_err();
+    assert_eq!(device_error(&err), Some(&rejected_row()));
+    // That one was waited on, so it isn't reported again
+    serial_conn.get_display_info().await.unwrap();
+    assert_eq!(serial_conn.take_rejection(), None);
+}
+
+#[tokio::test]
+async fn superseded_rows_are_not_retransmitted() {
+    let (serial_conn, _shutdown_handle, device, task) = unstarted_connection_with(SerialConfig {
+        retransmit: Some(RetransmitConfig::default()),
+        coalesce_rows: true,
+        ..Default::default()
+    });
+    let older = tokio::spawn({
+        let serial_conn = serial_conn.clone();
+        async move { serial_conn.update_row(0, [true; 8]).await }
+    });
+    let newer = tokio::spawn({
+        let serial_conn = serial_conn.clone();
+        async move { serial_conn.update_row(0, [false; 8]).await }
+    });
+    // Both are queued before anything is written, so the older one is dropped
+    tokio::task::yield_now().await;
+    tokio::spawn(task);
+    older.await.unwrap().unwrap();
+    newer.await.unwrap().unwrap();
+    serial_conn.get_display_info().await.unwrap();
+
+    let rows = device
+        .received_messages()
+        .into_iter()
+        .filter_map(|msg| match msg {
+            SerialMessage::UpdateRow(inner) => Some(inner.row_data),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rows, [[0x00]]);
+    assert_eq!(device.framebuffer()[0], [0; 8]);
+    assert_eq!(serial_conn.dropped_frame_count(), 1);
+}
+
+/// A device which stops reading after it's connected to, so that writes to it stall. It can only
+/// be connected to once.
+#[derive(Default)]
+struct StallingTransport {
+    device_end: Mutex<Option<DuplexStream>>,
+}
+
+impl fmt::Display for StallingTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stalling device")
+    }
+}
+
+impl Transport for StallingTransport {
+    fn connect(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn TransportStream>>> + Send + '_>> {
+        let mut device_end = self.device_end.lock().unwrap();
+        let stream = if device_end.is_none() {
+            // Nothing is ever read from the other end, so there's only room for a single byte
+            let (host_end, stalled_end) = tokio::io::duplex(1);
+            *device_end = Some(stalled_end);
+            Ok(Box::new(host_end) as Box<dyn TransportStream>)
+        } else {
+            Err(io::ErrorKind::ConnectionRefused.into())
+        };
+        Box::pin(std::future::ready(stream))
+    }
+}
+
+#[tokio::test]
+async fn stalled_writes_degrade_the_connection_before_it_drops() {
+    let (tx, rx) = async_channel::unbounded();
+    let config = SerialConfig {
+        write_timeout: Duration::from_millis(50),
+        keepalive: KeepaliveConfig {
+            enabled: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (serial_conn, _shutdown_handle, serial_task) =
+        serial::start_transport_task(Box::new(StallingTransport::default()), config, tx, rx);
+    let events = serial_conn.subscribe_events();
+    tokio::spawn(Box::into_pin(serial_task));
+    serial_conn.wait_for_connection().await.unwrap();
+
+    let err = serial_conn
+        .send_message(SerialMessage::Ping)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    let mut seen = Vec::new();
+    while let Ok(Ok(event)) = tokio::time::timeout(Duration::from_secs(1), events.recv()).await {
+        seen.push(event);
+        if event == ConnectionEvent::Disconnected {
+            break;
+        }
+    }
+    // The device stays degraded until reopening the port fails
+    assert_eq!(
+        seen,
+        [
+            ConnectionEvent::Connected,
+            ConnectionEvent::Degraded,
+            ConnectionEvent::Disconnected
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_connection_can_block_on_a_worker_thread() {
+    let (serial_conn, _shutdown_handle, device, task) = unstarted_connection();
+    tokio::spawn(task);
+    let serial_conn = SyncSerialConnection::new(serial_conn, tokio::runtime::Handle::current());
+
+    serial_conn.update_row(2, [true; 8]).unwrap();
+    serial_conn.get_display_info().unwrap();
+    assert_eq!(device.framebuffer()[2], [1; 8]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn sync_connection_can_block_inside_another_runtime() {
+    // The calling runtime has no other thread to hand its work to, so the connection has to
+    // run somewhere else
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let (serial_conn, _shutdown_handle, device, task) = unstarted_connection();
+    rt.spawn(task);
+    let serial_conn = SyncSerialConnection::new(serial_conn, rt.handle().clone());
+
+    serial_conn.update_row(5, [true; 8]).unwrap();
+    serial_conn.get_display_info().unwrap();
+    assert_eq!(device.framebuffer()[5], [1; 8]);
+    rt.shutdown_background();
+}
@@ -1,19 +1,45 @@
 use std::io;
-use strum::AsRefStr;
+use strum::{AsRefStr, IntoStaticStr};
 
-#[derive(Clone, Debug, AsRefStr)]
+#[derive(Clone, Debug, AsRefStr, IntoStaticStr)]
 pub enum SerialMessage {
     SetLedState(SetLedState),
     SetLedStateResponse(SetLedStateResponse),
     SetRgbState(SetRgbState),
     SetRgbStateResponse(SetRgbStateResponse),
+    SetBrightness(SetBrightness),
+    SetBrightnessResponse(SetBrightnessResponse),
     GetDisplayInfo(GetDisplayInfo),
     GetDisplayInfoResponse(GetDisplayInfoResponse),
+    /// Sent by firmware from before `ReportInput` whenever its one button is pressed
     ReportButtonPress,
+    /// A button being pressed or released, or an encoder being turned, sent by the device
+    /// whenever it happens
+    ReportInput(ReportInput),
+    GetFirmwareInfo(GetFirmwareInfo),
+    GetFirmwareInfoResponse(GetFirmwareInfoResponse),
+    DebugLog(DebugLog),
+    /// Rows wider than 255 pixels are sent in segments. Segments which don't start at the
+    /// beginning of the row carry the column they start at and are sent as a message kind of
+    /// their own, so firmware which only takes whole rows never sees them. The same goes for
+    /// the RGB row updates.
     UpdateRow(UpdateRow),
     UpdateRowResponse(UpdateRowResponse),
     UpdateRowRgb(UpdateRowRgb),
     UpdateRowRgbResponse(UpdateRowRgbResponse),
+    /// A row for displays with eight bits per channel. Acknowledged with an
+    /// `UpdateRowRgbResponse` like the 16 bit formats.
+    UpdateRowRgb888(UpdateRowRgb888),
+    CommitRender(CommitRender),
+    CommitRenderResponse(CommitRenderResponse),
+    /// Restarts the firmware. The device drops off the bus while it reboots instead of
+    /// responding.
+    ResetDevice,
+    /// Restarts into the bootloader so new firmware can be flashed
+    EnterBootloader,
+    /// Switches the device to protecting every frame after this one with a CRC16, in both
+    /// directions. Only sent to firmware reporting `Capabilities::CRC`, and not acknowledged.
+    EnableCrc,
     Ping,
     PingResponse,
 }
@@ -24,7 +50,7 @@ impl SerialMessage {
         match self {
             SerialMessage::UpdateRow(inner) => {
                 out.push(0xa0);
-                out.push(0x00);
+                out.push(if inner.column_offset == 0 { 0x00 } else { 0x09 });
                 out.append(&mut inner.to_bytes())
             }
             SerialMessage::UpdateRowResponse(inner) => {
@@ -34,7 +60,7 @@ impl SerialMessage {
             }
             SerialMessage::UpdateRowRgb(inner) => {
                 out.push(0xa0);
-                out.push(0x02);
+                out.push(if inner.column_offset == 0 { 0x02 } else { 0x0a });
                 out.append(&mut inner.to_bytes())
             }
             SerialMessage::UpdateRowRgbResponse(inner) => {
@@ -52,6 +78,21 @@ impl SerialMessage {
                 out.push(0x05);
                 out.append(&mut inner.to_bytes())
             }
+            SerialMessage::CommitRender(inner) => {
+                out.push(0xa0);
+                out.push(0x06);
+                out.append(&mut inner.to_bytes())
+            }
+            SerialMessage::CommitRenderResponse(inner) => {
+                out.push(0xa0);
+                out.push(0x07);
+                out.append(&mut inner.to_bytes())
+            }
+            SerialMessage::UpdateRowRgb888(inner) => {
+                out.push(0xa0);
+                out.push(if inner.column_offset == 0 { 0x08 } else { 0x0b });
+                out.append(&mut inner.to_bytes())
+            }
             SerialMessage::SetLedState(inner) => {
                 out.push(0xde);
                 out.push(0x00);
@@ -76,6 +117,48 @@ impl SerialMessage {
                 out.push(0xde);
                 out.push(0x04);
             }
+            SerialMessage::GetFirmwareInfo(inner) => {
+                out.push(0xde);
+                out.push(0x05);
+                out.append(&mut inner.to_bytes())
+            }
+            SerialMessage::GetFirmwareInfoResponse(inner) => {
+                out.push(0xde);
+                out.push(0x06);
+                out.append(&mut inner.to_bytes())
+            }
+            SerialMessage::DebugLog(inner) => {
+                out.push(0xde);
+                out.push(0x07);
+                out.append(&mut inner.to_bytes())
+            }
+            SerialMessage::SetBrightness(inner) => {
+                out.push(0xde);
+                out.push(0x08);
+                out.append(&mut inner.to_bytes())
+            }
+            SerialMessage::SetBrightnessResponse(inner) => {
+                out.push(0xde);
+                out.push(0x09);
+                out.append(&mut inner.to_bytes())
+            }
+            SerialMessage::ResetDevice => {
+                out.push(0xde);
+                out.push(0x0a);
+            }
+            SerialMessage::EnterBootloader => {
+                out.push(0xde);
+                out.push(0x0b);
+            }
+            SerialMessage::ReportInput(inner) => {
+                out.push(0xde);
+                out.push(0x0c);
+                out.append(&mut inner.to_bytes())
+            }
+            SerialMessage::EnableCrc => {
+                out.push(0xde);
+                out.push(0x0d);
+            }
             SerialMessage::Ping => {
                 out.push(0xde);
                 out.push(0xfe);
@@ -104,6 +187,30 @@ impl SerialMessage {
                 (0xa0, 0x03) => Ok(SerialMessage::UpdateRowRgbResponse(
                     UpdateRowRgbResponse::try_from_bytes(&data[2..])?,
                 )),
+                (0xa0, 0x04) => Ok(SerialMessage::GetDisplayInfo(
+                    GetDisplayInfo::try_from_bytes(&data[2..])?,
+                )),
+                (0xa0, 0x05) => Ok(SerialMessage::GetDisplayInfoResponse(
+                    GetDisplayInfoResponse::try_from_bytes(&data[2..])?,
+                )),
+                (0xa0, 0x06) => Ok(SerialMessage::CommitRender(CommitRender::try_from_bytes(
+                    &data[2..],
+                )?)),
+                (0xa0, 0x07) => Ok(SerialMessage::CommitRenderResponse(
+                    CommitRenderResponse::try_from_bytes(&data[2..])?,
+                )),
+                (0xa0, 0x08) => Ok(SerialMessage::UpdateRowRgb888(
+                    UpdateRowRgb888::try_from_bytes(&data[2..])?,
+                )),
+                (0xa0, 0x09) => Ok(SerialMessage::UpdateRow(UpdateRow::try_from_segment_bytes(
+                    &data[2..],
+                )?)),
+                (0xa0, 0x0a) => Ok(SerialMessage::UpdateRowRgb(
+                    UpdateRowRgb::try_from_segment_bytes(&data[2..])?,
+                )),
+                (0xa0, 0x0b) => Ok(SerialMessage::UpdateRowRgb888(
+                    UpdateRowRgb888::try_from_segment_bytes(&data[2..])?,
+                )),
                 (0xde, 0x00) => Ok(SerialMessage::SetLedState(SetLedState::try_from_bytes(
                     &data[2..],
                 )?)),
@@ -117,6 +224,28 @@ impl SerialMessage {
                     SetRgbStateResponse::try_from_bytes(&data[2..])?,
                 )),
                 (0xde, 0x04) => Ok(SerialMessage::ReportButtonPress),
+                (0xde, 0x05) => Ok(SerialMessage::GetFirmwareInfo(
+                    GetFirmwareInfo::try_from_bytes(&data[2..])?,
+                )),
+                (0xde, 0x06) => Ok(SerialMessage::GetFirmwareInfoResponse(
+                    GetFirmwareInfoResponse::try_from_bytes(&data[2..])?,
+                )),
+                (0xde, 0x07) => Ok(SerialMessage::DebugLog(DebugLog::try_from_bytes(
+                    &data[2..],
+                )?)),
+                (0xde, 0x08) => Ok(SerialMessage::SetBrightness(SetBrightness::try_from_bytes(
+                    &data[2..],
+                )?)),
+                (0xde, 0x09) => Ok(SerialMessage::SetBrightnessResponse(
+                    SetBrightnessResponse::try_from_bytes(&data[2..])?,
+                )),
+                (0xde, 0x0a) => Ok(SerialMessage::ResetDevice),
+                (0xde, 0x0b) => Ok(SerialMessage::EnterBootloader),
+                (0xde, 0x0c) => Ok(SerialMessage::ReportInput(ReportInput::try_from_bytes(
+                    &data[2..],
+                )?)),
+                (0xde, 0x0d) => Ok(SerialMessage::EnableCrc),
+                (0xde, 0xfe) => Ok(SerialMessage::Ping),
                 (0xde, 0xff) => Ok(SerialMessage::PingResponse),
                 _ => {
                     tracing::error!(
@@ -133,6 +262,100 @@ impl SerialMessage {
     }
 }
 
+/// Conversions between `SerialMessage` and the structs carried by its variants, so that a
+/// specific kind of message can be picked out with `T::try_from(msg)`. A failed conversion hands
+/// back the original message.
+macro_rules! impl_message_conversions {
+    ($($variant:ident),* $(,)?) => {
+        $(
+            impl From<$variant> for SerialMessage {
+                fn from(value: $variant) -> Self {
+                    SerialMessage::$variant(value)
+                }
+            }
+
+            impl TryFrom<SerialMessage> for $variant {
+                type Error = SerialMessage;
+                fn try_from(value: SerialMessage) -> Result<Self, Self::Error> {
+                    match value {
+                        SerialMessage::$variant(inner) => Ok(inner),
+                        other => Err(other),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_message_conversions!(
+    SetLedState,
+    SetLedStateResponse,
+    SetRgbState,
+    SetRgbStateResponse,
+    SetBrightness,
+    SetBrightnessResponse,
+    GetDisplayInfo,
+    GetDisplayInfoResponse,
+    GetFirmwareInfo,
+    GetFirmwareInfoResponse,
+    DebugLog,
+    ReportInput,
+    UpdateRow,
+    UpdateRowResponse,
+    UpdateRowRgb,
+    UpdateRowRgb888,
+    UpdateRowRgbResponse,
+    CommitRender,
+    CommitRenderResponse,
+);
+
+/// Computes the CRC-16/CCITT-FALSE checksum of a payload.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Appends the big-endian CRC16 of a payload to its end.
+pub fn append_crc(payload: &mut Vec<u8>) {
+    let crc = crc16(&payload[..]);
+    payload.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Verifies and strips the CRC16 trailing a payload.
+pub fn strip_crc(payload: &[u8]) -> io::Result<&[u8]> {
+    if payload.len() < 2 {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+    let (data, crc) = payload.split_at(payload.len() - 2);
+    if crc16(data).to_be_bytes() == crc {
+        Ok(data)
+    } else {
+        Err(io::ErrorKind::InvalidData.into())
+    }
+}
+
+/// Leads a payload which carries a sequence number ahead of the message, see
+/// [`Capabilities::SEQUENCING`]
+pub const SEQUENCE_MARKER: u8 = 0x5e;
+
+/// Splits the big-endian sequence number off the front of a payload, if it has one.
+pub fn split_sequence_number(payload: &[u8]) -> io::Result<(Option<u16>, &[u8])> {
+    match payload {
+        [SEQUENCE_MARKER, seq_hi, seq_lo, data @ ..] => {
+            Ok((Some(u16::from_be_bytes([*seq_hi, *seq_lo])), data))
+        }
+        [SEQUENCE_MARKER, ..] => Err(io::ErrorKind::InvalidData.into()),
+        data => Ok((None, data)),
+    }
+}
+
 #[derive(Clone, Debug)]
 #[repr(u8)]
 pub enum Status {
@@ -251,38 +474,118 @@ impl SetRgbStateResponse {
     }
 }
 
+/// Sets the global PWM brightness of the panel, from `0` (off) to `255` (full).
+#[derive(Clone, Debug)]
+pub struct SetBrightness {
+    pub level: u8,
+}
+
+impl SetBrightness {
+    pub fn to_bytes(self) -> Vec<u8> {
+        vec![self.level]
+    }
+
+    pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.len() == 1 {
+            Ok(Self { level: data[0] })
+        } else {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SetBrightnessResponse {
+    pub status: Status,
+}
+
+impl SetBrightnessResponse {
+    pub fn to_bytes(self) -> Vec<u8> {
+        vec![self.status.into()]
+    }
+
+    pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.len() == 1 {
+            Ok(Self {
+                status: Status::try_from(data[0])?,
+            })
+        } else {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct UpdateRow {
     pub row_number: u8,
+    /// The column the first pixel goes in, which is only non-zero for segments of rows wider
+    /// than 255 pixels
+    pub column_offset: u16,
     pub row_data_len: u8,
     pub row_data: Vec<u8>,
 }
 
 impl UpdateRow {
     pub fn to_bytes(mut self) -> Vec<u8> {
-        let mut out = vec![self.row_number, self.row_data_len];
+        let mut out = row_header(self.row_number, self.column_offset, self.row_data_len);
         out.append(&mut self.row_data);
         out
     }
 
     pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
-        if data.len() >= 3 {
-            let row_number = data[0];
-            let row_data_len = data[1];
-            let row_data = Vec::from(&data[2..]);
-            Ok(UpdateRow {
-                row_number,
-                row_data_len,
-                row_data,
-            })
-        } else {
-            Err(io::ErrorKind::InvalidData.into())
+        Self::parse(data, false)
+    }
+
+    /// Parses a segment which doesn't start at the beginning of the row.
+    pub fn try_from_segment_bytes(data: &[u8]) -> io::Result<Self> {
+        Self::parse(data, true)
+    }
+
+    fn parse(data: &[u8], is_segment: bool) -> io::Result<Self> {
+        let (row_number, column_offset, row_data_len, row_data) =
+            split_row_header(data, is_segment)?;
+        if row_data.is_empty() {
+            return Err(io::ErrorKind::InvalidData.into());
         }
+        Ok(UpdateRow {
+            row_number,
+            column_offset,
+            row_data_len,
+            row_data: row_data.to_vec(),
+        })
+    }
+}
+
+/// The fields every row update starts with. The column is only sent for segments which don't
+/// start at the beginning of the row.
+fn row_header(row_number: u8, column_offset: u16, row_data_len: u8) -> Vec<u8> {
+    let mut out = vec![row_number];
+    if column_offset != 0 {
+        out.extend(column_offset.to_be_bytes());
+    }
+    out.push(row_data_len);
+    out
+}
+
+/// Splits a row update into its row number, column, length, and pixel data.
+fn split_row_header(data: &[u8], is_segment: bool) -> io::Result<(u8, u16, u8, &[u8])> {
+    let (row_number, column_offset, rest) = match (is_segment, data) {
+        (true, [row_number, offset_high, offset_low, rest @ ..]) => (
+            *row_number,
+            u16::from_be_bytes([*offset_high, *offset_low]),
+            rest,
+        ),
+        (false, [row_number, rest @ ..]) => (*row_number, 0, rest),
+        _ => return Err(io::ErrorKind::InvalidData.into()),
+    };
+    match rest {
+        [row_data_len, row_data @ ..] => Ok((row_number, column_offset, *row_data_len, row_data)),
+        [] => Err(io::ErrorKind::InvalidData.into()),
     }
 }
 
 pub fn pack_bools_to_bytes(bits: &[bool]) -> Vec<u8> {
-    bits.into_iter()
+    bits.iter()
         .enumerate()
         .fold(Vec::new(), |mut acc, (idx, elem)| {
             let byte_idx = idx / 8;
@@ -297,6 +600,22 @@ pub fn pack_bools_to_bytes(bits: &[bool]) -> Vec<u8> {
         })
 }
 
+/// Packs 16 bit colors into the bytes an `UpdateRowRgb` carries, big endian.
+pub fn pack_rgb_to_bytes(pixels: &[u16]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_be_bytes())
+        .collect()
+}
+
+/// Packs `0x00RRGGBB` colors into the bytes an `UpdateRowRgb888` carries, red first.
+pub fn pack_rgb888_to_bytes(pixels: &[u32]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_be_bytes().into_iter().skip(1))
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct UpdateRowResponse {
     pub status: Status,
@@ -321,42 +640,97 @@ impl UpdateRowResponse {
 #[derive(Debug, Clone)]
 pub struct UpdateRowRgb {
     pub row_number: u8,
+    /// The column the first pixel goes in, see [`UpdateRow::column_offset`]
+    pub column_offset: u16,
     pub row_data_len: u8,
-    pub row_data: Vec<u16>,
+    /// The pixels as they're sent, see [`pack_rgb_to_bytes`]
+    pub row_data: Vec<u8>,
 }
 
 impl UpdateRowRgb {
-    pub fn to_bytes(self) -> Vec<u8> {
-        let mut out = vec![self.row_number, self.row_data_len];
-        let mut row_data = self
-            .row_data
-            .into_iter()
-            .map(|elem| elem.to_be_bytes())
-            .flatten()
-            .collect();
-        out.append(&mut row_data);
+    pub fn to_bytes(mut self) -> Vec<u8> {
+        let mut out = row_header(self.row_number, self.column_offset, self.row_data_len);
+        out.append(&mut self.row_data);
         out
     }
 
     pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
-        if data.len() >= 3 {
-            let row_number = data[0];
-            let row_data_len = data[1];
-            let row_data = Vec::from(&data[2..]);
-            let row_data = data[2..]
-                .iter()
-                .enumerate()
-                .step_by(2)
-                .map(|(idx, _elem)| u16::from_be_bytes([row_data[idx], row_data[idx + 1]]))
-                .collect();
-            Ok(Self {
-                row_number,
-                row_data_len,
-                row_data,
-            })
-        } else {
-            Err(io::ErrorKind::InvalidData.into())
+        Self::parse(data, false)
+    }
+
+    /// Parses a segment which doesn't start at the beginning of the row.
+    pub fn try_from_segment_bytes(data: &[u8]) -> io::Result<Self> {
+        Self::parse(data, true)
+    }
+
+    /// The 16 bit colors of the row's pixels.
+    pub fn pixels(&self) -> impl Iterator<Item = u16> + '_ {
+        self.row_data
+            .chunks_exact(2)
+            .map(|elem| u16::from_be_bytes([elem[0], elem[1]]))
+    }
+
+    fn parse(data: &[u8], is_segment: bool) -> io::Result<Self> {
+        let (row_number, column_offset, row_data_len, row_data) =
+            split_row_header(data, is_segment)?;
+        if row_data.is_empty() || !row_data.len().is_multiple_of(2) {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        Ok(Self {
+            row_number,
+            column_offset,
+            row_data_len,
+            row_data: row_data.to_vec(),
+        })
+    }
+}
+
+/// Each pixel is packed as `0x00RRGGBB` and sent as three bytes, red first.
+#[derive(Debug, Clone)]
+pub struct UpdateRowRgb888 {
+    pub row_number: u8,
+    /// The column the first pixel goes in, see [`UpdateRow::column_offset`]
+    pub column_offset: u16,
+    pub row_data_len: u8,
+    /// The pixels as they're sent, see [`pack_rgb888_to_bytes`]
+    pub row_data: Vec<u8>,
+}
+
+impl UpdateRowRgb888 {
+    pub fn to_bytes(mut self) -> Vec<u8> {
+        let mut out = row_header(self.row_number, self.column_offset, self.row_data_len);
+        out.append(&mut self.row_data);
+        out
+    }
+
+    pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
+        Self::parse(data, false)
+    }
+
+    /// Parses a segment which doesn't start at the beginning of the row.
+    pub fn try_from_segment_bytes(data: &[u8]) -> io::Result<Self> {
+        Self::parse(data, true)
+    }
+
+    /// The row's pixels, each packed as `0x00RRGGBB`.
+    pub fn pixels(&self) -> impl Iterator<Item = u32> + '_ {
+        self.row_data
+            .chunks_exact(3)
+            .map(|elem| u32::from_be_bytes([0, elem[0], elem[1], elem[2]]))
+    }
+
+    fn parse(data: &[u8], is_segment: bool) -> io::Result<Self> {
+        let (row_number, column_offset, row_data_len, row_data) =
+            split_row_header(data, is_segment)?;
+        if row_data.is_empty() || !row_data.len().is_multiple_of(3) {
+            return Err(io::ErrorKind::InvalidData.into());
         }
+        Ok(Self {
+            row_number,
+            column_offset,
+            row_data_len,
+            row_data: row_data.to_vec(),
+        })
     }
 }
 
@@ -390,7 +764,7 @@ impl GetDisplayInfo {
     }
 
     pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
-        if data.len() == 0 {
+        if data.is_empty() {
             Ok(Self {})
         } else {
             Err(io::ErrorKind::InvalidData.into())
@@ -398,11 +772,15 @@ impl GetDisplayInfo {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum PixelRepresentation {
     Monocolor = 0,
     RGB555 = 1,
+    /// Rows are sent with `UpdateRowRgb`, packed as `RRRRRGGGGGGBBBBB`
+    RGB565 = 2,
+    /// Rows are sent with `UpdateRowRgb888`
+    RGB888 = 3,
 }
 
 impl PixelRepresentation {
@@ -410,11 +788,17 @@ impl PixelRepresentation {
         Ok(match byte {
             0 => PixelRepresentation::Monocolor,
             1 => PixelRepresentation::RGB555,
+            2 => PixelRepresentation::RGB565,
+            3 => PixelRepresentation::RGB888,
             _ => {
                 return Err(io::ErrorKind::InvalidData.into());
             }
         })
     }
+
+    pub fn is_rgb(self) -> bool {
+        !matches!(self, PixelRepresentation::Monocolor)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -446,3 +830,257 @@ impl GetDisplayInfoResponse {
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct GetFirmwareInfo;
+
+impl GetFirmwareInfo {
+    pub fn to_bytes(self) -> Vec<u8> {
+        vec![]
+    }
+
+    pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.is_empty() {
+            Ok(Self {})
+        } else {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+    }
+}
+
+/// Shows the rows written since the last commit all at once. Only firmware which double buffers
+/// the display understands this, other firmware shows each row as soon as it's written.
+#[derive(Clone, Debug)]
+pub struct CommitRender;
+
+impl CommitRender {
+    pub fn to_bytes(self) -> Vec<u8> {
+        vec![]
+    }
+
+    pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.is_empty() {
+            Ok(Self)
+        } else {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CommitRenderResponse {
+    pub status: Status,
+}
+
+impl CommitRenderResponse {
+    pub fn to_bytes(self) -> Vec<u8> {
+        vec![self.status.into()]
+    }
+
+    pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.len() == 1 {
+            Ok(Self {
+                status: Status::try_from(data[0])?,
+            })
+        } else {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+    }
+}
+
+/// Features of the protocol which a firmware revision supports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities(pub u32);
+
+impl Capabilities {
+    pub const RGB: Capabilities = Capabilities(1 << 0);
+    pub const MULTI_ROW_UPDATES: Capabilities = Capabilities(1 << 1);
+    pub const CRC: Capabilities = Capabilities(1 << 2);
+    pub const BUTTONS: Capabilities = Capabilities(1 << 3);
+    pub const BRIGHTNESS: Capabilities = Capabilities(1 << 4);
+    pub const DOUBLE_BUFFERING: Capabilities = Capabilities(1 << 5);
+    /// Messages from the device are numbered so that retransmitted duplicates can be dropped
+    pub const SEQUENCING: Capabilities = Capabilities(1 << 6);
+    /// The firmware can be restarted, and restarted into its bootloader, over the serial port
+    pub const REMOTE_RESET: Capabilities = Capabilities(1 << 7);
+
+    /// What firmware which predates the firmware info request is assumed to support
+    pub const LEGACY: Capabilities = Capabilities(Self::RGB.0 | Self::BUTTONS.0);
+
+    pub const fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GetFirmwareInfoResponse {
+    pub version_major: u16,
+    pub version_minor: u16,
+    pub version_patch: u16,
+    pub capabilities: Capabilities,
+}
+
+impl GetFirmwareInfoResponse {
+    pub fn to_bytes(self) -> Vec<u8> {
+        [
+            &self.version_major.to_be_bytes()[..],
+            &self.version_minor.to_be_bytes()[..],
+            &self.version_patch.to_be_bytes()[..],
+            &self.capabilities.0.to_be_bytes()[..],
+        ]
+        .concat()
+    }
+
+    pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.len() == 10 {
+            Ok(Self {
+                version_major: u16::from_be_bytes(data[0..2].try_into().unwrap()),
+                version_minor: u16::from_be_bytes(data[2..4].try_into().unwrap()),
+                version_patch: u16::from_be_bytes(data[4..6].try_into().unwrap()),
+                capabilities: Capabilities(u32::from_be_bytes(data[6..10].try_into().unwrap())),
+            })
+        } else {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LogLevel {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+    Trace = 4,
+}
+
+impl TryFrom<u8> for LogLevel {
+    type Error = io::Error;
+    fn try_from(value: u8) -> Result<Self, io::Error> {
+        match value {
+            0 => Ok(LogLevel::Error),
+            1 => Ok(LogLevel::Warn),
+            2 => Ok(LogLevel::Info),
+            3 => Ok(LogLevel::Debug),
+            4 => Ok(LogLevel::Trace),
+            _ => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum InputKind {
+    ButtonPressed = 0,
+    ButtonReleased = 1,
+    /// The value is how many detents the encoder was turned by, positive for clockwise
+    EncoderTurned = 2,
+}
+
+impl TryFrom<u8> for InputKind {
+    type Error = io::Error;
+    fn try_from(value: u8) -> Result<Self, io::Error> {
+        match value {
+            0 => Ok(InputKind::ButtonPressed),
+            1 => Ok(InputKind::ButtonReleased),
+            2 => Ok(InputKind::EncoderTurned),
+            _ => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+/// Input from one of the device's buttons or encoders, which are numbered from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportInput {
+    pub kind: InputKind,
+    pub id: u8,
+    /// Only used by encoders, and 0 otherwise
+    pub value: i8,
+}
+
+impl ReportInput {
+    pub fn to_bytes(self) -> Vec<u8> {
+        vec![self.kind as u8, self.id, self.value.to_be_bytes()[0]]
+    }
+
+    pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
+        if data.len() == 3 {
+            Ok(Self {
+                kind: InputKind::try_from(data[0])?,
+                id: data[1],
+                value: i8::from_be_bytes([data[2]]),
+            })
+        } else {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+    }
+}
+
+/// A log line emitted by the firmware. The message is expected to be UTF-8, but isn't guaranteed
+/// to be.
+#[derive(Debug, Clone)]
+pub struct DebugLog {
+    pub level: LogLevel,
+    pub message: Vec<u8>,
+}
+
+impl DebugLog {
+    pub fn to_bytes(mut self) -> Vec<u8> {
+        let mut out = vec![self.level as u8];
+        out.append(&mut self.message);
+        out
+    }
+
+    pub fn try_from_bytes(data: &[u8]) -> io::Result<Self> {
+        if let Some((level, message)) = data.split_first() {
+            Ok(Self {
+                level: LogLevel::try_from(*level)?,
+                message: message.to_vec(),
+            })
+        } else {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_updates_without_pixels_are_rejected() {
+        // Row 3, with a length but no pixel data
+        let header = [3, 0];
+        assert!(UpdateRow::try_from_bytes(&header).is_err());
+        assert!(UpdateRowRgb::try_from_bytes(&header).is_err());
+        assert!(UpdateRowRgb888::try_from_bytes(&header).is_err());
+        // The same for a segment starting at column 255
+        let header = [3, 0, 255, 0];
+        assert!(UpdateRow::try_from_segment_bytes(&header).is_err());
+        assert!(UpdateRowRgb::try_from_segment_bytes(&header).is_err());
+        assert!(UpdateRowRgb888::try_from_segment_bytes(&header).is_err());
+    }
+
+    #[test]
+    fn rgb888_rows_round_trip() {
+        let update = UpdateRowRgb888 {
+            row_number: 3,
+            column_offset: 0,
+            row_data_len: 2,
+            row_data: pack_rgb888_to_bytes(&[0x123456, 0xabcdef]),
+        };
+        let msg = SerialMessage::try_from_bytes(&SerialMessage::from(update).to_bytes()).unwrap();
+        let update = UpdateRowRgb888::try_from(msg).unwrap();
+        assert_eq!(update.row_number, 3);
+        assert_eq!(update.pixels().collect::<Vec<_>>(), [0x123456, 0xabcdef]);
+        assert!(UpdateRowRgb888::try_from(SerialMessage::Ping).is_err());
+    }
+}
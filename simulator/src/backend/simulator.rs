@@ -49,8 +49,10 @@ async fn handle_serial_message(
                 .send(SerialMessage::PingResponse.to_bytes())
                 .await?;
         }
+        // The simulated displays are narrow enough that rows are never split into segments
         SerialMessage::UpdateRow(UpdateRow {
             row_number,
+            column_offset: 0,
             row_data_len,
             row_data,
         }) => {
@@ -60,16 +62,12 @@ async fn handle_serial_message(
                 .send(SerialMessage::UpdateRowResponse(UpdateRowResponse { status }).to_bytes())
                 .await?;
         }
-        SerialMessage::UpdateRowRgb(UpdateRowRgb {
-            row_number,
-            row_data_len: _,
-            row_data,
-        }) => {
+        SerialMessage::UpdateRowRgb(update) if update.column_offset == 0 => {
             to_ws
                 .send(
                     serde_json::to_string(&SimMessage::SetMatrixRowRgb(SetMatrixRowRgb {
-                        row: row_number as usize,
-                        data: row_data,
+                        row: update.row_number as usize,
+                        data: update.pixels().collect(),
                     }))
                     .unwrap(),
                 )
@@ -83,9 +81,22 @@ async fn handle_serial_message(
                 )
                 .await?;
         }
+        SerialMessage::GetFirmwareInfo(GetFirmwareInfo) => {
+            to_serial
+                .send(
+                    SerialMessage::GetFirmwareInfoResponse(GetFirmwareInfoResponse {
+                        version_major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+                        version_minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+                        version_patch: env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0),
+                        capabilities: Capabilities::RGB | Capabilities::BUTTONS,
+                    })
+                    .to_bytes(),
+                )
+                .await?
+        }
         SerialMessage::GetDisplayInfo(GetDisplayInfo) => {
             to_serial
-                .send(SerialMessage::GetDisplayInfoResponse(display_cfg.clone().into()).to_bytes())
+                .send(SerialMessage::GetDisplayInfoResponse((*display_cfg).into()).to_bytes())
                 .await?
         }
         SerialMessage::SetLedState(SetLedState { new_state }) => {
@@ -129,16 +140,12 @@ async fn handle_update_row(
     row_data_len: u8,
     row_data: Vec<u8>,
 ) -> Status {
-    if usize::from((row_data_len / 8) + if row_data_len % 8 == 0 { 0 } else { 1 }) == row_data.len()
+    if usize::from((row_data_len / 8) + if row_data_len.is_multiple_of(8) { 0 } else { 1 })
+        == row_data.len()
     {
         let pixel_states = row_data
             .into_iter()
-            .map(|byte| {
-                (0..8)
-                    .into_iter()
-                    .map(move |bit| (byte & (1 << bit)) != 0x00)
-            })
-            .flatten()
+            .flat_map(|byte| (0..8).map(move |bit| (byte & (1 << bit)) != 0x00))
             .collect::<Vec<bool>>();
         if display_cfg.is_rgb {
             Status::Failure